@@ -0,0 +1,5 @@
+//! Rebuild when a migration or query file changes: `sqlx::migrate!()` & `query_file*!()` read them at compile time.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=queries");
+}
@@ -0,0 +1,9 @@
+-- `Todo` records fetched from jsonplaceholder (`GET /todos`); `id` is the remote id.
+CREATE TABLE IF NOT EXISTS todos (
+    id        INTEGER PRIMARY KEY NOT NULL,
+    user_id   INTEGER NOT NULL,
+    title     TEXT    NOT NULL,
+    completed BOOLEAN NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS todos_user_id_completed ON todos (user_id, completed);
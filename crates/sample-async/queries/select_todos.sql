@@ -0,0 +1,6 @@
+-- ?1 user_id, ?2 completed; NULL matches anything
+SELECT id AS "id: u32", user_id AS "user_id: u32", title, completed AS "completed: bool"
+FROM todos
+WHERE (?1 IS NULL OR user_id = ?1)
+  AND (?2 IS NULL OR completed = ?2)
+ORDER BY id
@@ -0,0 +1,10 @@
+-- ?1 id, ?2 user_id, ?3 title, ?4 completed; an identical existing row is left untouched (0 rows affected)
+INSERT INTO todos (id, user_id, title, completed)
+VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT (id) DO UPDATE SET
+    user_id =   excluded.user_id,
+    title =     excluded.title,
+    completed = excluded.completed
+WHERE todos.user_id IS NOT excluded.user_id
+   OR todos.title IS NOT excluded.title
+   OR todos.completed IS NOT excluded.completed
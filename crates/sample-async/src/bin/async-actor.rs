@@ -2,224 +2,713 @@
 # Actor Example
 (To heck with streams ... they're ultimately a funny way of funelling code into something serialized.)
 [Rhyl: Actor Example](https://ryhl.io/blog/actors-with-tokio/)
+
+The printers run under a `utilities::supervisor::Supervisor`: `fail N` / `panic N` make printer N fail, and the
+REPL reports its restart (state is carried over by `recover`).  Strategy from the first argument:
+`one-for-one` (default) or `one-for-all`.
+
+Commands are parsed with clap (`help`, `help <COMMAND>`); words split shell-style, so messages can be quoted.  Most
+take a printer selection: `3`, `0..3`, `0..=3`, `2..`, `0,2..4` or `all`.
+```text
+> message 0..3 "hello  there"
+> rate all 250
+> toggle 1,4..
+> sleep 1s
+> printcount
+> report --live 10s
+```
+`report` shows each printer's state & `utilities::actor` metrics (mailbox depth, messages handled by type, handling
+time, ticks run & missed, restarts); `--live` keeps redrawing it.
+Interactive input has line editing & history; `--script <FILE>` runs a file of commands instead (e.g.
+`cargo run -p sample-async --bin async-actor -- --script data/scripts/actor-demo.txt`), then shuts down.
+
+`drop`, end of input or Ctrl-C shut down gracefully (`utilities::Shutdown`): each printer handles what's already in
+its mailbox and reports its print count; any still running after `SHUTDOWN_GRACE` is aborted.  A second Ctrl-C
+exits at once.
 */
 
 // ///////////////////////////////////////// [ use ] ///////////////////////////////////////// //
-use std::{error::Error, time::Duration};
+use std::{collections::BTreeSet, fmt::Write as _, io::IsTerminal as _, ops::ControlFlow,
+          path::PathBuf, process::ExitCode, str::FromStr, time::Duration};
 
+use clap::{Parser, ValueEnum, error::ErrorKind};
+use rustyline::{DefaultEditor, error::ReadlineError};
 use thiserror::Error;
-use tokio::{select,
-            sync::{Semaphore, mpsc, oneshot},
-            task,
-            time::{Interval, interval}};
+use tokio::sync::mpsc;
+use utilities::{CommonError, ReportFormat, Shutdown,
+                actor::{Actor, ActorHandle, ActorMetrics, Context, Reply},
+                supervisor::{Strategy, Supervisor, SupervisorEvent}};
 // ///////////////////////////////////////// [ error ] ///////////////////////////////////////// //
 #[derive(Error, Debug)]
-pub enum ActorError {
+pub enum PrinterError {
     #[error("Message alreaedy set {0}")]
     MessageAlreadySet(String),
-    // #[error("Failed to send message")]
-    // SendError(#[from] mpsc::error::SendError<Message>),
-    // #[error("Failed to receive message")]
-    // ReceiveError(#[from] mpsc::error::RecvError),
+    #[error("Failure requested")]
+    Requested,
 }
+utilities::error_wrapper!(
+    /// [`CommonError`] with the span trace & backtrace of where it was converted (`?`).
+    struct ErrWrapper(CommonError);
+);
 // ///////////////////////////////////////// [ actor-elements ] ///////////////////////////////////////// //
+/// Prints its message every `period`, while active.
+/// (mailbox, run loop & replies: `utilities::actor`)
 struct PrintingActor {
-    receiver:      mpsc::Receiver<ActorMessage>,
     print_message: Option<String>,
-    rate:          Interval,
+    period:        Duration,
     print_count:   u32,
-    _resource:     Semaphore,
     active:        bool,
 }
-enum ActorMessage {
+enum PrinterMsg {
     _InitMessage(String),
     SetMessage(String),
     SetPeriod(Duration),
     SetActive,
     SetInactive,
     ToggleActive,
-    GetPrintCount { resp_channel: oneshot::Sender<u32> },
-    GetStatus { resp_channel: oneshot::Sender<bool> },
+    GetPrintCount(Reply<u32>),
+    GetReport(Reply<PrinterReport>),
+    Fail,
+    Panic,
+}
+/// Answer to `GetReport`.
+struct PrinterReport {
+    active:      bool,
+    print_count: u32,
+    metrics:     ActorMetrics,
 }
+/// Mailbox capacity: senders wait once this many messages are queued.
+const MAILBOX: usize = 5;
+/// For `report`/`printcount`: an actor that doesn't answer by then is reported as unresponsive.
+const ASK_TIMEOUT: Duration = Duration::from_secs(1);
+/// `report --live`: redraw interval.
+const LIVE_REFRESH: Duration = Duration::from_millis(500);
+/// More than this many failures within `RESTART_WINDOW` and the supervisor stops every printer.
+const MAX_RESTARTS: u32 = 3;
+const RESTART_WINDOW: Duration = Duration::from_secs(10);
+/// On shutdown: how long printers get to drain their mailboxes before they're aborted.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
 // ///////////////////////////////////////// [ actor-impls ] ///////////////////////////////////////// //
+impl Default for PrintingActor {
+    fn default() -> Self {
+        Self { print_message: None,
+               period:        Duration::from_millis(100),
+               print_count:   0,
+               active:        true, }
+    }
+}
 impl PrintingActor {
-    pub async fn spawn(
-        )
-        -> (mpsc::Sender<ActorMessage>, task::JoinHandle<Result<(), ActorError>>)
-    {
-        let (tx, rx) = mpsc::channel(5);
-        let actor = Self { receiver:      rx,
-                           print_message: None,
-                           rate:          interval(Duration::from_millis(100)),
-                           print_count:   0,
-                           _resource:     Semaphore::new(1),
-                           active:        true, };
-        let task_handle = task::spawn(async move { actor.run().await });
-        (tx, task_handle)
-    }
-
-    /// Actor's run loop.
-    async fn run(mut self) -> Result<(), ActorError> {
-        loop {
-            select! {
-                Some(msg) = self.receiver.recv() => {
-                        self.handle_message(msg).await?
-                }
-                _ = self.rate.tick(), if self.active => {
-                    if let Some(message) = &self.print_message {
-                        println!("{}", message);
-                        self.print_count += 1;
-                    }
-                }
-            }
-        }
+    /// Tick at `period` while active.
+    fn reschedule(&self, ctx: &mut Context<Self>) {
+        ctx.set_interval(self.active
+                             .then_some(self.period));
+    }
+}
+impl Actor for PrintingActor {
+    type Error = PrinterError;
+    type Message = PrinterMsg;
+
+    async fn started(&mut self, ctx: &mut Context<Self>) -> Result<(), PrinterError> {
+        self.reschedule(ctx);
+        Ok(())
     }
 
-    pub async fn handle_message(&mut self, msg: ActorMessage) -> Result<(), ActorError> {
+    async fn handle(&mut self,
+                    msg: PrinterMsg,
+                    ctx: &mut Context<Self>)
+                    -> Result<(), PrinterError> {
+        let schedule = (self.active, self.period);
         match msg {
-            ActorMessage::_InitMessage(msg) => {
+            PrinterMsg::_InitMessage(msg) => {
                 if let Some(mssg) = &self.print_message {
-                    Err(ActorError::MessageAlreadySet(mssg.clone()))?;
+                    Err(PrinterError::MessageAlreadySet(mssg.clone()))?;
                 }
                 self.print_message = Some(msg);
             },
-            ActorMessage::SetMessage(msg) => self.print_message = Some(msg),
-            ActorMessage::SetPeriod(period) => self.rate = interval(period),
-            ActorMessage::SetActive => self.active = true,
-            ActorMessage::SetInactive => self.active = false,
-            ActorMessage::ToggleActive => self.active = !self.active,
-            ActorMessage::GetPrintCount { resp_channel } => resp_channel.send(self.print_count)
-                                                                        .unwrap(),
-            ActorMessage::GetStatus { resp_channel } => resp_channel.send(self.active)
-                                                                    .unwrap(),
+            PrinterMsg::SetMessage(msg) => self.print_message = Some(msg),
+            PrinterMsg::SetPeriod(period) => self.period = period,
+            PrinterMsg::SetActive => self.active = true,
+            PrinterMsg::SetInactive => self.active = false,
+            PrinterMsg::ToggleActive => self.active = !self.active,
+            PrinterMsg::GetPrintCount(reply) => reply.send(self.print_count),
+            PrinterMsg::GetReport(reply) =>
+                reply.send(PrinterReport { active:      self.active,
+                                           print_count: self.print_count,
+                                           metrics:     ctx.metrics().clone(), }),
+            PrinterMsg::Fail => Err(PrinterError::Requested)?,
+            PrinterMsg::Panic => panic!("panic requested"),
+        }
+        // only on a change: a new interval starts a whole period from now (an ask mustn't push the next print back)
+        if (self.active, self.period) != schedule {
+            self.reschedule(ctx);
         }
         Ok(())
     }
+
+    async fn tick(&mut self, _ctx: &mut Context<Self>) -> Result<(), PrinterError> {
+        if let Some(message) = &self.print_message {
+            println!("{}", message);
+            self.print_count += 1;
+        }
+        Ok(())
+    }
+
+    async fn stopping(&mut self, ctx: &mut Context<Self>) {
+        println!("{} stopping after {} prints", ctx.name(), self.print_count);
+    }
+
+    fn message_name(msg: &PrinterMsg) -> &'static str {
+        match msg {
+            PrinterMsg::_InitMessage(_) => "InitMessage",
+            PrinterMsg::SetMessage(_) => "SetMessage",
+            PrinterMsg::SetPeriod(_) => "SetPeriod",
+            PrinterMsg::SetActive => "SetActive",
+            PrinterMsg::SetInactive => "SetInactive",
+            PrinterMsg::ToggleActive => "ToggleActive",
+            PrinterMsg::GetPrintCount(_) => "GetPrintCount",
+            PrinterMsg::GetReport(_) => "GetReport",
+            PrinterMsg::Fail => "Fail",
+            PrinterMsg::Panic => "Panic",
+        }
+    }
+
+    /// Carry the settings & count over a restart.
+    fn recover(&mut self, failed: Self) {
+        *self = failed;
+    }
+}
+// ///////////////////////////////////////// [ cli ] ///////////////////////////////////////// //
+#[derive(Parser, Debug)]
+#[command(version,
+          about = "Supervised printing actors, driven by a small command language (`help` at the prompt)")]
+struct Cli {
+    /// How the supervisor restarts a failed printer
+    #[arg(value_enum, default_value_t = StrategyArg::OneForOne)]
+    strategy: StrategyArg,
+    /// Run the commands in FILE (one per line; `#` comments; `sleep 500ms` to pause), then shut down
+    #[arg(long, value_name = "FILE")]
+    script:   Option<PathBuf>,
+    /// Number of printers
+    #[arg(long, default_value_t = 6)]
+    printers: usize,
+}
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum StrategyArg {
+    OneForOne,
+    OneForAll,
+}
+impl From<StrategyArg> for Strategy {
+    fn from(strategy: StrategyArg) -> Self {
+        match strategy {
+            StrategyArg::OneForOne => Self::OneForOne,
+            StrategyArg::OneForAll => Self::OneForAll,
+        }
+    }
+}
+// ///////////////////////////////////////// [ commands ] ///////////////////////////////////////// //
+/// Drive the printers.  Quote arguments with spaces: `message 0 "hello  there"`.
+// (one line of input, split shell-style)
+#[derive(Parser, Debug)]
+#[command(multicall = true,
+          subcommand_value_name = "COMMAND",
+          subcommand_help_heading = "Commands")]
+enum ReplCommand {
+    /// Set what printers print
+    Message {
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+        #[arg(required = true)]
+        text:    Vec<String>,
+    },
+    /// Start printing
+    Activate {
+        #[arg(default_value = "all")]
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Stop printing
+    #[command(visible_alias = "pause")]
+    Deactivate {
+        #[arg(default_value = "all")]
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Switch printing on/off
+    Toggle {
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Print every MS milliseconds
+    Rate {
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        ms:      u64,
+    },
+    /// Make printers fail (the supervisor restarts them)
+    Fail {
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Make printers panic (the supervisor restarts them)
+    Panic {
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Show printers' state & metrics: mailbox, messages handled, handling time, ticks
+    Report {
+        #[arg(default_value = "all")]
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+        /// Keep redrawing the table for this long (e.g. `10s`)
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        live:    Option<Duration>,
+    },
+    /// Show how often printers have printed
+    Printcount {
+        #[arg(default_value = "all")]
+        #[arg(help = TARGETS_HELP)]
+        targets: Targets,
+    },
+    /// Wait before the next command (e.g. `sleep 1.5s`)
+    Sleep {
+        #[arg(value_parser = humantime::parse_duration)]
+        duration: Duration,
+    },
+    /// Stop the printers (after their queued messages) and exit
+    #[command(visible_aliases = ["exit", "quit"])]
+    Drop,
+}
+const TARGETS_HELP: &str = "Printers: 3, 0..3, 0..=3, 2.., 0,2..4 or all";
+impl ReplCommand {
+    fn parse_line(line: &str) -> Result<Self, clap::Error> {
+        let words = shlex::split(line).ok_or_else(|| {
+                                          clap::Error::raw(ErrorKind::InvalidValue,
+                                                           "unbalanced quotes\n")
+                                      })?;
+        Self::try_parse_from(words)
+    }
 }
-// ///////////////////////////////////////// [  ] ///////////////////////////////////////// //
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    println!("Hello, world!");
-    let mut controls = Vec::new();
-    for _ in 0..6 {
-        let (tx, handle) = PrintingActor::spawn().await;
-        controls.push((tx, handle));
-    }
-
-    loop {
-        let mut input = String::new();
-        let commands = indoc::indoc!(
-                                     "
-                        \nCommands:
-                        \"message N\": set message for actor N
-                        \"activate N\": activate actor N
-                        \"deactivate N\": deactivate actor N
-                        \"toggle N\": toggle actor N
-                        \"rate N MS\": set rate in ms for actor N
-                        \"pause\": pause all actors
-                        \"drop\": abort actors
-                        \"report\": report status of all actors
-                        \"printcount\": print count of actors
-                        "
-        );
-        println!("{commands}");
-
-        std::io::stdin().read_line(&mut input)
-                        .unwrap();
-        let parts: Vec<&str> = input.split_whitespace()
-                                    .collect();
-
-        match parts.as_slice() {
-            ["drop"] => {
-                for (_, handle) in &controls {
-                    handle.abort();
+/// Which printers a command applies to: `3`, `0..3` (end excluded), `0..=3`, `2..`, comma-separated lists of
+/// those (`0,2..4`), or `all`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Targets(Vec<(usize, Option<usize>)>);
+impl FromStr for Targets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Self(vec![(0, None)]));
+        }
+        let not_a_printer = |n: &str| format!("`{n}` is not a printer number (try `3`, `0..3`, `0..=3`, `2..` or `all`)");
+        let index = |n: &str| {
+            n.parse::<usize>()
+             .map_err(|_| not_a_printer(n))
+        };
+        // one past `n` (`usize::MAX` has no successor to end a range at)
+        let past = |n: &str| {
+            index(n)?.checked_add(1)
+                     .ok_or_else(|| not_a_printer(n))
+        };
+        s.split(',')
+         .map(|part| match part.split_once("..") {
+             None => Ok((index(part)?, Some(past(part)?))),
+             Some((start, "")) => index(start).map(|start| (start, None)),
+             Some((start, end)) => {
+                 let start = index(start)?;
+                 let end = match end.strip_prefix('=') {
+                     Some(end) => past(end)?,
+                     None => index(end)?,
+                 };
+                 match start < end {
+                     true => Ok((start, Some(end))),
+                     false => Err(format!("`{part}` is empty")),
+                 }
+             },
+         })
+         .collect::<Result<_, _>>()
+         .map(Self)
+    }
+}
+impl Targets {
+    /// Indices below `count`, ascending & without repeats.
+    fn resolve(&self, count: usize) -> Result<Vec<usize>, String> {
+        let mut indices = BTreeSet::new();
+        for &(start, end) in &self.0 {
+            let end = end.unwrap_or(count);
+            if end > count || start >= count {
+                return Err(format!("there are only {count} printers (0..{count})"));
+            }
+            indices.extend(start..end);
+        }
+        Ok(indices.into_iter().collect())
+    }
+}
+
+/// Run one command.  `Break` on `drop`; `Err` for an invalid selection.
+async fn execute(command: ReplCommand,
+                 printers: &[ActorHandle<PrintingActor>])
+                 -> Result<ControlFlow<()>, String> {
+    use ReplCommand as C;
+    match command {
+        C::Message { targets, text } => {
+            let text = text.join(" ");
+            tell_each(printers, &targets, || PrinterMsg::SetMessage(text.clone())).await?
+        },
+        C::Activate { targets } => tell_each(printers, &targets, || PrinterMsg::SetActive).await?,
+        C::Deactivate { targets } =>
+            tell_each(printers, &targets, || PrinterMsg::SetInactive).await?,
+        C::Toggle { targets } => tell_each(printers, &targets, || PrinterMsg::ToggleActive).await?,
+        C::Rate { targets, ms } =>
+            tell_each(printers, &targets, || {
+                PrinterMsg::SetPeriod(Duration::from_millis(ms))
+            }).await?,
+        C::Fail { targets } => tell_each(printers, &targets, || PrinterMsg::Fail).await?,
+        C::Panic { targets } => tell_each(printers, &targets, || PrinterMsg::Panic).await?,
+        C::Report { targets, live } => {
+            let indices = targets.resolve(printers.len())?;
+            let until = live.map(|live| tokio::time::Instant::now() + live);
+            // redraw in place on a terminal; otherwise (e.g. a script's output) one table after another
+            let redraw = std::io::stdout().is_terminal();
+            let mut drawn_lines = 0;
+            loop {
+                let table = report_table(printers, &indices).await;
+                if redraw && drawn_lines > 0 {
+                    // up to the table's first line, then clear to the end of the screen
+                    print!("\x1b[{drawn_lines}F\x1b[J");
                 }
-                break;
-            },
-            ["pause"] =>
-                for (tx, _) in &controls {
-                    tx.send(ActorMessage::SetInactive)
-                      .await?;
-                },
-            ["activate"] =>
-                for (tx, _) in &controls {
-                    tx.send(ActorMessage::SetActive)
-                      .await?;
-                },
-            ["message", n] =>
-                if let Ok(idx) = n.parse::<usize>()
-                   && let Some((tx, _)) = controls.get(idx)
-                {
-                    println!("Enter message for Actor {}:", idx);
-                    let mut msg = String::new();
-                    std::io::stdin().read_line(&mut msg)
-                                    .unwrap();
-                    tx.send(ActorMessage::SetMessage(msg.trim().to_string()))
-                      .await
-                      .unwrap_or_else(|e| eprintln!("Failed to send message: {}", e));
-                },
-            ["activate", n] =>
-                if let Ok(idx) = n.parse::<usize>()
-                   && let Some((tx, _)) = controls.get(idx)
-                {
-                    tx.send(ActorMessage::SetActive)
-                      .await
-                      .unwrap_or_else(|e| eprintln!("Failed to activate actor {}: {}", idx, e));
-                },
-            ["deactivate", n] =>
-                if let Ok(idx) = n.parse::<usize>()
-                   && let Some((tx, _)) = controls.get(idx)
-                {
-                    tx.send(ActorMessage::SetInactive)
-                      .await
-                      .unwrap_or_else(|e| eprintln!("Failed to deactivate actor {}: {}", idx, e));
-                },
-            ["toggle", n] =>
-                if let Ok(idx) = n.parse::<usize>()
-                   && let Some((tx, _)) = controls.get(idx)
-                {
-                    tx.send(ActorMessage::ToggleActive)
-                      .await
-                      .unwrap_or_else(|e| eprintln!("Failed to toggle actor {}: {}", idx, e));
-                },
-            ["rate", n, ms] =>
-                if let (Ok(idx), Ok(ms)) = (n.parse::<usize>(), ms.parse::<u64>())
-                   && let Some((tx, _)) = controls.get(idx)
+                print!("{table}");
+                drawn_lines = table.lines().count();
+                match until {
+                    Some(until) if tokio::time::Instant::now() + LIVE_REFRESH <= until =>
+                        tokio::time::sleep(LIVE_REFRESH).await,
+                    _ => break,
+                }
+            }
+        },
+        C::Printcount { targets } =>
+            for idx in targets.resolve(printers.len())? {
+                match printers[idx].ask_timeout(PrinterMsg::GetPrintCount, ASK_TIMEOUT)
+                                   .await
                 {
-                    tx.send(ActorMessage::SetPeriod(Duration::from_millis(ms)))
-                      .await
-                      .unwrap_or_else(|e| eprintln!("Failed to set rate for actor {}: {}", idx, e));
-                },
-            ["report"] =>
-                for (idx, (tx, _)) in controls.iter().enumerate() {
-                    let (otx, orx) = oneshot::channel();
-                    tx.send(ActorMessage::GetStatus { resp_channel: otx })
-                      .await
-                      .unwrap_or_else(|e| {
-                          eprintln!("Failed to report status for actor {}: {}", idx, e)
-                      });
-                    let active_status = orx.await?;
-                    println!("Actor {}: {}",
-                             idx,
-                             if active_status { "active" } else { "inactive" });
-                },
-            ["printcount"] =>
-                for (idx, (tx, _)) in controls.iter().enumerate() {
-                    let (otx, orx) = oneshot::channel();
-                    tx.send(ActorMessage::GetPrintCount { resp_channel: otx })
-                      .await
-                      .unwrap_or_else(|e| {
-                          eprintln!("Failed to get print count for actor {}: {}", idx, e)
-                      });
-                    let count = orx.await?;
-                    println!("Actor {} print count: {}", idx, count);
+                    Ok(count) => println!("Actor {} print count: {}", idx, count),
+                    Err(e) => eprintln!("Failed to get print count for actor {}: {}", idx, e),
+                }
+            },
+        C::Sleep { duration } => tokio::time::sleep(duration).await,
+        C::Drop => return Ok(ControlFlow::Break(())),
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+/// One row per printer: state, then its `ActorMetrics`.
+async fn report_table(printers: &[ActorHandle<PrintingActor>], indices: &[usize]) -> String {
+    let mut table = format!("{:>3} {:<8} {:>6} {:>7} {:>4} {:>7} {:>6} {:>9} {:>9} {:>6} {:>6} {:>8}  {}\n",
+                            "#",
+                            "state",
+                            "prints",
+                            "mailbox",
+                            "peak",
+                            "handled",
+                            "msg/s",
+                            "mean",
+                            "max",
+                            "ticks",
+                            "missed",
+                            "restarts",
+                            "by type");
+    for &idx in indices {
+        let report = match printers[idx].ask_timeout(PrinterMsg::GetReport, ASK_TIMEOUT)
+                                        .await
+        {
+            Ok(report) => report,
+            Err(e) => {
+                let _ = writeln!(table, "{idx:>3} {e}");
+                continue;
+            },
+        };
+        let metrics = &report.metrics;
+        let handled = metrics.total_handled();
+        let total: Duration = metrics.handled
+                                     .values()
+                                     .map(|stats| stats.total)
+                                     .sum();
+        let mean = total
+                   / handled.max(1)
+                            .try_into()
+                            .unwrap_or(u32::MAX);
+        let max = metrics.handled
+                         .values()
+                         .map(|stats| stats.max)
+                         .max()
+                         .unwrap_or_default();
+        let by_type = metrics.handled
+                             .iter()
+                             .map(|(name, stats)| format!("{name}:{}", stats.count))
+                             .collect::<Vec<_>>()
+                             .join(" ");
+        let _ = writeln!(table,
+                         "{idx:>3} {:<8} {:>6} {:>7} {:>4} {:>7} {:>6.1} {:>9} {:>9} {:>6} {:>6} {:>8}  {by_type}",
+                         if report.active { "active" } else { "inactive" },
+                         report.print_count,
+                         format!("{}/{}", metrics.mailbox_depth, metrics.mailbox_capacity),
+                         metrics.max_mailbox_depth,
+                         handled,
+                         metrics.throughput(),
+                         format!("{mean:.1?}"),
+                         format!("{max:.1?}"),
+                         metrics.ticks,
+                         metrics.ticks_missed,
+                         metrics.restarts);
+    }
+    table
+}
+
+async fn tell_each(printers: &[ActorHandle<PrintingActor>],
+                   targets: &Targets,
+                   msg: impl Fn() -> PrinterMsg)
+                   -> Result<(), String> {
+    for idx in targets.resolve(printers.len())? {
+        printers[idx].tell(msg())
+                     .await
+                     .unwrap_or_else(|e| eprintln!("Failed to send to actor {}: {}", idx, e));
+    }
+    Ok(())
+}
+// ///////////////////////////////////////// [ input ] ///////////////////////////////////////// //
+/// Where command lines come from.
+enum Input {
+    /// Line editing & history; read on a plain thread: a pending read doesn't hold up the runtime's shutdown.
+    Editor(mpsc::UnboundedReceiver<String>),
+    /// Echoed as they run; errors stop the script.
+    Script {
+        path:    PathBuf,
+        lines:   std::iter::Enumerate<std::vec::IntoIter<String>>,
+        line_no: usize,
+    },
+}
+impl Input {
+    fn editor() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            let mut editor = match DefaultEditor::new() {
+                Ok(editor) => editor,
+                Err(e) => return eprintln!("No line editor: {e}"),
+            };
+            loop {
+                match editor.readline("> ") {
+                    Ok(line) => {
+                        let _ = editor.add_history_entry(&line);
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    },
+                    // Ctrl-C (the terminal is in raw mode: no SIGINT) & Ctrl-D: end of input
+                    Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                    Err(e) => {
+                        eprintln!("Failed to read input: {e}");
+                        break;
+                    },
+                }
+            }
+        });
+        Self::Editor(receiver)
+    }
+
+    fn script(path: PathBuf) -> std::io::Result<Self> {
+        let lines: Vec<String> = std::fs::read_to_string(&path)?.lines()
+                                                                .map(str::to_owned)
+                                                                .collect();
+        Ok(Self::Script { path,
+                          lines: lines.into_iter().enumerate(),
+                          line_no: 0 })
+    }
+
+    /// `None` at end of input.
+    async fn next_line(&mut self) -> Option<String> {
+        match self {
+            Self::Editor(receiver) => receiver.recv().await,
+            Self::Script { lines, line_no, .. } => {
+                let (idx, line) = lines.next()?;
+                *line_no = idx + 1;
+                Some(line)
+            },
+        }
+    }
+
+    fn is_script(&self) -> bool {
+        matches!(self, Self::Script { .. })
+    }
+
+    /// `FILE:LINE` of the last script line.
+    fn location(&self) -> Option<String> {
+        match self {
+            Self::Editor(_) => None,
+            Self::Script { path, line_no, .. } => Some(format!("{}:{line_no}", path.display())),
+        }
+    }
+}
+// ///////////////////////////////////////// [ main ] ///////////////////////////////////////// //
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // e.g. an unreadable or failing `--script`
+        Err(error) => error.exit(ReportFormat::Text),
+    }
+}
+async fn run(cli: Cli) -> Result<(), ErrWrapper> {
+    let mut input = match cli.script {
+        Some(path) => Input::script(path)?,
+        None => Input::editor(),
+    };
+    let shutdown = Shutdown::new().with_ctrl_c();
+    let supervisor = Supervisor::builder().strategy(cli.strategy.into())
+                                          .max_restarts(MAX_RESTARTS)
+                                          .within(RESTART_WINDOW)
+                                          .shutdown(shutdown.clone())
+                                          .build();
+    let mut events = supervisor.events();
+    tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            match event {
+                SupervisorEvent::Restarted { .. } => println!("Supervisor: {event}"),
+                SupervisorEvent::GaveUp { .. } => eprintln!("Supervisor: {event}"),
+            }
+        }
+    });
+    let printers: Vec<_> = (0..cli.printers).map(|idx| {
+                                                supervisor.child(PrintingActor::default)
+                                                          .name(format!("printer-{idx}"))
+                                                          .mailbox(MAILBOX)
+                                                          .call()
+                                            })
+                                            .collect();
+    if !input.is_script() {
+        println!("{} printers running; `help` for commands", printers.len());
+    }
+
+    // Ctrl-C, end of input or an error in a script: as `drop` (a script error is then the exit status)
+    let mut script_error = None;
+    while let Some(Some(line)) = shutdown.run_until_triggered(input.next_line())
+                                         .await
+    {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if input.is_script() {
+            println!("> {line}");
+        }
+        let outcome = match ReplCommand::parse_line(line) {
+            Ok(command) => shutdown.run_until_triggered(execute(command, &printers))
+                                   .await
+                                   .unwrap_or(Ok(ControlFlow::Break(()))),
+            // (incl. `help`, which isn't an error)
+            Err(e) if !e.use_stderr() => {
+                let _ = e.print();
+                continue;
+            },
+            Err(e) => Err(e.render()
+                           .to_string()
+                           .trim_end()
+                           .to_owned()),
+        };
+        match outcome {
+            Ok(ControlFlow::Continue(())) => {},
+            Ok(ControlFlow::Break(())) => break,
+            Err(e) => match input.location() {
+                Some(location) => {
+                    script_error = Some(format!("{location}: {e}"));
+                    break;
                 },
+                None => eprintln!("{e}"),
+            },
+        }
+    }
+
+    println!("Shutting down: draining mailboxes...");
+    // each printer's `stopping` reports its print count
+    let drained = shutdown.drain(SHUTDOWN_GRACE)
+                          .await;
+    println!("Exiting ({drained})");
+    match script_error {
+        Some(error) => Err(CommonError::from(error))?,
+        None => Ok(()),
+    }
+}
+// ///////////////////////////////////////// [ tests ] ///////////////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_asks_dont_delay_ticks() {
+        let printer = PrintingActor { print_message: Some("tick".to_string()),
+                                      ..PrintingActor::default() };
+        let (printer, _task) = utilities::actor::spawn(printer).call();
+        // off the tick boundaries
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        // asked twice per period, as `report --live` does
+        for _ in 0..20 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            printer.ask(PrinterMsg::GetReport)
+                   .await
+                   .unwrap();
+        }
+        // 1s at 100ms
+        assert_eq!(printer.ask(PrinterMsg::GetPrintCount)
+                          .await
+                          .unwrap(),
+                   10);
 
-            _ => println!("Invalid command. Use: {commands}"),
+        // an unchanged setting doesn't restart the interval either
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            printer.tell(PrinterMsg::SetActive)
+                   .await
+                   .unwrap();
         }
+        assert_eq!(printer.ask(PrinterMsg::GetPrintCount)
+                          .await
+                          .unwrap(),
+                   12);
     }
 
-    println!("Exiting...");
-    Ok(())
+    #[test]
+    fn test_targets() {
+        let resolve = |s: &str| {
+            s.parse::<Targets>()?
+             .resolve(6)
+        };
+        assert_eq!(resolve("3"), Ok(vec![3]));
+        assert_eq!(resolve("0..3"), Ok(vec![0, 1, 2]));
+        assert_eq!(resolve("0..=3,2,5"), Ok(vec![0, 1, 2, 3, 5]));
+        assert_eq!(resolve("4.."), Ok(vec![4, 5]));
+        assert_eq!(resolve("all"), Ok((0..6).collect()));
+        assert!(resolve("6").is_err());
+        assert!(resolve("2..9").is_err());
+        assert!(resolve("3..3").is_err());
+        assert!(resolve("x").is_err());
+        assert!(resolve("0..=18446744073709551615").is_err());
+        assert!(resolve("18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert!(matches!(ReplCommand::parse_line(r#"message 0..2 "hello  there" again"#),
+                         Ok(ReplCommand::Message { text, .. }) if text == ["hello  there", "again"]));
+        assert!(matches!(ReplCommand::parse_line("pause"),
+                         Ok(ReplCommand::Deactivate { targets }) if targets == "all".parse().unwrap()));
+        assert!(matches!(ReplCommand::parse_line("sleep 1.5s"),
+                         Ok(ReplCommand::Sleep { duration }) if duration == Duration::from_millis(1500)));
+        assert!(ReplCommand::parse_line("message 0").is_err());
+        assert!(ReplCommand::parse_line("message 0 \"unbalanced").is_err());
+        assert!(ReplCommand::parse_line("rate 0 fast").is_err());
+        assert!(ReplCommand::parse_line("rate 0 0").is_err());
+        assert!(ReplCommand::parse_line("rate 0 1").is_ok());
+        assert!(!ReplCommand::parse_line("help").unwrap_err()
+                                                .use_stderr());
+    }
 }
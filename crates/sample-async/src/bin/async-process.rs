@@ -2,15 +2,28 @@
 # Async Processes
 [Tokio Processes](https://docs.rs/tokio/latest/tokio/process/index.html)
 */
+use std::process::ExitCode;
+
 use tokio::process::Command;
+use utilities::{CommonError, ReportFormat};
+
+utilities::error_wrapper!(
+    /// [`CommonError`] with the span trace & backtrace of where it was converted (`?`).
+    struct ErrWrapper(CommonError);
+);
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => error.exit(ReportFormat::Text),
+    }
+}
+async fn run() -> Result<(), ErrWrapper> {
     // The usage is similar as with the standard library's `Command` type
     let mut child = Command::new("echo").arg("hello")
                                         .arg("world")
-                                        .spawn()
-                                        .expect("failed to spawn");
+                                        .spawn()?;
 
     // Await until the command completes
     let status = child.wait().await?;
@@ -23,7 +36,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     dbg!(&output.status.success());
     dbg!(&output.stdout);
-    dbg!(std::str::from_utf8(&output.stdout)?);
+    dbg!(std::str::from_utf8(&output.stdout).map_err(CommonError::dyn_error)?);
 
     Ok(())
 }
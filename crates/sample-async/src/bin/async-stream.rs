@@ -1,15 +1,43 @@
 // ///////////////////////////////////////// [ use ] ///////////////////////////////////////// //
 // use tokio_stream::{self, StreamExt};
 // use futures::{StreamExt, Stream, stream};
-use std::{error::Error, sync::mpsc};
+use std::{process::ExitCode, sync::mpsc};
 
 use async_stream::stream;
+use clap::Parser;
 use futures::{StreamExt, pin_mut};
 use owo_colors::OwoColorize as _;
 use tokio::time::{self, Duration};
+use utilities::{CommonError, HttpClient, ReportFormat, RetryExt as _, RetryPolicy};
+// ///////////////////////////////////////// [ cli ] ///////////////////////////////////////// //
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Send requests here instead of httpbin (e.g. a local stand-in: `cargo run -p stand-in`)
+    #[arg(long, default_value = "https://httpbin.org")]
+    base_url: reqwest::Url,
+}
+// ///////////////////////////////////////// [ error ] ///////////////////////////////////////// //
+utilities::error_wrapper!(
+    /// [`CommonError`] with the span trace & backtrace of where it was converted (`?`).
+    struct ErrWrapper(CommonError);
+);
 // ///////////////////////////////////////// [ main ] ///////////////////////////////////////// //
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => error.exit(ReportFormat::Text),
+    }
+}
+async fn run(cli: Cli) -> Result<(), ErrWrapper> {
+    // `url` appends to any path on `--base-url` (a bare `Url::join("/get")` would replace it)
+    let api = HttpClient::builder().allow_http(cli.base_url.scheme() == "http")
+                                   .base_url(cli.base_url)
+                                   .build()?;
+    let get_url = api.url("get")?;
+    // connect errors & 429/502-504 retried with jittered backoff
+    let retry_policy = RetryPolicy::default();
     // ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ [ classic ] ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ //
     let mut stream = tokio_stream::iter(&[0, 1, 2]);
 
@@ -28,13 +56,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("got {}", value);
     }
     // ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ [ stream!-timed-call+responses ] ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ //
-    let client = reqwest::Client::builder().build()?;
+    let client = api.client().clone();
     let mut interval = time::interval(Duration::from_millis(300));
     let mut now_yield = time::Instant::now();
+    let stream_get_url = get_url.clone();
+    let stream_retry_policy = retry_policy.clone();
     let delstream = stream! {
             for i in 0..10 {
                     let now_req = time::Instant::now();
-                    let resp = client.get("https://httpbin.org/get").send().await.unwrap();
+                    let resp = client.get(stream_get_url.clone()).send_with_retry(&stream_retry_policy).await.unwrap();
                     let elapsed_req = now_req.elapsed();
 
                     let now_tick = time::Instant::now();
@@ -90,6 +120,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // }
     // ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ [ timed-call-tasks ] ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ //
     let (tx, rx) = mpsc::channel();
+    let task_get_url = get_url.clone();
+    let task_retry_policy = retry_policy.clone();
     tokio::task::spawn(async move {
         // tx moved here and therefore dropped at end
         let client = reqwest::Client::builder().build()?;
@@ -97,11 +129,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
         for i in 0..30 {
             let tx = tx.clone();
             let client = client.clone();
+            let get_url = task_get_url.clone();
+            let retry_policy = task_retry_policy.clone();
             interval.tick().await;
             tokio::task::spawn(async move {
                 let now_resp = time::Instant::now();
-                let resp = client.get("https://httpbin.org/get")
-                                 .send()
+                let resp = client.get(get_url)
+                                 .send_with_retry(&retry_policy)
                                  .await
                                  .unwrap();
                 let elapsed_resp = now_resp.elapsed();
@@ -124,8 +158,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
     // ----------------------------- [ reqwest ] ----------------------------- //
     let client = reqwest::Client::builder().build()?;
-    let resp = client.get("https://httpbin.org/get")
-                     .send()
+    let resp = client.get(get_url)
+                     .send_with_retry(&retry_policy)
                      .await?;
     println!("headers: {:#?}", resp.headers());
     println!("url:     {}", resp.url());
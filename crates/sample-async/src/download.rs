@@ -0,0 +1,90 @@
+//! `download` subcommand: stream a URL to a file, with a progress bar on stderr.
+//!
+//! - an interrupted run leaves `<FILE>.part`; running again resumes it (`Range` request), unless `--restart`
+//! - `--sha256` is checked once the file is complete
+//!
+//! e.g. `cargo run -p sample-async -- download 'http://127.0.0.1:8080/range/102400?duration=5' -o data/no-sync/range.bin`
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{io::Write as _,
+          path::PathBuf,
+          time::{Duration, Instant}};
+
+use clap::Args;
+use reqwest::Url;
+use tracing::instrument;
+use utilities::{DownloadProgress, HttpClient};
+
+use crate::SampleResult;
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+const BAR_WIDTH: usize = 30;
+/// Redraw at most this often (chunks can arrive far faster than a terminal can usefully show).
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+// ///////////////////////////////// [ cli ] ///////////////////////////////// //
+#[derive(Args, Debug, Clone)]
+pub struct DownloadArgs {
+    /// URL to download (plain `http` allowed)
+    url:     Url,
+    /// File to write
+    #[arg(short, long)]
+    output:  PathBuf,
+    /// Expected SHA-256 of the whole file (hex)
+    #[arg(long)]
+    sha256:  Option<String>,
+    /// Discard any partial download instead of resuming it
+    #[arg(long)]
+    restart: bool,
+    /// Whole-transfer timeout (e.g. `10m`)
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+}
+// ///////////////////////////////// [ run ] ///////////////////////////////// //
+#[instrument(skip_all, fields(url = %args.url, output = %args.output.display()))]
+pub async fn run(args: DownloadArgs) -> SampleResult<()> {
+    let client = HttpClient::builder().base_url(args.url.clone())
+                                      .allow_http(args.url.scheme() == "http")
+                                      .build()?;
+    let start = Instant::now();
+    let mut last_drawn: Option<Instant> = None;
+    let downloaded = client.download()
+                           .url(args.url)
+                           .to(args.output)
+                           .maybe_sha256(args.sha256)
+                           .resume(!args.restart)
+                           .timeout(args.timeout)
+                           .on_progress(Box::new(move |progress| {
+                                            let done = progress.total == Some(progress.downloaded);
+                                            if done
+                                               || last_drawn.is_none_or(|drawn| {
+                                                                drawn.elapsed() >= REDRAW_INTERVAL
+                                                            })
+                                            {
+                                                last_drawn = Some(Instant::now());
+                                                eprint!("\r{}", progress_bar(progress));
+                                                let _ = std::io::stderr().flush();
+                                            }
+                                        }))
+                           .call()
+                           .await;
+    eprintln!();
+    let downloaded = downloaded?;
+    println!("{}: {} bytes ({} resumed) in {:.2}s\nsha256 {}",
+             downloaded.path.display(),
+             downloaded.bytes,
+             downloaded.resumed_from,
+             start.elapsed().as_secs_f64(),
+             downloaded.sha256);
+    Ok(())
+}
+
+/// e.g. `[###############...............]  51200 / 102400 B (50%)`
+fn progress_bar(progress: &DownloadProgress) -> String {
+    match progress.fraction() {
+        Some(fraction) => {
+            let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+            format!("[{}{}]  {progress}",
+                    "#".repeat(filled),
+                    ".".repeat(BAR_WIDTH - filled))
+        },
+        None => format!("[{}]  {progress}", "?".repeat(BAR_WIDTH)),
+    }
+}
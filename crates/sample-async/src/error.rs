@@ -49,13 +49,13 @@
 //! `#![feature(error_generic_member_access)]`
 //! `use std::backtrace;`
 
-use std::io;
+use std::borrow::Cow;
 
-use derive_more::{Display, Error};
-use tracing::{instrument, subscriber::SetGlobalDefaultError};
+use derive_more::{Display, Error, From};
+use utilities::{Categorize, CommonError, Diagnose, ErrorCategory};
 
-// use derive_more::{Display, Error, derive::From};
-#[derive(Debug, Display, derive_more::From, Error)]
+/// This crate's errors; shared ones (io, http, ..) are [`CommonError`]s (see `utilities::error`).
+#[derive(Debug, Display, From, Error)]
 pub enum ErrKind {
     // `custom` errors //
 
@@ -63,80 +63,48 @@ pub enum ErrKind {
     #[display("CLI parsing library error: {}", source)]
     Clap { source: clap::Error },
 
-    #[display("Error with tracing_subscriber::EnvFilter parsing env directive: {}",
-              source)]
-    EnvError {
-        source: tracing_subscriber::filter::FromEnvError,
-    },
+    #[display("sqlx (database) error: {}", source)]
+    Sqlx { source: sqlx::Error },
 
-    #[display("hiddenvalue error: {}", source)]
-    HiddenValError { source: utilities::HiddenValueError },
-
-    #[display("io error: {}", source)]
-    Io { source: io::Error },
-
-    #[display("parse error: {}", source)]
-    ParseInt { source: std::num::ParseIntError },
-
-    #[display("reqwest error: {}", source)]
-    Reqwest { source: reqwest::Error },
-
-    #[display("Error setting tracing subscriber default: {}", source)]
-    TracingSubscriber { source: SetGlobalDefaultError },
-
-    #[display("url parse error: {}", source)]
-    Url { source: url::ParseError },
-
-    // `other` errors //
-    #[from(ignore)] // use `make_dyn_error` instead; would conflict with auto-derives
-    #[display("Uncategorized Error (dyn error object): {}", source)]
-    OtherDynError {
-        source: Box<dyn std::error::Error + Send + Sync>,
-    },
-
-    #[display(r#"Uncategorized string err: "{}""#, source_string)]
-    OtherStringError { source_string: String },
+    // `common` errors //
+    #[display("{}", source)]
+    Common { source: CommonError },
 }
-impl ErrKind {
-    /// Convenience asscfunction for transforming an error into a compabtible *dyn error*.
-    ///
-    /// ```ignore
-    /// use support::ErrKind;
-    /// let clip = arboard::Clipboard::new().map_err(ErrKind::into_dyn_error)?;
-    /// ```
-    #[instrument(skip_all)]
-    pub fn into_dyn_error<E>(error: E) -> Self
-        where E: Into<Box<dyn std::error::Error + Send + Sync>> {
-        Self::OtherDynError { source: error.into(), }
+impl Categorize for ErrKind {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Clap { .. } => ErrorCategory::User,
+            Self::Sqlx { source } => match source {
+                sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => ErrorCategory::Retryable,
+                sqlx::Error::Configuration(_) => ErrorCategory::User,
+                _ => ErrorCategory::Other,
+            },
+            Self::Common { source } => source.category(),
+        }
     }
 }
-
-#[derive(Display, Error)]
-#[display(
-        "error: {:#}\n\n\nspantrace capture: {:?}\n\n\nspantrace: {:#}",
-        source,
-        spantrace.status(),
-        spantrace,
-)]
-pub struct ErrWrapper {
-    source:    ErrKind,
-    spantrace: tracing_error::SpanTrace,
-    // backtrace: backtrace::Backtrace,
-}
-// Using custom display as debug so we can get SpanTrace auto printed.
-impl std::fmt::Debug for ErrWrapper {
-    #[instrument(skip_all)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+/// Exit codes: 40.. (20..=39 are `CommonError`'s); usage errors 2, as clap's own
+impl Diagnose for ErrKind {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Clap { source } => source.exit_code() as u8,
+            Self::Sqlx { .. } => 40,
+            Self::Common { source } => source.exit_code(),
+        }
     }
-}
-impl<E> From<E> for ErrWrapper where E: Into<ErrKind> {
-    #[instrument(skip_all)]
-    fn from(error: E) -> Self {
-        Self {
-            source:    error.into(),
-            spantrace: tracing_error::SpanTrace::capture(),
-            // backtrace: backtrace::Backtrace::capture(),
+
+    fn suggestion(&self) -> Option<Cow<'static, str>> {
+        match self {
+            Self::Clap { .. } => Some("see `--help`".into()),
+            Self::Sqlx { source: sqlx::Error::Configuration(_) | sqlx::Error::Migrate(_), } =>
+                Some("check the `--db` path (or delete a database from an older schema)".into()),
+            Self::Sqlx { .. } => None,
+            Self::Common { source } => source.suggestion(),
         }
     }
 }
+utilities::common_error_from!(ErrKind);
+utilities::error_wrapper!(
+    /// [`ErrKind`] with the span trace & backtrace of where it was converted (`?`).
+    pub struct ErrWrapper(ErrKind);
+);
@@ -0,0 +1,491 @@
+//! `load` subcommand: drive a URL with concurrent requests & report latency percentiles.
+//!
+//! - stop after `--requests` *or* `--duration`
+//! - `--concurrency` workers; each sends, reads the whole body, and records the latency
+//! - `--rps` throttles all workers together (governor, direct limiter)
+//! - requests go through [`HttpClient::send`]: `--rate-limits` (a quota table) & `--circuit-breaker` apply, as they
+//!   would to a real client (a short-circuited request counts as a `circuit_open` error)
+//! - latencies go into an HDR histogram (µs resolution, 3 significant digits)
+//!
+//! e.g. `cargo run -p sample-async -- load http://127.0.0.1:8080/get -c 16 -d 10s --rps 200 --output json`
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap,
+          num::{NonZeroU32, NonZeroUsize},
+          path::PathBuf,
+          sync::{Arc,
+                 atomic::{AtomicU64, Ordering}},
+          time::{Duration, Instant}};
+
+use clap::{Args, CommandFactory as _, ValueEnum, error::ErrorKind};
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use hdrhistogram::Histogram;
+use reqwest::{Method, Url};
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tracing::{Level as L, event, instrument};
+use utilities::{CircuitBreakers, HttpClient, QuotaTable, RateLimits, SendError,
+                TraceContextExt as _};
+
+use crate::{Cli, SampleResult, error::ErrKind};
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+const DEFAULT_REQUESTS: u64 = 100;
+/// Histogram range: 1µs ..= 10min
+const MAX_LATENCY_MICROS: u64 = 10 * 60 * 1_000_000;
+const HISTOGRAM_SIGFIGS: u8 = 3;
+// ///////////////////////////////// [ cli ] ///////////////////////////////// //
+#[derive(Args, Debug, Clone)]
+pub struct LoadArgs {
+    /// Target URL (plain `http` allowed)
+    url:             Url,
+    /// Total requests to send  [default: 100, unless `--duration` is given]
+    #[arg(short = 'n', long, conflicts_with = "duration")]
+    requests:        Option<u64>,
+    /// Keep sending for this long (e.g. `30s`, `2m`)
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    duration:        Option<Duration>,
+    /// Requests in flight at once
+    #[arg(short, long, default_value = "10")]
+    concurrency:     NonZeroUsize,
+    /// Target requests per second, across all workers  [default: unthrottled]
+    #[arg(long)]
+    rps:             Option<NonZeroU32>,
+    /// TOML file with a `[rate_limits]` quota table (see `utilities::rate_limit`), on top of `--rps`
+    #[arg(long, value_name = "FILE")]
+    rate_limits:     Option<PathBuf>,
+    /// Fail fast (`circuit_open`) while the host is failing, as `HttpClient` users would
+    #[arg(long)]
+    circuit_breaker: bool,
+    #[arg(short = 'X', long, default_value = "GET")]
+    method:          Method,
+    /// Per-request timeout (e.g. `5s`)
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    timeout:         Duration,
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    output:          OutputFormat,
+}
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+/// When to stop issuing requests.
+#[derive(Debug, Clone, Copy)]
+enum Budget {
+    Requests(u64),
+    Until(Instant),
+}
+// ///////////////////////////////// [ run ] ///////////////////////////////// //
+/// Run the load test and print its report.
+pub async fn run(args: LoadArgs) -> SampleResult<()> {
+    let report = load(&args).await?;
+    match args.output {
+        OutputFormat::Table => print!("{report}"),
+        OutputFormat::Json => println!("{}",
+                     serde_json::to_string_pretty(&report).map_err(ErrKind::into_dyn_error)?),
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, fields(url = %args.url, concurrency = args.concurrency))]
+async fn load(args: &LoadArgs) -> SampleResult<LoadReport> {
+    if args.duration
+           .is_some_and(|duration| duration.is_zero())
+       || args.requests == Some(0)
+    {
+        Err(ErrKind::from(Cli::command().error(ErrorKind::ValueValidation,
+                                               "`--requests` and `--duration` must be non-zero")))?;
+    }
+    let rate_limits = args.rate_limits
+                          .as_deref()
+                          .map(|path| RateLimits::new(&QuotaTable::from_file(path)?))
+                          .transpose()?;
+    let client = HttpClient::builder().base_url(args.url.clone())
+                                      .allow_http(args.url.scheme() == "http")
+                                      .timeout(args.timeout)
+                                      .maybe_rate_limits(rate_limits)
+                                      .maybe_circuit_breakers(args.circuit_breaker
+                                                                  .then(CircuitBreakers::default))
+                                      .build()?;
+    // burst of 1: evenly spaced sends, instead of a full second's worth up front
+    let rate_limiter = args.rps
+                           .map(|rps| Arc::new(RateLimiter::direct(Quota::per_second(rps).allow_burst(NonZeroU32::MIN))));
+    let start = Instant::now();
+    let budget = match args.duration {
+        Some(duration) => Budget::Until(start + duration),
+        None => Budget::Requests(args.requests
+                                     .unwrap_or(DEFAULT_REQUESTS)),
+    };
+    event!(L::INFO, ?budget, rps = ?args.rps, "load test starting");
+
+    let issued = Arc::new(AtomicU64::new(0));
+    let mut workers = JoinSet::new();
+    for _ in 0..args.concurrency.get() {
+        workers.spawn(worker(client.clone(),
+                             args.url.clone(),
+                             args.method.clone(),
+                             budget,
+                             issued.clone(),
+                             rate_limiter.clone()));
+    }
+    let mut tally = Tally::new();
+    while let Some(worker_tally) = workers.join_next().await {
+        tally.merge(worker_tally.map_err(ErrKind::into_dyn_error)?);
+    }
+    Ok(LoadReport::new(args, &tally, start.elapsed()))
+}
+
+/// Send requests until the shared budget runs out.
+async fn worker(client: HttpClient,
+                url: Url,
+                method: Method,
+                budget: Budget,
+                issued: Arc<AtomicU64>,
+                rate_limiter: Option<Arc<DefaultDirectRateLimiter>>)
+                -> Tally {
+    let mut tally = Tally::new();
+    loop {
+        match budget {
+            Budget::Requests(total) =>
+                if issued.fetch_add(1, Ordering::Relaxed) >= total {
+                    break;
+                },
+            Budget::Until(deadline) =>
+                if Instant::now() >= deadline {
+                    break;
+                },
+        }
+        if let Some(rate_limiter) = &rate_limiter {
+            rate_limiter.until_ready()
+                        .await;
+        }
+        let sent = Instant::now();
+        let request = client.client()
+                            .request(method.clone(), url.clone())
+                            .with_trace_context();
+        let result = match client.send(request).await {
+            Ok(response) => {
+                let status = response.status();
+                response.bytes()
+                        .await
+                        .map(|body| (status, body.len()))
+                        .map_err(SendError::from)
+            },
+            Err(error) => Err(error),
+        };
+        tally.record(sent.elapsed(), result);
+    }
+    tally
+}
+// ///////////////////////////////// [ tally ] ///////////////////////////////// //
+/// Raw results (per worker, then merged).
+#[derive(Debug)]
+struct Tally {
+    latency_micros: Histogram<u64>,
+    statuses:       BTreeMap<u16, u64>,
+    errors:         BTreeMap<&'static str, u64>,
+    bytes:          u64,
+}
+impl Tally {
+    fn new() -> Self {
+        Self { latency_micros: Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, HISTOGRAM_SIGFIGS).expect("static histogram bounds should be valid"),
+               statuses:       BTreeMap::new(),
+               errors:         BTreeMap::new(),
+               bytes:          0, }
+    }
+
+    /// Latency is recorded for every completed exchange, including errors.
+    fn record(&mut self,
+              latency: Duration,
+              result: Result<(reqwest::StatusCode, usize), SendError>) {
+        self.latency_micros
+            .saturating_record(latency.as_micros()
+                                      .try_into()
+                                      .unwrap_or(u64::MAX));
+        match result {
+            Ok((status, bytes)) => {
+                *self.statuses
+                     .entry(status.as_u16())
+                     .or_default() += 1;
+                self.bytes += bytes as u64;
+            },
+            Err(error) => {
+                event!(L::DEBUG, %error, "request failed");
+                *self.errors
+                     .entry(error_kind(&error))
+                     .or_default() += 1;
+            },
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.latency_micros
+            .add(&other.latency_micros)
+            .expect("histograms share bounds");
+        for (status, count) in other.statuses {
+            *self.statuses
+                 .entry(status)
+                 .or_default() += count;
+        }
+        for (kind, count) in other.errors {
+            *self.errors
+                 .entry(kind)
+                 .or_default() += count;
+        }
+        self.bytes += other.bytes;
+    }
+}
+
+fn error_kind(error: &SendError) -> &'static str {
+    let error = match error {
+        SendError::Reqwest(error) => error,
+        SendError::CircuitOpen(_) => return "circuit_open",
+        SendError::Fixture(_) => return "other",
+    };
+    if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connect"
+    } else if error.is_body() || error.is_decode() {
+        "body"
+    } else if error.is_request() {
+        "request"
+    } else {
+        "other"
+    }
+}
+// ///////////////////////////////// [ report ] ///////////////////////////////// //
+#[derive(Debug, Serialize)]
+struct LoadReport {
+    url:            String,
+    method:         String,
+    concurrency:    usize,
+    target_rps:     Option<u32>,
+    requests:       u64,
+    errors:         u64,
+    elapsed_secs:   f64,
+    throughput_rps: f64,
+    bytes:          u64,
+    latency_ms:     LatencySummary,
+    /// status code -> count
+    statuses:       BTreeMap<u16, u64>,
+    /// error kind -> count
+    error_kinds:    BTreeMap<&'static str, u64>,
+}
+#[derive(Debug, Serialize)]
+struct LatencySummary {
+    min:   f64,
+    mean:  f64,
+    stdev: f64,
+    p50:   f64,
+    p90:   f64,
+    p95:   f64,
+    p99:   f64,
+    p99_9: f64,
+    max:   f64,
+}
+impl LoadReport {
+    fn new(args: &LoadArgs, tally: &Tally, elapsed: Duration) -> Self {
+        let histogram = &tally.latency_micros;
+        let ms = |micros: f64| micros / 1_000.0;
+        let quantile = |q: f64| ms(histogram.value_at_quantile(q) as f64);
+        let latency_ms = LatencySummary { min:   ms(histogram.min() as f64),
+                                          mean:  ms(histogram.mean()),
+                                          stdev: ms(histogram.stdev()),
+                                          p50:   quantile(0.50),
+                                          p90:   quantile(0.90),
+                                          p95:   quantile(0.95),
+                                          p99:   quantile(0.99),
+                                          p99_9: quantile(0.999),
+                                          max:   ms(histogram.max() as f64), };
+        let requests = histogram.len();
+        Self { url: args.url.to_string(),
+               method: args.method.to_string(),
+               concurrency: args.concurrency.get(),
+               target_rps: args.rps.map(NonZeroU32::get),
+               requests,
+               errors: tally.errors.values().sum(),
+               elapsed_secs: elapsed.as_secs_f64(),
+               throughput_rps: requests as f64
+                               / elapsed.as_secs_f64()
+                                        .max(f64::EPSILON),
+               bytes: tally.bytes,
+               latency_ms,
+               statuses: tally.statuses.clone(),
+               error_kinds: tally.errors.clone() }
+    }
+}
+impl std::fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {}", self.method, self.url)?;
+        writeln!(f,
+                 "  concurrency {:>6}    target rps {:>8}",
+                 self.concurrency,
+                 self.target_rps
+                     .map_or("-".to_string(), |rps| rps.to_string()))?;
+        writeln!(f,
+                 "  requests    {:>6}    errors     {:>8}    elapsed {:>8.2}s    throughput {:>8.1} req/s    received {} B",
+                 self.requests, self.errors, self.elapsed_secs, self.throughput_rps, self.bytes)?;
+        writeln!(f, "\n  latency (ms)")?;
+        let latency = &self.latency_ms;
+        for (label, value) in [("min", latency.min),
+                               ("mean", latency.mean),
+                               ("stdev", latency.stdev),
+                               ("p50", latency.p50),
+                               ("p90", latency.p90),
+                               ("p95", latency.p95),
+                               ("p99", latency.p99),
+                               ("p99.9", latency.p99_9),
+                               ("max", latency.max)]
+        {
+            writeln!(f, "    {label:<6} {value:>10.2}")?;
+        }
+        writeln!(f, "\n  status codes")?;
+        for (status, count) in &self.statuses {
+            writeln!(f, "    {status:<6} {count:>10}")?;
+        }
+        if !self.error_kinds.is_empty() {
+            writeln!(f, "\n  errors")?;
+            for (kind, count) in &self.error_kinds {
+                writeln!(f, "    {kind:<8} {count:>8}")?;
+            }
+        }
+        Ok(())
+    }
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use clap::Parser as _;
+    use utilities::CircuitOpen;
+
+    use super::*;
+    use crate::Command;
+
+    fn args(line: &[&str]) -> LoadArgs {
+        match Cli::parse_from(["sample-async", "load"].iter()
+                                                      .chain(line)).command
+        {
+            Some(Command::Load(args)) => args,
+            _ => unreachable!("parsed as `load`"),
+        }
+    }
+    fn circuit_open() -> SendError {
+        SendError::CircuitOpen(CircuitOpen { host:     "host.test".to_string(),
+                                             retry_in: Duration::from_secs(1), })
+    }
+
+    #[test]
+    fn test_tally_record_and_merge() {
+        let mut tally = Tally::new();
+        tally.record(Duration::from_millis(10), Ok((reqwest::StatusCode::OK, 5)));
+        tally.record(Duration::from_millis(20), Err(circuit_open()));
+        let mut other = Tally::new();
+        other.record(Duration::from_millis(30),
+                     Ok((reqwest::StatusCode::SERVICE_UNAVAILABLE, 1)));
+        other.record(Duration::from_millis(40), Ok((reqwest::StatusCode::OK, 2)));
+        tally.merge(other);
+        assert_eq!(tally.statuses, BTreeMap::from([(200, 2), (503, 1)]));
+        assert_eq!(tally.errors, BTreeMap::from([("circuit_open", 1)]));
+        assert_eq!(tally.bytes, 8);
+        // errors have a latency too
+        assert_eq!(tally.latency_micros.len(), 4);
+        assert!(tally.latency_micros
+                     .equivalent(tally.latency_micros.max(), 40_000));
+    }
+
+    #[tokio::test]
+    async fn test_error_kinds() {
+        let server = stand_in::StandInServer::start_local().await
+                                                           .unwrap();
+        let client = HttpClient::builder().base_url(server.base_url()
+                                                          .parse()
+                                                          .unwrap())
+                                          .allow_http(true)
+                                          .timeout(Duration::from_millis(50))
+                                          .build()
+                                          .unwrap();
+        let timeout = client.send(client.get("delay/1").unwrap())
+                            .await
+                            .unwrap_err();
+        assert_eq!(error_kind(&timeout), "timeout");
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap()
+                                                               .local_addr()
+                                                               .unwrap();
+        let refused = client.send(client.client()
+                                        .get(format!("http://{closed}/")))
+                            .await
+                            .unwrap_err();
+        assert_eq!(error_kind(&refused), "connect");
+        assert_eq!(error_kind(&circuit_open()), "circuit_open");
+    }
+
+    #[tokio::test]
+    async fn test_request_budget_and_report() {
+        let server = stand_in::StandInServer::start_local().await
+                                                           .unwrap();
+        let url = format!("{}/get", server.base_url());
+        let report = load(&args(&[&url, "-n", "7", "-c", "3"])).await
+                                                               .unwrap();
+        assert_eq!((report.requests, report.errors), (7, 0));
+        assert_eq!(report.statuses, BTreeMap::from([(200, 7)]));
+        assert!(report.bytes > 0);
+
+        let table = report.to_string();
+        assert!(table.starts_with(&format!("GET {url}\n")), "{table}");
+        assert!(table.contains("  requests         7    errors            0"),
+                "{table}");
+        assert!(table.contains("\n  status codes\n    200             7\n"),
+                "{table}");
+        assert!(!table.contains("errors\n"), "{table}");
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["requests"], 7);
+        assert_eq!(json["concurrency"], 3);
+        assert_eq!(json["statuses"]["200"], 7);
+        assert!(json["latency_ms"]["p99"].as_f64()
+                                         .is_some_and(|p99| p99 > 0.0),
+                "{json:#}");
+    }
+
+    #[tokio::test]
+    async fn test_duration_budget() {
+        let server = stand_in::StandInServer::start_local().await
+                                                           .unwrap();
+        let url = format!("{}/get", server.base_url());
+        let report = load(&args(&[&url, "-d", "300ms", "-c", "2", "--rps", "20"])).await
+                                                                                  .unwrap();
+        assert!(report.elapsed_secs >= 0.3 && report.elapsed_secs < 2.0,
+                "{}",
+                report.elapsed_secs);
+        // ~20 rps for 0.3s, evenly spaced
+        assert!((2..=10).contains(&report.requests), "{}", report.requests);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_and_circuit_breaker_apply() {
+        let server = stand_in::StandInServer::start_local().await
+                                                           .unwrap();
+        let table =
+            std::env::temp_dir().join(format!("sample-async-load-{}.toml", std::process::id()));
+        std::fs::write(&table,
+                       "[rate_limits]\ndefault = { per_second = 10, burst = 1 }\n").unwrap();
+        let url = format!("{}/get", server.base_url());
+        let report =
+            load(&args(&[&url, "-n", "4", "--rate-limits", table.to_str().unwrap()])).await
+                                                                                     .unwrap();
+        std::fs::remove_file(&table).unwrap();
+        // 1 right away, then 1 per 100ms
+        assert!(report.elapsed_secs >= 0.3, "{}", report.elapsed_secs);
+
+        // failing host: short-circuited once enough calls have failed
+        let url = format!("{}/status/503", server.base_url());
+        let report = load(&args(&[&url, "-n", "20", "-c", "1", "--circuit-breaker"])).await
+                                                                                     .unwrap();
+        assert!(report.error_kinds
+                      .get("circuit_open")
+                      .is_some_and(|&count| count > 0),
+                "{report:?}");
+    }
+}
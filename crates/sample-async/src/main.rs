@@ -15,29 +15,181 @@
 //!   - [htpbin](https://httpbin.org)
 //!   - [typicode: jsonplaceholder](https://jsonplaceholder.typicode.com)
 //!
+//!   - local stand-in for both (offline): `cargo run -p stand-in`, then `--base-url http://127.0.0.1:8080`
+//!
 //! ## Note
 //! **tokio** is not compatible with wasm target.
+//!
+//! ## Load tool
+//! `cargo run -p sample-async -- load <URL> [-n N | -d 30s] [-c 10] [--rps 50] [--output json]`
+//! (see [`load`])
+//!
+//! ## Download tool
+//! `cargo run -p sample-async -- download <URL> -o <FILE> [--sha256 <HEX>] [--restart]`
+//! (see [`download`])
+//!
+//! ## Sync tool
+//! `cargo run -p sample-async -- sync [--db data/no-sync/todos.sqlite] [--user-id N] [--completed BOOL]`
+//! (see [`sync`]; SQLite via sqlx, see [`store`])
 
+mod download;
 mod error;
-use crate::error::ErrWrapper;
-pub type SampleResult<T> = std::result::Result<T, ErrWrapper>;
+mod load;
+mod store;
+mod sync;
+use crate::error::{ErrKind, ErrWrapper};
+pub type SampleResult<T> = std::result::Result<T, Box<ErrWrapper>>;
 
-use std::time::Duration;
+use std::{num::NonZeroU32, path::PathBuf, process::ExitCode, time::Duration};
 
+use clap::{Parser, Subcommand};
 use reqwest::{Method, Url,
               header::{self, HeaderMap}};
-use serde::{Deserialize, Serialize};
-use tracing::{Level as L, event};
-use utilities::activate_global_default_tracing_subscriber;
+use tracing::{Instrument as _, Level as L, Span, event, info_span};
+use utilities::{CircuitBreakers, FixtureMode, Fixtures, HttpClient, INTERRUPTED_EXIT_CODE,
+                LoggingConfigSource, OtlpConfig, QuotaTable, RateLimits, ReportFormat,
+                RetryExt as _, RetryPolicy, Shutdown, SubscriberGuard, TraceContextExt as _,
+                activate_global_default_tracing_subscriber,
+                jsonplaceholder::{JsonPlaceholder, NewTodo, PostFilter, TodoFilter, TodoPatch}};
+
+const URL_HTTPBIN: &str = "https://httpbin.org";
+const URL_TYPICODE: &str = JsonPlaceholder::BASE_URL;
+/// Typed-client pagination demo: `_limit` per request
+const PAGE_LIMIT: NonZeroU32 = NonZeroU32::new(4).unwrap();
+/// Used unless `--rate-limits` names a file.  (Route rules apply to a `--base-url` stand-in as well.)
+const RATE_LIMITS_TOML: &str = r#"
+[rate_limits]
+default = { per_second = 20, burst = 10 }
+
+[[rate_limits.rules]]
+name =  "todos"
+route = "/todos"
+quota = { per_second = 4, burst = 2 }
+
+[[rate_limits.rules]]
+name =  "httpbin"
+host =  "httpbin.org"
+quota = { per_second = 12, burst = 6 }
+"#;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Send both httpbin & jsonplaceholder requests here (e.g. a local stand-in: `cargo run -p stand-in`)
+    #[arg(long)]
+    base_url:     Option<Url>,
+    /// TOML file with a `[rate_limits]` quota table (see `utilities::rate_limit`)
+    #[arg(long)]
+    rate_limits:  Option<PathBuf>,
+    /// Record every exchange sent via `HttpClient` to this fixture file (e.g. `data/fixtures/sample-async.json`)
+    #[arg(long, conflicts_with = "replay")]
+    record:       Option<PathBuf>,
+    /// Replay exchanges from this fixture file instead of using the network (with the `--base-url` recorded with)
+    #[arg(long)]
+    replay:       Option<PathBuf>,
+    /// How a failure is reported on stderr: `text` (coloured on a terminal) or `json`
+    #[arg(long, global = true, default_value_t = ReportFormat::Text)]
+    error_format: ReportFormat,
+    /// Without a subcommand: run the request demos below
+    #[command(subcommand)]
+    command:      Option<Command>,
+}
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Load-test a URL: latency percentiles (HDR histogram), throughput & status codes
+    Load(load::LoadArgs),
+    /// Stream a URL to a file: progress bar, resume of partial downloads, SHA-256 check
+    Download(download::DownloadArgs),
+    /// Fetch all jsonplaceholder todos into a local SQLite database & query it
+    Sync(sync::SyncArgs),
+}
 
 // #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> SampleResult<()> {
-    let _writer_guard =
-        activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
-                                                    .maybe_error_logging_level(None)
-                                                    .call()?;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    // before the root span is created: a span opened without the OTLP layer is never exported
+    let _writer_guard = match activate_tracing(OtlpConfig::from_env(env!("CARGO_PKG_NAME"))) {
+        Ok(guard) => guard,
+        Err(error) => return error.exit(error_format),
+    };
+    let shutdown = Shutdown::new().with_ctrl_c();
+    let root_span = root_span(&cli);
+    match run(cli, &shutdown).instrument(root_span)
+                             .await
+    {
+        Ok(Some(())) => ExitCode::SUCCESS,
+        Ok(None) => {
+            eprintln!("interrupted");
+            ExitCode::from(INTERRUPTED_EXIT_CODE)
+        },
+        Err(error) => match error.kind() {
+            // usage errors: clap's own message & exit code (as for `Cli::parse`)
+            ErrKind::Clap { source } => source.exit(),
+            // exit code per `ErrKind` variant (see `utilities::diagnostic`)
+            _ => error.exit(error_format),
+        },
+    }
+}
+
+fn activate_tracing(otlp: Option<OtlpConfig>) -> SampleResult<SubscriberGuard> {
+    Ok(activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
+                                                   .maybe_error_logging_level(None)
+                                                   .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
+                                                   .maybe_otlp(otlp)
+                                                   .call()?)
+}
+
+/// Span for the whole run: exported via OTLP (`--features otlp`) & propagated as `traceparent` on requests.
+fn root_span(cli: &Cli) -> Span {
+    let command = match &cli.command {
+        Some(Command::Load(_)) => "load",
+        Some(Command::Download(_)) => "download",
+        Some(Command::Sync(_)) => "sync",
+        None => "demos",
+    };
+    info_span!("sample-async", command)
+}
+
+/// `Ok(None)` if interrupted.  Ctrl-C drops the command or demos: in-flight requests are cancelled, an open `sync`
+/// transaction rolls back; fixtures being recorded are still saved.
+async fn run(mut cli: Cli, shutdown: &Shutdown) -> SampleResult<Option<()>> {
+    match cli.command.take() {
+        Some(Command::Load(args)) =>
+            return shutdown.run_until_triggered(load::run(args))
+                           .await
+                           .transpose(),
+        Some(Command::Download(args)) =>
+            return shutdown.run_until_triggered(download::run(args))
+                           .await
+                           .transpose(),
+        Some(Command::Sync(args)) =>
+            return shutdown.run_until_triggered(sync::run(args, cli.base_url))
+                           .await
+                           .transpose(),
+        None => {},
+    }
+
+    // # Fixtures
+    // - `--record`: exchanges sent via `HttpClient` (not the raw `client` in the demos) are saved at the end of the
+    //   run, interrupted or not
+    // - `--replay`: same exchanges, no network (recorded latencies included)
+    let fixtures = match (&cli.record, &cli.replay) {
+        (Some(path), _) => Some(Fixtures::record(path)),
+        (None, Some(path)) => Some(Fixtures::replay().path(path)
+                                                     .call()?),
+        (None, None) => None,
+    };
+    let demos = shutdown.run_until_triggered(demos(&cli, fixtures.clone()))
+                        .await;
+    if let Some(fixtures) = fixtures.filter(|fixtures| fixtures.mode() == FixtureMode::Record) {
+        fixtures.save()?;
+    }
+    demos.transpose()
+}
 
+async fn demos(cli: &Cli, fixtures: Option<Fixtures>) -> SampleResult<()> {
     // # `Url`
     // - (sealed) trait `IntoUrl`
     //   - impl for `String` and `&str`
@@ -46,21 +198,16 @@ async fn main() -> SampleResult<()> {
     // - Url is foot-gun heavy
     //   - e.g. whether a path ends in `/` can result in the whole end being stripped
     //   - sequential joins fail in experiments
+    //   - (`utilities::HttpClient::url(_)` always *appends* to the base path instead)
     //
-    const URL_HTTPBIN: &str = "https://httpbin.org";
-    const URL_TYPICODE: &str = "https://jsonplaceholder.typicode.com";
-    let base_typicode = Url::parse(URL_TYPICODE)?;
-    let todos_typicode = base_typicode.join("/todos")?;
-    let base_httpbin = Url::parse(URL_HTTPBIN)?;
-    let delay_httpbin = base_httpbin.join("/delay/")?;
-    let json_httpbin = base_httpbin.join("/json")?;
-    event!(L::DEBUG,
-           ?base_httpbin,
-           ?base_typicode,
-           ?todos_typicode,
-           ?delay_httpbin,
-           ?json_httpbin);
-    event!(L::INFO, a_url=?json_httpbin.as_str());
+    let base_typicode = match &cli.base_url {
+        Some(base_url) => base_url.clone(),
+        None => Url::parse(URL_TYPICODE)?,
+    };
+    let base_httpbin = match &cli.base_url {
+        Some(base_url) => base_url.clone(),
+        None => Url::parse(URL_HTTPBIN)?,
+    };
 
     // # Header-module
     // - `HeaderMap`
@@ -71,45 +218,75 @@ async fn main() -> SampleResult<()> {
     //   - map insertion can panic >32e+3 values
     // - various constant header names available, but don't seem necessary
     // - client can be builg with default_headers for general use
+    //   - (`HttpClient` already sends JSON `accept`/`content-type` & a user agent; these are *extra*)
     let default_headers = {
         let mut headers = HeaderMap::new();
-
-        headers.insert(header::ACCEPT,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::CONTENT_TYPE,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::USER_AGENT,
-                       "rust-reqwest-client".parse()
-                                            .unwrap());
-        // headers.insert(header::AUTHORIZATION, _);
+        headers.insert(header::ACCEPT_LANGUAGE, "en".parse().unwrap());
         // headers.insert(header::COOKIE, _);
         // headers.insert(header::DATE, _);
         headers
     };
     event!(L::DEBUG, ?default_headers);
 
+    // # Rate limits
+    // - keyed buckets (per rule & host) from a quota table; shared by every `HttpClient` derived from `httpbin` below
+    // - only `HttpClient::send`/`execute` wait on them (not the raw `reqwest::Client`)
+    let quota_table = match &cli.rate_limits {
+        Some(path) => QuotaTable::from_file(path)?,
+        None => QuotaTable::from_toml_str(RATE_LIMITS_TOML).map_err(ErrKind::into_dyn_error)?,
+    };
+    event!(L::DEBUG, ?quota_table);
+
     // # `Client`
     // - prefer `::builder()`
     //   - alt: `::new()` is effectively `::default()`
     //   - see ClientBuilder
     // - `Arc` used internally
     // - holds an internal connection pool
-    let client = reqwest::Client::builder().https_only(true) // this will error for `http` (WARN: not compile-time checked)
-                                           .use_rustls_tls()
-                                           .default_headers(default_headers)
-                                           .timeout(Duration::from_secs(30)) // default is *no* timeout
-                                           .build()?;
+    // - `utilities::HttpClient`: https-only (unless `allow_http`), rustls, 30s timeout (reqwest default is *no* timeout)
+    //   - auth via `HttpAuth::Bearer(HiddenValue<_>)` (no auth needed here)
+    //   - circuit breakers: once a host is failing, `HttpClient::send*` fail fast instead of waiting out timeouts
+    let httpbin = HttpClient::builder().base_url(base_httpbin)
+                                       .default_headers(default_headers)
+                                       .connect_timeout(Duration::from_secs(10))
+                                       // a `--base-url` stand-in is typically plain `http`
+                                       .allow_http(cli.base_url.is_some())
+                                       .rate_limits(RateLimits::new(&quota_table)?)
+                                       .circuit_breakers(CircuitBreakers::default())
+                                       .maybe_fixtures(fixtures)
+                                       .build()?;
+    // same connection pool, different base
+    let typicode = httpbin.with_base_url(base_typicode)?;
+    let todos_typicode = typicode.url("todos")?;
+    let delay_httpbin = httpbin.url("delay/")?;
+    let json_httpbin = httpbin.url("json")?;
+    event!(L::DEBUG,
+           base_httpbin = %httpbin.base_url(),
+           base_typicode = %typicode.base_url(),
+           %todos_typicode,
+           %delay_httpbin,
+           %json_httpbin);
+    event!(L::INFO, a_url=?json_httpbin.as_str());
+    let client = httpbin.client().clone();
     event!(L::DEBUG, ?client);
 
+    // # Retries
+    // - connect errors & 429/502/503/504 are retried; exponential backoff with full jitter
+    // - `Retry-After` honored; whole exchange bounded by `max_elapsed`
+    // - each retry is logged (INFO) within the request's span
+    let retry_policy = RetryPolicy::builder().max_retries(3)
+                                             .max_elapsed(Duration::from_secs(20))
+                                             .build();
+    event!(L::DEBUG, ?retry_policy);
+
     // # `Request`
     // see RequestBuilder
-    let request = client.request(Method::GET, base_httpbin.join("/headers")?)
+    let request = client.request(Method::GET, httpbin.url("headers")?)
                         .header("Authorization", "prettyplease")
                         .header("Fanciful", "ladeeda")
                         .query(&[("query_key", "query_value")])
                         .body("the exact body that is sent")
+                        .with_trace_context()
                         .build()?;
     event!(L::DEBUG, ?request);
 
@@ -120,20 +297,22 @@ async fn main() -> SampleResult<()> {
     // - url
     // - text
     // - json
-    let response = client.execute(request)
-                         .await?;
+    let response = retry_policy.execute(&client, request)
+                               .await?;
     event!(L::DEBUG, ?response);
     event!(L::INFO, resp_status=?response.status());
 
     // # JSON, typed
-    // (see struct `Todo` below)
+    // - `utilities::jsonplaceholder`: a typed resource per collection (`Todo`, `Post`, ..), filters as structs
+    // - sent via `HttpClient` (rate limits, circuit breakers & fixtures apply), with `retry_policy` (idempotent methods only: `create` & `patch` are sent once)
+    let jsonplaceholder = JsonPlaceholder::builder().http(typicode.clone())
+                                                    .retry(retry_policy.clone())
+                                                    .build();
     {
-        let response = client.request(Method::GET, base_typicode.join("/todos")?)
-                             .query(&[("userId", "1"), ("completed", "false")])
-                             .send()
-                             .await?;
-
-        let todos_type_matched: Vec<Todo> = response.json().await?;
+        let todos_type_matched = jsonplaceholder.todos()
+                                                .list(&TodoFilter { user_id:   Some(1),
+                                                                    completed: Some(false), })
+                                                .await?;
         event!(L::INFO, "Retrieved {} todos", todos_type_matched.len());
         event!(L::DEBUG, two_todos = ?todos_type_matched.get(0..=1));
         event!(L::DEBUG, ?todos_type_matched);
@@ -142,10 +321,12 @@ async fn main() -> SampleResult<()> {
     }
     // # JSON, ad hoc
     {
-        let response = client.request(Method::GET, base_typicode.join("/todos")?)
-                             .query(&[("userId", "1"), ("completed", "false")])
-                             .send()
-                             .await?;
+        let response = httpbin.send_with_retry(client.request(Method::GET, todos_typicode.clone())
+                                                     .query(&[("userId", "1"),
+                                                              ("completed", "false")])
+                                                     .with_trace_context(),
+                                               &retry_policy)
+                              .await?;
 
         let todos_ad_hoc: serde_json::Value = response.json().await?;
         event!(L::DEBUG, ?todos_ad_hoc);
@@ -153,20 +334,51 @@ async fn main() -> SampleResult<()> {
                  todos_ad_hoc.get(0));
     }
 
+    // # Typed CRUD & pagination
+    // - jsonplaceholder fakes writes: the created/changed todo is echoed back, but not stored
+    // - `stream(..)`: items across `_page`s, fetched lazily as the stream is polled
+    {
+        use futures::TryStreamExt as _;
+
+        let todos = jsonplaceholder.todos();
+        let first = todos.get(1).await?;
+        let created = todos.create(&NewTodo { user_id:   first.user_id,
+                                              title:     "try the typed client".into(),
+                                              completed: false, })
+                           .await?;
+        let patched = todos.patch(first.id,
+                                  &TodoPatch { completed: Some(!first.completed),
+                                               ..Default::default() })
+                           .await?;
+        todos.delete(first.id).await?;
+        event!(L::INFO, ?first, ?created, ?patched, "typed CRUD");
+
+        let user_1_posts: Vec<_> =
+            jsonplaceholder.posts()
+                           .stream(PostFilter { user_id: Some(1) }, PAGE_LIMIT)
+                           .try_collect()
+                           .await?;
+        println!("Typed client: created todo {}, patched todo {} (completed: {}), {} posts by user 1 (pages of {PAGE_LIMIT})",
+                 created.id,
+                 patched.id,
+                 patched.completed,
+                 user_1_posts.len());
+    }
+
     // # Parallel requests with `Futures`
     {
         use futures::future;
 
-        let urls = vec![base_httpbin.join("/delay/3")?,
-                        base_httpbin.join("/delay/2")?,
-                        base_httpbin.join("/delay/3")?,
-                        base_httpbin.join("/delay/2")?,
-                        base_httpbin.join("/delay/3")?,
-                        base_httpbin.join("/delay/2")?,
-                        base_httpbin.join("/delay/3")?,
-                        base_httpbin.join("/delay/2")?,
-                        base_httpbin.join("/delay/3")?,
-                        base_httpbin.join("/delay/2")?,];
+        let urls = vec![httpbin.url("delay/3")?,
+                        httpbin.url("delay/2")?,
+                        httpbin.url("delay/3")?,
+                        httpbin.url("delay/2")?,
+                        httpbin.url("delay/3")?,
+                        httpbin.url("delay/2")?,
+                        httpbin.url("delay/3")?,
+                        httpbin.url("delay/2")?,
+                        httpbin.url("delay/3")?,
+                        httpbin.url("delay/2")?,];
 
         // ## `futures::join_all`
         // - single future gives a single return of a vec (e.g. of results)
@@ -178,7 +390,8 @@ async fn main() -> SampleResult<()> {
             let futures: Vec<_> = urls.iter()
                                       .map(|url| {
                                           client.request(Method::GET, url.clone())
-                                                .send()
+                                                .with_trace_context()
+                                                .send_with_retry(&retry_policy)
                                       })
                                       .collect();
 
@@ -195,15 +408,20 @@ async fn main() -> SampleResult<()> {
         }
         // ## `futures::stream`
         // Parallel requests with option for max concurrent requests
+        // (sent via `HttpClient`: short-circuited if httpbin is down)
         {
             use futures::stream::{self, StreamExt};
 
             const BUFFER_SIZE: usize = 2;
             let start_time = std::time::Instant::now();
             let mut count = 0;
-            let mut buffered_stream = stream::iter(urls).map(|url| client.get(url).send())
-                                                        // .buffered(2) // yields responses only in the order futures were arranged
-                                                        .buffer_unordered(BUFFER_SIZE); // Only 2 requests in flight at once
+            let mut buffered_stream = stream::iter(urls.clone()).map(|url| {
+                                          httpbin.send_with_retry(client.get(url)
+                                                                        .with_trace_context(),
+                                                                  &retry_policy)
+                                      })
+                                      // .buffered(2) // yields responses only in the order futures were arranged
+                                      .buffer_unordered(BUFFER_SIZE); // Only 2 requests in flight at once
 
             while let Some(result) = buffered_stream.next().await {
                 println!("{}: {:.2}",
@@ -220,6 +438,78 @@ async fn main() -> SampleResult<()> {
                      count,
                      time_passed.as_secs_f64());
         }
+        // ## `futures::join_all`, hedged
+        // - as `join_all` above, but a request still running after the hedge delay gets a duplicate; first success wins
+        // - all start together, before any latency is observed: each waits `initial_delay` (2.5s), so the 3s URLs are
+        //   hedged (a long-lived policy switches to the running p50 once `min_samples` responses are in)
+        // - the loser is dropped (cancelled); at most half the requests may be duplicated
+        // - httpbin's delays are fixed per URL, so the duplicate is no faster here: compare against `join_all` above
+        //   (hedging cuts *random* tails: a slow server instance, a lost packet, a GC pause)
+        {
+            use futures::future;
+            use utilities::HedgePolicy;
+
+            let hedge_policy = HedgePolicy::builder().percentile(0.5)
+                                                     .initial_delay(Duration::from_millis(2_500))
+                                                     .min_samples(4)
+                                                     .max_extra_ratio(0.5)
+                                                     .build();
+            let futures: Vec<_> = urls.iter()
+                                      .map(|url| {
+                                          httpbin.send_hedged(client.get(url.clone())
+                                                                    .with_trace_context(),
+                                                              &hedge_policy)
+                                      })
+                                      .collect();
+            let start_time = std::time::Instant::now();
+            let results = future::join_all(futures).await;
+            let time_passed = start_time.elapsed();
+            let stats = hedge_policy.stats();
+            event!(L::INFO,
+                   ?stats,
+                   "All hedged requests completed in {:?}",
+                   time_passed);
+            println!("`Join_All` hedged: {} results returned in a total of {} seconds; {} hedges sent, {} won, {} over budget.",
+                     results.len(),
+                     time_passed.as_secs_f64(),
+                     stats.hedges,
+                     stats.hedge_wins,
+                     stats.budget_denied);
+        }
+        // ## `utilities::AdaptiveStreamExt::buffer_adaptive`
+        // As above, but the in-flight limit adapts: +1 per window of healthy responses, halved on timeouts/429s/latency spikes
+        {
+            use futures::stream::{self, StreamExt};
+            use utilities::{AdaptiveLimiter, AdaptiveStreamExt as _, LimiterOutcome};
+
+            let limiter = AdaptiveLimiter::builder().initial_limit(2)
+                                                    .max_limit(8)
+                                                    .build();
+            let start_time = std::time::Instant::now();
+            let mut count = 0;
+            let requests = stream::iter(urls).map(|url| {
+                                                 let request = client.get(url)
+                                                                     .with_trace_context();
+                                                 httpbin.send_with_retry(request, &retry_policy)
+                                             });
+            let mut adaptive_stream =
+                requests.buffer_adaptive(limiter.clone(), LimiterOutcome::from_send_result);
+
+            while let Some(result) = adaptive_stream.next().await {
+                println!("{}: {:.2} (limit: {})",
+                         count,
+                         start_time.elapsed()
+                                   .as_secs_f64(),
+                         limiter.limit());
+                count += 1;
+                event!(L::DEBUG, ?result);
+            }
+            println!("`Stream.buffer_adaptive(..)`: {} results streamed back, in a total of {} seconds; final limit {}.",
+                     count,
+                     start_time.elapsed()
+                               .as_secs_f64(),
+                     limiter.limit());
+        }
     }
 
     // # `Governor`
@@ -246,7 +536,7 @@ async fn main() -> SampleResult<()> {
         // wrap in an `Arc` to share with various futures/threads
         let arc_rate_limiter = Arc::new(rate_limiter);
 
-        let single_url = [base_httpbin.join("/json")?];
+        let single_url = [json_httpbin.clone()];
 
         let start_time = std::time::Instant::now();
         let mut regulated_request_stream = stream::iter(single_url.into_iter()
@@ -254,10 +544,14 @@ async fn main() -> SampleResult<()> {
                                                                   .take(27)).map(|url| {
                                                let client = client.clone();
                                                let arc_rate_limiter = arc_rate_limiter.clone();
+                                               let retry_policy = &retry_policy;
                                                async move {
                                                    arc_rate_limiter.until_ready()
                                                                    .await;
-                                                   client.get(url).send().await
+                                                   client.get(url)
+                                                         .with_trace_context()
+                                                         .send_with_retry(retry_policy)
+                                                         .await
                                                }
                                            })
                                            .buffer_unordered(BURST_LIMIT.get() as usize);
@@ -279,44 +573,156 @@ async fn main() -> SampleResult<()> {
         }
     }
 
-    Ok(())
-}
-
-/// Struct to pull typicode responses into
-/// Example of using 'typed' JSON with Serde
-#[derive(Debug, Serialize, Deserialize)]
-struct Todo {
-    #[serde(rename = "userId")]
-    user_id:   i32,
-    id:        i32,
-    title:     String,
-    completed: bool,
-}
+    // # Keyed rate limits (`utilities::RateLimits`)
+    // - one process, different upstreams, different quotas: the `todos` route & httpbin draw from separate buckets
+    // - each retry attempt also waits on its bucket (`HttpClient::send_with_retry`)
+    {
+        use futures::stream::{self, StreamExt};
 
-/// Example async function with retries
-async fn _fetch_with_retry(client: &reqwest::Client,
-                           url: &str,
-                           max_retries: u32)
-                           -> SampleResult<reqwest::Response> {
-    let mut retries = 0;
-    loop {
-        match client.get(url).send().await {
-            Ok(response) => return Ok(response),
-            Err(e) if retries < max_retries => {
-                event!(L::WARN, "Request failed, retrying: {}", e);
-                retries += 1;
-                tokio::time::sleep(Duration::from_millis(2u64.pow(retries))).await;
-            },
-            Err(e) => {
-                event!(L::ERROR,
-                       "Request tried {} times without success. Last error returned: {}",
-                       retries,
-                       e);
-                return Err(e.into());
-            },
+        let start_time = std::time::Instant::now();
+        let requests = (0..12).map(|i| match i % 2 {
+                                  0 => client.get(todos_typicode.clone()),
+                                  _ => client.get(json_httpbin.clone()),
+                              });
+        let results: Vec<_> = stream::iter(requests).map(|request| {
+                                  httpbin.send_with_retry(request.with_trace_context(),
+                                                          &retry_policy)
+                              })
+                              .buffer_unordered(12)
+                              .collect()
+                              .await;
+        event!(L::DEBUG, ?results);
+        println!("Keyed rate limits: {} requests in {:.2} seconds",
+                 results.len(),
+                 start_time.elapsed()
+                           .as_secs_f64());
+        for (bucket, stats) in httpbin.rate_limits()
+                                      .map(RateLimits::stats)
+                                      .unwrap_or_default()
+        {
+            println!("  {bucket}: {} requests, {} delayed, mean wait {:?}, max wait {:?}",
+                     stats.requests,
+                     stats.delayed,
+                     stats.mean_wait(),
+                     stats.max_wait);
         }
+        event!(L::INFO, circuits = ?httpbin.circuit_breakers().map(CircuitBreakers::states));
     }
+    Ok(())
 }
 
 // #[cfg(target_arch = "wasm32")]
 // fn main() {}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+/// Replayed from `data/fixtures/sample-async.json`, recorded against a local stand-in (re-record: `cargo run -p stand-in`,
+/// then `--base-url http://127.0.0.1:8080 --record data/fixtures/sample-async.json`).
+#[cfg(test)]
+mod tests {
+    use futures::stream::{self, StreamExt as _};
+    use utilities::{FixtureMode, Fixtures, HttpClient, RetryPolicy, jsonplaceholder::Todo};
+
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                  "/../../data/fixtures/sample-async.json");
+    /// The `--base-url` recorded with: fixtures match on the origin.
+    const RECORDED_BASE: &str = "http://127.0.0.1:8080";
+    fn replay_client(latency_scale: f64) -> HttpClient {
+        let fixtures = Fixtures::replay().path(FIXTURE)
+                                         .latency_scale(latency_scale)
+                                         .call()
+                                         .unwrap();
+        assert_eq!(fixtures.mode(), FixtureMode::Replay);
+        HttpClient::builder().base_url(RECORDED_BASE.parse().unwrap())
+                             .allow_http(true)
+                             .fixtures(fixtures)
+                             .build()
+                             .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_todos_parse_from_fixture() {
+        let typicode = replay_client(0.0);
+        let request = typicode.get("todos")
+                              .unwrap()
+                              .query(&[("userId", "1"), ("completed", "false")]);
+        let todos: Vec<Todo> = typicode.send_with_retry(request, &RetryPolicy::default())
+                                       .await
+                                       .unwrap()
+                                       .json()
+                                       .await
+                                       .unwrap();
+        assert!(!todos.is_empty());
+        assert!(todos.iter()
+                     .all(|todo| todo.user_id == 1 && !todo.completed && !todo.title.is_empty()),
+                "{todos:#?}");
+    }
+
+    /// The `buffer_unordered` demo: 2 in flight over recorded 2s & 3s delays, in (paused) virtual time.
+    #[tokio::test(start_paused = true)]
+    async fn test_buffer_unordered_replays_recorded_latency() {
+        let httpbin = replay_client(1.0);
+        let policy = RetryPolicy::default();
+        let urls: Vec<_> = (0..10).map(|i| {
+                                      httpbin.url(&format!("delay/{}", 2 + i % 2))
+                                             .unwrap()
+                                  })
+                                  .collect();
+        let start = tokio::time::Instant::now();
+        let statuses: Vec<_> =
+            stream::iter(urls).map(|url| {
+                                  httpbin.send_with_retry(httpbin.client().get(url), &policy)
+                              })
+                              .buffer_unordered(2)
+                              .map(|result| result.unwrap().status())
+                              .collect()
+                              .await;
+        assert_eq!(statuses.len(), 10);
+        assert!(statuses.iter()
+                        .all(|status| status.is_success()));
+        // 5 × 2s + 5 × 3s, two at a time
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(12) && elapsed < Duration::from_secs(15),
+                "{elapsed:?}");
+    }
+}
+/// The root span is created after the OTLP layer is installed, so requests within it carry a `traceparent`.
+#[cfg(all(test, feature = "otlp"))]
+mod otlp_tests {
+    use utilities::HttpClient;
+
+    use super::*;
+
+    // multi-threaded: dropping the guard flushes (blocking) to the stand-in, which must keep serving meanwhile
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_traceparent_reaches_stand_in() {
+        let server = stand_in::StandInServer::start_local().await
+                                                           .unwrap();
+        // also the collector: export errors are only logged
+        let _guard = activate_tracing(Some(OtlpConfig::new(server.base_url(),
+                                                           "sample-async-test"))).unwrap();
+        let cli = Cli::parse_from(["sample-async", "--base-url", &server.base_url()]);
+        let httpbin = HttpClient::builder().base_url(server.base_url()
+                                                           .parse()
+                                                           .unwrap())
+                                           .allow_http(true)
+                                           .build()
+                                           .unwrap();
+        let echo: serde_json::Value = async {
+                                          httpbin.send(httpbin.get("headers")
+                                                              .unwrap()
+                                                              .with_trace_context())
+                                                 .await
+                                                 .unwrap()
+                                                 .json()
+                                                 .await
+                                                 .unwrap()
+                                      }.instrument(root_span(&cli))
+                                       .await;
+        let traceparent =
+            echo["headers"]["traceparent"].as_str()
+                                          .unwrap_or_else(|| panic!("no traceparent in {echo:#}"));
+        assert_eq!(traceparent.split('-').count(), 4, "{traceparent}");
+    }
+}
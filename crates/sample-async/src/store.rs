@@ -0,0 +1,178 @@
+//! SQLite store for `Todo`s fetched from jsonplaceholder.
+//!
+//! - schema: `migrations/` (embedded via `sqlx::migrate!()`, applied on open); queries: `queries/`
+//! - queries are checked at compile time against `.sqlx/` (offline metadata, committed)
+//!   - so no live database is needed to build
+//!   - after changing a query or migration, regenerate it:
+//!     `cargo sqlx prepare` (sqlx-cli), or build with `DATABASE_URL=sqlite:<migrated db>` & `SQLX_OFFLINE_DIR=.sqlx`
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::path::Path;
+
+use sqlx::{SqlitePool,
+           sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions}};
+use tracing::{Level as L, event, instrument};
+use utilities::jsonplaceholder::{Todo, TodoFilter};
+// ///////////////////////////////// [ store ] ///////////////////////////////// //
+/// Connection pool over a migrated database.  Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct TodoStore {
+    pool: SqlitePool,
+}
+impl TodoStore {
+    /// Open (creating file & parent directories if missing) and migrate.
+    #[instrument]
+    pub async fn open(path: &Path) -> Result<Self, sqlx::Error> {
+        if let Some(parent) = path.parent()
+           && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let options = SqliteConnectOptions::new().filename(path)
+                                                 .create_if_missing(true)
+                                                 .journal_mode(SqliteJournalMode::Wal);
+        Self::connect(SqlitePoolOptions::new(), options).await
+    }
+
+    async fn connect(pool: SqlitePoolOptions,
+                     options: SqliteConnectOptions)
+                     -> Result<Self, sqlx::Error> {
+        let pool = pool.connect_with(options)
+                       .await?;
+        sqlx::migrate!().run(&pool)
+                        .await?;
+        Ok(Self { pool })
+    }
+
+    /// Insert new todos & update changed ones, in one transaction.
+    /// Returns the number of rows inserted or changed (unchanged rows are left untouched).
+    #[instrument(skip_all, fields(todos = todos.len()))]
+    pub async fn upsert(&self, todos: &[Todo]) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut changed = 0;
+        for todo in todos {
+            changed += sqlx::query_file!("queries/upsert_todo.sql",
+                                         todo.id,
+                                         todo.user_id,
+                                         todo.title,
+                                         todo.completed).execute(&mut *tx)
+                                                        .await?
+                                                        .rows_affected();
+        }
+        tx.commit().await?;
+        event!(L::DEBUG, changed, "upserted todos");
+        Ok(changed)
+    }
+
+    /// Todos matching `filter` (`None` fields match anything), by id.
+    #[instrument(skip(self))]
+    pub async fn query(&self, filter: &TodoFilter) -> Result<Vec<Todo>, sqlx::Error> {
+        sqlx::query_file_as!(Todo,
+                             "queries/select_todos.sql",
+                             filter.user_id,
+                             filter.completed).fetch_all(&self.pool)
+                                              .await
+    }
+
+    /// Number of stored todos.
+    pub async fn count(&self) -> Result<u64, sqlx::Error> {
+        let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count: u64" FROM todos"#).fetch_one(&self.pool)
+                                                                                      .await?;
+        Ok(count)
+    }
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    impl TodoStore {
+        /// Private in-memory database (a single connection: each `:memory:` connection is its own database).
+        async fn in_memory() -> Result<Self, sqlx::Error> {
+            Self::connect(SqlitePoolOptions::new().max_connections(1),
+                          SqliteConnectOptions::from_str("sqlite::memory:")?).await
+        }
+    }
+
+    fn todo(id: u32, user_id: u32, completed: bool) -> Todo {
+        Todo { user_id,
+               id,
+               title: format!("todo {id}"),
+               completed }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_inserts_updates_and_skips_unchanged() {
+        let store = TodoStore::in_memory().await
+                                          .unwrap();
+        let mut todos = vec![todo(1, 1, false), todo(2, 1, true), todo(3, 2, false)];
+        assert_eq!(store.upsert(&todos)
+                        .await
+                        .unwrap(),
+                   3);
+        assert_eq!(store.upsert(&todos)
+                        .await
+                        .unwrap(),
+                   0,
+                   "unchanged rows are not rewritten");
+
+        todos[1].title = "renamed".into();
+        todos.push(todo(4, 2, true));
+        assert_eq!(store.upsert(&todos)
+                        .await
+                        .unwrap(),
+                   2);
+        assert_eq!(store.count().await.unwrap(), 4);
+        assert_eq!(store.query(&TodoFilter::default())
+                        .await
+                        .unwrap(),
+                   todos);
+    }
+
+    #[tokio::test]
+    async fn test_query_by_user_and_completed() {
+        let store = TodoStore::in_memory().await
+                                          .unwrap();
+        let todos = [todo(1, 1, false),
+                     todo(2, 1, true),
+                     todo(3, 2, false),
+                     todo(4, 2, true)];
+        store.upsert(&todos)
+             .await
+             .unwrap();
+
+        let ids = async |user_id, completed| {
+            store.query(&TodoFilter { user_id, completed })
+                 .await
+                 .unwrap()
+                 .iter()
+                 .map(|todo| todo.id)
+                 .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(Some(1), None).await, [1, 2]);
+        assert_eq!(ids(None, Some(false)).await, [1, 3]);
+        assert_eq!(ids(Some(2), Some(true)).await, [4]);
+        assert_eq!(ids(Some(3), None).await, [] as [u32; 0]);
+    }
+
+    #[tokio::test]
+    async fn test_open_creates_file_and_reopens() {
+        let dir = std::env::temp_dir().join(format!("sample-async-store-{}", std::process::id()));
+        let path = dir.join("nested/todos.sqlite");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = TodoStore::open(&path).await
+                                          .unwrap();
+        store.upsert(&[todo(1, 1, false)])
+             .await
+             .unwrap();
+        drop(store);
+
+        // migrations are idempotent: reopening keeps the data
+        let store = TodoStore::open(&path).await
+                                          .unwrap();
+        assert_eq!(store.count().await.unwrap(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
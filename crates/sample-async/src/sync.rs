@@ -0,0 +1,79 @@
+//! `sync` subcommand: fetch jsonplaceholder's todo list into a local SQLite store, then query it.
+//!
+//! - rows are upserted by id: re-running only rewrites todos that changed remotely
+//! - `--user-id`/`--completed` filter the *local* query printed afterwards (the whole list is always synced)
+//!
+//! e.g. `cargo run -p sample-async -- sync --user-id 1 --completed false`
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{num::NonZeroU32, path::PathBuf};
+
+use clap::Args;
+use futures::TryStreamExt as _;
+use reqwest::Url;
+use tracing::{Level as L, event, instrument};
+use utilities::{HttpClient, RetryPolicy,
+                jsonplaceholder::{JsonPlaceholder, Todo, TodoFilter}};
+
+use crate::{SampleResult, store::TodoStore};
+// ///////////////////////////////// [ cli ] ///////////////////////////////// //
+#[derive(Args, Debug, Clone)]
+pub struct SyncArgs {
+    /// SQLite database file (created & migrated if missing)
+    #[arg(long, default_value = "data/no-sync/todos.sqlite")]
+    db:         PathBuf,
+    /// Todos fetched per request (`_limit`)
+    #[arg(long, default_value = "50")]
+    page_limit: NonZeroU32,
+    /// Only print stored todos of this user
+    #[arg(long)]
+    user_id:    Option<u32>,
+    /// Only print stored todos with this completion state
+    #[arg(long)]
+    completed:  Option<bool>,
+}
+// ///////////////////////////////// [ run ] ///////////////////////////////// //
+/// `base_url`: jsonplaceholder, or a stand-in (`--base-url`)
+#[instrument(skip_all, fields(db = %args.db.display()))]
+pub async fn run(args: SyncArgs, base_url: Option<Url>) -> SampleResult<()> {
+    let base_url = match base_url {
+        Some(base_url) => base_url,
+        None => Url::parse(JsonPlaceholder::BASE_URL)?,
+    };
+    let http = HttpClient::builder().allow_http(base_url.scheme() == "http")
+                                    .base_url(base_url)
+                                    .build()?;
+    let api = JsonPlaceholder::builder().http(http)
+                                        .retry(RetryPolicy::default())
+                                        .build();
+    let store = TodoStore::open(&args.db).await?;
+
+    let remote: Vec<Todo> = api.todos()
+                               .stream(TodoFilter::default(), args.page_limit)
+                               .try_collect()
+                               .await?;
+    let changed = store.upsert(&remote).await?;
+    let stored = store.count().await?;
+    event!(L::INFO,
+           fetched = remote.len(),
+           changed,
+           stored,
+           "synced todos");
+    println!("Synced {} todos into {} ({changed} new or changed, {stored} stored)",
+             remote.len(),
+             args.db.display());
+
+    let filter = TodoFilter { user_id:   args.user_id,
+                              completed: args.completed, };
+    if filter != TodoFilter::default() {
+        let todos = store.query(&filter).await?;
+        println!("{} stored todos match {filter:?}:", todos.len());
+        for todo in todos {
+            println!("  {:>4}  user {:>2}  [{}]  {}",
+                     todo.id,
+                     todo.user_id,
+                     if todo.completed { "x" } else { " " },
+                     todo.title);
+        }
+    }
+    Ok(())
+}
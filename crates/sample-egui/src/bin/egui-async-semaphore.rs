@@ -21,30 +21,59 @@ It's intentionally variatals box to play with some options.
 // ///////////////////////////////// [ use ] ///////////////////////////////// //
 use std::{error::Error,
           num::NonZeroU32,
+          process::ExitCode,
           sync::{Arc, Mutex as StdMutex,
                  atomic::{AtomicUsize, Ordering},
                  mpsc::{Receiver as BlockingReceiver, Sender as BlockingSender}},
           time::Duration};
 
+use clap::Parser;
 use eframe::egui;
-use reqwest::{self, Client as ReqClient, Method, StatusCode, Url,
-              header::{self, HeaderMap}};
+use reqwest::{self, Method, StatusCode, Url};
+use sample_egui::SampleResult;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use tokio::{sync::Semaphore, task::JoinSet, time::interval};
+use tokio::{task::JoinSet, time::interval};
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::{Instrument, Level as L, debug_span, event, instrument};
-use utilities::activate_global_default_tracing_subscriber;
+use utilities::{AdaptiveLimiter, CircuitBreakers, HttpClient, LimiterOutcome, LoggingConfigSource,
+                OtlpConfig, ReportFormat, SendError, TraceContextExt as _,
+                activate_global_default_tracing_subscriber};
+// ///////////////////////////////// [ cli ] ///////////////////////////////// //
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Send requests here instead of httpbin (e.g. a local stand-in: `cargo run -p stand-in`)
+    #[arg(long, default_value = URL_HTTPBIN)]
+    base_url:     Url,
+    /// How a failure is reported on stderr: `text` (coloured on a terminal) or `json`
+    #[arg(long, default_value_t = ReportFormat::Text)]
+    error_format: ReportFormat,
+}
+const URL_HTTPBIN: &str = "https://httpbin.org";
 // ///////////////////////////////// [ main ] ///////////////////////////////// //
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let _writer_guard: tracing_appender::non_blocking::WorkerGuard =
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        // exit code per `ErrKind` variant (see `utilities::diagnostic`)
+        Err(error) => error.exit(error_format),
+    }
+}
+fn run(cli: Cli) -> SampleResult<()> {
+    let _writer_guard: utilities::SubscriberGuard =
         activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
                                                     .maybe_error_logging_level(None)
+                                                    .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
+                                                    .maybe_otlp(OtlpConfig::from_env(env!("CARGO_PKG_NAME")))
                                                     .call()?;
+    // the runtime's view of the JoinSet/semaphore experiments (per-worker detail at `RUST_LOG=debug`)
+    let _metrics_task = utilities::spawn_runtime_metrics_logger(Duration::from_secs(5));
     eframe::run_native("Async Future Memory Management",
                        eframe::NativeOptions::default(),
-                       Box::new(|_cc| Ok(Box::new(FuturesApp::default()))))?;
+                       Box::new(|_cc| Ok(Box::new(FuturesApp::new(cli.base_url)))))?;
     Ok(())
 }
 // ///////////////////////////////// [ App Memory ] ///////////////////////////////// //
@@ -53,12 +82,13 @@ type JoinSetArcMutex<T> = Arc<StdMutex<JoinSet<T>>>;
 #[derive(Debug)]
 struct FuturesApp {
     // channel
-    tx: BlockingSender<reqwest::StatusCode>,
-    rx: BlockingReceiver<reqwest::StatusCode>,
+    tx: BlockingSender<Completion>,
+    rx: BlockingReceiver<Completion>,
     //  general
     _loading: bool,
-    client: ReqClient,
-    semaphore: Arc<Semaphore>,
+    client: HttpClient,
+    /// adaptive replacement for a fixed `Semaphore::new(10)`
+    limiter: AdaptiveLimiter,
     delay_sec: u8,
     requests_to_queue: NonZeroU32,
     // raw tasks
@@ -73,13 +103,37 @@ struct FuturesApp {
     count_200: usize,
     count_400: usize,
     count_other: usize,
+    count_short_circuited: usize,
+}
+/// How a queued request ended.
+#[derive(Debug, Clone, Copy)]
+enum Completion {
+    Status(StatusCode),
+    /// Not sent: the host's circuit was open.
+    ShortCircuited,
 }
 impl Default for FuturesApp {
-    #[instrument]
     fn default() -> Self {
+        Self::new(Url::parse(URL_HTTPBIN).expect("static url should parse"))
+    }
+}
+impl FuturesApp {
+    #[instrument]
+    fn new(base_url: Url) -> Self {
         let (tx, rx) = std::sync::mpsc::channel();
-        let semaphore = Arc::new(Semaphore::new(10));
-        let client = generate_client().unwrap();
+        let limiter = AdaptiveLimiter::builder().initial_limit(10)
+                                                .max_limit(100)
+                                                .build();
+        // `--base-url` may point at a (plain `http`) local stand-in
+        let allow_http = base_url.scheme() == "http";
+        // a down host fails fast (instead of every queued request waiting out the 30s timeout)
+        let circuit_breakers = CircuitBreakers::builder().open_for(Duration::from_secs(5))
+                                                         .build();
+        let client = HttpClient::builder().base_url(base_url)
+                                          .allow_http(allow_http)
+                                          .circuit_breakers(circuit_breakers)
+                                          .build()
+                                          .unwrap();
         let join_set = tokio::task::JoinSet::new();
         let join_set_caged = Arc::new(StdMutex::new(tokio::task::JoinSet::new()));
         let task_gen_tracker = TaskTracker::new();
@@ -89,7 +143,7 @@ impl Default for FuturesApp {
                // general
                _loading: false,
                client,
-               semaphore,
+               limiter,
                delay_sec: 1,
                requests_to_queue: NonZeroU32::new(1).unwrap(),
                // raw tasks
@@ -103,49 +157,61 @@ impl Default for FuturesApp {
                // results
                count_200: 0,
                count_400: 0,
-               count_other: 0 }
+               count_other: 0,
+               count_short_circuited: 0 }
     }
 }
 impl FuturesApp {
     #[instrument]
-    /// Generate a task that executes a semaphore-gated request.
+    /// Generate a task that executes a limiter-gated request.
     /// This *ought* to take self, but that would make calling it from an independent task (the generator generator) more difficult.
     fn queue_request(delay: u8,
-                     client: ReqClient,
-                     semaphore: Arc<Semaphore>,
-                     tx: BlockingSender<StatusCode>,
+                     client: HttpClient,
+                     limiter: AdaptiveLimiter,
+                     tx: BlockingSender<Completion>,
                      join_set: &mut JoinSet<Result<(), Box<dyn Error + Send + Sync>>>,
                      ctx: egui::Context) {
-        let endpoint = {
-            const HTTPBIN_DELAY_URL: &str = "https://httpbin.org/delay";
-            Url::parse(&format!("{}/{}", HTTPBIN_DELAY_URL, delay)).unwrap()
-        };
+        let endpoint = client.url_segments(["delay", &delay.to_string()]);
         // NOTE: we're not actually using JoinSet -- in fact we're leaking due to lack of poll
         join_set.spawn(async move {
-                           let req = client.request(Method::GET, endpoint)
+                           let req = client.client()
+                                           .request(Method::GET, endpoint)
+                                           .with_trace_context()
                                            .build()
                                            .expect("should be valid reqwest");
                            event!(L::INFO, ?req);
-                           let _permit = semaphore.acquire().await;
+                           let permit = limiter.acquire().await;
                            ctx.request_repaint();
-                           event!(L::INFO, ?_permit);
-                           let resp = client.execute(req).await?;
-                           event!(L::INFO, ?resp);
-                           tx.send(resp.status())?;
+                           event!(L::INFO, ?permit);
+                           let resp = client.execute(req).await;
+                           // latency, timeouts & 429s adjust the limit
+                           permit.record(LimiterOutcome::from_send_result(&resp));
+                           let completion = match resp {
+                               Ok(resp) => {
+                                   event!(L::INFO, ?resp);
+                                   Completion::Status(resp.status())
+                               },
+                               Err(SendError::CircuitOpen(open)) => {
+                                   event!(L::DEBUG, %open);
+                                   Completion::ShortCircuited
+                               },
+                               Err(error) => return Err(error.into()),
+                           };
+                           tx.send(completion)?;
                            // REPAINT
                            ctx.request_repaint();
                            Ok::<(), Box<dyn Error + Send + Sync>>(())
                        }.instrument(debug_span!("reqwest", ?delay)));
     }
 
-    /// Generate a Generator of tasks that execute semaphore-gated requests.
+    /// Generate a Generator of tasks that execute limiter-gated requests.
     #[instrument]
     fn metered_queue_request(&mut self, ctx: egui::Context) {
         // ....................... [ cheap-clones-for-task ] ....................... //
 
         let requests_to_queue = self.requests_to_queue;
         let endpoint_delay = self.delay_sec;
-        let semaphore = self.semaphore.clone();
+        let limiter = self.limiter.clone();
         let tx = self.tx.clone();
         let mut interval = interval(self.request_period);
         let client = self.client.clone();
@@ -155,7 +221,7 @@ impl FuturesApp {
         let cancel_token = self.generator_cancel.clone();
         // ....................... [ update-atomic-counter ] ....................... //
         atomic_counter.fetch_add(requests_to_queue.get() as usize, Ordering::Relaxed);
-        // ----------------------------- [ spawn-semaphore-gated-reqwest-task ] ----------------------------- //
+        // ----------------------------- [ spawn-limiter-gated-reqwest-task ] ------------------------------- //
         self.task_gen_tracker.spawn(
             async move {
                 // let mut interval = interval(request_period);
@@ -167,7 +233,7 @@ impl FuturesApp {
                                     FuturesApp::queue_request(
                                             endpoint_delay,
                                             client.clone(),
-                                            semaphore.clone(),
+                                            limiter.clone(),
                                             tx.clone(),
                                             &mut join_set_caged,
                                             ctx.clone(),
@@ -201,27 +267,6 @@ struct RemoteDelayResponse {
     #[serde(flatten)]
     other:   JsonValue,
 }
-fn generate_client() -> Result<reqwest::Client, Box<dyn std::error::Error>> {
-    let default_headers = {
-        let mut headers = HeaderMap::new();
-        headers.insert(header::ACCEPT,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::CONTENT_TYPE,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::USER_AGENT,
-                       "rust-reqwest-client".parse()
-                                            .unwrap());
-        headers
-    };
-    let client = reqwest::Client::builder().https_only(true) // this will error for `http` (WARN: not compile-time checked)
-                                           .use_rustls_tls()
-                                           .default_headers(default_headers)
-                                           .timeout(Duration::from_secs(30)) // default is *no* timeout
-                                           .build()?;
-    Ok(client)
-}
 // ///////////////////////////////// [ loop ] ///////////////////////////////// //
 const NON_ZERO_MIN: NonZeroU32 = NonZeroU32::new(1).unwrap();
 const NON_ZERO_MAX: NonZeroU32 = NonZeroU32::new(u32::MAX).unwrap();
@@ -229,11 +274,12 @@ impl eframe::App for FuturesApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ [ check-'n-count ] ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~ //
         // NOTE: We would normally loop over the JoinSet, but we're intentionally over-using tools for exploration purposes.
-        if let Ok(status) = self.rx.try_recv() {
-            match status {
-                _ if status.is_success() => self.count_200 += 1,
-                _ if status.is_client_error() => self.count_400 += 1,
-                _ => self.count_other += 1,
+        if let Ok(completion) = self.rx.try_recv() {
+            match completion {
+                Completion::Status(status) if status.is_success() => self.count_200 += 1,
+                Completion::Status(status) if status.is_client_error() => self.count_400 += 1,
+                Completion::Status(_) => self.count_other += 1,
+                Completion::ShortCircuited => self.count_short_circuited += 1,
             }
             let _log = self.join_set.try_join_next();
             event!(L::TRACE, join_set_clear_result=?_log);
@@ -255,7 +301,7 @@ impl eframe::App for FuturesApp {
                                         FuturesApp::queue_request(
                                                 self.delay_sec,
                                                 self.client.clone(),
-                                                self.semaphore.clone(),
+                                                self.limiter.clone(),
                                                 self.tx.clone(),
                                                 &mut self.join_set,
                                                 ctx.clone(),
@@ -330,10 +376,20 @@ impl eframe::App for FuturesApp {
                                          ui.label(format!("200 count: {}", self.count_200));
                                          ui.label(format!("400 count: {}", self.count_400));
                                          ui.label(format!("Other count: {}", self.count_other));
+                                         ui.label(format!("Short-circuited count: {}",
+                                                          self.count_short_circuited));
                                          ui.add_space(10.0);
-                                         ui.label(format!("Semaphore available: {}",
-                                                          self.semaphore
-                                                              .available_permits()));
+                                         ui.label(format!("Concurrency limit: {}",
+                                                          self.limiter.limit()));
+                                         ui.label(format!("Requests in flight: {}",
+                                                          self.limiter.in_flight()));
+                                         for (host, state) in self.client
+                                                                  .circuit_breakers()
+                                                                  .map(CircuitBreakers::states)
+                                                                  .unwrap_or_default()
+                                         {
+                                             ui.label(format!("Circuit {host}: {state}"));
+                                         }
                                          ui.label(format!("Queued Requests: {}",
                                                           self.join_set.len()));
                                          ui.label(format!("Queued Requests metered: {}",
@@ -1,10 +1,16 @@
 // ///////////////////////////////// [ use ] ///////////////////////////////// //
-use std::time::Duration;
+use std::{num::NonZeroU32, path::PathBuf, process::ExitCode};
 
+use clap::Parser;
 use eframe::egui;
-use reqwest::{Client, Method, Url,
-              header::{self, HeaderMap}};
-use serde::{Deserialize, Serialize};
+use futures::{StreamExt as _, pin_mut};
+use reqwest::Url;
+use sample_egui::SampleResult;
+use tracing::{Instrument as _, instrument};
+use utilities::{DownloadProgress, Downloaded, HttpCache, HttpClient, LoggingConfigSource,
+                OtlpConfig, ReportFormat, RetryPolicy, activate_global_default_tracing_subscriber,
+                jsonplaceholder::{JsonPlaceholder, JsonPlaceholderError, Todo, TodoFilter,
+                                  TodoPatch}};
 // ///////////////////////////////// [ main ] ///////////////////////////////// //
 // fn main() -> Result<(), Box<dyn std::error::Error>> {
 //         // tokio::runtime::Builder::new_current_thread()
@@ -27,85 +33,172 @@ use serde::{Deserialize, Serialize};
 
 //         Ok(())
 // }
+// ///////////////////////////////// [ cli ] ///////////////////////////////// //
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Send requests here instead of jsonplaceholder (e.g. a local stand-in: `cargo run -p stand-in`)
+    #[arg(long, default_value = JsonPlaceholder::BASE_URL)]
+    base_url:     Url,
+    /// Keep cached responses in memory only (default: also under `$XDG_CACHE_HOME/sample-egui/http`)
+    #[arg(long)]
+    memory_cache: bool,
+    /// Fetched by the "Download" button (streamed to a temp file, with a progress bar)
+    #[arg(long, default_value = DEFAULT_DOWNLOAD_URL)]
+    download_url: Url,
+    /// How a failure is reported on stderr: `text` (coloured on a terminal) or `json`
+    #[arg(long, default_value_t = ReportFormat::Text)]
+    error_format: ReportFormat,
+}
+/// 100 KiB, trickled out over 5s: long enough to watch the progress bar
+const DEFAULT_DOWNLOAD_URL: &str = "https://httpbin.org/range/102400?duration=5&chunk_size=4096";
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => error.exit(error_format),
+    }
+}
+fn run(cli: Cli) -> SampleResult<()> {
+    let _writer_guard: utilities::SubscriberGuard =
+        activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
+                                                    .maybe_error_logging_level(None)
+                                                    .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
+                                                    .maybe_otlp(OtlpConfig::from_env(env!("CARGO_PKG_NAME")))
+                                                    .call()?;
+    let cache_dir = (!cli.memory_cache).then(|| HttpCache::xdg_dir(env!("CARGO_PKG_NAME")))
+                                       .flatten();
+    let cache = HttpCache::builder().maybe_dir(cache_dir)
+                                    .build();
     eframe::run_native("Hello egui + tokio",
                        eframe::NativeOptions::default(),
-                       Box::new(|_cc| Ok(Box::new(ChannelApp::default()))))?;
+                       Box::new(|_cc| {
+                           Ok(Box::new(ChannelApp::new(cli.base_url,
+                                                       cache,
+                                                       cli.download_url)))
+                       }))?;
     Ok(())
 }
 // ///////////////////////////////// [ App Memory ] ///////////////////////////////// //
 //                                     and init
 struct ChannelApp {
     // Sender/Receiver for async notifications.
-    tx:             std::sync::mpsc::Sender<Vec<Todo>>,
-    rx:             std::sync::mpsc::Receiver<Vec<Todo>>,
+    tx:             std::sync::mpsc::Sender<Update>,
+    rx:             std::sync::mpsc::Receiver<Update>,
     // jsons: Vec<serde_json::Value>,
     todos:          Vec<Todo>,
     loading:        bool,
     error:          Option<String>,
-    client:         reqwest::Client,
+    api:            JsonPlaceholder,
+    /// Re-fetches within `max-age` are served from here; later ones are revalidated (`304`: no re-download).
+    cache:          HttpCache,
+    download_url:   Url,
+    /// In flight: latest progress.
+    download:       Option<DownloadProgress>,
+    downloaded:     Option<Downloaded>,
     _selected_todo: Option<usize>,
 }
 impl Default for ChannelApp {
     fn default() -> Self {
+        Self::new(Url::parse(JsonPlaceholder::BASE_URL).expect("static url should parse"),
+                  HttpCache::builder().build(),
+                  Url::parse(DEFAULT_DOWNLOAD_URL).expect("static url should parse"))
+    }
+}
+impl ChannelApp {
+    fn new(base_url: Url, cache: HttpCache, download_url: Url) -> Self {
         let (tx, rx) = std::sync::mpsc::channel();
-        let client = generate_client().unwrap();
+        // `--base-url` may point at a (plain `http`) local stand-in
+        let allow_http = base_url.scheme() == "http";
+        let http = HttpClient::builder().base_url(base_url)
+                                        .allow_http(allow_http)
+                                        .cache(cache.clone())
+                                        .build()
+                                        .unwrap();
+        let api = JsonPlaceholder::builder().http(http)
+                                            // idempotent requests only (see `JsonPlaceholder::new`)
+                                            .retry(RetryPolicy::default())
+                                            .build();
         Self { tx,
                rx,
                todos: Vec::new(),
                loading: false,
                error: None,
                _selected_todo: None,
-               client }
+               api,
+               cache,
+               download_url,
+               download: None,
+               downloaded: None }
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Todos(todos) => {
+                self.todos = todos;
+                self.loading = false;
+            },
+            Update::Page { todos, last } => {
+                self.todos.extend(todos);
+                self.loading = !last;
+            },
+            Update::Changed(changed) => {
+                if let Some(todo) = self.todos
+                                        .iter_mut()
+                                        .find(|todo| todo.id == changed.id)
+                {
+                    *todo = changed;
+                }
+            },
+            Update::Deleted(id) => self.todos
+                                       .retain(|todo| todo.id != id),
+            Update::Progress(progress) => self.download = Some(progress),
+            Update::Downloaded(downloaded) => {
+                self.download = None;
+                self.downloaded = Some(downloaded);
+            },
+            Update::Failed(error) => {
+                self.error = Some(error);
+                self.loading = false;
+                self.download = None;
+            },
+        }
     }
 }
 // ///////////////////////////////// [ app accessory ] ///////////////////////////////// //
-/// Struct to pull typicode responses into
-/// Example of using 'typed' JSON with Serde
-#[derive(Debug, Serialize, Deserialize)]
-struct Todo {
-    #[serde(rename = "userId")]
-    user_id:   i32,
-    id:        i32,
-    title:     String,
-    completed: bool,
-}
-fn generate_client() -> Result<reqwest::Client, Box<dyn std::error::Error>> {
-    let default_headers = {
-        let mut headers = HeaderMap::new();
-        headers.insert(header::ACCEPT,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::CONTENT_TYPE,
-                       "application/json".parse()
-                                         .unwrap());
-        headers.insert(header::USER_AGENT,
-                       "rust-reqwest-client".parse()
-                                            .unwrap());
-        headers
-    };
-    let client = reqwest::Client::builder().https_only(true) // this will error for `http` (WARN: not compile-time checked)
-                                           .use_rustls_tls()
-                                           .default_headers(default_headers)
-                                           .timeout(Duration::from_secs(30)) // default is *no* timeout
-                                           .build()?;
-    Ok(client)
+/// Results sent back from request tasks.
+/// (jsonplaceholder fakes writes: changes show here, but a re-fetch returns the originals.)
+#[derive(Debug)]
+enum Update {
+    /// Replaces the list.
+    Todos(Vec<Todo>),
+    /// Appends to the list.
+    Page {
+        todos: Vec<Todo>,
+        last:  bool,
+    },
+    Changed(Todo),
+    Deleted(u32),
+    Progress(DownloadProgress),
+    Downloaded(Downloaded),
+    Failed(String),
 }
+const PAGE_LIMIT: NonZeroU32 = NonZeroU32::new(25).unwrap();
 // ///////////////////////////////// [ loop ] ///////////////////////////////// //
 impl eframe::App for ChannelApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if let Ok(todos) = self.rx.try_recv() {
-            self.todos = todos;
-            self.loading = false;
+        while let Ok(update) = self.rx.try_recv() {
+            self.apply(update);
             ctx.request_repaint();
         }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Async Fetch Example - grabs TODOs from Typicode");
-            ui.label("Press the button to initiate an HTTP request.");
+            ui.label("Press a button to initiate an HTTP request.");
             if self.loading {
                 ui.spinner();
-                ui.label("Loading...");
+                ui.label(format!("Loading... ({} so far)", self.todos.len()));
             } else if let Some(err) = &self.error {
                 ui.colored_label(egui::Color32::RED, format!("Error: {}", err));
             } else if self.todos.is_empty() {
@@ -113,23 +206,70 @@ impl eframe::App for ChannelApp {
             } else {
                 ui.label(format!("Loaded {} todos", self.todos.len()));
             }
+            let stats = self.cache.stats();
+            ui.horizontal(|ui| {
+                  ui.label(format!("Cache: {} hits, {} revalidated, {} misses",
+                                   stats.hits, stats.revalidated, stats.misses));
+                  if ui.small_button("Clear")
+                       .clicked()
+                  {
+                      let cache = self.cache.clone();
+                      tokio::spawn(async move { cache.clear().await });
+                  }
+              });
             ui.add_space(10.0);
-            for i in 1..=4 {
-                if ui.button(format!("Request id: {}", i))
+            for user_id in 1..=4 {
+                if ui.button(format!("User id: {}", user_id))
                      .clicked()
                    && !self.loading
                 {
                     self.loading = true;
-                    send_request(self.client.clone(), i, self.tx.clone(), ctx.clone());
+                    self.error = None;
+                    fetch_user_todos(self.api.clone(), user_id, self.tx.clone(), ctx.clone());
                 }
             }
-            // ui.horizontal(|ui| {
-            //         for user_id in 1..=5 {
-            //                 if ui.button(format!("User {}", user_id)).clicked() && !self.loading {
-            //                         self.fetch_todos(Some(user_id), ctx.clone());
-            //                 }
-            //         }
-            // });
+            if ui.button(format!("All todos ({} per page)", PAGE_LIMIT))
+                 .clicked()
+               && !self.loading
+            {
+                self.loading = true;
+                self.error = None;
+                self.todos.clear();
+                stream_all_todos(self.api.clone(), self.tx.clone(), ctx.clone());
+            }
+            ui.add_space(10.0);
+            ui.separator();
+            match &self.download {
+                Some(progress) => {
+                    let bar = match progress.fraction() {
+                        Some(fraction) => egui::ProgressBar::new(fraction).show_percentage(),
+                        None => egui::ProgressBar::new(0.0).animate(true),
+                    };
+                    ui.add(bar.text(progress.to_string()));
+                },
+                None => {
+                    if ui.button("Download")
+                         .clicked()
+                    {
+                        self.error = None;
+                        self.downloaded = None;
+                        self.download = Some(DownloadProgress { downloaded:   0,
+                                                                total:        None,
+                                                                resumed_from: 0, });
+                        download(self.api.http().clone(),
+                                 self.download_url.clone(),
+                                 self.tx.clone(),
+                                 ctx.clone());
+                    }
+                    if let Some(downloaded) = &self.downloaded {
+                        ui.label(format!("{}: {} bytes ({} resumed)\nsha256 {}",
+                                         downloaded.path.display(),
+                                         downloaded.bytes,
+                                         downloaded.resumed_from,
+                                         downloaded.sha256));
+                    }
+                },
+            }
         });
         egui::SidePanel::right("panel").show(ctx, |ui| {
                                            ui.heading("Todos");
@@ -139,9 +279,22 @@ impl eframe::App for ChannelApp {
                                                egui::ScrollArea::vertical().show(ui, |ui| {
                                                    for todo in &self.todos {
                                                        ui.horizontal(|ui| {
-                                                             ui.checkbox(&mut todo.completed
-                                                                                  .clone(),
-                                                                         "");
+                                                             let mut completed = todo.completed;
+                                                             if ui.checkbox(&mut completed, "")
+                                                                  .changed()
+                                                             {
+                                                                 set_completed(self.api.clone(),
+                                                                               todo.id,
+                                                                               completed,
+                                                                               self.tx.clone(),
+                                                                               ctx.clone());
+                                                             }
+                                                             if ui.small_button("🗑").clicked() {
+                                                                 delete_todo(self.api.clone(),
+                                                                             todo.id,
+                                                                             self.tx.clone(),
+                                                                             ctx.clone());
+                                                             }
                                                              let text = format!("{} - {}",
                                                                                 todo.id,
                                                                                 todo.title);
@@ -168,27 +321,110 @@ impl eframe::App for ChannelApp {
     }
 }
 // ///////////////////////////////// [ loop methods ] ///////////////////////////////// //
-fn send_request(client: Client,
-                req_id: u8,
-                tx: std::sync::mpsc::Sender<Vec<Todo>>,
-                ctx: egui::Context) {
-    const URL_TYPICODE: &str = "https://jsonplaceholder.typicode.com";
-    let todos_typicode = Url::parse(URL_TYPICODE).unwrap()
-                                                 .join("/todos")
-                                                 .unwrap();
+/// Run `request` on the runtime; send its result (or error) back & repaint.
+fn spawn_request<F>(tx: std::sync::mpsc::Sender<Update>, ctx: egui::Context, request: F)
+    where F: Future<Output = Result<Update, JsonPlaceholderError>> + Send + 'static {
     tokio::task::spawn(async move {
-        let response = client.request(Method::GET, todos_typicode)
-                             .query(&[("userId", req_id)])
-                             // .query(&[("userId", "1"), ("completed", "false")])
-                             .send()
-                             .await
-                             .unwrap();
-        let todos: Vec<Todo> = response.json().await.unwrap();
-        let _ = tx.send(todos);
-        ctx.request_repaint();
+                           let update =
+                               request.await
+                                      .unwrap_or_else(|error| Update::Failed(error.to_string()));
+                           let _ = tx.send(update);
+                           ctx.request_repaint();
+                       }.in_current_span());
+}
+
+#[instrument(skip(api, tx, ctx))]
+fn fetch_user_todos(api: JsonPlaceholder,
+                    user_id: u32,
+                    tx: std::sync::mpsc::Sender<Update>,
+                    ctx: egui::Context) {
+    spawn_request(tx, ctx, async move {
+        let filter = TodoFilter { user_id: Some(user_id),
+                                  ..Default::default() };
+        Ok(Update::Todos(api.todos()
+                            .list(&filter)
+                            .await?))
+    });
+}
+
+/// Each page is shown as it arrives.
+#[instrument(skip(api, tx, ctx))]
+fn stream_all_todos(api: JsonPlaceholder, tx: std::sync::mpsc::Sender<Update>, ctx: egui::Context) {
+    tokio::task::spawn(async move {
+                           let pages = api.todos()
+                                          .pages(TodoFilter::default(), PAGE_LIMIT);
+                           pin_mut!(pages);
+                           while let Some(page) = pages.next().await {
+                               let update = match page {
+                                   Ok(page) => Update::Page { last:  page.is_last(),
+                                                              todos: page.items, },
+                                   Err(error) => Update::Failed(error.to_string()),
+                               };
+                               let _ = tx.send(update);
+                               ctx.request_repaint();
+                           }
+                       }.in_current_span());
+}
+
+#[instrument(skip(api, tx, ctx))]
+fn set_completed(api: JsonPlaceholder,
+                 id: u32,
+                 completed: bool,
+                 tx: std::sync::mpsc::Sender<Update>,
+                 ctx: egui::Context) {
+    spawn_request(tx, ctx, async move {
+        let patch = TodoPatch { completed: Some(completed),
+                                ..Default::default() };
+        Ok(Update::Changed(api.todos()
+                              .patch(id, &patch)
+                              .await?))
     });
 }
 
+#[instrument(skip(api, tx, ctx))]
+fn delete_todo(api: JsonPlaceholder,
+               id: u32,
+               tx: std::sync::mpsc::Sender<Update>,
+               ctx: egui::Context) {
+    spawn_request(tx, ctx, async move {
+        api.todos().delete(id).await?;
+        Ok(Update::Deleted(id))
+    });
+}
+
+/// Stream `url` to a temp file; progress updates go through the same channel as everything else.
+#[instrument(skip(http, tx, ctx))]
+fn download(http: HttpClient, url: Url, tx: std::sync::mpsc::Sender<Update>, ctx: egui::Context) {
+    let to = download_path(&url);
+    let progress_tx = tx.clone();
+    let progress_ctx = ctx.clone();
+    tokio::task::spawn(async move {
+                           let update = match http.download()
+                                                  .url(url)
+                                                  .to(to)
+                                                  .on_progress(Box::new(move |progress| {
+                                                      let _ = progress_tx.send(Update::Progress(*progress));
+                                                      progress_ctx.request_repaint();
+                                                  }))
+                                                  .call()
+                                                  .await
+                           {
+                               Ok(downloaded) => Update::Downloaded(downloaded),
+                               Err(error) => Update::Failed(error.to_string()),
+                           };
+                           let _ = tx.send(update);
+                           ctx.request_repaint();
+                       }.in_current_span());
+}
+/// `<temp dir>/egui-async-<last path segment>`
+fn download_path(url: &Url) -> PathBuf {
+    let name = url.path_segments()
+                  .and_then(|mut segments| segments.next_back())
+                  .filter(|name| !name.is_empty())
+                  .unwrap_or("download");
+    std::env::temp_dir().join(format!("egui-async-{name}"))
+}
+
 // let mut suspense = EguiSuspense::reloadable(|cb| {
 //         std::thread::spawn(move || {
 //                 std::thread::sleep(std::time::Duration::from_secs(1));
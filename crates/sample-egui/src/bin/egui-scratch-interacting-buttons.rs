@@ -3,12 +3,13 @@
 // use egui_extras::DatePickerButton;
 use eframe::egui;
 use egui::pos2;
-use utilities::activate_global_default_tracing_subscriber;
+use utilities::{LoggingConfigSource, activate_global_default_tracing_subscriber};
 
 fn main() {
-    let _writer_guard: tracing_appender::non_blocking::WorkerGuard =
+    let _writer_guard: utilities::SubscriberGuard =
         activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
                                                     .maybe_error_logging_level(None)
+                                                    .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
                                                     .call()
                                                     .unwrap();
 
@@ -3,7 +3,7 @@
 //! ## Common ErrorKinds
 //! // //
 //! // // `custom` errors
-//! // #[from(ignore)] // manually generate; would conflict with `OtherStringError` auto-derive
+//! // #[from(ignore)] // manually generate; would conflict with `From<String>` (`CommonError::OtherString`)
 //! // #[display("Error splitting on ':' : {}", source_input)]
 //! // InputNoColon { source_input: String },
 //! // #[from(ignore)]
@@ -50,115 +50,69 @@
 //! `#![feature(error_generic_member_access)]`
 //! `use std::backtrace;`
 
-use std::io;
-
 use derive_more::{Display, Error, From};
-use tracing::{instrument, subscriber::SetGlobalDefaultError};
+use utilities::{Categorize, CommonError, Diagnose, ErrorCategory};
 
-// use derive_more::{Display, Error, derive::From};
+/// This crate's errors; shared ones (io, tracing setup, ..) are [`CommonError`]s (see `utilities::error`).
 #[derive(Debug, Display, From, Error)]
 pub enum ErrKind {
     // `custom` errors //
-    #[from(ignore)] // manually generate; would conflict with `OtherStringError` auto-derive
+    #[from(ignore)] // manually generate; would conflict with `From<String>` (`CommonError::OtherString`)
     #[display("Error splitting on ':' : {}", source_input)]
     InputNoColon { source_input: String },
 
-    #[from(ignore)] // manually generate; would conflict with `OtherStringError` auto-derive
+    #[from(ignore)] // manually generate; would conflict with `From<String>` (`CommonError::OtherString`)
     #[display("Error extracting lines from input: {}", source_input)]
     InputNoLines { source_input: String },
 
+    // `packed` errors //
     #[display("eframe (egui) error: {}", source)]
     EFrame { source: eframe::Error },
 
-    #[display("Error with tracing_subscriber::EnvFilter parsing env directive: {}",
-              source)]
-    EnvError {
-        source: tracing_subscriber::filter::FromEnvError,
-    },
-
-    #[display("hiddenvalue error: {}", source)]
-    HiddenValError { source: utilities::HiddenValueError },
-
-    #[display("io error: {}", source)]
-    Io { source: io::Error },
-
-    #[display("parse error: {}", source)]
-    ParseInt { source: std::num::ParseIntError },
-
-    #[display("Error setting tracing subscriber default: {}", source)]
-    TracingSubscriber { source: SetGlobalDefaultError },
-
-    // `other` errors //
-    #[from(ignore)] // use `make_dyn_error` instead; would conflict with auto-derives
-    #[display("Uncategorized Error (dyn error object): {}", source)]
-    OtherDynError {
-        source: Box<dyn std::error::Error + Send + Sync>,
-    },
-
-    #[display(r#"Uncategorized string err: "{}""#, source_string)]
-    OtherStringError { source_string: String },
+    // `common` errors //
+    #[display("{}", source)]
+    Common { source: CommonError },
 }
-impl ErrKind {
-    /// Convenience asscfunction for transforming an error into a compabtible *dyn error*.
-    ///
-    /// ```ignore
-    /// use support::ErrKind;
-    /// let clip = arboard::Clipboard::new().map_err(ErrKind::into_dyn_error)?;
-    /// ```
-    #[instrument(skip_all)]
-    pub fn into_dyn_error<E>(error: E) -> Self
-        where E: Into<Box<dyn std::error::Error + Send + Sync>> {
-        Self::OtherDynError { source: error.into(), }
-    }
-}
-
-#[derive(Display, Error, From)]
-#[display(
-        "error: {:#}\n\n\nspantrace capture: {:?}\n\n\nspantrace: {:#}",
-        source,
-        spantrace.status(),
-        spantrace,
-)]
-pub struct ErrWrapper {
-    source:    ErrKind,
-    spantrace: tracing_error::SpanTrace,
-    // backtrace: backtrace::Backtrace,
-}
-// Using custom display as debug so we can get SpanTrace auto printed.
-impl std::fmt::Debug for ErrWrapper {
-    #[instrument(skip_all)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+impl Categorize for ErrKind {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::InputNoColon { .. } | Self::InputNoLines { .. } => ErrorCategory::User,
+            Self::EFrame { .. } => ErrorCategory::Other,
+            Self::Common { source } => source.category(),
+        }
     }
 }
-impl<T> From<T> for ErrWrapper where T: Into<ErrKind> {
-    #[instrument(skip_all)]
-    fn from(error: T) -> Self {
-        Self {
-            source:    error.into(),
-            spantrace: tracing_error::SpanTrace::capture(),
-            // backtrace: backtrace::Backtrace::capture(),
+/// Exit codes: 40.. (20..=39 are `CommonError`'s)
+impl Diagnose for ErrKind {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::InputNoColon { .. } => 40,
+            Self::InputNoLines { .. } => 41,
+            Self::EFrame { .. } => 42,
+            Self::Common { source } => source.exit_code(),
         }
     }
-}
 
-impl<T> From<T> for Box<ErrWrapper> where T: Into<ErrKind> {
-    #[instrument(skip_all)]
-    fn from(error: T) -> Self {
-        Box::new(ErrWrapper {
-            source:    error.into(),
-            spantrace: tracing_error::SpanTrace::capture(),
-            // backtrace: backtrace::Backtrace::capture(),
-        })
+    fn suggestion(&self) -> Option<std::borrow::Cow<'static, str>> {
+        match self {
+            Self::InputNoColon { .. } | Self::InputNoLines { .. } => None,
+            Self::EFrame { .. } =>
+                Some("is a display available? (`DISPLAY`/`WAYLAND_DISPLAY`)".into()),
+            Self::Common { source } => source.suggestion(),
+        }
     }
 }
+utilities::common_error_from!(ErrKind);
+utilities::error_wrapper!(
+    /// [`ErrKind`] with the span trace & backtrace of where it was converted (`?`).
+    pub struct ErrWrapper(ErrKind);
+);
 
 pub trait ToOther {
     fn to_other(self) -> Box<ErrWrapper>;
 }
 impl<E> ToOther for E where E: Into<Box<dyn std::error::Error + Send + Sync>> {
-    #[instrument(skip_all)]
     fn to_other(self) -> Box<ErrWrapper> {
-        Box::new(ErrKind::OtherDynError { source: self.into(), }.into())
+        ErrKind::into_dyn_error(self).into()
     }
 }
@@ -1,11 +1,22 @@
+use std::process::ExitCode;
+
 use sample_egui::*;
-use utilities::activate_global_default_tracing_subscriber;
+use utilities::{LoggingConfigSource, ReportFormat, activate_global_default_tracing_subscriber};
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        // exit code per `ErrKind` variant (see `utilities::diagnostic`)
+        Err(error) => error.exit(ReportFormat::Text),
+    }
+}
 
-fn main() -> SampleResult<()> {
+fn run() -> SampleResult<()> {
     // #[cfg(debug_assertions)]
-    let _writer_guard: tracing_appender::non_blocking::WorkerGuard =
+    let _writer_guard: utilities::SubscriberGuard =
         activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
                                                     .maybe_error_logging_level(None)
+                                                    .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
                                                     .call()?;
 
     let native_options =
@@ -0,0 +1,89 @@
+//! Local stand-in for the httpbin & jsonplaceholder endpoints used by the sample binaries.
+//!
+//! Serves (HTTP/1.1, plain `http`) on a local port from within the calling process's tokio runtime.
+//! See [`routes`] for the supported endpoints.
+//!
+//! ## Use:
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let server = stand_in::StandInServer::start_local().await?;
+//! let todos = reqwest::get(format!("{}/todos?userId=1", server.base_url())).await?
+//!                                                                          .text()
+//!                                                                          .await?;
+//! # Ok(())
+//! # }
+//! ```
+pub mod routes;
+
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{io, net::SocketAddr};
+
+use hyper::{server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use tokio::{net::{TcpListener, ToSocketAddrs},
+            task::{JoinHandle, JoinSet}};
+use tracing::{Level as L, event, instrument};
+// ///////////////////////////////// [ server ] ///////////////////////////////// //
+/// A running stand-in server.  Stops serving (incl. open connections) when dropped.
+#[derive(Debug)]
+pub struct StandInServer {
+    local_addr: SocketAddr,
+    task:       JoinHandle<()>,
+}
+impl StandInServer {
+    /// Bind to `addr` and serve on a spawned task.
+    ///
+    /// ## Panics
+    /// If called outside of a tokio runtime.
+    pub async fn start(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let task = tokio::spawn(serve(listener));
+        event!(L::INFO, %local_addr, "stand-in server started");
+        Ok(Self { local_addr, task })
+    }
+
+    /// Bind to an OS-assigned port on `127.0.0.1`.
+    pub async fn start_local() -> io::Result<Self> {
+        Self::start(("127.0.0.1", 0)).await
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// e.g. `http://127.0.0.1:50123` (no trailing slash)
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.local_addr)
+    }
+}
+impl Drop for StandInServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Accept & serve connections until the task is cancelled.  (Connections are cancelled with it.)
+#[instrument(skip_all, fields(local_addr = ?listener.local_addr().ok()))]
+pub async fn serve(listener: TcpListener) {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, remote_addr)) => {
+                    connections.spawn(async move {
+                        let service = service_fn(move |request| routes::route(request, remote_addr));
+                        if let Err(error) = http1::Builder::new().serve_connection(TokioIo::new(stream), service)
+                                                                 .await
+                        {
+                            event!(L::DEBUG, %remote_addr, %error, "connection closed with error");
+                        }
+                    });
+                },
+                Err(error) => event!(L::WARN, %error, "failed to accept connection"),
+            },
+            // reap finished connections
+            Some(_) = connections.join_next(), if !connections.is_empty() => {},
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Run the stand-in server standalone, e.g. for `--base-url` in the sample binaries.
+//!
+//! `cargo run -p stand-in -- --port 8080`
+use std::{net::IpAddr, process::ExitCode};
+
+use clap::Parser;
+use tracing::{Level as L, event};
+use utilities::{CommonError, LoggingConfigSource, ReportFormat, Shutdown,
+                activate_global_default_tracing_subscriber};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Address to bind to
+    #[arg(long, default_value = "127.0.0.1")]
+    host: IpAddr,
+    /// Port to bind to (0: OS-assigned)
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+}
+utilities::error_wrapper!(
+    /// [`CommonError`] with the span trace & backtrace of where it was converted (`?`).
+    struct ErrWrapper(CommonError);
+);
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        // e.g. the port is taken
+        Err(error) => error.exit(ReportFormat::Text),
+    }
+}
+async fn run() -> Result<(), ErrWrapper> {
+    let _writer_guard =
+        activate_global_default_tracing_subscriber().maybe_default_logging_level(None)
+                                                    .maybe_error_logging_level(None)
+                                                    .logging_config(LoggingConfigSource::xdg(env!("CARGO_PKG_NAME")))
+                                                    .call()?;
+    let cli = Cli::parse();
+    let shutdown = Shutdown::new().with_ctrl_c();
+    let server = stand_in::StandInServer::start((cli.host, cli.port)).await?;
+    println!("stand-in serving on {}  (ctrl-c to stop)",
+             server.base_url());
+    shutdown.triggered().await;
+    event!(L::INFO, "shutting down");
+    Ok(())
+}
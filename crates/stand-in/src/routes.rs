@@ -0,0 +1,787 @@
+//! Request routing & handlers.
+//!
+//! | path             | stand-in for      | behavior                                                |
+//! | :--------------- | :---------------- | :------------------------------------------------------ |
+//! | `/get`           | httpbin           | echo of args, headers, origin & url                     |
+//! | `/headers`       | httpbin           | echo of headers                                         |
+//! | `/delay/{n}`     | httpbin           | as `/get`, after `n` seconds (capped at [`MAX_DELAY`])  |
+//! | `/json`          | httpbin           | fixed JSON document                                     |
+//! | `/status/{code}` | httpbin           | empty response with that status (`200..=599`)           |
+//! | `/range/{n}`     | httpbin           | `n` bytes (`a`..`z`, repeating; max [`MAX_RANGE_BYTES`]) |
+//! | `/users`         | jsonplaceholder   | [`USER_COUNT`] fixed users                              |
+//! | `/posts`         | jsonplaceholder   | [`POST_COUNT`] fixed posts (10 per user)                |
+//! | `/comments`      | jsonplaceholder   | [`COMMENT_COUNT`] fixed comments (5 per post)           |
+//! | `/todos`         | jsonplaceholder   | [`TODO_COUNT`] fixed todos (20 per user)                |
+//!
+//! httpbin paths accept any method (as with httpbin's `/anything`); unknown paths are `404`.
+//!
+//! ## `/range/{n}`
+//! - honours a single `Range: bytes=<start>-[<end>]` (or `bytes=-<suffix>`): `206` with `content-range`, or `416`
+//! - `?chunk_size=<bytes>` (default 10 KiB) & `?duration=<seconds>`: streamed in chunks, spread over `duration`
+//!
+//! ## jsonplaceholder collections
+//! - `GET /{collection}`: filtered by any field (`?userId=1&completed=false`: exact match on the field's text)
+//!   - `_page` (1-based) & `_limit` (default 10 with `_page`) paginate; `x-total-count` is then the filtered total
+//! - `GET /{collection}/{id}`: the item, or `404` with `{}`
+//! - `POST /{collection}`: `201`, the body echoed with the next id
+//! - `PUT`/`PATCH /{collection}/{id}`: the body (replacing / merged into the item), with the path's id
+//! - `DELETE /{collection}/{id}`: `{}`
+//!
+//! As with jsonplaceholder, writes are faked: nothing is stored.
+//!
+//! `GET`s of collections & items carry a weak `etag` & `cache-control: max-age=`[`CACHE_MAX_AGE`];
+//! a matching `if-none-match` gets an empty `304`.
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap,
+          convert::Infallible,
+          hash::{DefaultHasher, Hash as _, Hasher as _},
+          net::SocketAddr,
+          time::Duration};
+
+use bytes::Bytes;
+use futures::stream;
+use http_body_util::{BodyExt as _, Full, StreamBody, combinators::BoxBody};
+use hyper::{Method, Request, Response, StatusCode,
+            body::{Frame, Incoming},
+            header};
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use tracing::{Level as L, event};
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+/// httpbin caps delays at 10s
+pub const MAX_DELAY: Duration = Duration::from_secs(10);
+/// httpbin caps `/range/{n}` at 100 KiB
+pub const MAX_RANGE_BYTES: usize = 100 * 1024;
+/// httpbin's `/range` chunk size, without `chunk_size`
+const DEFAULT_RANGE_CHUNK: usize = 10 * 1024;
+/// jsonplaceholder serves 10 users
+pub const USER_COUNT: u32 = 10;
+/// jsonplaceholder serves 100 posts (10 per user)
+pub const POST_COUNT: u32 = 100;
+const POSTS_PER_USER: u32 = 10;
+/// jsonplaceholder serves 500 comments (5 per post)
+pub const COMMENT_COUNT: u32 = 500;
+const COMMENTS_PER_POST: u32 = 5;
+/// jsonplaceholder serves 200 todos (20 per user, 10 users)
+pub const TODO_COUNT: u32 = 200;
+const TODOS_PER_USER: u32 = 20;
+/// json-server's page size, when `_page` is given without `_limit`
+const DEFAULT_PAGE_LIMIT: usize = 10;
+const COLLECTIONS: [&str; 4] = ["users", "posts", "comments", "todos"];
+/// Seconds jsonplaceholder `GET`s may be cached for
+pub const CACHE_MAX_AGE: u64 = 10;
+// ///////////////////////////////// [ routing ] ///////////////////////////////// //
+pub type StandInResponse = Response<BoxBody<Bytes, Infallible>>;
+
+/// Route a request to its handler.  Never fails: errors are expressed as HTTP statuses.
+pub async fn route(request: Request<Incoming>,
+                   remote_addr: SocketAddr)
+                   -> Result<StandInResponse, Infallible> {
+    let echo = Echo::from_request(&request, remote_addr);
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let if_none_match = request.headers()
+                               .get(header::IF_NONE_MATCH)
+                               .cloned();
+    let range = request.headers()
+                       .get(header::RANGE)
+                       .and_then(|value| value.to_str().ok())
+                       .map(str::to_string);
+    let segments: Vec<&str> = uri.path()
+                                 .trim_matches('/')
+                                 .split('/')
+                                 .collect();
+    // a body that fails to arrive is treated as empty
+    let body = request.into_body()
+                      .collect()
+                      .await
+                      .map(|body| body.to_bytes())
+                      .unwrap_or_default();
+    let response = match segments.as_slice() {
+        ["get"] => json_response(StatusCode::OK, &echo),
+        ["headers"] => json_response(StatusCode::OK, &json!({ "headers": echo.headers })),
+        ["delay", seconds] => match seconds.parse::<u64>() {
+            Ok(seconds) => {
+                tokio::time::sleep(delay_duration(seconds)).await;
+                json_response(StatusCode::OK, &echo)
+            },
+            Err(_) => status_response(StatusCode::BAD_REQUEST),
+        },
+        ["json"] => json_response(StatusCode::OK, &slideshow()),
+        // no `1xx`: informational, not a final response
+        ["status", code] => match code.parse::<u16>()
+                                      .ok()
+                                      .filter(|code| (200..=599).contains(code))
+                                      .and_then(|code| StatusCode::from_u16(code).ok())
+        {
+            Some(status) => status_response(status),
+            None => status_response(StatusCode::BAD_REQUEST),
+        },
+        ["range", bytes] => match bytes.parse::<usize>() {
+            Ok(bytes) if bytes <= MAX_RANGE_BYTES =>
+                range_response(bytes, range.as_deref(), &echo.args),
+            _ => status_response(StatusCode::BAD_REQUEST),
+        },
+        [collection, id @ ..] if COLLECTIONS.contains(collection) && id.len() <= 1 =>
+            with_validators(jsonplaceholder(&method,
+                                            collection,
+                                            id.first().copied(),
+                                            &echo.args,
+                                            &body),
+                            &method,
+                            &uri,
+                            if_none_match.as_ref()),
+        _ => status_response(StatusCode::NOT_FOUND),
+    };
+    event!(L::DEBUG,
+           %method,
+           %uri,
+           status = %response.status(),
+           "stand-in request");
+    Ok(response)
+}
+// ///////////////////////////////// [ httpbin ] ///////////////////////////////// //
+/// httpbin-style request echo.
+#[derive(Debug, Serialize)]
+struct Echo {
+    args:    BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    origin:  String,
+    url:     String,
+}
+impl Echo {
+    fn from_request(request: &Request<Incoming>, remote_addr: SocketAddr) -> Self {
+        let args = request.uri()
+                          .query()
+                          .map(|query| {
+                              url::form_urlencoded::parse(query.as_bytes()).into_owned()
+                                                                           .collect()
+                          })
+                          .unwrap_or_default();
+        let headers = request.headers()
+                             .iter()
+                             .map(|(name, value)| {
+                                 (name.to_string(),
+                                  String::from_utf8_lossy(value.as_bytes()).into_owned())
+                             })
+                             .collect();
+        let host = request.headers()
+                          .get(header::HOST)
+                          .and_then(|host| host.to_str().ok())
+                          .unwrap_or("localhost");
+        Self { args,
+               headers,
+               origin: remote_addr.ip().to_string(),
+               url: format!("http://{}{}", host, request.uri()) }
+    }
+}
+
+/// `/delay/{n}`'s wait: `n` seconds, clamped to [`MAX_DELAY`].
+fn delay_duration(seconds: u64) -> Duration {
+    Duration::from_secs(seconds).min(MAX_DELAY)
+}
+
+/// httpbin's `/json` document.
+fn slideshow() -> JsonValue {
+    json!({
+        "slideshow": {
+            "author": "Yours Truly",
+            "date": "date of publication",
+            "slides": [
+                { "title": "Wake up to WonderWidgets!", "type": "all" },
+                { "items": ["Why <em>WonderWidgets</em> are great", "Who <em>buys</em> WonderWidgets"],
+                  "title": "Overview",
+                  "type": "all" }
+            ],
+            "title": "Sample Slide Show"
+        }
+    })
+}
+// ///////////////////////////////// [ jsonplaceholder ] ///////////////////////////////// //
+/// jsonplaceholder's user shape (minus `address` & `company`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct User {
+    pub id:       u32,
+    pub name:     String,
+    pub username: String,
+    pub email:    String,
+    pub phone:    String,
+    pub website:  String,
+}
+/// jsonplaceholder's post shape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Post {
+    pub user_id: u32,
+    pub id:      u32,
+    pub title:   String,
+    pub body:    String,
+}
+/// jsonplaceholder's comment shape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub post_id: u32,
+    pub id:      u32,
+    pub name:    String,
+    pub email:   String,
+    pub body:    String,
+}
+/// jsonplaceholder's todo shape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Todo {
+    pub user_id:   u32,
+    pub id:        u32,
+    pub title:     String,
+    pub completed: bool,
+}
+
+// The fixed sets: deterministic, so tests can rely on them.
+pub fn users() -> impl Iterator<Item = User> {
+    (1..=USER_COUNT).map(|id| User { id,
+                                     name: format!("Stand-in User {id}"),
+                                     username: format!("user{id}"),
+                                     email: format!("user{id}@stand-in.test"),
+                                     phone: format!("555-01{id:02}"),
+                                     website: format!("user{id}.stand-in.test") })
+}
+pub fn posts() -> impl Iterator<Item = Post> {
+    (1..=POST_COUNT).map(|id| Post { user_id: (id - 1) / POSTS_PER_USER + 1,
+                                     id,
+                                     title: format!("stand-in post {id}"),
+                                     body: format!("body of stand-in post {id}") })
+}
+pub fn comments() -> impl Iterator<Item = Comment> {
+    (1..=COMMENT_COUNT).map(|id| Comment { post_id: (id - 1) / COMMENTS_PER_POST + 1,
+                                           id,
+                                           name: format!("stand-in comment {id}"),
+                                           email: format!("commenter{id}@stand-in.test"),
+                                           body: format!("body of stand-in comment {id}") })
+}
+pub fn todos() -> impl Iterator<Item = Todo> {
+    (1..=TODO_COUNT).map(|id| Todo { user_id: (id - 1) / TODOS_PER_USER + 1,
+                                     id,
+                                     title: format!("stand-in todo {id}"),
+                                     completed: id % 3 == 0 })
+}
+
+/// A collection's items, as JSON.
+fn collection_items(collection: &str) -> Vec<JsonValue> {
+    fn to_json(item: impl Serialize) -> JsonValue {
+        serde_json::to_value(item).expect("stand-in items should serialize")
+    }
+    match collection {
+        "users" => users().map(to_json).collect(),
+        "posts" => posts().map(to_json).collect(),
+        "comments" => comments().map(to_json)
+                                .collect(),
+        "todos" => todos().map(to_json).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Filter like json-server: each (non-`_`) query param must match its field's text exactly.
+/// Unknown fields & unparsable values match nothing.
+fn filter_items(items: Vec<JsonValue>, args: &BTreeMap<String, String>) -> Vec<JsonValue> {
+    let field_text = |value: &JsonValue| match value {
+        JsonValue::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    items.into_iter()
+         .filter(|item| {
+             args.iter()
+                 .filter(|(key, _)| !key.starts_with('_'))
+                 .all(|(key, expected)| {
+                     item.get(key)
+                         .is_some_and(|value| field_text(value) == *expected)
+                 })
+         })
+         .collect()
+}
+
+fn jsonplaceholder(method: &Method,
+                   collection: &str,
+                   id: Option<&str>,
+                   args: &BTreeMap<String, String>,
+                   body: &Bytes)
+                   -> StandInResponse {
+    let items = collection_items(collection);
+    let Some(id) = id else {
+        return match *method {
+            Method::GET => list_response(filter_items(items, args), args),
+            Method::POST => match json_object(body) {
+                Some(mut created) => {
+                    created.insert("id".into(), json!(items.len() + 1));
+                    json_response(StatusCode::CREATED, &created)
+                },
+                None => status_response(StatusCode::BAD_REQUEST),
+            },
+            _ => status_response(StatusCode::METHOD_NOT_ALLOWED),
+        };
+    };
+    let Some(item) = id.parse::<u64>()
+                       .ok()
+                       .and_then(|id| {
+                           items.into_iter()
+                                .find(|item| item["id"] == id)
+                       })
+    else {
+        return json_response(StatusCode::NOT_FOUND, &json!({}));
+    };
+    let with_id = |mut object: serde_json::Map<String, JsonValue>| {
+        object.insert("id".into(), item["id"].clone());
+        json_response(StatusCode::OK, &object)
+    };
+    match *method {
+        Method::GET => json_response(StatusCode::OK, &item),
+        Method::PUT =>
+            json_object(body).map_or_else(|| status_response(StatusCode::BAD_REQUEST), with_id),
+        Method::PATCH => match (item.as_object(), json_object(body)) {
+            (Some(existing), Some(patch)) => with_id(existing.clone()
+                                                             .into_iter()
+                                                             .chain(patch)
+                                                             .collect()),
+            _ => status_response(StatusCode::BAD_REQUEST),
+        },
+        Method::DELETE => json_response(StatusCode::OK, &json!({})),
+        _ => status_response(StatusCode::METHOD_NOT_ALLOWED),
+    }
+}
+
+/// Apply `_page`/`_limit`, if given.  (Only `_limit`: the first `_limit` items.)
+fn list_response(items: Vec<JsonValue>, args: &BTreeMap<String, String>) -> StandInResponse {
+    let arg = |key: &str| {
+        args.get(key)
+            .and_then(|value| value.parse::<usize>().ok())
+    };
+    let (page, limit) = match (arg("_page"), arg("_limit")) {
+        (None, None) => return json_response(StatusCode::OK, &items),
+        (page, limit) => (page.unwrap_or(1).max(1), limit.unwrap_or(DEFAULT_PAGE_LIMIT)),
+    };
+    let total = items.len();
+    let paged: Vec<_> = items.into_iter()
+                             .skip((page - 1) * limit)
+                             .take(limit)
+                             .collect();
+    let mut response = json_response(StatusCode::OK, &paged);
+    response.headers_mut()
+            .insert("x-total-count", total.into());
+    response
+}
+
+/// Add an `etag` & `cache-control` to a `200` for a `GET`; `304` (no body) if `if-none-match` already has it.
+///
+/// The data is fixed (writes are not stored), so the path & query alone determine the body: the etag hashes those.
+fn with_validators(mut response: StandInResponse,
+                   method: &Method,
+                   uri: &hyper::Uri,
+                   if_none_match: Option<&header::HeaderValue>)
+                   -> StandInResponse {
+    if *method != Method::GET || response.status() != StatusCode::OK {
+        return response;
+    }
+    let mut hasher = DefaultHasher::new();
+    uri.path_and_query()
+       .map(|path| path.as_str())
+       .hash(&mut hasher);
+    let etag = format!(r#"W/"{:016x}""#, hasher.finish());
+    let not_modified = if_none_match.and_then(|value| value.to_str().ok())
+                                    .is_some_and(|value| {
+                                        value.split(',')
+                                             .any(|tag| tag.trim() == etag || tag.trim() == "*")
+                                    });
+    if not_modified {
+        response = status_response(StatusCode::NOT_MODIFIED);
+    }
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG,
+                   etag.parse()
+                       .expect("hex etags should be valid header values"));
+    headers.insert(header::CACHE_CONTROL,
+                   format!("max-age={CACHE_MAX_AGE}").parse().expect("static cache-control should be a valid header value"));
+    response
+}
+
+fn json_object(body: &Bytes) -> Option<serde_json::Map<String, JsonValue>> {
+    match serde_json::from_slice(body).ok()? {
+        JsonValue::Object(object) => Some(object),
+        _ => None,
+    }
+}
+/// httpbin's `/range/{n}`: `a`..`z` repeating, ranges honoured, optionally streamed slowly.
+fn range_response(size: usize,
+                  range: Option<&str>,
+                  args: &BTreeMap<String, String>)
+                  -> StandInResponse {
+    let arg = |key: &str| {
+        args.get(key)
+            .and_then(|value| value.parse::<f64>().ok())
+    };
+    let (start, end) = match range.map(|range| parse_range(range, size)) {
+        None => (0, size),
+        Some(Some(bounds)) => bounds,
+        Some(None) => {
+            let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.headers_mut()
+                    .insert(header::CONTENT_RANGE,
+                            format!("bytes */{size}").parse().expect("numeric content-range should be a valid header value"));
+            return response;
+        },
+    };
+    let body: Bytes = (start..end).map(|i| b'a' + (i % 26) as u8)
+                                  .collect();
+    let chunk_size = arg("chunk_size").map_or(DEFAULT_RANGE_CHUNK, |size| (size as usize).max(1));
+    let chunks: Vec<Bytes> =
+        (0..body.len()).step_by(chunk_size)
+                       .map(|offset| body.slice(offset..(offset + chunk_size).min(body.len())))
+                       .collect();
+    let pause = Duration::from_secs_f64(arg("duration").unwrap_or(0.0)
+                                                       .clamp(0.0, MAX_DELAY.as_secs_f64()))
+                / chunks.len().max(1) as u32;
+    let frames = stream::unfold(chunks.into_iter(), move |mut chunks| async move {
+        let chunk = chunks.next()?;
+        tokio::time::sleep(pause).await;
+        Some((Ok(Frame::data(chunk)), chunks))
+    });
+    let mut builder = Response::builder().header(header::CONTENT_TYPE, "application/octet-stream")
+                                         .header(header::CONTENT_LENGTH, end - start)
+                                         .header(header::ACCEPT_RANGES, "bytes")
+                                         .header(header::ETAG, format!("range{size}"));
+    builder = match range {
+        Some(_) => builder.status(StatusCode::PARTIAL_CONTENT)
+                          .header(header::CONTENT_RANGE,
+                                  format!("bytes {start}-{}/{size}", end.saturating_sub(1))),
+        None => builder.status(StatusCode::OK),
+    };
+    builder.body(StreamBody::new(frames).boxed())
+           .expect("range response parts should be valid")
+}
+
+/// A single `bytes=` range, as `[start, end)`; `None` if unsatisfiable or malformed.
+fn parse_range(range: &str, size: usize) -> Option<(usize, usize)> {
+    let (first, last) = range.strip_prefix("bytes=")?
+                             .split_once('-')?;
+    let (start, end) = match (first.trim(), last.trim()) {
+        ("", suffix) => (size.saturating_sub(suffix.parse().ok()?), size),
+        (first, "") => (first.parse().ok()?, size),
+        (first, last) => (first.parse().ok()?,
+                          last.parse::<usize>()
+                              .ok()?
+                              .saturating_add(1)
+                              .min(size)),
+    };
+    (start < end).then_some((start, end))
+}
+
+// ///////////////////////////////// [ response helpers ] ///////////////////////////////// //
+fn json_response(status: StatusCode, body: &impl Serialize) -> StandInResponse {
+    let body = serde_json::to_vec_pretty(body).expect("stand-in response bodies should serialize");
+    Response::builder().status(status)
+                       .header(header::CONTENT_TYPE, "application/json")
+                       .body(Full::new(Bytes::from(body)).boxed())
+                       .expect("static response parts should be valid")
+}
+fn status_response(status: StatusCode) -> StandInResponse {
+    Response::builder().status(status)
+                       .body(Full::new(Bytes::new()).boxed())
+                       .expect("static response parts should be valid")
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::StandInServer;
+    #[test]
+    fn test_todos_fixed_set() {
+        let todos: Vec<_> = todos().collect();
+        assert_eq!(todos.len(), TODO_COUNT as usize);
+        assert_eq!(todos.iter()
+                        .filter(|todo| todo.user_id == 1)
+                        .count(),
+                   TODOS_PER_USER as usize);
+        assert_eq!(todos.last()
+                        .map(|todo| todo.user_id),
+                   Some(10));
+    }
+    #[test]
+    fn test_filter_items() {
+        let args = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs.iter()
+                 .map(|(k, v)| (k.to_string(), v.to_string()))
+                 .collect()
+        };
+        let filter_todos = |args| filter_items(collection_items("todos"), &args);
+        let user_2_open = filter_todos(args(&[("userId", "2"), ("completed", "false")]));
+        assert!(!user_2_open.is_empty());
+        assert!(user_2_open.iter()
+                           .all(|todo| todo["userId"] == 2 && todo["completed"] == false));
+        assert!(filter_todos(args(&[("userId", "not-a-number")])).is_empty());
+        assert!(filter_todos(args(&[("noSuchField", "1")])).is_empty());
+        // pagination params are not filters
+        assert_eq!(filter_todos(args(&[("_page", "2")])).len(),
+                   TODO_COUNT as usize);
+        assert_eq!(filter_todos(args(&[])).len(), TODO_COUNT as usize);
+    }
+    #[tokio::test]
+    async fn test_endpoints_over_http() {
+        let server = StandInServer::start_local().await
+                                                 .unwrap();
+        let base = server.base_url();
+        let client = reqwest::Client::new();
+
+        let get: JsonValue = client.get(format!("{base}/get"))
+                                   .query(&[("a", "1")])
+                                   .header("x-probe", "yes")
+                                   .send()
+                                   .await
+                                   .unwrap()
+                                   .json()
+                                   .await
+                                   .unwrap();
+        assert_eq!(get["args"]["a"], "1");
+        assert_eq!(get["headers"]["x-probe"], "yes");
+        assert_eq!(get["origin"], "127.0.0.1");
+
+        let headers: JsonValue = client.get(format!("{base}/headers"))
+                                       .header("x-probe", "yes")
+                                       .send()
+                                       .await
+                                       .unwrap()
+                                       .json()
+                                       .await
+                                       .unwrap();
+        assert_eq!(headers["headers"]["x-probe"], "yes");
+
+        let json: JsonValue = client.get(format!("{base}/json"))
+                                    .send()
+                                    .await
+                                    .unwrap()
+                                    .json()
+                                    .await
+                                    .unwrap();
+        assert_eq!(json["slideshow"]["title"], "Sample Slide Show");
+
+        for code in [200, 418, 503] {
+            let response = client.get(format!("{base}/status/{code}"))
+                                 .send()
+                                 .await
+                                 .unwrap();
+            assert_eq!(response.status().as_u16(), code);
+        }
+        for code in ["nope", "100", "600"] {
+            let bad_status = client.get(format!("{base}/status/{code}"))
+                                   .send()
+                                   .await
+                                   .unwrap();
+            assert_eq!(bad_status.status(), StatusCode::BAD_REQUEST, "{code}");
+        }
+
+        let todos: Vec<JsonValue> = client.get(format!("{base}/todos"))
+                                          .query(&[("userId", "1"), ("completed", "false")])
+                                          .send()
+                                          .await
+                                          .unwrap()
+                                          .json()
+                                          .await
+                                          .unwrap();
+        assert!(!todos.is_empty());
+        assert!(todos.iter()
+                     .all(|todo| todo["userId"] == 1 && todo["completed"] == false));
+
+        let missing = client.get(format!("{base}/nowhere"))
+                            .send()
+                            .await
+                            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+    #[tokio::test]
+    async fn test_etag_not_modified() {
+        let server = StandInServer::start_local().await
+                                                 .unwrap();
+        let url = format!("{}/todos?userId=1", server.base_url());
+        let client = reqwest::Client::new();
+        let first = client.get(&url)
+                          .send()
+                          .await
+                          .unwrap();
+        assert_eq!(first.headers()[header::CACHE_CONTROL],
+                   format!("max-age={CACHE_MAX_AGE}"));
+        let etag = first.headers()[header::ETAG].clone();
+        assert!(etag.to_str()
+                    .unwrap()
+                    .starts_with("W/\""));
+
+        let revalidated = client.get(&url)
+                                .header(header::IF_NONE_MATCH, etag.clone())
+                                .send()
+                                .await
+                                .unwrap();
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers()[header::ETAG], etag);
+        assert!(revalidated.bytes()
+                           .await
+                           .unwrap()
+                           .is_empty());
+
+        // another query is another body (& etag); writes carry none
+        let other = client.get(format!("{}/todos?userId=2", server.base_url()))
+                          .header(header::IF_NONE_MATCH, etag)
+                          .send()
+                          .await
+                          .unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+        let deleted = client.delete(format!("{}/todos/1", server.base_url()))
+                            .send()
+                            .await
+                            .unwrap();
+        assert!(!deleted.headers()
+                        .contains_key(header::ETAG));
+    }
+    #[tokio::test]
+    async fn test_range_bytes_and_ranges() {
+        let server = StandInServer::start_local().await
+                                                 .unwrap();
+        let url = format!("{}/range/30", server.base_url());
+        let client = reqwest::Client::new();
+        let full = client.get(format!("{url}?chunk_size=7"))
+                         .send()
+                         .await
+                         .unwrap();
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.content_length(), Some(30));
+        assert_eq!(full.text().await.unwrap(), "abcdefghijklmnopqrstuvwxyzabcd");
+
+        for (range, status, body, content_range) in [("bytes=26-", 206, "abcd", "bytes 26-29/30"),
+                                                     ("bytes=1-2", 206, "bc", "bytes 1-2/30"),
+                                                     ("bytes=-3", 206, "bcd", "bytes 27-29/30"),
+                                                     ("bytes=30-", 416, "", "bytes */30")]
+        {
+            let response = client.get(&url)
+                                 .header(header::RANGE, range)
+                                 .send()
+                                 .await
+                                 .unwrap();
+            assert_eq!(response.status().as_u16(), status, "{range}");
+            assert_eq!(response.headers()[header::CONTENT_RANGE],
+                       content_range,
+                       "{range}");
+            assert_eq!(response.text().await.unwrap(), body, "{range}");
+        }
+        let too_big = client.get(format!("{}/range/{}", server.base_url(), MAX_RANGE_BYTES + 1))
+                            .send()
+                            .await
+                            .unwrap();
+        assert_eq!(too_big.status(), StatusCode::BAD_REQUEST);
+    }
+    #[tokio::test]
+    async fn test_jsonplaceholder_crud_and_pages() {
+        let server = StandInServer::start_local().await
+                                                 .unwrap();
+        let base = server.base_url();
+        let client = reqwest::Client::new();
+
+        for (collection, count) in [("users", USER_COUNT),
+                                    ("posts", POST_COUNT),
+                                    ("comments", COMMENT_COUNT)]
+        {
+            let items: Vec<JsonValue> = client.get(format!("{base}/{collection}"))
+                                              .send()
+                                              .await
+                                              .unwrap()
+                                              .json()
+                                              .await
+                                              .unwrap();
+            assert_eq!(items.len(), count as usize, "{collection}");
+        }
+        let post: JsonValue = client.get(format!("{base}/posts/11"))
+                                    .send()
+                                    .await
+                                    .unwrap()
+                                    .json()
+                                    .await
+                                    .unwrap();
+        assert_eq!(post["userId"], 2);
+        let missing = client.get(format!("{base}/posts/999"))
+                            .send()
+                            .await
+                            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        // page 3 of user 1's 20 todos, 8 per page: the last 4
+        let page = client.get(format!("{base}/todos"))
+                         .query(&[("userId", "1"), ("_page", "3"), ("_limit", "8")])
+                         .send()
+                         .await
+                         .unwrap();
+        assert_eq!(page.headers()["x-total-count"], "20");
+        let page: Vec<JsonValue> = page.json().await.unwrap();
+        assert_eq!(page.iter()
+                       .map(|todo| todo["id"].as_u64().unwrap())
+                       .collect::<Vec<_>>(),
+                   [17, 18, 19, 20]);
+
+        let created = client.post(format!("{base}/todos"))
+                            .json(&json!({ "userId": 1, "title": "new", "completed": false }))
+                            .send()
+                            .await
+                            .unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+        let created: JsonValue = created.json().await.unwrap();
+        assert_eq!(created["id"], TODO_COUNT + 1);
+        assert_eq!(created["title"], "new");
+
+        let patched: JsonValue = client.patch(format!("{base}/todos/1"))
+                                       .json(&json!({ "completed": true }))
+                                       .send()
+                                       .await
+                                       .unwrap()
+                                       .json()
+                                       .await
+                                       .unwrap();
+        assert_eq!(patched,
+                   json!({ "userId": 1, "id": 1, "title": "stand-in todo 1", "completed": true }));
+        let replaced: JsonValue =
+            client.put(format!("{base}/todos/1"))
+                  .json(&json!({ "userId": 3, "title": "replaced", "completed": false }))
+                  .send()
+                  .await
+                  .unwrap()
+                  .json()
+                  .await
+                  .unwrap();
+        assert_eq!(replaced,
+                   json!({ "userId": 3, "id": 1, "title": "replaced", "completed": false }));
+        let deleted = client.delete(format!("{base}/todos/1"))
+                            .send()
+                            .await
+                            .unwrap();
+        assert_eq!(deleted.status(), StatusCode::OK);
+        // writes are faked
+        let unchanged: JsonValue = client.get(format!("{base}/todos/1"))
+                                         .send()
+                                         .await
+                                         .unwrap()
+                                         .json()
+                                         .await
+                                         .unwrap();
+        assert_eq!(unchanged["title"], "stand-in todo 1");
+    }
+    // real time (paused time doesn't mix with real sockets): a short delay over http, the cap directly
+    #[tokio::test]
+    async fn test_delay_waits_and_caps() {
+        let server = StandInServer::start_local().await
+                                                 .unwrap();
+        let start = std::time::Instant::now();
+        let response = reqwest::get(format!("{}/delay/1", server.base_url())).await
+                                                                             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(5),
+                "{elapsed:?}");
+        assert_eq!(delay_duration(60), MAX_DELAY);
+    }
+}
@@ -0,0 +1,845 @@
+//! Actors on tokio tasks: a mailbox, a run loop & typed replies.
+//! (after [Ryhl: Actors with Tokio](https://ryhl.io/blog/actors-with-tokio/))
+//!
+//! - [`Actor`]: the state, its `Message` type & handlers; lifecycle hooks [`started`](Actor::started) &
+//!   [`stopping`](Actor::stopping); an optional periodic [`tick`](Actor::tick) (see [`Context::set_interval`])
+//! - [`spawn`]: start one on its own task, with a bounded mailbox
+//! - [`ActorHandle`]: cheap to clone; [`tell`](ActorHandle::tell) (fire & forget) & [`ask`](ActorHandle::ask)
+//!   (request/response via a [`Reply`] carried in the message)
+//!   - a full mailbox is handled per [`Backpressure`]
+//! - [`ActorMetrics`]: mailbox depth, messages handled (per [`message_name`](Actor::message_name)) & how long they
+//!   took, ticks run & missed; kept by the run loop, read by the actor via [`Context::metrics`] (e.g. to answer
+//!   an `ask`)
+//!
+//! The actor stops once every handle is dropped, on [`ActorHandle::stop`] (after already queued messages),
+//! on [`Context::stop`], or when a handler returns an error.  `stopping` runs in every case.  Spawned with a
+//! [`Shutdown`], it also stops once that is triggered: no new messages, the queued ones are handled, no more ticks.
+//!
+//! ```
+//! use utilities::actor::{self, Actor, Context, Reply};
+//!
+//! struct Counter(u64);
+//! enum CounterMsg {
+//!     Add(u64),
+//!     Get(Reply<u64>),
+//! }
+//! impl Actor for Counter {
+//!     type Error = std::convert::Infallible;
+//!     type Message = CounterMsg;
+//!
+//!     async fn handle(&mut self,
+//!                     msg: CounterMsg,
+//!                     _ctx: &mut Context<Self>)
+//!                     -> Result<(), Self::Error> {
+//!         match msg {
+//!             CounterMsg::Add(n) => self.0 += n,
+//!             CounterMsg::Get(reply) => reply.send(self.0),
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), actor::ActorError> {
+//! let (counter, _task) = actor::spawn(Counter(0)).name("counter")
+//!                                                .call();
+//! counter.tell(CounterMsg::Add(2))
+//!        .await?;
+//! assert_eq!(counter.ask(CounterMsg::Get)
+//!                   .await?,
+//!            2);
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap, fmt, future::Future, sync::Arc, time::Duration};
+
+use bon::builder;
+use thiserror::Error;
+use tokio::{select,
+            sync::{mpsc, oneshot},
+            task::JoinHandle,
+            time::{Instant, Interval, MissedTickBehavior}};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument as _, Level as L, event, info_span};
+
+use crate::Shutdown;
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+/// Failure to deliver a message to (or get a reply from) an actor.
+#[derive(Debug, Error)]
+pub enum ActorError {
+    #[error("Actor `{actor}` has stopped")]
+    Stopped { actor: Arc<str> },
+    #[error("Mailbox of actor `{actor}` is full")]
+    MailboxFull { actor: Arc<str> },
+    #[error("Actor `{actor}` did not respond within {timeout:?}")]
+    Timeout {
+        actor:   Arc<str>,
+        timeout: Duration,
+    },
+    #[error("Actor `{actor}` dropped the request without replying")]
+    NoReply { actor: Arc<str> },
+}
+// ///////////////////////////////// [ actor ] ///////////////////////////////// //
+/// Shortest tick period (see [`Context::set_interval`]).
+pub const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// State owned by one task, changed only by handling its messages (one at a time).
+///
+/// Handlers are `async fn`s; their futures must be `Send` (the actor runs on a spawned task).
+pub trait Actor: Send + Sized + 'static {
+    /// Usually an enum; requests that want an answer carry a [`Reply`].
+    type Message: Send + 'static;
+    /// Returned by a handler: stops the actor (after [`stopping`](Actor::stopping)).
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Before the first message.  (e.g. [`Context::set_interval`])
+    fn started(&mut self,
+               _ctx: &mut Context<Self>)
+               -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    fn handle(&mut self,
+              msg: Self::Message,
+              ctx: &mut Context<Self>)
+              -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// On each tick of the interval set with [`Context::set_interval`].
+    fn tick(&mut self,
+            _ctx: &mut Context<Self>)
+            -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// After the last message, however the actor stops.  (Messages still queued are dropped, unless a
+    /// [`Supervisor`](crate::supervisor::Supervisor) restarts it: then they go to the next instance.)
+    fn stopping(&mut self, _ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Label for `msg` in [`ActorMetrics::handled`], e.g. its variant name.  (Default: the message type's name.)
+    fn message_name(_msg: &Self::Message) -> &'static str {
+        short_type_name::<Self::Message>()
+    }
+
+    /// Restarted by a [`Supervisor`](crate::supervisor::Supervisor): `self` is a fresh instance (before its
+    /// `started`), `failed` the one it replaces, as it was when it stopped (possibly mid-update, after a panic).
+    /// Carry over whatever should survive.  (Default: nothing.)
+    fn recover(&mut self, _failed: Self) {}
+}
+
+/// An actor's view of its own run loop.
+pub struct Context<A: Actor> {
+    name:               Arc<str>,
+    sender:             mpsc::WeakSender<Envelope<A::Message>>,
+    interval:           Option<Interval>,
+    stop:               bool,
+    cancel:             CancellationToken,
+    pub(crate) metrics: ActorMetrics,
+}
+impl<A: Actor> Context<A> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// As of the message being handled (or the last one, in a tick).
+    pub fn metrics(&self) -> &ActorMetrics {
+        &self.metrics
+    }
+
+    /// The [`Shutdown`] it was spawned with has been triggered: the actor is draining its mailbox.
+    pub fn is_shutting_down(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// A handle to this actor (e.g. to pass to another actor), unless it is already stopping.
+    ///
+    /// The context holds no handle itself: otherwise the mailbox would never close.
+    pub fn handle(&self, backpressure: Backpressure) -> Option<ActorHandle<A>> {
+        Some(ActorHandle { name: self.name.clone(),
+                           sender: self.sender.upgrade()?,
+                           backpressure })
+    }
+
+    /// Call [`Actor::tick`] every `period` (first tick: one `period` from now), or stop ticking (`None`).
+    /// Ticks missed while handling messages are skipped, not bunched up.  A zero period is raised to
+    /// [`MIN_INTERVAL`].
+    pub fn set_interval(&mut self, period: Option<Duration>) {
+        self.interval =
+            period.map(|period| {
+                      let period = period.max(MIN_INTERVAL);
+                      let mut interval =
+                          tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                      interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                      interval
+                  });
+    }
+
+    /// Stop after the current handler returns.
+    pub fn stop(&mut self) {
+        self.stop = true;
+    }
+}
+
+/// Answer to an [`ActorHandle::ask`], sent by the actor's handler.
+pub struct Reply<R>(oneshot::Sender<R>);
+impl<R> Reply<R> {
+    /// (If the asker has gone, e.g. timed out, the value is dropped.)
+    pub fn send(self, value: R) {
+        let _ = self.0.send(value);
+    }
+}
+impl<R> fmt::Debug for Reply<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reply")
+         .field("asker_waiting", &!self.0.is_closed())
+         .finish()
+    }
+}
+// ///////////////////////////////// [ metrics ] ///////////////////////////////// //
+/// Counters kept by an actor's run loop (across [`Supervisor`](crate::supervisor::Supervisor) restarts).
+#[derive(Debug, Clone)]
+pub struct ActorMetrics {
+    /// Messages still queued when the current one was taken.
+    pub mailbox_depth:     usize,
+    /// Highest `mailbox_depth` so far.
+    pub max_mailbox_depth: usize,
+    pub mailbox_capacity:  usize,
+    /// Per [`Actor::message_name`].
+    pub handled:           BTreeMap<&'static str, MessageStats>,
+    /// Ticks run.
+    pub ticks:             u64,
+    /// Ticks skipped because a handler (or tick) overran the period (see [`Context::set_interval`]).
+    pub ticks_missed:      u64,
+    /// By a supervisor.
+    pub restarts:          u32,
+    pub since:             Instant,
+}
+/// Handling time of one kind of message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max:   Duration,
+}
+impl ActorMetrics {
+    fn new(mailbox_capacity: usize) -> Self {
+        Self { mailbox_depth: 0,
+               max_mailbox_depth: 0,
+               mailbox_capacity,
+               handled: BTreeMap::new(),
+               ticks: 0,
+               ticks_missed: 0,
+               restarts: 0,
+               since: Instant::now() }
+    }
+
+    /// Messages of all kinds.
+    pub fn total_handled(&self) -> u64 {
+        self.handled
+            .values()
+            .map(|stats| stats.count)
+            .sum()
+    }
+
+    /// Messages handled per second, since the actor was spawned.
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.since
+                          .elapsed()
+                          .as_secs_f64();
+        match elapsed > 0.0 {
+            true => self.total_handled() as f64 / elapsed,
+            false => 0.0,
+        }
+    }
+
+    fn dequeued(&mut self, still_queued: usize) {
+        self.mailbox_depth = still_queued;
+        self.max_mailbox_depth = self.max_mailbox_depth
+                                     .max(still_queued);
+    }
+
+    fn handled(&mut self, name: &'static str, took: Duration) {
+        let stats = self.handled
+                        .entry(name)
+                        .or_default();
+        stats.count += 1;
+        stats.total += took;
+        stats.max = stats.max.max(took);
+    }
+
+    /// A tick due at `scheduled`: every whole `period` it ran late by was skipped.
+    fn ticked(&mut self, scheduled: Instant, period: Duration) {
+        self.ticks += 1;
+        let late = scheduled.elapsed();
+        self.ticks_missed += (late.as_nanos() / period.as_nanos().max(1)) as u64;
+    }
+}
+impl MessageStats {
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n =>
+                self.total
+                / n.try_into()
+                   .unwrap_or(u32::MAX),
+        }
+    }
+}
+// ///////////////////////////////// [ handle ] ///////////////////////////////// //
+/// What a [`tell`](ActorHandle::tell) does when the mailbox is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for space: slows senders down to the actor's pace.
+    #[default]
+    Wait,
+    /// Wait at most this long, then fail with [`ActorError::MailboxFull`].
+    Timeout(Duration),
+    /// Fail at once with [`ActorError::MailboxFull`]: for senders that must not block (e.g. a UI thread).
+    Reject,
+}
+
+pub(crate) enum Envelope<M> {
+    Message(M),
+    Stop,
+}
+
+/// Sends to one actor.  Cheap to clone; the actor stops once every handle is dropped.
+pub struct ActorHandle<A: Actor> {
+    name:         Arc<str>,
+    sender:       mpsc::Sender<Envelope<A::Message>>,
+    backpressure: Backpressure,
+}
+impl<A: Actor> Clone for ActorHandle<A> {
+    fn clone(&self) -> Self {
+        Self { name:         self.name.clone(),
+               sender:       self.sender.clone(),
+               backpressure: self.backpressure, }
+    }
+}
+impl<A: Actor> fmt::Debug for ActorHandle<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActorHandle")
+         .field("name", &self.name)
+         .field("backpressure", &self.backpressure)
+         .field("stopped", &self.is_stopped())
+         .finish()
+    }
+}
+impl<A: Actor> ActorHandle<A> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The same actor, with another [`Backpressure`] policy for sends via this handle.
+    pub fn with_backpressure(&self, backpressure: Backpressure) -> Self {
+        Self { backpressure,
+               ..self.clone() }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Queue a message.  A full mailbox is handled per this handle's [`Backpressure`].
+    pub async fn tell(&self, msg: A::Message) -> Result<(), ActorError> {
+        self.deliver(Envelope::Message(msg))
+            .await
+    }
+
+    /// Send a request & wait for the [`Reply`]: `make` builds the message around it.
+    ///
+    /// ```ignore
+    /// let count = printer.ask(|reply| PrinterMsg::GetPrintCount { reply }).await?;
+    /// ```
+    pub async fn ask<R>(&self, make: impl FnOnce(Reply<R>) -> A::Message) -> Result<R, ActorError> {
+        let (reply, answer) = oneshot::channel();
+        self.tell(make(Reply(reply)))
+            .await?;
+        answer.await
+              .map_err(|_| ActorError::NoReply { actor: self.name.clone(), })
+    }
+
+    /// As [`ask`](Self::ask), failing with [`ActorError::Timeout`] if delivery & reply take longer than `timeout`.
+    pub async fn ask_timeout<R>(&self,
+                                make: impl FnOnce(Reply<R>) -> A::Message,
+                                timeout: Duration)
+                                -> Result<R, ActorError> {
+        tokio::time::timeout(timeout, self.ask(make)).await
+                                                     .map_err(|_| ActorError::Timeout { actor: self.name.clone(),
+                                                                                        timeout })?
+    }
+
+    /// Stop the actor once the messages already queued are handled.  (Waits for mailbox space, whatever the policy.)
+    pub async fn stop(&self) -> Result<(), ActorError> {
+        self.sender
+            .send(Envelope::Stop)
+            .await
+            .map_err(|_| self.stopped())
+    }
+
+    async fn deliver(&self, envelope: Envelope<A::Message>) -> Result<(), ActorError> {
+        use mpsc::error::{SendTimeoutError, TrySendError};
+        match self.backpressure {
+            Backpressure::Wait => self.sender
+                                      .send(envelope)
+                                      .await
+                                      .map_err(|_| self.stopped()),
+            Backpressure::Timeout(timeout) => match self.sender
+                                                        .send_timeout(envelope, timeout)
+                                                        .await
+            {
+                Ok(()) => Ok(()),
+                Err(SendTimeoutError::Timeout(_)) => Err(self.full()),
+                Err(SendTimeoutError::Closed(_)) => Err(self.stopped()),
+            },
+            Backpressure::Reject => match self.sender.try_send(envelope) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => Err(self.full()),
+                Err(TrySendError::Closed(_)) => Err(self.stopped()),
+            },
+        }
+    }
+
+    fn stopped(&self) -> ActorError {
+        ActorError::Stopped { actor: self.name.clone(), }
+    }
+
+    fn full(&self) -> ActorError {
+        event!(L::DEBUG, actor = %self.name, "mailbox full");
+        ActorError::MailboxFull { actor: self.name.clone(), }
+    }
+}
+// ///////////////////////////////// [ spawn & run ] ///////////////////////////////// //
+/// Start `actor` on a new task.  The task ends with the actor: `Err` if a handler failed.
+///
+/// - `name`: for errors & the `actor` tracing span (default: the type name)
+/// - `mailbox`: capacity; senders beyond it are subject to `backpressure`
+/// - `shutdown`: drain & stop when it's triggered; the task is one [`Shutdown::drain`] waits for
+///
+/// (To restart it on failure instead, spawn it as a [`Supervisor`](crate::supervisor::Supervisor) child.)
+#[builder]
+pub fn spawn<A: Actor>(#[builder(start_fn)] actor: A,
+                       #[builder(into)] name: Option<String>,
+                       #[builder(default = 16)] mailbox: usize,
+                       #[builder(default)] backpressure: Backpressure,
+                       shutdown: Option<Shutdown>)
+                       -> (ActorHandle<A>, JoinHandle<Result<(), A::Error>>) {
+    let (handle, receiver, ctx) = mailbox_for(name, mailbox, backpressure, shutdown.as_ref());
+    let span = info_span!("actor", name = %handle.name);
+    let run = run(actor, receiver, ctx).instrument(span);
+    let task = match shutdown {
+        Some(shutdown) => shutdown.spawn(run),
+        None => tokio::spawn(run),
+    };
+    (handle, task)
+}
+
+/// A mailbox, its handle & the context of the actor(s) that will read it.
+pub(crate) fn mailbox_for<A: Actor>(name: Option<String>,
+                                    capacity: usize,
+                                    backpressure: Backpressure,
+                                    shutdown: Option<&Shutdown>)
+                                    -> (ActorHandle<A>, Mailbox<A>, Context<A>) {
+    let name: Arc<str> = name.unwrap_or_else(|| short_type_name::<A>().to_owned())
+                             .into();
+    let (sender, receiver) = mpsc::channel(capacity);
+    let ctx = Context { name:     name.clone(),
+                        sender:   sender.downgrade(),
+                        interval: None,
+                        stop:     false,
+                        cancel:   shutdown.map_or_else(CancellationToken::new, Shutdown::token),
+                        metrics:  ActorMetrics::new(capacity), };
+    (ActorHandle { name,
+                   sender,
+                   backpressure },
+     receiver,
+     ctx)
+}
+pub(crate) type Mailbox<A> = mpsc::Receiver<Envelope<<A as Actor>::Message>>;
+
+async fn run<A: Actor>(mut actor: A,
+                       mut receiver: Mailbox<A>,
+                       mut ctx: Context<A>)
+                       -> Result<(), A::Error> {
+    event!(L::DEBUG, "actor starting");
+    let result = run_until_stop(&mut actor, &mut receiver, &mut ctx).await;
+    // no more sends: anything queued (incl. pending asks) is dropped with the receiver
+    receiver.close();
+    actor.stopping(&mut ctx).await;
+    match &result {
+        Ok(()) => event!(L::DEBUG, "actor stopped"),
+        Err(error) => event!(L::WARN, %error, "actor stopped by error"),
+    }
+    result
+}
+
+/// `started`, then messages & ticks until a stop or a handler error.  (Not `stopping`; the mailbox stays open,
+/// unless closed for a shutdown.)
+pub(crate) async fn run_until_stop<A: Actor>(actor: &mut A,
+                                             receiver: &mut Mailbox<A>,
+                                             ctx: &mut Context<A>)
+                                             -> Result<(), A::Error> {
+    ctx.interval = None;
+    ctx.stop = false;
+    let cancel = ctx.cancel.clone();
+    let mut draining = false;
+    actor.started(ctx).await?;
+    while !ctx.stop {
+        select! {
+            () = cancel.cancelled(), if !draining => {
+                // no new messages: `recv` returns those already queued, then `None`
+                event!(L::DEBUG, "shutdown: draining mailbox");
+                draining = true;
+                receiver.close();
+            },
+            envelope = receiver.recv() => match envelope {
+                Some(Envelope::Message(msg)) => {
+                    let name = A::message_name(&msg);
+                    ctx.metrics.dequeued(receiver.len());
+                    let start = Instant::now();
+                    let handled = actor.handle(msg, ctx).await;
+                    ctx.metrics.handled(name, start.elapsed());
+                    handled?
+                },
+                Some(Envelope::Stop) | None => break,
+            },
+            (scheduled, period) = next_tick(&mut ctx.interval), if !draining => {
+                ctx.metrics.ticked(scheduled, period);
+                actor.tick(ctx).await?
+            },
+        }
+    }
+    Ok(())
+}
+
+/// When the tick was due, & the period.  Never ready without an interval.
+async fn next_tick(interval: &mut Option<Interval>) -> (Instant, Duration) {
+    match interval {
+        Some(interval) => (interval.tick().await, interval.period()),
+        None => std::future::pending().await,
+    }
+}
+
+/// `my_crate::module::Thing<T>` -> `Thing<T>`
+pub(crate) fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let base = name.split('<')
+                   .next()
+                   .unwrap_or(name);
+    &name[base.rfind("::")
+              .map_or(0, |i| i + 2)..]
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[error("counter overflow")]
+    struct Overflow;
+
+    #[derive(Default)]
+    struct Counter {
+        count:  u8,
+        ticks:  u32,
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+    enum CounterMsg {
+        Add(u8),
+        Get(Reply<u8>),
+        Ticks(Reply<u32>),
+        Every(Option<Duration>),
+        Sleep(Duration),
+        StopSelf,
+        Metrics(Reply<ActorMetrics>),
+    }
+    impl Actor for Counter {
+        type Error = Overflow;
+        type Message = CounterMsg;
+
+        async fn started(&mut self, _ctx: &mut Context<Self>) -> Result<(), Overflow> {
+            self.events
+                .lock()
+                .unwrap()
+                .push("started");
+            Ok(())
+        }
+
+        async fn handle(&mut self,
+                        msg: CounterMsg,
+                        ctx: &mut Context<Self>)
+                        -> Result<(), Overflow> {
+            match msg {
+                CounterMsg::Add(n) =>
+                    self.count = self.count
+                                     .checked_add(n)
+                                     .ok_or(Overflow)?,
+                CounterMsg::Get(reply) => reply.send(self.count),
+                CounterMsg::Ticks(reply) => reply.send(self.ticks),
+                CounterMsg::Every(period) => ctx.set_interval(period),
+                CounterMsg::Sleep(duration) => tokio::time::sleep(duration).await,
+                CounterMsg::StopSelf => ctx.stop(),
+                CounterMsg::Metrics(reply) => reply.send(ctx.metrics().clone()),
+            }
+            Ok(())
+        }
+
+        fn message_name(msg: &CounterMsg) -> &'static str {
+            match msg {
+                CounterMsg::Sleep(_) => "Sleep",
+                CounterMsg::Metrics(_) => "Metrics",
+                _ => "other",
+            }
+        }
+
+        async fn tick(&mut self, _ctx: &mut Context<Self>) -> Result<(), Overflow> {
+            self.ticks += 1;
+            Ok(())
+        }
+
+        async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push("stopping");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tell_ask_and_lifecycle() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (counter, task) = spawn(Counter { events: events.clone(),
+                                              ..Default::default() }).call();
+        assert_eq!(counter.name(), "Counter");
+        counter.tell(CounterMsg::Add(2))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Add(3))
+               .await
+               .unwrap();
+        assert_eq!(counter.ask(CounterMsg::Get)
+                          .await
+                          .unwrap(),
+                   5);
+
+        drop(counter);
+        task.await.unwrap().unwrap();
+        assert_eq!(*events.lock().unwrap(), ["started", "stopping"]);
+    }
+
+    #[tokio::test]
+    async fn test_handler_error_stops_actor() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (counter, task) = spawn(Counter { events: events.clone(),
+                                              ..Default::default() }).name("overflowing")
+                                                                     .call();
+        counter.tell(CounterMsg::Add(200))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Add(100))
+               .await
+               .unwrap();
+        assert!(matches!(task.await.unwrap(), Err(Overflow)));
+        assert_eq!(*events.lock().unwrap(), ["started", "stopping"]);
+
+        assert!(counter.is_stopped());
+        assert!(matches!(counter.ask(CounterMsg::Get).await,
+                         Err(ActorError::Stopped { actor }) if &*actor == "overflowing"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_after_queued_messages() {
+        let (counter, task) = spawn(Counter::default()).call();
+        let other = counter.clone();
+        counter.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        counter.stop().await.unwrap();
+        task.await.unwrap().unwrap();
+        assert!(matches!(other.tell(CounterMsg::Add(1))
+                              .await,
+                         Err(ActorError::Stopped { .. })));
+
+        let (counter, task) = spawn(Counter::default()).call();
+        counter.tell(CounterMsg::StopSelf)
+               .await
+               .unwrap();
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ask_timeout_and_dropped_reply() {
+        let (counter, _task) = spawn(Counter::default()).call();
+        counter.tell(CounterMsg::Sleep(Duration::from_secs(10)))
+               .await
+               .unwrap();
+        let timeout = Duration::from_secs(1);
+        assert!(matches!(counter.ask_timeout(CounterMsg::Get, timeout).await,
+                         Err(ActorError::Timeout { timeout: t, .. }) if t == timeout));
+        // the late reply goes nowhere; the actor carries on
+        assert_eq!(counter.ask_timeout(CounterMsg::Get, Duration::from_secs(20))
+                          .await
+                          .unwrap(),
+                   0);
+
+        // stopped with the request still queued
+        counter.tell(CounterMsg::StopSelf)
+               .await
+               .unwrap();
+        assert!(matches!(counter.ask(CounterMsg::Get)
+                                .await,
+                         Err(ActorError::NoReply { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backpressure_policies() {
+        let (counter, _task) = spawn(Counter::default()).mailbox(1)
+                                                        .backpressure(Backpressure::Reject)
+                                                        .call();
+        // busy for 10s; then the one free slot is filled
+        counter.tell(CounterMsg::Sleep(Duration::from_secs(10)))
+               .await
+               .unwrap();
+        tokio::task::yield_now().await;
+        counter.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        assert!(matches!(counter.tell(CounterMsg::Add(1))
+                                .await,
+                         Err(ActorError::MailboxFull { .. })));
+
+        let waiting = counter.with_backpressure(Backpressure::Timeout(Duration::from_secs(1)));
+        assert!(matches!(waiting.tell(CounterMsg::Add(1))
+                                .await,
+                         Err(ActorError::MailboxFull { .. })));
+
+        let start = tokio::time::Instant::now();
+        let patient = counter.with_backpressure(Backpressure::Wait);
+        patient.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(8),
+                "{:?}",
+                start.elapsed());
+        assert_eq!(patient.ask(CounterMsg::Get)
+                          .await
+                          .unwrap(),
+                   2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_drains_queued_messages() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = Shutdown::new();
+        let (counter, task) = spawn(Counter { events: events.clone(),
+                                              ..Default::default() }).shutdown(shutdown.clone())
+                                                                     .call();
+        counter.tell(CounterMsg::Every(Some(Duration::from_millis(10))))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Sleep(Duration::from_millis(100)))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        // queued before the shutdown (mid-`Sleep`): still answered
+        let (count, drained) = tokio::join!(counter.ask(CounterMsg::Get), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            shutdown.drain(Duration::from_secs(1))
+                    .await
+        });
+        assert_eq!(count.unwrap(), 1);
+        assert!(drained.is_clean());
+        task.await.unwrap().unwrap();
+        assert_eq!(*events.lock().unwrap(), ["started", "stopping"]);
+        assert!(matches!(counter.tell(CounterMsg::Add(1))
+                                .await,
+                         Err(ActorError::Stopped { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval_ticks() {
+        let (counter, _task) = spawn(Counter::default()).call();
+        counter.tell(CounterMsg::Every(Some(Duration::from_millis(100))))
+               .await
+               .unwrap();
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert_eq!(counter.ask(CounterMsg::Ticks)
+                          .await
+                          .unwrap(),
+                   3);
+
+        counter.tell(CounterMsg::Every(None))
+               .await
+               .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(counter.ask(CounterMsg::Ticks)
+                          .await
+                          .unwrap(),
+                   3);
+
+        // zero: clamped, not a panic
+        counter.tell(CounterMsg::Every(Some(Duration::ZERO)))
+               .await
+               .unwrap();
+        tokio::time::sleep(MIN_INTERVAL * 5).await;
+        assert!(counter.ask(CounterMsg::Ticks)
+                       .await
+                       .unwrap()
+                > 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_metrics() {
+        let (counter, _task) = spawn(Counter::default()).mailbox(4)
+                                                        .call();
+        counter.tell(CounterMsg::Every(Some(Duration::from_millis(100))))
+               .await
+               .unwrap();
+        // overruns ticks due at 100ms, 200ms & 300ms: the first runs late (350ms), the others are skipped
+        counter.tell(CounterMsg::Sleep(Duration::from_millis(350)))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        counter.tell(CounterMsg::Add(1))
+               .await
+               .unwrap();
+        tokio::time::sleep(Duration::from_millis(450)).await;
+        let metrics = counter.ask(CounterMsg::Metrics)
+                             .await
+                             .unwrap();
+
+        assert_eq!(metrics.mailbox_capacity, 4);
+        assert_eq!(metrics.max_mailbox_depth, 3,
+                   "all sent before the actor first ran");
+        assert_eq!(metrics.mailbox_depth, 0);
+        assert_eq!(metrics.handled["Sleep"],
+                   MessageStats { count: 1,
+                                  total: Duration::from_millis(350),
+                                  max:   Duration::from_millis(350), });
+        assert_eq!(metrics.handled["other"].count, 3);
+        assert_eq!(metrics.handled.get("Metrics"),
+                   None,
+                   "recorded after it's handled");
+        assert_eq!(metrics.total_handled(), 4);
+        assert_eq!((metrics.ticks, metrics.ticks_missed),
+                   (2, 2),
+                   "ticks at 350ms & 400ms");
+        assert!((metrics.throughput() - 4.0 / 0.45).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name::<Counter>(), "Counter");
+        assert_eq!(short_type_name::<Vec<u8>>(), "Vec<u8>");
+    }
+}
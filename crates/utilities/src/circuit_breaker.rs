@@ -0,0 +1,393 @@
+//! Per-host circuit breakers: stop sending to a host that is failing, instead of waiting out every timeout.
+//!
+//! ## States
+//! - **closed**: calls pass; outcomes are kept for a rolling `window`
+//! - **open**: calls fail immediately with [`CircuitOpen`], for `open_for`
+//! - **half-open**: up to `half_open_probes` calls pass as probes; one success closes the circuit, one failure re-opens it
+//!
+//! A closed circuit opens when, within the window, there were at least `minimum_calls`
+//! and the failed fraction reached `failure_ratio`.
+//! Failures are transport errors (connect, timeout, ...) and `5xx` responses; anything else is a success.
+//!
+//! Transitions are logged: `WARN` on opening, `INFO` otherwise.
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::CircuitBreakers;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let breakers = CircuitBreakers::builder().build();
+//! let client = reqwest::Client::new();
+//! let request = client.get("https://httpbin.org/status/503")
+//!                     .build()?;
+//! let permit = breakers.try_acquire(request.url())?;
+//! let result = client.execute(request).await;
+//! permit.record_result(&result);
+//! println!("{:?}", breakers.states());
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::{BTreeMap, HashMap, VecDeque},
+          fmt,
+          sync::{Arc, Mutex},
+          time::Duration};
+
+use bon::bon;
+use reqwest::{Response, Url};
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{Level as L, event};
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+/// A call was short-circuited.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Circuit open for `{host}`: not sending (next probe in {retry_in:?})")]
+pub struct CircuitOpen {
+    pub host:     String,
+    /// Until the circuit goes half-open.  (Zero: half-open, but all probe slots are taken.)
+    pub retry_in: Duration,
+}
+// ///////////////////////////////// [ state ] ///////////////////////////////// //
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+                        Self::Closed => "closed",
+                        Self::Open => "open",
+                        Self::HalfOpen => "half-open",
+                    })
+    }
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state:           CircuitState,
+    /// (when, failed) -- closed state only
+    outcomes:        VecDeque<(Instant, bool)>,
+    opened_at:       Instant,
+    probes_inflight: usize,
+}
+impl Circuit {
+    fn new(now: Instant) -> Self {
+        Self { state:           CircuitState::Closed,
+               outcomes:        VecDeque::new(),
+               opened_at:       now,
+               probes_inflight: 0, }
+    }
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// Registry of per-host circuits; cheap to clone & shared across tasks.  See [module docs](self).
+#[derive(Clone)]
+pub struct CircuitBreakers {
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+    config:   BreakerConfig,
+}
+#[derive(Debug, Clone, Copy)]
+struct BreakerConfig {
+    window:           Duration,
+    minimum_calls:    usize,
+    failure_ratio:    f64,
+    open_for:         Duration,
+    half_open_probes: usize,
+}
+impl fmt::Debug for CircuitBreakers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreakers")
+         .field("config", &self.config)
+         .field("states", &self.states())
+         .finish()
+    }
+}
+#[bon]
+impl CircuitBreakers {
+    #[builder]
+    pub fn new(/// Outcomes older than this are forgotten.
+               #[builder(default = Duration::from_secs(30))]
+               window: Duration,
+               /// Calls (in the window) needed before the failure ratio is considered.
+               #[builder(default = 5)]
+               minimum_calls: usize,
+               /// Failed fraction (in the window) that opens the circuit.  (Clamped to `(0, 1]`.)
+               #[builder(default = 0.5)]
+               failure_ratio: f64,
+               /// Time spent open before probing.
+               #[builder(default = Duration::from_secs(10))]
+               open_for: Duration,
+               #[builder(default = 1)] half_open_probes: usize)
+               -> Self {
+        let config = BreakerConfig { window,
+                                     minimum_calls: minimum_calls.max(1),
+                                     failure_ratio: failure_ratio.clamp(f64::MIN_POSITIVE, 1.0),
+                                     open_for,
+                                     half_open_probes: half_open_probes.max(1) };
+        Self { circuits: Arc::default(),
+               config }
+    }
+}
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+impl CircuitBreakers {
+    /// Permission to call `url`'s host, or [`CircuitOpen`].
+    pub fn try_acquire(&self, url: &Url) -> Result<CircuitPermit, CircuitOpen> {
+        let host = host_key(url);
+        let now = Instant::now();
+        let mut circuits = self.lock();
+        let circuit = circuits.entry(host.clone())
+                              .or_insert_with(|| Circuit::new(now));
+        if circuit.state == CircuitState::Open {
+            let open_until = circuit.opened_at + self.config.open_for;
+            if now < open_until {
+                return Err(CircuitOpen { host,
+                                         retry_in: open_until - now });
+            }
+            transition(&host, circuit, CircuitState::HalfOpen);
+        }
+        let probe = circuit.state == CircuitState::HalfOpen;
+        if probe {
+            if circuit.probes_inflight >= self.config.half_open_probes {
+                return Err(CircuitOpen { host,
+                                         retry_in: Duration::ZERO });
+            }
+            circuit.probes_inflight += 1;
+        }
+        Ok(CircuitPermit { breakers: self.clone(),
+                           host,
+                           probe,
+                           recorded: false })
+    }
+
+    /// State for `url`'s host.  (Unknown hosts are closed.)
+    pub fn state(&self, url: &Url) -> CircuitState {
+        self.lock()
+            .get(&host_key(url))
+            .map_or(CircuitState::Closed, |circuit| circuit.state)
+    }
+
+    /// State of every host seen so far.
+    pub fn states(&self) -> BTreeMap<String, CircuitState> {
+        self.lock()
+            .iter()
+            .map(|(host, circuit)| (host.clone(), circuit.state))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Circuit>> {
+        self.circuits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn on_outcome(&self, host: &str, probe: bool, failed: Option<bool>) {
+        let now = Instant::now();
+        let mut circuits = self.lock();
+        let Some(circuit) = circuits.get_mut(host) else {
+            return;
+        };
+        if probe {
+            circuit.probes_inflight = circuit.probes_inflight
+                                             .saturating_sub(1);
+        }
+        let Some(failed) = failed else {
+            return;
+        };
+        match circuit.state {
+            CircuitState::HalfOpen if probe && failed => {
+                circuit.opened_at = now;
+                transition(host, circuit, CircuitState::Open);
+            },
+            CircuitState::HalfOpen if probe => {
+                circuit.outcomes.clear();
+                transition(host, circuit, CircuitState::Closed);
+            },
+            CircuitState::Closed => {
+                circuit.outcomes
+                       .push_back((now, failed));
+                while circuit.outcomes
+                             .front()
+                             .is_some_and(|(at, _)| now.duration_since(*at) > self.config.window)
+                {
+                    circuit.outcomes.pop_front();
+                }
+                let calls = circuit.outcomes.len();
+                let failures = circuit.outcomes
+                                      .iter()
+                                      .filter(|(_, failed)| *failed)
+                                      .count();
+                if calls >= self.config.minimum_calls
+                   && failures as f64 >= self.config.failure_ratio * calls as f64
+                {
+                    event!(L::DEBUG, host, calls, failures, "failure ratio reached");
+                    circuit.outcomes.clear();
+                    circuit.opened_at = now;
+                    transition(host, circuit, CircuitState::Open);
+                }
+            },
+            // (late result of a call permitted before the circuit opened)
+            CircuitState::HalfOpen | CircuitState::Open => {},
+        }
+    }
+}
+
+/// Permission for one call.  Report its outcome with [`record`](Self::record) or
+/// [`record_result`](Self::record_result); dropping it reports nothing (frees a half-open probe slot).
+#[derive(Debug)]
+#[must_use = "an unrecorded permit tells the breaker nothing"]
+pub struct CircuitPermit {
+    breakers: CircuitBreakers,
+    host:     String,
+    probe:    bool,
+    recorded: bool,
+}
+impl CircuitPermit {
+    pub fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breakers
+            .on_outcome(&self.host, self.probe, Some(!success));
+    }
+
+    /// Transport errors & `5xx` are failures.
+    pub fn record_result(self, result: &Result<Response, reqwest::Error>) {
+        let success = result.as_ref()
+                            .is_ok_and(|response| {
+                                !response.status()
+                                         .is_server_error()
+                            });
+        self.record(success);
+    }
+}
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breakers
+                .on_outcome(&self.host, self.probe, None);
+        }
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+/// `host:port` (port defaulted by scheme).
+fn host_key(url: &Url) -> String {
+    let host = url.host_str()
+                  .unwrap_or_default();
+    match url.port_or_known_default() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    }
+}
+
+fn transition(host: &str, circuit: &mut Circuit, to: CircuitState) {
+    let from = circuit.state;
+    circuit.state = to;
+    match to {
+        CircuitState::Open => event!(L::WARN, host, %from, %to, "circuit opened"),
+        _ => event!(L::INFO, host, %from, %to, "circuit state changed"),
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::testing::EventCapture;
+    fn url(host: &str) -> Url {
+        format!("https://{host}/x").parse()
+                                   .unwrap()
+    }
+    fn breakers() -> CircuitBreakers {
+        CircuitBreakers::builder().minimum_calls(4)
+                                  .failure_ratio(0.5)
+                                  .window(Duration::from_secs(10))
+                                  .open_for(Duration::from_secs(5))
+                                  .build()
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_opens_on_failure_ratio() {
+        let breakers = breakers();
+        let down = url("down.test");
+        for success in [true, false, true] {
+            breakers.try_acquire(&down)
+                    .unwrap()
+                    .record(success);
+        }
+        assert_eq!(breakers.state(&down),
+                   CircuitState::Closed,
+                   "below minimum_calls");
+        breakers.try_acquire(&down)
+                .unwrap()
+                .record(false);
+        assert_eq!(breakers.state(&down), CircuitState::Open);
+        let open = breakers.try_acquire(&down)
+                           .unwrap_err();
+        assert_eq!(open.host, "down.test:443");
+        assert_eq!(open.retry_in, Duration::from_secs(5));
+        // other hosts unaffected
+        assert!(breakers.try_acquire(&url("up.test"))
+                        .is_ok());
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_window_forgets_old_failures() {
+        let breakers = breakers();
+        let flaky = url("flaky.test");
+        for _ in 0..3 {
+            breakers.try_acquire(&flaky)
+                    .unwrap()
+                    .record(false);
+        }
+        tokio::time::advance(Duration::from_secs(11)).await;
+        for success in [true, true, true, false] {
+            breakers.try_acquire(&flaky)
+                    .unwrap()
+                    .record(success);
+        }
+        assert_eq!(breakers.state(&flaky), CircuitState::Closed);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_probe_closes_or_reopens() {
+        let capture = EventCapture::start();
+        let breakers = breakers();
+        let host = url("host.test");
+        for _ in 0..4 {
+            breakers.try_acquire(&host)
+                    .unwrap()
+                    .record(false);
+        }
+        tokio::time::advance(Duration::from_secs(5)).await;
+        // one probe at a time
+        let probe = breakers.try_acquire(&host)
+                            .unwrap();
+        assert_eq!(breakers.state(&host), CircuitState::HalfOpen);
+        assert_eq!(breakers.try_acquire(&host)
+                           .unwrap_err()
+                           .retry_in,
+                   Duration::ZERO);
+        probe.record(false);
+        assert_eq!(breakers.state(&host), CircuitState::Open);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        // an unrecorded probe frees its slot without closing
+        drop(breakers.try_acquire(&host)
+                     .unwrap());
+        assert_eq!(breakers.state(&host), CircuitState::HalfOpen);
+        breakers.try_acquire(&host)
+                .unwrap()
+                .record(true);
+        assert_eq!(breakers.state(&host), CircuitState::Closed);
+
+        let events = capture.events();
+        let opened = events.iter()
+                           .filter(|event| event.message.as_deref() == Some("circuit opened"))
+                           .count();
+        assert_eq!(opened, 2);
+        assert!(events.iter()
+                      .any(|event| event.field("to") == Some("closed")));
+    }
+}
@@ -0,0 +1,528 @@
+//! Adaptive concurrency limit: AIMD with a Vegas-style latency signal.
+//!
+//! A drop-in for a fixed `Semaphore::new(N)` or `buffer_unordered(N)`, where `N` is a guess.
+//!
+//! ## Adjustment
+//! - **increase** (additive): `+1` after a full limit's worth of healthy successes (i.e. roughly once per round trip)
+//! - **decrease** (multiplicative): `limit * backoff_ratio` on an overload signal (timeout, `429`, `503`),
+//!   or when smoothed latency exceeds `latency_tolerance` × the baseline (lowest recent) latency
+//! - decreases are spaced at least one smoothed latency apart, so one burst of failures counts once
+//! - the limit stays within `[min_limit, max_limit]`
+//!
+//! Lowering the limit never cancels in-flight work: surplus permits are retired as they are released.
+//!
+//! ## Use:
+//! ```no_run
+//! use futures::StreamExt as _;
+//! use utilities::{AdaptiveLimiter, AdaptiveStreamExt as _, LimiterOutcome};
+//!
+//! # async fn run(urls: Vec<String>) {
+//! let limiter = AdaptiveLimiter::builder().max_limit(32)
+//!                                         .build();
+//! let client = reqwest::Client::new();
+//! let mut responses =
+//!     futures::stream::iter(urls).map(|url| client.get(url).send())
+//!                                .buffer_adaptive(limiter.clone(), LimiterOutcome::from_result);
+//! while let Some(response) = responses.next().await {
+//!     println!("limit: {} -> {:?}",
+//!              limiter.limit(),
+//!              response.map(|r| r.status()));
+//! }
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{pin::Pin,
+          sync::{Arc, Mutex},
+          task::{Context, Poll, ready},
+          time::Duration};
+
+use bon::bon;
+use futures::{Stream, StreamExt as _,
+              stream::{Fuse, FuturesUnordered}};
+use pin_project_lite::pin_project;
+use reqwest::{Response, StatusCode};
+use tokio::{sync::{OwnedSemaphorePermit, Semaphore},
+            time::Instant};
+use tracing::{Level as L, event};
+
+use crate::SendError;
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+/// Weight of the newest sample in the smoothed latency.
+const LATENCY_SMOOTHING: f64 = 0.2;
+/// Per-sample growth allowed to the baseline latency, so that it can follow a genuinely slower server.
+const BASELINE_DRIFT: f64 = 1.01;
+// ///////////////////////////////// [ outcome ] ///////////////////////////////// //
+/// What a completed request says about the remote's capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimiterOutcome {
+    /// Completed normally: its latency feeds the latency signal.
+    Success,
+    /// Timeout or explicit push-back (`429`, `503`): back off.
+    Overload,
+    /// No signal (e.g. a failure unrelated to load, or cancelled work).
+    Ignore,
+}
+impl LimiterOutcome {
+    /// `429`/`503` are overload; other `5xx` carry no signal; everything else is success.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => Self::Overload,
+            _ if status.is_server_error() => Self::Ignore,
+            _ => Self::Success,
+        }
+    }
+
+    /// As [`from_status`](Self::from_status); timeouts are overload, other errors carry no signal.
+    pub fn from_result(result: &Result<Response, reqwest::Error>) -> Self {
+        match result {
+            Ok(response) => Self::from_status(response.status()),
+            Err(error) if error.is_timeout() => Self::Overload,
+            Err(_) => Self::Ignore,
+        }
+    }
+
+    /// As [`from_result`](Self::from_result); a short-circuited call carries no signal.
+    pub fn from_send_result(result: &Result<Response, SendError>) -> Self {
+        match result {
+            Ok(response) => Self::from_status(response.status()),
+            Err(SendError::Reqwest(error)) if error.is_timeout() => Self::Overload,
+            Err(_) => Self::Ignore,
+        }
+    }
+}
+// ///////////////////////////////// [ limiter ] ///////////////////////////////// //
+/// Shared, cheaply cloneable concurrency limiter.  See [module docs](self).
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimiter {
+    semaphore: Arc<Semaphore>,
+    state:     Arc<Mutex<LimitState>>,
+    config:    LimitConfig,
+}
+#[derive(Debug, Clone, Copy)]
+struct LimitConfig {
+    min_limit:         usize,
+    max_limit:         usize,
+    backoff_ratio:     f64,
+    latency_tolerance: f64,
+}
+#[derive(Debug)]
+struct LimitState {
+    limit:            usize,
+    /// Permits currently held that must be retired (not returned) on release, after a decrease.
+    debt:             usize,
+    /// Healthy successes since the last increase.
+    successes:        usize,
+    baseline_latency: Option<Duration>,
+    smoothed_latency: Option<Duration>,
+    last_decrease:    Option<Instant>,
+}
+#[bon]
+impl AdaptiveLimiter {
+    /// Bounds are normalized: `1 <= min_limit <= initial_limit <= max_limit`.
+    #[builder]
+    pub fn new(#[builder(default = 4)] initial_limit: usize,
+               #[builder(default = 1)] min_limit: usize,
+               #[builder(default = 64)] max_limit: usize,
+               /// Multiplier applied on a decrease.  (Clamped to `[0.1, 0.9]`.)
+               #[builder(default = 0.5)]
+               backoff_ratio: f64,
+               /// Smoothed latency above `baseline * latency_tolerance` is treated as overload.
+               #[builder(default = 2.0)]
+               latency_tolerance: f64)
+               -> Self {
+        let min_limit = min_limit.max(1);
+        let max_limit = max_limit.max(min_limit);
+        let limit = initial_limit.clamp(min_limit, max_limit);
+        let config = LimitConfig { min_limit,
+                                   max_limit,
+                                   backoff_ratio: backoff_ratio.clamp(0.1, 0.9),
+                                   latency_tolerance: latency_tolerance.max(1.0) };
+        let state = LimitState { limit,
+                                 debt: 0,
+                                 successes: 0,
+                                 baseline_latency: None,
+                                 smoothed_latency: None,
+                                 last_decrease: None };
+        Self { semaphore: Arc::new(Semaphore::new(limit)),
+               state: Arc::new(Mutex::new(state)),
+               config }
+    }
+}
+impl Default for AdaptiveLimiter {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+impl AdaptiveLimiter {
+    /// Wait for a slot.  Report how the work went via [`AdaptivePermit::record`]; dropping the permit reports nothing.
+    pub async fn acquire(&self) -> AdaptivePermit {
+        let permit = self.semaphore
+                         .clone()
+                         .acquire_owned()
+                         .await
+                         .expect("limiter semaphore is never closed");
+        AdaptivePermit { limiter: self.clone(),
+                         permit:  Some(permit),
+                         start:   Instant::now(), }
+    }
+
+    /// Current concurrency limit.
+    pub fn limit(&self) -> usize {
+        self.lock().limit
+    }
+
+    /// Permits currently held.  (May briefly exceed [`limit`](Self::limit) after a decrease.)
+    pub fn in_flight(&self) -> usize {
+        let state = self.lock();
+        (state.limit + state.debt).saturating_sub(self.semaphore
+                                                      .available_permits())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimitState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn on_outcome(&self, outcome: LimiterOutcome, latency: Duration) {
+        let mut state = self.lock();
+        match outcome {
+            LimiterOutcome::Ignore => {},
+            LimiterOutcome::Overload => self.decrease(&mut state, "overload"),
+            LimiterOutcome::Success => {
+                let baseline = state.baseline_latency
+                                    .map_or(latency, |baseline| {
+                                        latency.min(baseline.mul_f64(BASELINE_DRIFT))
+                                    });
+                let smoothed = state.smoothed_latency
+                                    .map_or(latency, |smoothed| {
+                                        smoothed.mul_f64(1.0 - LATENCY_SMOOTHING)
+                                        + latency.mul_f64(LATENCY_SMOOTHING)
+                                    });
+                state.baseline_latency = Some(baseline);
+                state.smoothed_latency = Some(smoothed);
+                if smoothed > baseline.mul_f64(self.config.latency_tolerance) {
+                    self.decrease(&mut state, "latency");
+                } else {
+                    state.successes += 1;
+                    if state.successes >= state.limit {
+                        state.successes = 0;
+                        self.increase(&mut state);
+                    }
+                }
+            },
+        }
+    }
+
+    fn increase(&self, state: &mut LimitState) {
+        if state.limit >= self.config.max_limit {
+            return;
+        }
+        state.limit += 1;
+        if state.debt > 0 {
+            state.debt -= 1;
+        } else {
+            self.semaphore.add_permits(1);
+        }
+        event!(L::DEBUG, limit = state.limit, "concurrency limit increased");
+    }
+
+    fn decrease(&self, state: &mut LimitState, reason: &'static str) {
+        let now = Instant::now();
+        let cooldown = state.smoothed_latency
+                            .unwrap_or_default();
+        if state.last_decrease
+                .is_some_and(|last| now.duration_since(last) < cooldown)
+        {
+            return;
+        }
+        state.last_decrease = Some(now);
+        state.successes = 0;
+        let target =
+            ((state.limit as f64 * self.config.backoff_ratio) as usize).max(self.config.min_limit);
+        let surplus = state.limit - target.min(state.limit);
+        if surplus == 0 {
+            return;
+        }
+        let retired = self.semaphore
+                          .forget_permits(surplus);
+        state.debt += surplus - retired;
+        state.limit -= surplus;
+        event!(L::INFO,
+               limit = state.limit,
+               reason,
+               "concurrency limit decreased");
+    }
+
+    fn release(&self, permit: OwnedSemaphorePermit) {
+        let mut state = self.lock();
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+// ///////////////////////////////// [ permit ] ///////////////////////////////// //
+/// A held slot; released on drop.
+#[derive(Debug)]
+pub struct AdaptivePermit {
+    limiter: AdaptiveLimiter,
+    permit:  Option<OwnedSemaphorePermit>,
+    start:   Instant,
+}
+impl AdaptivePermit {
+    /// Report the outcome of the work done under this permit (latency is measured from acquisition), then release it.
+    pub fn record(self, outcome: LimiterOutcome) {
+        self.limiter
+            .on_outcome(outcome, self.start.elapsed());
+    }
+}
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            self.limiter.release(permit);
+        }
+    }
+}
+// ///////////////////////////////// [ stream combinator ] ///////////////////////////////// //
+/// [`buffer_unordered`](futures::StreamExt::buffer_unordered), with an [`AdaptiveLimiter`] instead of a fixed size.
+pub trait AdaptiveStreamExt: Stream + Sized
+    where Self::Item: Future {
+    /// Run up to `limiter.limit()` of the stream's futures at once, yielding outputs as they complete.
+    /// Each output is passed to `classify` to adjust the limit.
+    fn buffer_adaptive<F>(self, limiter: AdaptiveLimiter, classify: F) -> BufferAdaptive<Self, F>
+        where F: FnMut(&<Self::Item as Future>::Output) -> LimiterOutcome {
+        BufferAdaptive { stream: self.fuse(),
+                         in_flight: FuturesUnordered::new(),
+                         acquiring: None,
+                         limiter,
+                         classify }
+    }
+}
+impl<St> AdaptiveStreamExt for St
+    where St: Stream,
+          St::Item: Future
+{
+}
+
+type AcquireFuture = Pin<Box<dyn Future<Output = AdaptivePermit> + Send>>;
+pin_project! {
+    /// Stream for [`AdaptiveStreamExt::buffer_adaptive`].
+    #[must_use = "streams do nothing unless polled"]
+    pub struct BufferAdaptive<St, F>
+        where St: Stream,
+              St::Item: Future,
+    {
+        #[pin]
+        stream:    Fuse<St>,
+        in_flight: FuturesUnordered<Tracked<St::Item>>,
+        acquiring: Option<AcquireFuture>,
+        limiter:   AdaptiveLimiter,
+        classify:  F,
+    }
+}
+impl<St, F> Stream for BufferAdaptive<St, F>
+    where St: Stream,
+          St::Item: Future,
+          F: FnMut(&<St::Item as Future>::Output) -> LimiterOutcome
+{
+    type Item = <St::Item as Future>::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        // fill free slots from the source: permit first, then the next future
+        while !this.stream.is_done() {
+            let limiter = this.limiter.clone();
+            let acquiring =
+                this.acquiring
+                    .get_or_insert_with(|| Box::pin(async move { limiter.acquire().await }));
+            let Poll::Ready(permit) = acquiring.as_mut().poll(cx) else {
+                break;
+            };
+            match this.stream
+                      .as_mut()
+                      .poll_next(cx)
+            {
+                Poll::Ready(Some(future)) => {
+                    *this.acquiring = None;
+                    this.in_flight
+                        .push(Tracked { future,
+                                        permit: Some(permit) });
+                },
+                Poll::Ready(None) => *this.acquiring = None,
+                // keep the permit for the next item
+                Poll::Pending => {
+                    *this.acquiring = Some(Box::pin(std::future::ready(permit)));
+                    break;
+                },
+            }
+        }
+        match ready!(this.in_flight
+                         .poll_next_unpin(cx))
+        {
+            Some((output, permit)) => {
+                permit.record((this.classify)(&output));
+                Poll::Ready(Some(output))
+            },
+            None if this.stream.is_done() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+pin_project! {
+    /// A future paired with the permit it runs under.
+    struct Tracked<Fut> {
+        #[pin]
+        future: Fut,
+        permit: Option<AdaptivePermit>,
+    }
+}
+impl<Fut: Future> Future for Tracked<Fut> {
+    type Output = (Fut::Output, AdaptivePermit);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.future.poll(cx));
+        let permit = this.permit
+                         .take()
+                         .expect("`Tracked` polled after completion");
+        Poll::Ready((output, permit))
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::stream;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    const RTT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_builder_normalizes_bounds() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(100)
+                                                .min_limit(0)
+                                                .max_limit(8)
+                                                .build();
+        assert_eq!(limiter.limit(), 8);
+        assert_eq!(limiter.config.min_limit, 1);
+    }
+    #[test]
+    fn test_outcome_classification() {
+        assert_eq!(LimiterOutcome::from_status(StatusCode::OK),
+                   LimiterOutcome::Success);
+        assert_eq!(LimiterOutcome::from_status(StatusCode::NOT_FOUND),
+                   LimiterOutcome::Success);
+        assert_eq!(LimiterOutcome::from_status(StatusCode::TOO_MANY_REQUESTS),
+                   LimiterOutcome::Overload);
+        assert_eq!(LimiterOutcome::from_status(StatusCode::SERVICE_UNAVAILABLE),
+                   LimiterOutcome::Overload);
+        assert_eq!(LimiterOutcome::from_status(StatusCode::BAD_GATEWAY),
+                   LimiterOutcome::Ignore);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_additive_increase_per_window() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(2)
+                                                .build();
+        // a window is `limit` successes
+        limiter.on_outcome(LimiterOutcome::Success, RTT);
+        assert_eq!(limiter.limit(), 2);
+        limiter.on_outcome(LimiterOutcome::Success, RTT);
+        assert_eq!(limiter.limit(), 3);
+        for _ in 0..3 {
+            limiter.on_outcome(LimiterOutcome::Success, RTT);
+        }
+        assert_eq!(limiter.limit(), 4);
+        assert_eq!(limiter.semaphore
+                          .available_permits(),
+                   4);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_multiplicative_decrease_with_cooldown() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(16)
+                                                .min_limit(3)
+                                                .build();
+        limiter.on_outcome(LimiterOutcome::Success, RTT);
+        limiter.on_outcome(LimiterOutcome::Overload, RTT);
+        assert_eq!(limiter.limit(), 8);
+        // same burst: ignored
+        limiter.on_outcome(LimiterOutcome::Overload, RTT);
+        assert_eq!(limiter.limit(), 8);
+        tokio::time::advance(RTT).await;
+        limiter.on_outcome(LimiterOutcome::Overload, RTT);
+        assert_eq!(limiter.limit(), 4);
+        tokio::time::advance(RTT).await;
+        limiter.on_outcome(LimiterOutcome::Overload, RTT);
+        assert_eq!(limiter.limit(), 3, "floored at min_limit");
+        limiter.on_outcome(LimiterOutcome::Ignore, RTT);
+        assert_eq!(limiter.limit(), 3);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_latency_inflation_decreases() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(10)
+                                                .build();
+        limiter.on_outcome(LimiterOutcome::Success, RTT);
+        let mut latency = RTT;
+        while limiter.limit() == 10 {
+            latency *= 2;
+            assert!(latency < RTT * 64, "latency never triggered a decrease");
+            limiter.on_outcome(LimiterOutcome::Success, latency);
+        }
+        assert_eq!(limiter.limit(), 5);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_decrease_retires_permits_as_released() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(4)
+                                                .build();
+        let mut permits = Vec::new();
+        for _ in 0..4 {
+            permits.push(limiter.acquire().await);
+        }
+        limiter.on_outcome(LimiterOutcome::Overload, RTT);
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.in_flight(), 4);
+        // the first two releases retire surplus permits
+        drop(permits.pop());
+        drop(permits.pop());
+        assert_eq!(limiter.in_flight(), 2);
+        assert_eq!(limiter.semaphore
+                          .available_permits(),
+                   0);
+        drop(permits.pop());
+        assert_eq!(limiter.semaphore
+                          .available_permits(),
+                   1);
+        assert_eq!(limiter.in_flight(), 1);
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_buffer_adaptive_respects_limit() {
+        let limiter = AdaptiveLimiter::builder().initial_limit(2)
+                                                .max_limit(5)
+                                                .build();
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let outputs: Vec<usize> =
+            stream::iter(0..40).map(|i| {
+                                   let running = running.clone();
+                                   let peak = peak.clone();
+                                   async move {
+                                       let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                                       peak.fetch_max(now, Ordering::SeqCst);
+                                       tokio::time::sleep(RTT).await;
+                                       running.fetch_sub(1, Ordering::SeqCst);
+                                       i
+                                   }
+                               })
+                               .buffer_adaptive(limiter.clone(), |_| LimiterOutcome::Success)
+                               .collect()
+                               .await;
+        assert_eq!(outputs.len(), 40);
+        assert_eq!(limiter.limit(), 5, "healthy latency grows to max_limit");
+        assert_eq!(peak.load(Ordering::SeqCst), 5);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+}
@@ -0,0 +1,407 @@
+//! Rendering [`Traced`] errors for people & machines, and mapping them to process exit codes.
+//!
+//! - [`Diagnose`]: per-variant exit code & an optional suggestion (on top of [`Categorize`])
+//! - [`Report`]: the error, its cause chain, span trace (with fields), backtrace (if captured) & a suggestion
+//!   - [`ReportFormat::Text`]: indented, colourised if stderr is a terminal (and `NO_COLOR` unset)
+//!   - [`ReportFormat::Json`]: one object, for log shippers & wrapper scripts
+//!
+//! ## Exit codes
+//! | code    | meaning                                        |
+//! |---------|------------------------------------------------|
+//! | 0       | success                                        |
+//! | 1       | unspecified (e.g. a `main` returning `Err`)    |
+//! | 2       | command line usage (clap's own `exit`)         |
+//! | 20..=39 | [`CommonError`] variants (see its `exit_code`) |
+//! | 40..    | each crate's own `ErrKind` variants            |
+//! | 101     | panic                                          |
+//!
+//! ```ignore
+//! async fn main() -> ExitCode {
+//!     match run(Cli::parse()).await {
+//!         Ok(()) => ExitCode::SUCCESS,
+//!         Err(error) => error.exit(ReportFormat::Text),
+//!     }
+//! }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{backtrace::BacktraceStatus, borrow::Cow, error::Error, fmt, io::IsTerminal as _,
+          process::ExitCode, str::FromStr};
+
+use owo_colors::{OwoColorize as _, Style};
+use serde_json::json;
+
+use crate::{Categorize, CommonError, ErrorCategory, HttpClientError, SendError, Traced};
+// ///////////////////////////////// [ diagnose ] ///////////////////////////////// //
+/// What a `main` should do with an error: exit code & advice.
+pub trait Diagnose: Categorize {
+    /// Process exit code: distinct per variant (see the [module docs](self) for the ranges in use).
+    fn exit_code(&self) -> u8;
+
+    /// What the person running the program could try.  (Defaults to advice by [`ErrorCategory`].)
+    fn suggestion(&self) -> Option<Cow<'static, str>> {
+        None
+    }
+}
+impl Diagnose for CommonError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Download(_) => 20,
+            Self::EnvFilter(_) => 21,
+            Self::Fixture(_) => 22,
+            Self::HiddenValue(_) => 23,
+            Self::HttpClient(_) => 24,
+            Self::Io(_) => 25,
+            Self::JsonPlaceholder(_) => 26,
+            Self::ParseInt(_) => 27,
+            Self::RateLimit(_) => 28,
+            Self::Reqwest(_) => 29,
+            Self::Send(_) => 30,
+            Self::TracingSubscriber(_) => 31,
+            Self::Url(_) => 32,
+            Self::OtherDyn(_) => 38,
+            Self::OtherString(_) => 39,
+        }
+    }
+
+    fn suggestion(&self) -> Option<Cow<'static, str>> {
+        let suggestion = match self {
+            Self::EnvFilter(_) => "check the `RUST_LOG` directives (e.g. `info,utilities=debug`)",
+            Self::Fixture(_) =>
+                "re-record the fixture file (`--record <FILE>`), or run without `--replay`",
+            Self::HiddenValue(_) => "set the variable in the environment or in a `.env` file",
+            Self::HttpClient(HttpClientError::InsecureBaseUrl { .. }) =>
+                "use an `https` URL (plain `http` is only allowed for local stand-ins)",
+            Self::RateLimit(_) => "check the `[rate_limits]` quota table",
+            Self::Send(SendError::CircuitOpen(_)) =>
+                "the host has been failing: wait for its circuit to close",
+            Self::Reqwest(source) | Self::Send(SendError::Reqwest(source))
+                if source.is_connect() =>
+                "is the server reachable? (for a local stand-in: `cargo run -p stand-in`, then `--base-url`)",
+            _ => return None,
+        };
+        Some(suggestion.into())
+    }
+}
+// ///////////////////////////////// [ format ] ///////////////////////////////// //
+/// How a [`Report`] is rendered.  Parses from `text` / `json` (e.g. as a `--error-format` argument).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+}
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown report format `{other}` (expected `text` or `json`)")),
+        }
+    }
+}
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+                        Self::Text => "text",
+                        Self::Json => "json",
+                    })
+    }
+}
+// ///////////////////////////////// [ report ] ///////////////////////////////// //
+/// A [`Traced`] error, ready to print.  (`Display` renders it as configured.)
+pub struct Report<'a, K> {
+    traced: &'a Traced<K>,
+    format: ReportFormat,
+    color:  bool,
+}
+impl<K: Diagnose + Error + 'static> Traced<K> {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.kind().exit_code())
+    }
+
+    /// Text report; coloured if stderr is a terminal & `NO_COLOR` is unset.
+    pub fn report(&self) -> Report<'_, K> {
+        Report { traced: self,
+                 format: ReportFormat::Text,
+                 color:  std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(), }
+    }
+
+    /// Print the report to stderr & return the exit code: the tail of a `main -> ExitCode`.
+    pub fn exit(&self, format: ReportFormat) -> ExitCode {
+        eprintln!("{}", self.report().format(format));
+        self.exit_code()
+    }
+}
+impl<'a, K: Diagnose + Error + 'static> Report<'a, K> {
+    pub fn format(mut self, format: ReportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// The kind's own suggestion, or general advice for its category.
+    pub fn suggestion(&self) -> Option<Cow<'static, str>> {
+        self.traced
+            .kind()
+            .suggestion()
+            .or_else(|| match self.traced.category() {
+                ErrorCategory::Retryable => Some("this may be transient: try again".into()),
+                ErrorCategory::Bug => {
+                    Some("this is a bug: please report it, with a backtrace (`RUST_BACKTRACE=1`)".into())
+                },
+                ErrorCategory::User | ErrorCategory::Other => None,
+            })
+    }
+
+    /// Messages of the kind's sources, outermost first.  A source whose message its parent already ends with
+    /// (e.g. a `transparent`/`"{0}"` wrapper) is skipped.
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = Vec::new();
+        let mut shown = self.traced.kind().to_string();
+        let mut source = self.traced.kind().source();
+        while let Some(error) = source {
+            let message = error.to_string();
+            if !shown.ends_with(&message) {
+                causes.push(message.clone());
+            }
+            shown = message;
+            source = error.source();
+        }
+        causes
+    }
+
+    /// Span trace, innermost span first.
+    pub fn spans(&self) -> Vec<SpanEntry> {
+        let mut spans = Vec::new();
+        self.traced
+            .spantrace()
+            .with_spans(|metadata, fields| {
+                spans.push(SpanEntry { name:   metadata.name(),
+                                       target: metadata.target(),
+                                       fields: fields.to_owned(),
+                                       file:   metadata.file(),
+                                       line:   metadata.line(), });
+                true
+            });
+        spans
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let kind = self.traced.kind();
+        let backtrace = self.traced.backtrace();
+        json!({
+            "error": kind.to_string(),
+            "category": self.traced.category().to_string(),
+            "exit_code": kind.exit_code(),
+            "causes": self.causes(),
+            "spantrace": self.spans()
+                             .iter()
+                             .map(|span| json!({
+                                 "name": span.name,
+                                 "target": span.target,
+                                 "fields": span.fields,
+                                 "file": span.file,
+                                 "line": span.line,
+                             }))
+                             .collect::<Vec<_>>(),
+            "backtrace": (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string()),
+            "suggestion": self.suggestion(),
+        })
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = |style: Style| if self.color { style } else { Style::new() };
+        let heading = style(Style::new().bold());
+        let dimmed = style(Style::new().dimmed());
+        let kind = self.traced.kind();
+
+        writeln!(f,
+                 "{}{} {}",
+                 "error".style(style(Style::new().red().bold())),
+                 format!("[{}, exit {}]:", self.traced.category(), kind.exit_code()).style(dimmed),
+                 kind.style(heading))?;
+        let causes = self.causes();
+        if !causes.is_empty() {
+            writeln!(f, "\n  {}", "caused by:".style(heading))?;
+            for (i, cause) in causes.iter().enumerate() {
+                writeln!(f, "    {i}: {cause}")?;
+            }
+        }
+        let spans = self.spans();
+        if !spans.is_empty() {
+            writeln!(f, "\n  {}", "span trace:".style(heading))?;
+            for (i, span) in spans.iter().enumerate() {
+                writeln!(f,
+                         "    {i}: {}",
+                         format!("{}::{}", span.target, span.name).style(style(Style::new().cyan())))?;
+                if !span.fields.is_empty() {
+                    writeln!(f, "         with {}", span.fields)?;
+                }
+                if let (Some(file), Some(line)) = (span.file, span.line) {
+                    writeln!(f, "         {}", format!("at {file}:{line}").style(dimmed))?;
+                }
+            }
+        }
+        let backtrace = self.traced.backtrace();
+        match backtrace.status() {
+            BacktraceStatus::Captured => {
+                writeln!(f, "\n  {}", "backtrace:".style(heading))?;
+                for line in backtrace.to_string().lines() {
+                    writeln!(f, "    {line}")?;
+                }
+            },
+            _ => writeln!(f,
+                          "\n  {}",
+                          "backtrace: not captured (set `RUST_BACKTRACE=1`)".style(dimmed))?,
+        }
+        if let Some(suggestion) = self.suggestion() {
+            write!(f,
+                   "\n  {} {suggestion}",
+                   "help:".style(style(Style::new().green().bold())))?;
+        }
+        Ok(())
+    }
+}
+impl<K: Diagnose + Error + 'static> fmt::Display for Report<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            ReportFormat::Text => self.write_text(f),
+            ReportFormat::Json => write!(f, "{}", self.to_json()),
+        }
+    }
+}
+
+/// One span of a [`Report`]'s span trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanEntry {
+    pub name:   &'static str,
+    pub target: &'static str,
+    /// As formatted by the subscriber's `ErrorLayer` (e.g. `url=http://.. attempt=2`)
+    pub fields: String,
+    pub file:   Option<&'static str>,
+    pub line:   Option<u32>,
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use pretty_assertions::assert_eq;
+    use thiserror::Error;
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    enum ErrKind {
+        #[error("no config at `{0}`")]
+        NoConfig(String, #[source] io::Error),
+        #[error("invariant broken")]
+        Invariant,
+        #[error(transparent)]
+        Common(#[from] CommonError),
+    }
+    impl Categorize for ErrKind {
+        fn category(&self) -> ErrorCategory {
+            match self {
+                Self::NoConfig(..) => ErrorCategory::User,
+                Self::Invariant => ErrorCategory::Bug,
+                Self::Common(source) => source.category(),
+            }
+        }
+    }
+    impl Diagnose for ErrKind {
+        fn exit_code(&self) -> u8 {
+            match self {
+                Self::NoConfig(..) => 40,
+                Self::Invariant => 41,
+                Self::Common(source) => source.exit_code(),
+            }
+        }
+
+        fn suggestion(&self) -> Option<Cow<'static, str>> {
+            match self {
+                Self::NoConfig(path, _) => Some(format!("create `{path}`").into()),
+                Self::Invariant => None,
+                Self::Common(source) => source.suggestion(),
+            }
+        }
+    }
+
+    fn no_config() -> Traced<ErrKind> {
+        Traced::new(ErrKind::NoConfig("app.toml".into(), io::Error::from(io::ErrorKind::NotFound)))
+    }
+
+    #[test]
+    fn test_text_report_sections() {
+        let report = no_config().report()
+                                .color(false)
+                                .to_string();
+        assert!(report.starts_with("error[user error, exit 40]: no config at `app.toml`\n"),
+                "{report}");
+        assert!(report.contains("caused by:\n    0: entity not found\n"),
+                "{report}");
+        assert!(report.ends_with("help: create `app.toml`"), "{report}");
+        assert!(!report.contains("\u{1b}["),
+                "no escape codes without colour: {report}");
+        assert!(no_config().report()
+                           .color(true)
+                           .to_string()
+                           .contains("\u{1b}["));
+    }
+
+    #[test]
+    fn test_span_trace_with_fields() {
+        let subscriber = tracing_subscriber::registry().with(tracing_error::ErrorLayer::default());
+        let traced = tracing::subscriber::with_default(subscriber, || {
+            let _outer = info_span!("load_config", path = "app.toml").entered();
+            let _inner = info_span!("parse", line = 3).entered();
+            Traced::new(ErrKind::Invariant)
+        });
+        let report = traced.report().color(false);
+        let spans: Vec<_> = report.spans()
+                                  .into_iter()
+                                  .map(|span| (span.name, span.fields))
+                                  .collect();
+        assert_eq!(spans,
+                   [("parse", "line=3".to_string()),
+                    ("load_config", r#"path="app.toml""#.to_string())]);
+        let text = report.to_string();
+        assert!(text.contains("0: utilities::diagnostic::tests::parse\n         with line=3\n"),
+                "{text}");
+        assert!(text.contains("help: this is a bug"), "{text}");
+    }
+
+    #[test]
+    fn test_json_report() {
+        let traced =
+            Traced::new(ErrKind::from(CommonError::from(io::Error::from(io::ErrorKind::TimedOut))));
+        let json = traced.report()
+                         .format(ReportFormat::Json)
+                         .to_string();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["category"], "retryable");
+        assert_eq!(value["exit_code"], 25);
+        // `transparent` & `"io error: {0}"` wrappers don't repeat their source's message
+        assert_eq!(value["causes"], json!([]));
+        assert_eq!(value["suggestion"], "this may be transient: try again");
+        assert_eq!(ExitCode::from(25), traced.exit_code());
+    }
+
+    #[test]
+    fn test_format_parses() {
+        assert_eq!("json".parse(), Ok(ReportFormat::Json));
+        assert_eq!(ReportFormat::Text.to_string()
+                                     .parse(),
+                   Ok(ReportFormat::Text));
+        assert!("yaml".parse::<ReportFormat>()
+                      .is_err());
+    }
+}
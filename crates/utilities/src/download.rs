@@ -0,0 +1,632 @@
+//! Streaming downloads via [`HttpClient::download`]: the body goes to disk chunk by chunk, never buffered whole.
+//!
+//! - written to `<to>.part`, renamed to `<to>` once complete (& verified)
+//! - **progress**: `on_progress` is called once before the first chunk & after each one (bytes so far, total if known)
+//! - **resume**: an existing `.part` file is hashed, then continued with `Range: bytes=<len>-` and `If-Range: <validator>`
+//!   - the validator (the response's strong `ETag`, else its `Last-Modified`) is kept beside the part file, in
+//!     `<to>.part.validator`; a part file without one is started over
+//!   - `206` starting at `<len>`: appended
+//!   - `200` (the server ignores ranges, or the resource changed): started over
+//!   - `416` with `Content-Range: bytes */<len>` (nothing left to send): the part file is taken as complete;
+//!     any other `416` (e.g. the resource shrank): started over
+//! - **checksum**: with `sha256`, a mismatch fails the download & removes the part file (so a retry starts clean)
+//!
+//! An interrupted download leaves its part file in place, for the next call to resume.
+//! Downloads go through circuit breakers, rate limits & fixtures as usual, but not the response cache.
+//! The client's total timeout covers the whole transfer: set `timeout` for large files.
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::HttpClient;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let api = HttpClient::builder().base_url("https://httpbin.org".parse()?)
+//!                                .build()?;
+//! let downloaded = api.download()
+//!                     .url(api.url("range/102400")?)
+//!                     .to("data/no-sync/range.bin")
+//!                     .on_progress(Box::new(|progress| eprint!("\r{progress}")))
+//!                     .call()
+//!                     .await?;
+//! println!("\n{} bytes, sha256 {}", downloaded.bytes, downloaded.sha256);
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{ffi::OsString,
+          io,
+          path::{Path, PathBuf},
+          time::Duration};
+
+use bon::bon;
+use futures::StreamExt as _;
+use reqwest::{Response, StatusCode, Url, header};
+use ring::digest;
+use thiserror::Error;
+use tokio::{fs,
+            io::{AsyncReadExt as _, AsyncWriteExt as _, BufWriter}};
+use tracing::{Level as L, event, instrument};
+
+use crate::{HttpClient, SendError};
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error(transparent)]
+    Send(#[from] SendError),
+    #[error("Download of `{url}` failed with status {status}")]
+    Status { url: String, status: StatusCode },
+    #[error("Error reading the body of `{url}`: {source}")]
+    Body {
+        url:    String,
+        source: reqwest::Error,
+    },
+    #[error("I/O error on `{path}`: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Checksum mismatch for `{path}`: expected sha256 {expected}, got {actual}")]
+    Checksum {
+        path:     PathBuf,
+        expected: String,
+        actual:   String,
+    },
+}
+// ///////////////////////////////// [ progress & result ] ///////////////////////////////// //
+/// Called with each [`DownloadProgress`] update.  (e.g. redraw a progress bar, or send to a UI thread)
+pub type ProgressFn = Box<dyn FnMut(&DownloadProgress) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// Bytes on disk, including any resumed part.
+    pub downloaded:   u64,
+    /// Full size, if the server said.
+    pub total:        Option<u64>,
+    /// Bytes already on disk when the download (re)started.
+    pub resumed_from: u64,
+}
+impl DownloadProgress {
+    /// `0.0..=1.0`, if the total is known.
+    pub fn fraction(&self) -> Option<f32> {
+        self.total.map(|total| {
+                      if total == 0 {
+                          1.0
+                      } else {
+                          (self.downloaded as f64 / total as f64).min(1.0) as f32
+                      }
+                  })
+    }
+}
+/// e.g. `51200 / 102400 B (50%)`
+impl std::fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.total, self.fraction()) {
+            (Some(total), Some(fraction)) => write!(f,
+                                                    "{} / {total} B ({:.0}%)",
+                                                    self.downloaded,
+                                                    fraction * 100.0),
+            _ => write!(f, "{} B", self.downloaded),
+        }
+    }
+}
+
+/// A completed (& verified, if a checksum was given) download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downloaded {
+    pub path:         PathBuf,
+    /// File size.
+    pub bytes:        u64,
+    /// Bytes reused from an earlier, partial download.
+    pub resumed_from: u64,
+    /// Lower-case hex.
+    pub sha256:       String,
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+#[bon]
+impl HttpClient {
+    /// Stream `url` to the file `to`.  See [`crate::download`].
+    ///
+    /// - `sha256`: expected digest (hex, any case); verified once complete
+    /// - `resume`: continue an existing `<to>.part` file (default: `true`; `false` starts over)
+    /// - `timeout`: for the whole transfer, instead of the client's
+    #[builder]
+    #[instrument(skip_all, fields(%url, to = %to.display()))]
+    pub async fn download(&self,
+                          url: Url,
+                          #[builder(into)] to: PathBuf,
+                          #[builder(into)] sha256: Option<String>,
+                          #[builder(default = true)] resume: bool,
+                          timeout: Option<Duration>,
+                          mut on_progress: Option<ProgressFn>)
+                          -> Result<Downloaded, DownloadError> {
+        let part = part_path(&to);
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| DownloadError::Io { path, source }
+        };
+        if let Some(parent) = to.parent()
+                                .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).await
+                                      .map_err(io_error(parent))?;
+        }
+        let mut hasher = digest::Context::new(&digest::SHA256);
+        let mut offset = if resume {
+            hash_existing(&part, &mut hasher).await
+                                             .map_err(io_error(&part))?
+        } else {
+            0
+        };
+        let validator_file = validator_path(&part);
+        let validator = match offset {
+            0 => None,
+            _ => fs::read_to_string(&validator_file).await
+                                                    .ok()
+                                                    .filter(|validator| !validator.is_empty()),
+        };
+        if offset > 0 && validator.is_none() {
+            event!(L::DEBUG,
+                   offset,
+                   "part file has no validator (ETag/Last-Modified); starting over");
+            offset = 0;
+            hasher = digest::Context::new(&digest::SHA256);
+        }
+
+        let send = |range: Option<(u64, &str)>| {
+            let mut request = self.client().get(url.clone());
+            if let Some((offset, validator)) = range {
+                request = request.header(header::RANGE, format!("bytes={offset}-"))
+                                 .header(header::IF_RANGE, validator);
+            }
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+            async move {
+                self.execute_uncached(request.build()
+                                             .map_err(SendError::from)?)
+                    .await
+            }
+        };
+        let mut response = send(validator.as_deref()
+                                         .map(|validator| (offset, validator))).await?;
+        if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            if content_range(&response) == Some(("*", &offset.to_string())) {
+                event!(L::DEBUG,
+                       offset,
+                       "nothing left to download; part file is complete");
+                drop(response);
+                return finish(part, to, hasher, sha256, offset, offset).await;
+            }
+            event!(L::DEBUG,
+                   offset,
+                   content_range = ?content_range(&response),
+                   "range not satisfiable; starting over");
+            offset = 0;
+            hasher = digest::Context::new(&digest::SHA256);
+            response = send(None).await?;
+        }
+        let status = response.status();
+        let total = match status {
+            StatusCode::PARTIAL_CONTENT
+                if offset > 0 && content_range_start(&response) == Some(offset) =>
+                content_range_total(&response).or_else(|| {
+                                                  response.content_length()
+                                                          .map(|len| offset + len)
+                                              }),
+            status if status.is_success() && status != StatusCode::PARTIAL_CONTENT => {
+                if offset > 0 {
+                    event!(L::DEBUG, offset, %status, "range not honoured; starting over");
+                    offset = 0;
+                    hasher = digest::Context::new(&digest::SHA256);
+                }
+                response.content_length()
+            },
+            status =>
+                return Err(DownloadError::Status { url: url.into(),
+                                                   status }),
+        };
+        event!(L::DEBUG, offset, ?total, %status, "downloading");
+        // a new part file: keep what identifies this version of the resource, for a later resume
+        if offset == 0 {
+            let written = match response_validator(&response) {
+                Some(validator) => fs::write(&validator_file, validator).await,
+                None => remove_if_exists(&validator_file).await,
+            };
+            written.map_err(io_error(&validator_file))?;
+        }
+
+        let file = fs::OpenOptions::new().create(true)
+                                         .write(true)
+                                         .append(offset > 0)
+                                         .truncate(offset == 0)
+                                         .open(&part)
+                                         .await
+                                         .map_err(io_error(&part))?;
+        let mut writer = BufWriter::new(file);
+        let mut progress = DownloadProgress { downloaded: offset,
+                                              total,
+                                              resumed_from: offset };
+        let mut report = |progress: &DownloadProgress| {
+            if let Some(on_progress) = &mut on_progress {
+                on_progress(progress);
+            }
+        };
+        report(&progress);
+        let mut body = response.bytes_stream();
+        let copied = async {
+                         while let Some(chunk) = body.next().await {
+                             let chunk =
+                                 chunk.map_err(|source| DownloadError::Body { url:
+                                                                                  url.to_string(),
+                                                                              source })?;
+                             hasher.update(&chunk);
+                             writer.write_all(&chunk)
+                                   .await
+                                   .map_err(io_error(&part))?;
+                             progress.downloaded += chunk.len() as u64;
+                             report(&progress);
+                         }
+                         Ok::<_, DownloadError>(())
+                     }.await;
+        // flushed even on failure: whatever arrived stays on disk, to resume from
+        writer.flush()
+              .await
+              .map_err(io_error(&part))?;
+        copied?;
+        finish(part,
+               to,
+               hasher,
+               sha256,
+               progress.downloaded,
+               progress.resumed_from).await
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+/// `<to>.part`
+fn part_path(to: &Path) -> PathBuf {
+    let mut name = to.file_name()
+                     .map(OsString::from)
+                     .unwrap_or_default();
+    name.push(".part");
+    to.with_file_name(name)
+}
+
+/// `<to>.part.validator`: the `If-Range` value for resuming the part file.
+fn validator_path(part: &Path) -> PathBuf {
+    let mut name = part.as_os_str().to_owned();
+    name.push(".validator");
+    PathBuf::from(name)
+}
+
+/// A strong `ETag`, else `Last-Modified`: what `If-Range` accepts.
+fn response_validator(response: &Response) -> Option<&str> {
+    let header = |name| {
+        response.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+    };
+    header(header::ETAG).filter(|etag| !etag.starts_with("W/"))
+                        .or_else(|| header(header::LAST_MODIFIED))
+}
+
+async fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        removed => removed,
+    }
+}
+
+/// Feed an existing part file to `hasher`; its length (`0` if absent).
+async fn hash_existing(part: &Path, hasher: &mut digest::Context) -> io::Result<u64> {
+    let mut file = match fs::File::open(part).await {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let mut buffer = vec![0; 64 * 1024];
+    let mut length = 0;
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(length);
+        }
+        hasher.update(&buffer[..read]);
+        length += read as u64;
+    }
+}
+
+/// Verify the digest, then move the part file into place.
+async fn finish(part: PathBuf,
+                to: PathBuf,
+                hasher: digest::Context,
+                expected: Option<String>,
+                bytes: u64,
+                resumed_from: u64)
+                -> Result<Downloaded, DownloadError> {
+    let actual = hex::encode(hasher.finish());
+    // done with, either way
+    let _ = remove_if_exists(&validator_path(&part)).await;
+    if let Some(expected) = expected
+       && !expected.trim()
+                   .eq_ignore_ascii_case(&actual)
+    {
+        event!(L::WARN, path = %part.display(), %expected, %actual, "checksum mismatch; removing part file");
+        let _ = fs::remove_file(&part).await;
+        return Err(DownloadError::Checksum { path: to,
+                                             expected,
+                                             actual });
+    }
+    fs::rename(&part, &to).await
+                          .map_err(|source| DownloadError::Io { path: to.clone(),
+                                                                source })?;
+    event!(L::INFO, path = %to.display(), bytes, resumed_from, sha256 = %actual, "download complete");
+    Ok(Downloaded { path: to,
+                    bytes,
+                    resumed_from,
+                    sha256: actual })
+}
+
+/// `Content-Range: bytes <start>-<end>/<total>`
+fn content_range(response: &Response) -> Option<(&str, &str)> {
+    response.headers()
+            .get(header::CONTENT_RANGE)?
+            .to_str()
+            .ok()?
+            .strip_prefix("bytes ")?
+            .split_once('/')
+}
+fn content_range_start(response: &Response) -> Option<u64> {
+    let (range, _) = content_range(response)?;
+    range.split_once('-')?
+         .0
+         .parse()
+         .ok()
+}
+fn content_range_total(response: &Response) -> Option<u64> {
+    content_range(response)?.1
+                            .parse()
+                            .ok()
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::http_stub::{StubResponse, spawn_http_stub};
+
+    const BODY: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
+    const ETAG: &str = "\"v1\"";
+
+    fn sha256(bytes: &[u8]) -> String {
+        hex::encode(digest::digest(&digest::SHA256, bytes))
+    }
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("utilities-download-{}-{name}.bin",
+                                                     std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(part_path(&path));
+        let _ = std::fs::remove_file(validator_path(&part_path(&path)));
+        path
+    }
+    /// A part file of `bytes`, with `validator` beside it.
+    fn write_part(to: &Path, bytes: &str, validator: &str) {
+        std::fs::write(part_path(to), bytes).unwrap();
+        std::fs::write(validator_path(&part_path(to)), validator).unwrap();
+    }
+    fn client(base: &str) -> HttpClient {
+        HttpClient::builder().base_url(base.parse().unwrap())
+                             .allow_http(true)
+                             .build()
+                             .unwrap()
+    }
+    /// Serves [`BODY`] tagged [`ETAG`], honouring `Range: bytes=<n>-` if `ranges` & `If-Range` matches.
+    fn range_stub(ranges: bool)
+                  -> (String, std::sync::mpsc::Receiver<crate::http_stub::RecordedRequest>) {
+        spawn_http_stub(move |request| {
+            let start = request.header("range")
+                               .filter(|_| ranges && request.header("if-range") == Some(ETAG))
+                               .and_then(|range| range.strip_prefix("bytes="))
+                               .and_then(|range| {
+                                   range.trim_end_matches('-')
+                                        .parse::<usize>()
+                                        .ok()
+                               });
+            match start {
+                Some(start) if start >= BODY.len() =>
+                    StubResponse::new(416, "").with_header("content-range",
+                                                           format!("bytes */{}", BODY.len()))
+                                              .with_header("etag", ETAG),
+                Some(start) =>
+                    StubResponse::new(206, &BODY[start..]).with_header("content-range",
+                                 format!("bytes {start}-{}/{}", BODY.len() - 1, BODY.len()))
+                    .with_header("etag", ETAG),
+                None => StubResponse::new(200, BODY).with_header("etag", ETAG),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_download_with_progress_and_checksum() {
+        let (base, _) = range_stub(true);
+        let api = client(&base);
+        let to = temp_file("full");
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let recorded = updates.clone();
+        let downloaded = api.download()
+                            .url(api.url("range").unwrap())
+                            .to(&to)
+                            .sha256(sha256(BODY.as_bytes()).to_uppercase())
+                            .on_progress(Box::new(move |progress| {
+                                             recorded.lock()
+                                                     .unwrap()
+                                                     .push(*progress)
+                                         }))
+                            .call()
+                            .await
+                            .unwrap();
+        assert_eq!(downloaded,
+                   Downloaded { path:         to.clone(),
+                                bytes:        BODY.len() as u64,
+                                resumed_from: 0,
+                                sha256:       sha256(BODY.as_bytes()), });
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), BODY);
+        assert!(!part_path(&to).exists());
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.first()
+                          .unwrap()
+                          .downloaded,
+                   0);
+        assert_eq!(updates.last()
+                          .unwrap()
+                          .fraction(),
+                   Some(1.0));
+        let _ = std::fs::remove_file(&to);
+    }
+
+    #[tokio::test]
+    async fn test_resume_appends_to_part_file() {
+        let (base, requests) = range_stub(true);
+        let api = client(&base);
+        let to = temp_file("resume");
+        write_part(&to, &BODY[..10], ETAG);
+        let downloaded = api.download()
+                            .url(api.url("range").unwrap())
+                            .to(&to)
+                            .sha256(sha256(BODY.as_bytes()))
+                            .call()
+                            .await
+                            .unwrap();
+        assert_eq!(downloaded.resumed_from, 10);
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), BODY);
+        let request = requests.recv().unwrap();
+        assert_eq!((request.header("range"), request.header("if-range")),
+                   (Some("bytes=10-"), Some(ETAG)));
+        assert!(!validator_path(&part_path(&to)).exists());
+
+        // a complete part file: `416`, nothing more to fetch
+        write_part(&to, BODY, ETAG);
+        let downloaded = api.download()
+                            .url(api.url("range").unwrap())
+                            .to(&to)
+                            .call()
+                            .await
+                            .unwrap();
+        assert_eq!((downloaded.bytes, downloaded.resumed_from),
+                   (BODY.len() as u64, BODY.len() as u64));
+        assert_eq!(downloaded.sha256, sha256(BODY.as_bytes()));
+        let _ = std::fs::remove_file(&to);
+    }
+
+    #[tokio::test]
+    async fn test_ignored_range_starts_over() {
+        let (base, _) = range_stub(false);
+        let api = client(&base);
+        let to = temp_file("ignored");
+        write_part(&to, "stale bytes", ETAG);
+        let downloaded = api.download()
+                            .url(api.url("range").unwrap())
+                            .to(&to)
+                            .call()
+                            .await
+                            .unwrap();
+        assert_eq!(downloaded.resumed_from, 0);
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), BODY);
+        let _ = std::fs::remove_file(&to);
+    }
+
+    #[tokio::test]
+    async fn test_changed_or_unknown_version_starts_over() {
+        let (base, requests) = range_stub(true);
+        let api = client(&base);
+        let to = temp_file("changed");
+        let download = || {
+            api.download()
+               .url(api.url("range").unwrap())
+               .to(&to)
+               .sha256(sha256(BODY.as_bytes()))
+               .call()
+        };
+
+        // `If-Range` doesn't match: the whole (new) resource
+        write_part(&to, "stale bytes", "\"v0\"");
+        assert_eq!(download().await
+                             .unwrap()
+                             .resumed_from,
+                   0);
+        assert_eq!(requests.recv()
+                           .unwrap()
+                           .header("if-range"),
+                   Some("\"v0\""));
+
+        // no validator: no `Range` sent at all
+        std::fs::write(part_path(&to), &BODY[..10]).unwrap();
+        assert_eq!(download().await
+                             .unwrap()
+                             .resumed_from,
+                   0);
+        assert_eq!(requests.recv()
+                           .unwrap()
+                           .header("range"),
+                   None);
+
+        // `416` for another length than the part file's (the resource shrank): started over
+        write_part(&to, &format!("{BODY}-and-more"), ETAG);
+        assert_eq!(download().await
+                             .unwrap()
+                             .resumed_from,
+                   0);
+        let ranges = requests.iter()
+                             .take(2)
+                             .map(|request| {
+                                 request.header("range")
+                                        .map(str::to_owned)
+                             })
+                             .collect::<Vec<_>>();
+        assert_eq!(ranges, [Some(format!("bytes={}-", BODY.len() + 9)), None]);
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), BODY);
+        let _ = std::fs::remove_file(&to);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_and_status_errors() {
+        let (base, _) = range_stub(true);
+        let api = client(&base);
+        let to = temp_file("mismatch");
+        let error = api.download()
+                       .url(api.url("range").unwrap())
+                       .to(&to)
+                       .sha256("00")
+                       .call()
+                       .await
+                       .unwrap_err();
+        assert!(matches!(error, DownloadError::Checksum { ref actual, .. } if *actual == sha256(BODY.as_bytes())),
+                "{error}");
+        assert!(!to.exists());
+        assert!(!part_path(&to).exists());
+
+        let (base, _) = spawn_http_stub(|_| StubResponse::new(404, ""));
+        let api = client(&base);
+        let error = api.download()
+                       .url(api.url("missing").unwrap())
+                       .to(&to)
+                       .call()
+                       .await
+                       .unwrap_err();
+        assert!(matches!(error,
+                         DownloadError::Status { status: StatusCode::NOT_FOUND,
+                                                 .. }),
+                "{error}");
+    }
+
+    #[test]
+    fn test_progress_display() {
+        let progress = DownloadProgress { downloaded:   512,
+                                          total:        Some(2048),
+                                          resumed_from: 0, };
+        assert_eq!(progress.to_string(), "512 / 2048 B (25%)");
+        assert_eq!(DownloadProgress { total: None,
+                                      ..progress }.to_string(),
+                   "512 B");
+    }
+}
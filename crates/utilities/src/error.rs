@@ -0,0 +1,490 @@
+//! Error core shared by the workspace's binaries.
+//!
+//! - [`CommonError`]: the errors every crate meets (`utilities`' own, io, url, reqwest, ..)
+//! - [`ErrorCategory`] via [`Categorize`]: retryable / user error / bug, for deciding what to do (or say) about a failure
+//!   - exit codes, suggestions & rendering: [`Diagnose`] & [`Report`](crate::Report)
+//! - [`Traced`]: an error kind plus its [`SpanTrace`] & [`Backtrace`], captured at conversion
+//!
+//! Each crate keeps its own `ErrKind` enum (its own variants, plus one holding [`CommonError`]) and wires it up with:
+//! - [`common_error_from!`](crate::common_error_from): `From<_>` for every [`CommonError`] source, so `?` just works
+//! - [`error_wrapper!`](crate::error_wrapper): a local `ErrWrapper` newtype over [`Traced`] (`From<impl Into<ErrKind>>`, also
+//!   for `Box<ErrWrapper>`)
+//!
+//! ```
+//! use utilities::{Categorize, CommonError, Diagnose, ErrorCategory};
+//!
+//! #[derive(Debug, thiserror::Error)]
+//! enum ErrKind {
+//!     #[error("config has no `{0}` key")]
+//!     MissingKey(String),
+//!     #[error(transparent)]
+//!     Common(#[from] CommonError),
+//! }
+//! impl Categorize for ErrKind {
+//!     fn category(&self) -> ErrorCategory {
+//!         match self {
+//!             Self::MissingKey(_) => ErrorCategory::User,
+//!             Self::Common(source) => source.category(),
+//!         }
+//!     }
+//! }
+//! impl Diagnose for ErrKind {
+//!     fn exit_code(&self) -> u8 {
+//!         match self {
+//!             Self::MissingKey(_) => 40,
+//!             Self::Common(source) => source.exit_code(),
+//!         }
+//!     }
+//! }
+//! utilities::common_error_from!(ErrKind);
+//! utilities::error_wrapper!(
+//!     struct ErrWrapper(ErrKind);
+//! );
+//!
+//! fn port(text: &str) -> Result<u16, ErrWrapper> {
+//!     Ok(text.parse()?)
+//! }
+//! assert_eq!(port("http").unwrap_err()
+//!                        .category(),
+//!            ErrorCategory::User);
+//! ```
+//!
+//! ## Note
+//! A backtrace is only captured if enabled via `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE` (see [`Backtrace::capture`]).
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{backtrace::Backtrace, fmt, io};
+
+use reqwest::StatusCode;
+use thiserror::Error;
+use tracing_error::SpanTrace;
+
+use crate::{Diagnose, DownloadError, FixtureError, HiddenValueError, HttpClientError,
+            RateLimitError, SendError, SubscriberError, jsonplaceholder::JsonPlaceholderError};
+// ///////////////////////////////// [ category ] ///////////////////////////////// //
+/// What kind of failure an error is, independent of where it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Transient: the same operation may succeed if tried again (timeouts, refused connections, 5xx, ..)
+    Retryable,
+    /// Caused by input or configuration: fixable by whoever ran the program
+    User,
+    /// An invariant was broken: fixable only in the code
+    Bug,
+    /// Anything else (or not known)
+    Other,
+}
+impl ErrorCategory {
+    /// From a response status: 408/429/5xx are retryable, other 4xx are the caller's.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => Self::Retryable,
+            status if status.is_server_error() => Self::Retryable,
+            status if status.is_client_error() => Self::User,
+            _ => Self::Other,
+        }
+    }
+}
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+                        Self::Retryable => "retryable",
+                        Self::User => "user error",
+                        Self::Bug => "bug",
+                        Self::Other => "other",
+                    })
+    }
+}
+
+/// Classify an error.  (Implement for each crate's `ErrKind`, delegating to [`CommonError`] for shared variants.)
+pub trait Categorize {
+    fn category(&self) -> ErrorCategory;
+}
+impl Categorize for io::Error {
+    fn category(&self) -> ErrorCategory {
+        use io::ErrorKind as K;
+        match self.kind() {
+            K::TimedOut
+            | K::Interrupted
+            | K::WouldBlock
+            | K::ConnectionRefused
+            | K::ConnectionReset
+            | K::ConnectionAborted => ErrorCategory::Retryable,
+            K::NotFound | K::PermissionDenied | K::AlreadyExists | K::InvalidInput =>
+                ErrorCategory::User,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+impl Categorize for reqwest::Error {
+    fn category(&self) -> ErrorCategory {
+        if self.is_timeout() || self.is_connect() {
+            ErrorCategory::Retryable
+        } else if let Some(status) = self.status() {
+            ErrorCategory::from_status(status)
+        } else if self.is_builder() {
+            ErrorCategory::Bug
+        } else {
+            ErrorCategory::Other
+        }
+    }
+}
+impl Categorize for SendError {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Reqwest(source) => source.category(),
+            // the host is failing: retryable, just not within a backoff
+            Self::CircuitOpen(_) => ErrorCategory::Retryable,
+            Self::Fixture(_) => ErrorCategory::User,
+        }
+    }
+}
+impl Categorize for JsonPlaceholderError {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Url(_) | Self::NotFound { .. } => ErrorCategory::User,
+            Self::Send(source) => source.category(),
+            Self::Status { status, .. } => ErrorCategory::from_status(*status),
+            // the response didn't match our types
+            Self::Decode { .. } => ErrorCategory::Bug,
+        }
+    }
+}
+impl Categorize for DownloadError {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Send(source) => source.category(),
+            Self::Status { status, .. } => ErrorCategory::from_status(*status),
+            Self::Body { source, .. } => source.category(),
+            Self::Io { source, .. } => source.category(),
+            // the partial file is removed: trying again downloads afresh
+            Self::Checksum { .. } => ErrorCategory::Retryable,
+        }
+    }
+}
+// ///////////////////////////////// [ common errors ] ///////////////////////////////// //
+/// Errors shared by the workspace's crates.  Held by one variant of each crate's `ErrKind`.
+#[derive(Debug, Error)]
+pub enum CommonError {
+    #[error("download error: {0}")]
+    Download(#[from] DownloadError),
+    #[error("Error with tracing_subscriber::EnvFilter parsing env directive: {0}")]
+    EnvFilter(#[from] tracing_subscriber::filter::FromEnvError),
+    #[error("http fixture error: {0}")]
+    Fixture(#[from] FixtureError),
+    #[error("hiddenvalue error: {0}")]
+    HiddenValue(#[from] HiddenValueError),
+    #[error("http client error: {0}")]
+    HttpClient(#[from] HttpClientError),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("jsonplaceholder error: {0}")]
+    JsonPlaceholder(#[from] JsonPlaceholderError),
+    #[error("parse error: {0}")]
+    ParseInt(#[from] std::num::ParseIntError),
+    #[error("rate limit config error: {0}")]
+    RateLimit(#[from] RateLimitError),
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("request error: {0}")]
+    Send(#[from] SendError),
+    #[error("Error setting up tracing subscriber: {0}")]
+    TracingSubscriber(#[from] SubscriberError),
+    #[error("url parse error: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Uncategorized Error (dyn error object): {0}")]
+    OtherDyn(Box<dyn std::error::Error + Send + Sync>),
+    #[error(r#"Uncategorized string err: "{0}""#)]
+    OtherString(String),
+}
+impl CommonError {
+    /// Wrap any error as [`CommonError::OtherDyn`].
+    ///
+    /// ```
+    /// # use utilities::CommonError;
+    /// let error = "{".parse::<toml::Table>()
+    ///                .map_err(CommonError::dyn_error);
+    /// assert!(matches!(error, Err(CommonError::OtherDyn(_))));
+    /// ```
+    pub fn dyn_error<E>(error: E) -> Self
+        where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+        Self::OtherDyn(error.into())
+    }
+}
+impl From<String> for CommonError {
+    fn from(source: String) -> Self {
+        Self::OtherString(source)
+    }
+}
+impl Categorize for CommonError {
+    fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Download(source) => source.category(),
+            Self::Io(source) => source.category(),
+            Self::JsonPlaceholder(source) => source.category(),
+            Self::Reqwest(source) => source.category(),
+            Self::Send(source) => source.category(),
+            Self::EnvFilter(_)
+            | Self::Fixture(_)
+            | Self::HiddenValue(_)
+            | Self::HttpClient(_)
+            | Self::ParseInt(_)
+            | Self::RateLimit(_)
+            | Self::Url(_) => ErrorCategory::User,
+            // e.g. a second global default subscriber
+            Self::TracingSubscriber(_) => ErrorCategory::Bug,
+            Self::OtherDyn(_) | Self::OtherString(_) => ErrorCategory::Other,
+        }
+    }
+}
+// ///////////////////////////////// [ traced ] ///////////////////////////////// //
+/// An error kind with the span trace & backtrace of where it was created.
+///
+/// `Display` (and `Debug`, so that an error returned from `main` shows it all) is its text [`report`](Self::report).
+pub struct Traced<K> {
+    kind:      K,
+    spantrace: SpanTrace,
+    backtrace: Backtrace,
+}
+impl<K> Traced<K> {
+    /// Capture the current span trace & (if enabled) backtrace.
+    pub fn new(kind: K) -> Self {
+        Self { kind,
+               spantrace: SpanTrace::capture(),
+               backtrace: Backtrace::capture() }
+    }
+
+    pub fn kind(&self) -> &K {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> K {
+        self.kind
+    }
+
+    pub fn spantrace(&self) -> &SpanTrace {
+        &self.spantrace
+    }
+
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+impl<K: Categorize> Traced<K> {
+    pub fn category(&self) -> ErrorCategory {
+        self.kind.category()
+    }
+}
+/// The [`Report`](crate::diagnostic::Report), uncoloured.
+impl<K: Diagnose + std::error::Error + 'static> fmt::Display for Traced<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report().color(false))
+    }
+}
+// Using custom display as debug so we can get SpanTrace auto printed.
+impl<K: Diagnose + std::error::Error + 'static> fmt::Debug for Traced<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+impl<K: Diagnose + std::error::Error + 'static> std::error::Error for Traced<K> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+// ///////////////////////////////// [ macros ] ///////////////////////////////// //
+/// Source types named by [`common_error_from!`](crate::common_error_from), so callers needn't depend on their crates.
+#[doc(hidden)]
+pub mod __sources {
+    pub use reqwest::Error as ReqwestError;
+    pub use tracing_subscriber::filter::FromEnvError;
+    pub use url::ParseError as UrlParseError;
+}
+
+/// `impl From<_> for $kind` for each [`CommonError`] source, via `$kind: From<CommonError>`;
+/// plus `$kind::into_dyn_error` (as [`CommonError::dyn_error`]).
+#[macro_export]
+macro_rules! common_error_from {
+    ($kind:ty) => {
+        $crate::common_error_from!(@from $kind:
+            $crate::DownloadError,
+            $crate::FixtureError,
+            $crate::HiddenValueError,
+            $crate::HttpClientError,
+            $crate::RateLimitError,
+            $crate::SendError,
+            $crate::SubscriberError,
+            $crate::jsonplaceholder::JsonPlaceholderError,
+            $crate::error::__sources::FromEnvError,
+            $crate::error::__sources::ReqwestError,
+            $crate::error::__sources::UrlParseError,
+            ::std::io::Error,
+            ::std::num::ParseIntError,
+            ::std::string::String,
+        );
+        impl $kind {
+            /// Convenience function for transforming an error into a compatible *dyn error*.
+            pub fn into_dyn_error<E>(error: E) -> Self
+                where E: ::std::convert::Into<::std::boxed::Box<dyn ::std::error::Error + Send + Sync>> {
+                $crate::CommonError::dyn_error(error).into()
+            }
+        }
+    };
+    (@from $kind:ty: $($source:ty),+ $(,)?) => {
+        $(
+            impl ::std::convert::From<$source> for $kind {
+                fn from(source: $source) -> Self {
+                    $crate::CommonError::from(source).into()
+                }
+            }
+        )+
+    };
+}
+
+/// A local newtype over [`Traced<$kind>`](Traced), so that `?` converts anything `Into<$kind>` (a blanket
+/// `From` can't be written for `Traced` itself).  Also converts into `Box<$wrapper>`, for a smaller `Result`.
+///
+/// `$kind` must implement `Error` & [`Diagnose`].  Derefs to [`Traced`] (`kind()`, `category()`, `report()`, `exit(_)`, ..).
+#[macro_export]
+macro_rules! error_wrapper {
+    ($(#[$meta:meta])* $vis:vis struct $wrapper:ident($kind:ty);) => {
+        $(#[$meta])*
+        $vis struct $wrapper($crate::Traced<$kind>);
+        impl $wrapper {
+            pub fn into_kind(self) -> $kind {
+                self.0.into_kind()
+            }
+        }
+        impl ::std::ops::Deref for $wrapper {
+            type Target = $crate::Traced<$kind>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+        impl ::std::fmt::Display for $wrapper {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                ::std::fmt::Display::fmt(&self.0, f)
+            }
+        }
+        impl ::std::fmt::Debug for $wrapper {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                ::std::fmt::Debug::fmt(&self.0, f)
+            }
+        }
+        impl ::std::error::Error for $wrapper {
+            fn source(&self) -> ::std::option::Option<&(dyn ::std::error::Error + 'static)> {
+                ::std::option::Option::Some(self.0.kind())
+            }
+        }
+        impl<E> ::std::convert::From<E> for $wrapper where E: ::std::convert::Into<$kind> {
+            fn from(error: E) -> Self {
+                Self($crate::Traced::new(error.into()))
+            }
+        }
+        impl<E> ::std::convert::From<E> for ::std::boxed::Box<$wrapper> where E: ::std::convert::Into<$kind> {
+            fn from(error: E) -> Self {
+                ::std::boxed::Box::new($wrapper::from(error))
+            }
+        }
+    };
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+    use thiserror::Error;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    enum ErrKind {
+        #[error("bad input: {0}")]
+        Input(String),
+        #[error("invariant broken")]
+        Invariant,
+        #[error(transparent)]
+        Common(#[from] CommonError),
+    }
+    impl Categorize for ErrKind {
+        fn category(&self) -> ErrorCategory {
+            match self {
+                Self::Input(_) => ErrorCategory::User,
+                Self::Invariant => ErrorCategory::Bug,
+                Self::Common(source) => source.category(),
+            }
+        }
+    }
+    impl Diagnose for ErrKind {
+        fn exit_code(&self) -> u8 {
+            match self {
+                Self::Input(_) => 40,
+                Self::Invariant => 41,
+                Self::Common(source) => source.exit_code(),
+            }
+        }
+    }
+    crate::common_error_from!(ErrKind);
+    crate::error_wrapper!(
+        struct ErrWrapper(ErrKind);
+    );
+
+    fn read(kind: io::ErrorKind) -> Result<(), Box<ErrWrapper>> {
+        Err(io::Error::from(kind))?
+    }
+
+    #[test]
+    fn test_question_mark_converts_common_and_own_errors() {
+        let error = read(io::ErrorKind::TimedOut).unwrap_err();
+        assert!(matches!(error.kind(), ErrKind::Common(CommonError::Io(_))));
+        assert_eq!(error.category(), ErrorCategory::Retryable);
+        assert_eq!(read(io::ErrorKind::NotFound).unwrap_err()
+                                                .category(),
+                   ErrorCategory::User);
+
+        let parse = || -> Result<u8, Box<ErrWrapper>> { Ok("256".parse()?) };
+        assert_eq!(parse().unwrap_err()
+                          .category(),
+                   ErrorCategory::User);
+
+        let own: ErrWrapper = ErrKind::Invariant.into();
+        assert_eq!(own.category(), ErrorCategory::Bug);
+        assert!(matches!(ErrWrapper::from(ErrKind::Input("x".into())).into_kind(),
+                         ErrKind::Input(_)));
+    }
+
+    #[test]
+    fn test_dyn_and_string_errors_are_other() {
+        let dyn_error = ErrKind::into_dyn_error("boxed");
+        assert!(matches!(dyn_error, ErrKind::Common(CommonError::OtherDyn(_))));
+        assert_eq!(dyn_error.category(), ErrorCategory::Other);
+        let string: ErrWrapper = String::from("loose").into();
+        assert_eq!(string.kind().to_string(),
+                   r#"Uncategorized string err: "loose""#);
+    }
+
+    #[test]
+    fn test_display_is_uncoloured_report() {
+        let shown = ErrWrapper::from(ErrKind::Input("port".into())).to_string();
+        assert!(shown.starts_with("error[user error, exit 40]: bad input: port\n"),
+                "{shown}");
+        assert!(shown.contains("backtrace"), "{shown}");
+        assert!(!shown.contains('\u{1b}'), "{shown}");
+        // `Debug` is `Display`: an error returned from `main` shows the whole report
+        assert!(format!("{:?}", ErrWrapper::from(ErrKind::Invariant)).starts_with("error[bug, exit 41]: invariant broken\n"));
+    }
+
+    #[test]
+    fn test_status_categories() {
+        assert_eq!(ErrorCategory::from_status(StatusCode::SERVICE_UNAVAILABLE),
+                   ErrorCategory::Retryable);
+        assert_eq!(ErrorCategory::from_status(StatusCode::TOO_MANY_REQUESTS),
+                   ErrorCategory::Retryable);
+        assert_eq!(ErrorCategory::from_status(StatusCode::NOT_FOUND),
+                   ErrorCategory::User);
+        assert_eq!(ErrorCategory::from_status(StatusCode::OK),
+                   ErrorCategory::Other);
+    }
+}
@@ -0,0 +1,518 @@
+//! HTTP record/replay fixtures for [`HttpClient`](crate::HttpClient): deterministic tests without network.
+//!
+//! - **record**: requests go out as usual; each response is buffered, stored (status, headers, body, latency), and handed back
+//! - **replay**: nothing is sent; responses come from the fixture file, after sleeping the recorded latency (scaled)
+//!
+//! Only the transport step is swapped: circuit breakers, rate limits & retries behave as they would live.
+//!
+//! ## Matching
+//! Exchanges are keyed by method, origin (`scheme://host:port`) and path+query: replay against the base URL(s) recorded
+//! with, and two hosts serving the same path don't collide.
+//! Repeats of a key replay in recorded order (every exchange is stored, so e.g. `503`, `503`, `200` replays as such);
+//! once exhausted, the last one repeats.
+//!
+//! ## File format
+//! JSON: `{ "exchanges": [ { "request": { "method", "origin", "path" }, "response": { "status", "headers", "body", "latency_ms" } } ] }`.
+//! Request headers & bodies are *not* stored (no credentials in fixtures).  Non-UTF-8 bodies are stored as a byte array.
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::{Fixtures, HttpClient};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! // record once, against the real host
+//! let fixtures = Fixtures::record("data/fixtures/todos.json");
+//! let api = HttpClient::builder().base_url("https://jsonplaceholder.typicode.com".parse()?)
+//!                                .fixtures(fixtures.clone())
+//!                                .build()?;
+//! api.send(api.get("todos/1")?)
+//!    .await?;
+//! fixtures.save()?;
+//!
+//! // replay in tests: same client setup, no network, no waiting
+//! let fixtures = Fixtures::replay().path("data/fixtures/todos.json")
+//!                                  .latency_scale(0.0)
+//!                                  .call()?;
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::HashMap,
+          fs, io,
+          path::{Path, PathBuf},
+          sync::{Arc, Mutex},
+          time::{Duration, Instant}};
+
+use bon::bon;
+use reqwest::{Request, Response, ResponseBuilderExt as _};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{Level as L, event};
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("Error reading fixture file `{path}`: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Error writing fixture file `{path}`: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("Invalid fixture file `{path}`: {source}")]
+    Parse {
+        path:   PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("No recorded exchange for `{method} {origin}{path}` in `{fixture}`")]
+    NoMatch {
+        method:  String,
+        origin:  String,
+        path:    String,
+        fixture: PathBuf,
+    },
+    #[error("Recorded response for `{method} {path}` is not valid HTTP: {source}")]
+    InvalidResponse {
+        method: String,
+        path:   String,
+        source: http::Error,
+    },
+}
+// ///////////////////////////////// [ file format ] ///////////////////////////////// //
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Cassette {
+    exchanges: Vec<Exchange>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exchange {
+    request:  RecordedRequest,
+    response: RecordedResponse,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    /// `scheme://host:port` (the port always given, default or not).
+    origin: String,
+    /// Path and query.
+    path:   String,
+}
+impl RecordedRequest {
+    fn of(request: &Request) -> Self {
+        let url = request.url();
+        let origin = format!("{}://{}:{}",
+                             url.scheme(),
+                             url.host_str()
+                                .unwrap_or_default(),
+                             url.port_or_known_default()
+                                .map_or_else(String::new, |port| port.to_string()));
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        Self { method: request.method().to_string(),
+               origin,
+               path }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status:     u16,
+    headers:    Vec<(String, String)>,
+    body:       RecordedBody,
+    latency_ms: u64,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RecordedBody {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+impl RecordedBody {
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> Self {
+        String::from_utf8(bytes).map_or_else(|error| Self::Bytes(error.into_bytes()), Self::Text)
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Text(text) => text.as_bytes().to_vec(),
+            Self::Bytes(bytes) => bytes.clone(),
+        }
+    }
+}
+/// Describe the body framing of the *original* response; the replayed body is a single buffer.
+const SKIPPED_HEADERS: [&str; 3] = ["connection", "content-length", "transfer-encoding"];
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+/// A fixture file, in record or replay mode.  Clones share the same exchanges.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    inner: Arc<FixturesInner>,
+}
+#[derive(Debug)]
+struct FixturesInner {
+    path:          PathBuf,
+    mode:          FixtureMode,
+    latency_scale: f64,
+    state:         Mutex<FixtureState>,
+}
+#[derive(Debug, Default)]
+struct FixtureState {
+    cassette: Cassette,
+    /// Replay: next exchange per request.
+    cursors:  HashMap<RecordedRequest, usize>,
+}
+#[bon]
+impl Fixtures {
+    /// Record exchanges, to be written to `path` by [`save`](Self::save).  (An existing file is overwritten.)
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self::new(path.into(), FixtureMode::Record, 1.0, Cassette::default())
+    }
+
+    /// Replay the exchanges recorded at `path`.
+    ///
+    /// - `latency_scale`: multiplies each recorded latency (default: `1.0`; `0.0` replies immediately)
+    #[builder]
+    pub fn replay(#[builder(into)] path: PathBuf,
+                  #[builder(default = 1.0)] latency_scale: f64)
+                  -> Result<Self, FixtureError> {
+        let contents =
+            fs::read_to_string(&path).map_err(|source| FixtureError::Read { path: path.clone(),
+                                                                            source })?;
+        let cassette =
+            serde_json::from_str(&contents).map_err(|source| FixtureError::Parse { path:
+                                                                                       path.clone(),
+                                                                                   source })?;
+        Ok(Self::new(path,
+                     FixtureMode::Replay,
+                     latency_scale,
+                     cassette))
+    }
+
+    fn new(path: PathBuf, mode: FixtureMode, latency_scale: f64, cassette: Cassette) -> Self {
+        event!(L::DEBUG, path = %path.display(), ?mode, exchanges = cassette.exchanges.len(), "fixtures opened");
+        Self { inner: Arc::new(FixturesInner { path,
+                                               mode,
+                                               latency_scale: latency_scale.max(0.0),
+                                               state: Mutex::new(FixtureState { cassette,
+                                                                                cursors:
+                                                                                    HashMap::new() }) }), }
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.inner.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Number of exchanges recorded (or available to replay).
+    pub fn len(&self) -> usize {
+        self.lock()
+            .cassette
+            .exchanges
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the recorded exchanges to the fixture file (creating parent directories).
+    pub fn save(&self) -> Result<(), FixtureError> {
+        let path = &self.inner.path;
+        let write_error = |source| FixtureError::Write { path: path.clone(),
+                                                         source };
+        let contents = serde_json::to_string_pretty(&self.lock().cassette).expect("fixtures should serialize to JSON");
+        if let Some(parent) = path.parent()
+                                  .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(write_error)?;
+        }
+        fs::write(path, contents + "\n").map_err(write_error)?;
+        event!(L::INFO, path = %path.display(), exchanges = self.len(), "fixtures saved");
+        Ok(())
+    }
+
+    /// Stand-in for `client.execute(request)`.
+    ///
+    /// The outer error is a fixture problem (nothing to replay); the inner result is the (recorded or live) exchange.
+    pub(crate) async fn execute(&self,
+                                client: &reqwest::Client,
+                                request: Request)
+                                -> Result<Result<Response, reqwest::Error>, FixtureError> {
+        let recorded_request = RecordedRequest::of(&request);
+        match self.inner.mode {
+            FixtureMode::Replay => self.replay_exchange(recorded_request, request)
+                                       .await
+                                       .map(Ok),
+            FixtureMode::Record => {
+                let started = Instant::now();
+                let result = match client.execute(request).await {
+                    Ok(response) =>
+                        self.record_exchange(recorded_request, response, started)
+                            .await,
+                    Err(error) => Err(error),
+                };
+                Ok(result)
+            },
+        }
+    }
+
+    async fn replay_exchange(&self,
+                             recorded_request: RecordedRequest,
+                             request: Request)
+                             -> Result<Response, FixtureError> {
+        let response = {
+            let mut state = self.lock();
+            let FixtureState { cassette, cursors } = &mut *state;
+            let matches: Vec<_> = cassette.exchanges
+                                          .iter()
+                                          .filter(|exchange| exchange.request == recorded_request)
+                                          .collect();
+            let cursor = cursors.entry(recorded_request.clone())
+                                .or_default();
+            let Some(exchange) = matches.get(*cursor)
+                                        .or(matches.last())
+            else {
+                return Err(FixtureError::NoMatch { method:  recorded_request.method,
+                                                   origin:  recorded_request.origin,
+                                                   path:    recorded_request.path,
+                                                   fixture: self.inner.path.clone(), });
+            };
+            *cursor += 1;
+            exchange.response.clone()
+        };
+        event!(L::DEBUG, method = %recorded_request.method, origin = %recorded_request.origin, path = %recorded_request.path, status = response.status, "replaying fixture");
+        let latency = Duration::from_millis(response.latency_ms).mul_f64(self.inner.latency_scale);
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        let mut builder = http::Response::builder().status(response.status)
+                                                   .url(request.url().clone());
+        for (name, value) in &response.headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(response.body.to_bytes())
+               .map(Response::from)
+               .map_err(|source| FixtureError::InvalidResponse { method: recorded_request.method,
+                                                                 path: recorded_request.path,
+                                                                 source })
+    }
+
+    /// Buffer `response`, store it, and return an equivalent response.
+    async fn record_exchange(&self,
+                             recorded_request: RecordedRequest,
+                             response: Response,
+                             started: Instant)
+                             -> Result<Response, reqwest::Error> {
+        let status = response.status();
+        let url = response.url().clone();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        let recorded_response =
+            RecordedResponse { status:     status.as_u16(),
+                               headers:
+                                   headers.iter()
+                                          .filter(|(name, _)| {
+                                              !SKIPPED_HEADERS.contains(&name.as_str())
+                                          })
+                                          .filter_map(|(name, value)| {
+                                              Some((name.to_string(),
+                                                    value.to_str()
+                                                         .ok()?
+                                                         .to_string()))
+                                          })
+                                          .collect(),
+                               body:       RecordedBody::from_bytes(body.to_vec()),
+                               latency_ms: started.elapsed()
+                                                  .as_millis()
+                                                  .try_into()
+                                                  .unwrap_or(u64::MAX), };
+        event!(L::DEBUG, method = %recorded_request.method, origin = %recorded_request.origin, path = %recorded_request.path, %status, "recorded fixture");
+        self.lock()
+            .cassette
+            .exchanges
+            .push(Exchange { request:  recorded_request,
+                             response: recorded_response, });
+
+        let mut rebuilt = http::Response::builder().status(status)
+                                                   .url(url);
+        if let Some(rebuilt_headers) = rebuilt.headers_mut() {
+            *rebuilt_headers = headers;
+        }
+        Ok(rebuilt.body(body)
+                  .map(Response::from)
+                  .expect("parts taken from a valid response"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FixtureState> {
+        self.inner
+            .state
+            .lock()
+            .expect("fixture lock should not be poisoned")
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{HttpClient, RetryPolicy, SendError,
+                http_stub::{StubResponse, spawn_http_stub}};
+
+    fn temp_fixture(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("utilities-fixtures-{}-{name}.json", std::process::id()))
+    }
+    fn client(base: &str, fixtures: &Fixtures) -> HttpClient {
+        HttpClient::builder().base_url(base.parse().unwrap())
+                             .allow_http(true)
+                             .fixtures(fixtures.clone())
+                             .build()
+                             .unwrap()
+    }
+    fn write_fixture(name: &str, exchanges: &str) -> PathBuf {
+        let path = temp_fixture(name);
+        fs::write(&path, format!(r#"{{ "exchanges": [{exchanges}] }}"#)).unwrap();
+        path
+    }
+    const REPLAY_BASE: &str = "http://replay.invalid";
+    fn exchange(path: &str, status: u16, body: &str, latency_ms: u64) -> String {
+        format!(r#"{{ "request": {{ "method": "GET", "origin": "{REPLAY_BASE}:80", "path": "{path}" }},
+                      "response": {{ "status": {status}, "headers": [["content-type", "application/json"]],
+                                     "body": {body:?}, "latency_ms": {latency_ms} }} }}"#)
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay_without_network() {
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let (base, requests) = spawn_http_stub(move |request| {
+            // busy, then fine
+            match calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed) {
+                0 => StubResponse::new(503, "busy"),
+                _ => StubResponse::ok_json(format!(r#"{{"path":"{}"}}"#, request.path)).with_header("x-stub",
+                                                                                                    "yes"),
+            }
+        });
+        let path = temp_fixture("roundtrip");
+        let recorder = Fixtures::record(&path);
+        let api = client(&base, &recorder);
+        let mut live = Vec::new();
+        for _ in 0..3 {
+            live.push(api.send(api.get("todos?userId=1")
+                                  .unwrap())
+                         .await
+                         .unwrap()
+                         .status()
+                         .as_u16());
+        }
+        assert_eq!(live, [503, 200, 200]);
+        // every exchange, repeats included
+        assert_eq!(recorder.len(), 3);
+        recorder.save().unwrap();
+        assert_eq!(requests.try_iter().count(), 3);
+
+        let replayer = Fixtures::replay().path(&path)
+                                         .latency_scale(0.0)
+                                         .call()
+                                         .unwrap();
+        let api = client(&base, &replayer);
+        let mut replayed = Vec::new();
+        for _ in 0..3 {
+            replayed.push(api.send(api.get("todos?userId=1")
+                                      .unwrap())
+                             .await
+                             .unwrap());
+        }
+        assert_eq!(replayed.iter()
+                           .map(|response| response.status().as_u16())
+                           .collect::<Vec<_>>(),
+                   [503, 200, 200]);
+        let replayed = replayed.pop().unwrap();
+        assert_eq!(replayed.headers()["x-stub"], "yes");
+        assert_eq!(replayed.url().as_str(), format!("{base}/todos?userId=1"));
+        assert_eq!(replayed.text().await.unwrap(),
+                   r#"{"path":"/todos?userId=1"}"#);
+        assert_eq!(requests.try_iter().count(), 0);
+
+        // another host, same path: not a match
+        let api = client(REPLAY_BASE, &replayer);
+        let error = api.send(api.get("todos?userId=1")
+                                .unwrap())
+                       .await
+                       .unwrap_err();
+        assert!(matches!(error, SendError::Fixture(FixtureError::NoMatch { ref origin, .. }) if origin == "http://replay.invalid:80"),
+                "{error:?}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_order_then_repeat_last_and_no_match() {
+        let path = write_fixture("order",
+                                 &[exchange("/json", 503, "busy", 0),
+                                   exchange("/json", 200, "{}", 0)].join(","));
+        let fixtures = Fixtures::replay().path(&path)
+                                         .call()
+                                         .unwrap();
+        let api = client(REPLAY_BASE, &fixtures);
+        let mut statuses = Vec::new();
+        for _ in 0..3 {
+            statuses.push(api.send(api.get("json").unwrap())
+                             .await
+                             .unwrap()
+                             .status()
+                             .as_u16());
+        }
+        assert_eq!(statuses, [503, 200, 200]);
+
+        let error = api.send(api.get("missing").unwrap())
+                       .await
+                       .unwrap_err();
+        assert!(matches!(error, SendError::Fixture(FixtureError::NoMatch { ref path, .. }) if path == "/missing"),
+                "{error:?}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_simulates_latency_and_drives_retries() {
+        let path = write_fixture("retry",
+                                 &[exchange("/status", 503, "", 200),
+                                   exchange("/status", 200, "ok", 1_000)].join(","));
+        let fixtures = Fixtures::replay().path(&path)
+                                         .call()
+                                         .unwrap();
+        let api = client(REPLAY_BASE, &fixtures);
+        let policy = RetryPolicy::builder().max_retries(2)
+                                           .base_delay(Duration::from_millis(100))
+                                           .build();
+        let start = tokio::time::Instant::now();
+        let response = api.send_with_retry(api.get("status").unwrap(), &policy)
+                          .await
+                          .unwrap();
+        assert_eq!(response.status(), 200);
+        // 200ms + backoff (≤ 100ms, jittered) + 1s
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(1_200) && elapsed <= Duration::from_millis(1_300),
+                "{elapsed:?}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_reports_missing_and_invalid_files() {
+        let missing = Fixtures::replay().path(temp_fixture("missing"))
+                                        .call()
+                                        .unwrap_err();
+        assert!(matches!(missing, FixtureError::Read { .. }), "{missing:?}");
+
+        let path = temp_fixture("invalid");
+        fs::write(&path, "not json").unwrap();
+        let invalid = Fixtures::replay().path(&path)
+                                        .call()
+                                        .unwrap_err();
+        assert!(matches!(invalid, FixtureError::Parse { .. }), "{invalid:?}");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+//! Hedged requests: if a request is slower than usual, send a duplicate & take whichever succeeds first.
+//!
+//! ## Delay
+//! The duplicate (*hedge*) goes out once the original has taken longer than the `percentile` (e.g. p95)
+//! of recently observed latencies.  Until `min_samples` latencies have been seen, `initial_delay` is used.
+//! Observed latency is end-to-end, from sending the original to the winning response (a winning hedge's own, shorter
+//! time would drag the percentile down, and with it the delay).
+//!
+//! ## Winner
+//! The first *success* (a response without a `5xx` status) wins; the other attempt is dropped, which cancels it.
+//! If the first to finish failed, the other is awaited instead.
+//! If the original fails before the hedge delay, it is returned as-is (that is a job for a [`RetryPolicy`](crate::RetryPolicy)).
+//!
+//! ## Budget
+//! Hedges add load, so they are capped: at most `max_extra_ratio` of requests (plus `min_extra`, for a cold start).
+//! Past the cap, requests just wait for the original.
+//!
+//! Only idempotent methods (`GET`, `HEAD`, `PUT`, `DELETE`, ...) with clonable bodies are hedged.
+//!
+//! ## Use:
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use utilities::{HedgePolicy, HttpClient};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let api = HttpClient::builder().base_url("https://httpbin.org".parse()?)
+//!                                .build()?;
+//! // shared by all requests it should learn latencies from
+//! let hedge = HedgePolicy::builder().percentile(0.9)
+//!                                   .initial_delay(Duration::from_millis(500))
+//!                                   .build();
+//! let response = api.send_hedged(api.get("get")?, &hedge)
+//!                   .await?;
+//! println!("{:?}", hedge.stats());
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::VecDeque,
+          pin::pin,
+          sync::{Arc, Mutex,
+                 atomic::{AtomicU64, Ordering}},
+          time::Duration};
+
+use bon::bon;
+use futures::future::{self, Either};
+use reqwest::{Request, Response};
+use serde::Serialize;
+use tokio::time::{self, Instant};
+use tracing::{Level as L, event, instrument};
+// ///////////////////////////////// [ stats ] ///////////////////////////////// //
+/// Running counts (shared by all clones of a [`HedgePolicy`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct HedgeStats {
+    /// Requests executed through the policy.
+    pub requests:      u64,
+    /// Duplicates sent.
+    pub hedges:        u64,
+    /// Duplicates that finished (successfully) first.
+    pub hedge_wins:    u64,
+    /// Hedge delay passed, but the extra-load budget was spent.
+    pub budget_denied: u64,
+}
+#[derive(Debug, Default)]
+struct Counters {
+    requests:      AtomicU64,
+    hedges:        AtomicU64,
+    hedge_wins:    AtomicU64,
+    budget_denied: AtomicU64,
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// When to send a duplicate request, and how many.  See [module docs](self).  Clones share latencies & counters.
+#[derive(Debug, Clone)]
+pub struct HedgePolicy {
+    inner: Arc<HedgeInner>,
+}
+#[derive(Debug)]
+struct HedgeInner {
+    percentile:      f64,
+    initial_delay:   Duration,
+    min_delay:       Duration,
+    min_samples:     usize,
+    window:          usize,
+    max_extra_ratio: f64,
+    min_extra:       u64,
+    latencies:       Mutex<VecDeque<Duration>>,
+    counters:        Counters,
+}
+#[bon]
+impl HedgePolicy {
+    #[builder]
+    pub fn new(/// Hedge once the original is slower than this quantile of recent latencies (`0.0..=1.0`).
+               #[builder(default = 0.95)]
+               percentile: f64,
+               /// Hedge delay until `min_samples` latencies are known.
+               #[builder(default = Duration::from_secs(1))]
+               initial_delay: Duration,
+               /// Floor on the hedge delay (very fast percentiles would otherwise hedge almost everything).
+               #[builder(default = Duration::from_millis(10))]
+               min_delay: Duration,
+               #[builder(default = 20)] min_samples: usize,
+               /// Recent latencies kept for the percentile.
+               #[builder(default = 200)]
+               window: usize,
+               /// Hedges allowed, as a fraction of requests.
+               #[builder(default = 0.1)]
+               max_extra_ratio: f64,
+               /// Hedges allowed on top of the ratio.
+               #[builder(default = 1)]
+               min_extra: u64)
+               -> Self {
+        Self { inner: Arc::new(HedgeInner { percentile: percentile.clamp(0.0, 1.0),
+                                            initial_delay,
+                                            min_delay,
+                                            min_samples: min_samples.max(1),
+                                            window: window.max(1),
+                                            max_extra_ratio: max_extra_ratio.max(0.0),
+                                            min_extra,
+                                            latencies: Mutex::new(VecDeque::new()),
+                                            counters: Counters::default() }), }
+    }
+}
+impl Default for HedgePolicy {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+impl HedgePolicy {
+    pub fn stats(&self) -> HedgeStats {
+        let counters = &self.inner.counters;
+        HedgeStats { requests:      counters.requests
+                                            .load(Ordering::Relaxed),
+                     hedges:        counters.hedges
+                                            .load(Ordering::Relaxed),
+                     hedge_wins:    counters.hedge_wins
+                                            .load(Ordering::Relaxed),
+                     budget_denied: counters.budget_denied
+                                            .load(Ordering::Relaxed), }
+    }
+
+    /// How long the next request may take before it is hedged.
+    pub fn delay(&self) -> Duration {
+        let latencies = self.lock();
+        if latencies.len() < self.inner.min_samples {
+            return self.inner.initial_delay;
+        }
+        let mut sorted: Vec<_> = latencies.iter()
+                                          .copied()
+                                          .collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() - 1) as f64 * self.inner.percentile).round() as usize;
+        sorted[index].max(self.inner.min_delay)
+    }
+
+    /// Send `request` via `send`, hedging it with a duplicate per this policy.
+    /// (e.g. [`HttpClient::execute`](crate::HttpClient::execute), so that both attempts wait on rate limits)
+    #[instrument(skip_all, fields(method = %request.method(), url = %request.url()))]
+    pub async fn execute_with<F, Fut, E>(&self, request: Request, send: F) -> Result<Response, E>
+        where F: Fn(Request) -> Fut,
+              Fut: Future<Output = Result<Response, E>> {
+        self.inner
+            .counters
+            .requests
+            .fetch_add(1, Ordering::Relaxed);
+        let duplicate = request.try_clone()
+                               .filter(|_| {
+                                   request.method()
+                                          .is_idempotent()
+                               });
+        let started = Instant::now();
+        let mut original = pin!(send(request));
+        let Some(duplicate) = duplicate else {
+            return self.observe(original.await, started);
+        };
+        let delay = self.delay();
+        let original = match future::select(original.as_mut(), pin!(time::sleep(delay))).await {
+            Either::Left((outcome, _)) => return self.observe(outcome, started),
+            Either::Right(((), original)) => original,
+        };
+        if !self.try_spend() {
+            event!(L::DEBUG,
+                   ?delay,
+                   "hedge delay passed, but the hedge budget is spent");
+            return self.observe(original.await, started);
+        }
+        event!(L::DEBUG, ?delay, "original is slow; sending hedge");
+        let hedge = pin!(send(duplicate));
+        // the loser (pinned on this stack frame) is dropped, i.e. cancelled, on return
+        match future::select(original, hedge).await {
+            Either::Left((outcome, _)) if is_success(&outcome) => {
+                event!(L::DEBUG, "original won; cancelling hedge");
+                self.observe(outcome, started)
+            },
+            Either::Left((_, hedge)) => self.observe_hedge(hedge.await, started),
+            Either::Right((outcome, _)) if is_success(&outcome) => {
+                event!(L::DEBUG, "hedge won; cancelling original");
+                self.observe_hedge(outcome, started)
+            },
+            Either::Right((_, original)) => self.observe(original.await, started),
+        }
+    }
+
+    /// Count a hedge against the budget, if there's room.
+    fn try_spend(&self) -> bool {
+        let counters = &self.inner.counters;
+        let allowed = self.inner.min_extra
+                      + (counters.requests
+                                 .load(Ordering::Relaxed) as f64
+                         * self.inner.max_extra_ratio) as u64;
+        let spent = counters.hedges
+                            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |hedges| {
+                                (hedges < allowed).then_some(hedges + 1)
+                            })
+                            .is_ok();
+        if !spent {
+            counters.budget_denied
+                    .fetch_add(1, Ordering::Relaxed);
+        }
+        spent
+    }
+
+    /// `started`: the *original*'s start.
+    fn observe_hedge<E>(&self,
+                        outcome: Result<Response, E>,
+                        started: Instant)
+                        -> Result<Response, E> {
+        if is_success(&outcome) {
+            self.inner
+                .counters
+                .hedge_wins
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        self.observe(outcome, started)
+    }
+
+    /// Record a successful attempt's latency.
+    fn observe<E>(&self, outcome: Result<Response, E>, started: Instant) -> Result<Response, E> {
+        if is_success(&outcome) {
+            let mut latencies = self.lock();
+            latencies.push_back(started.elapsed());
+            if latencies.len() > self.inner.window {
+                latencies.pop_front();
+            }
+        }
+        outcome
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Duration>> {
+        self.inner
+            .latencies
+            .lock()
+            .expect("hedge latency lock should not be poisoned")
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+fn is_success<E>(outcome: &Result<Response, E>) -> bool {
+    outcome.as_ref()
+           .is_ok_and(|response| {
+               !response.status()
+                        .is_server_error()
+           })
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    use pretty_assertions::assert_eq;
+    use reqwest::{Method, StatusCode, Url};
+
+    use super::*;
+
+    fn request(method: Method) -> Request {
+        Request::new(method, Url::parse("http://hedge.invalid/get").unwrap())
+    }
+    fn response(status: u16) -> Response {
+        Response::from(http::Response::builder().status(status)
+                                                .body(Vec::new())
+                                                .unwrap())
+    }
+    /// `send` whose n-th call answers `status` after `delays[n]`.
+    type Sent = futures::future::BoxFuture<'static, Result<Response, ()>>;
+    fn scripted(delays: &'static [(u64, u16)]) -> (impl Fn(Request) -> Sent, Arc<AtomicU64>) {
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        let send = move |_request| {
+            let (delay, status) = delays[counter.fetch_add(1, Ordering::Relaxed) as usize];
+            Box::pin(async move {
+                time::sleep(Duration::from_millis(delay)).await;
+                Ok(response(status))
+            }) as Sent
+        };
+        (send, calls)
+    }
+    fn policy() -> HedgePolicy {
+        HedgePolicy::builder().initial_delay(Duration::from_millis(500))
+                              .build()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_original_is_hedged() {
+        let (send, calls) = scripted(&[(3_000, 200), (100, 200)]);
+        let policy = policy();
+        let start = Instant::now();
+        let response = policy.execute_with(request(Method::GET), send)
+                             .await
+                             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(start.elapsed(), Duration::from_millis(600));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        // end-to-end, not the hedge's own 100ms
+        assert_eq!(policy.lock()
+                         .iter()
+                         .copied()
+                         .collect::<Vec<_>>(),
+                   [Duration::from_millis(600)]);
+        assert_eq!(policy.stats(),
+                   HedgeStats { requests:      1,
+                                hedges:        1,
+                                hedge_wins:    1,
+                                budget_denied: 0, });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_original_is_not_hedged() {
+        let (send, calls) = scripted(&[(200, 200)]);
+        let policy = policy();
+        policy.execute_with(request(Method::GET), send)
+              .await
+              .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(policy.stats().hedges, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_hedge_falls_back_to_original() {
+        let (send, _) = scripted(&[(1_000, 200), (10, 503)]);
+        let policy = policy();
+        let start = Instant::now();
+        let response = policy.execute_with(request(Method::GET), send)
+                             .await
+                             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(start.elapsed(), Duration::from_millis(1_000));
+        assert_eq!(policy.stats().hedge_wins, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_budget_and_method_limit_hedges() {
+        let policy = HedgePolicy::builder().initial_delay(Duration::from_millis(500))
+                                           .max_extra_ratio(0.0)
+                                           .min_extra(1)
+                                           .build();
+        let (send, calls) = scripted(&[(1_000, 200), (10, 200), (1_000, 200), (1_000, 200)]);
+        policy.execute_with(request(Method::GET), &send)
+              .await
+              .unwrap();
+        // budget of 1 spent: waits for the original
+        policy.execute_with(request(Method::GET), &send)
+              .await
+              .unwrap();
+        // not idempotent: never hedged
+        policy.execute_with(request(Method::POST), &send)
+              .await
+              .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+        assert_eq!(policy.stats(),
+                   HedgeStats { requests:      3,
+                                hedges:        1,
+                                hedge_wins:    1,
+                                budget_denied: 1, });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_loser_is_cancelled() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let calls = AtomicU64::new(0);
+        let send = |_request| {
+            let finished = finished.clone();
+            let call = calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if call == 0 {
+                    time::sleep(Duration::from_secs(10)).await;
+                    finished.store(true, Ordering::Relaxed);
+                } else {
+                    time::sleep(Duration::from_millis(10)).await;
+                }
+                Ok::<_, ()>(response(200))
+            }
+        };
+        policy().execute_with(request(Method::GET), send)
+                .await
+                .unwrap();
+        time::sleep(Duration::from_secs(20)).await;
+        assert!(!finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_delay_tracks_percentile() {
+        let policy = HedgePolicy::builder().percentile(0.9)
+                                           .min_samples(10)
+                                           .initial_delay(Duration::from_secs(1))
+                                           .build();
+        assert_eq!(policy.delay(), Duration::from_secs(1));
+        for millis in 1..=100 {
+            let _ = policy.observe(Ok::<_, ()>(response(200)),
+                                   Instant::now() - Duration::from_millis(millis));
+        }
+        // tokio's clock is real here: allow for the time taken to record
+        let delay = policy.delay();
+        assert!((Duration::from_millis(90)..Duration::from_millis(95)).contains(&delay),
+                "{delay:?}");
+    }
+}
@@ -282,6 +282,29 @@ mod tests {
 
         assert!(matches!(result, Err(HiddenValueError::RevealLengthTooLong { .. })));
     }
+    #[test]
+    fn test_secret_never_logged() {
+        const TEST_KEY_3: &str = "TEST_KEY_3";
+        const TEST_VALUE_3: &str = "s3cr3t-do-not-log";
+        // SAFETY: Test code only. Sets an env variable.
+        //         Cost of collision should be low.
+        //         (And test should be run in independent process.)
+        #[expect(unsafe_code)]
+        unsafe {
+            std::env::set_var(TEST_KEY_3, TEST_VALUE_3)
+        };
+        let capture = crate::testing::EventCapture::start();
+        let hidden = HiddenValue::from_env_builder().key(TEST_KEY_3)
+                                                    .load_env_file(false)
+                                                    .reveal_len(NonZeroUsize::new(3).unwrap())
+                                                    .build()
+                                                    .unwrap();
+        let _ = hidden.expose_value();
+        tracing::event!(L::INFO, ?hidden);
+
+        assert!(!capture.events().is_empty());
+        assert!(!capture.any_contains(TEST_VALUE_3));
+    }
 }
 #[cfg(test)]
 mod quickcheck_tests {
@@ -0,0 +1,835 @@
+//! Private HTTP response cache for [`HttpClient`](crate::HttpClient): in memory, optionally mirrored to disk.
+//!
+//! - only `GET` responses with status `200` are stored, and only if they can be reused:
+//!   a freshness lifetime (`Cache-Control: max-age`, or `Expires` - `Date`) and/or a validator (`ETag`, `Last-Modified`)
+//! - **fresh** entries are served without a request (*hit*)
+//! - **stale** entries (or a request with `Cache-Control: no-cache`/`max-age=0`) are revalidated:
+//!   the request goes out with `If-None-Match`/`If-Modified-Since`, and a `304` serves the stored body with refreshed headers
+//! - `Cache-Control: no-store` (request or response) bypasses the cache, as do `Range` requests; `Vary: *` is never stored
+//! - a successful `POST`/`PUT`/`PATCH`/`DELETE` drops the entry for its URL
+//!
+//! ## Keys
+//! Entries are keyed by method & URL; the request headers named by the response's `Vary` are stored with it,
+//! and a lookup whose values differ is a miss (and its response replaces the entry).
+//! (Client default headers, e.g. `accept`, are added by reqwest after this point: only headers set on the request count.)
+//!
+//! ## Disk
+//! With a `dir`, each entry is also written there as JSON (`<SHA-256 of key>.json`) and read back on a memory miss,
+//! so a cache survives restarts (and Rust upgrades: the file name doesn't depend on `std`'s hasher).  Files are read &
+//! written with `tokio::fs`, off the runtime's worker threads.  Disk errors are logged and otherwise ignored: the cache is only ever an optimisation.
+//!
+//! ## Counters
+//! Every lookup emits a `DEBUG` event with its outcome & the running [`HttpCacheStats`].
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::{HttpCache, HttpClient};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let cache = HttpCache::builder().dir(std::env::temp_dir().join("todos-cache"))
+//!                                 .build();
+//! let api = HttpClient::builder().base_url("https://jsonplaceholder.typicode.com".parse()?)
+//!                                .cache(cache.clone())
+//!                                .build()?;
+//! api.send(api.get("todos?userId=1")?)
+//!    .await?;
+//! // fresh: served from the cache (stale: `If-None-Match` -> `304`, body from the cache)
+//! api.send(api.get("todos?userId=1")?)
+//!    .await?;
+//! println!("{:?}", cache.stats());
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::HashMap,
+          env, io,
+          path::{Path, PathBuf},
+          sync::{Arc, Mutex,
+                 atomic::{AtomicU64, Ordering}},
+          time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use bon::bon;
+use reqwest::{Method, Request, Response, ResponseBuilderExt as _, StatusCode, Url,
+              header::{self, HeaderMap, HeaderName, HeaderValue}};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{Level as L, event};
+
+use crate::fixtures::RecordedBody;
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+/// Describe the body framing of the *original* response; a cached body is a single buffer.
+const SKIPPED_HEADERS: [&str; 3] = ["connection", "content-length", "transfer-encoding"];
+// ///////////////////////////////// [ stats ] ///////////////////////////////// //
+/// Running lookup counts (shared by all clones of an [`HttpCache`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct HttpCacheStats {
+    /// Served from a fresh entry, without a request.
+    pub hits:        u64,
+    /// No usable entry: sent as usual.
+    pub misses:      u64,
+    /// Stale entry confirmed by a `304`.
+    pub revalidated: u64,
+    /// Responses stored (new, or replacing an entry).
+    pub stores:      u64,
+    /// Not cacheable: `no-store`, a method other than `GET`, a `Range`, or a conditional request of the caller's own.
+    pub bypassed:    u64,
+}
+#[derive(Debug, Default)]
+struct Counters {
+    hits:        AtomicU64,
+    misses:      AtomicU64,
+    revalidated: AtomicU64,
+    stores:      AtomicU64,
+    bypassed:    AtomicU64,
+}
+impl Counters {
+    fn snapshot(&self) -> HttpCacheStats {
+        HttpCacheStats { hits:        self.hits
+                                          .load(Ordering::Relaxed),
+                         misses:      self.misses
+                                          .load(Ordering::Relaxed),
+                         revalidated: self.revalidated
+                                          .load(Ordering::Relaxed),
+                         stores:      self.stores
+                                          .load(Ordering::Relaxed),
+                         bypassed:    self.bypassed
+                                          .load(Ordering::Relaxed), }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Hit,
+    Miss,
+    Revalidated,
+    Bypassed,
+}
+// ///////////////////////////////// [ entries ] ///////////////////////////////// //
+/// A stored response (also the on-disk format).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key:         String,
+    /// `Vary`-named request headers (lower-cased) & their values when stored.
+    vary:        Vec<(String, Option<String>)>,
+    status:      u16,
+    headers:     Vec<(String, String)>,
+    body:        RecordedBody,
+    /// Unix seconds.
+    fresh_until: u64,
+    #[serde(skip)]
+    last_used:   u64,
+}
+impl CacheEntry {
+    fn header(&self, name: &HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn matches_vary(&self, request: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request_header(request, name) == value.as_deref())
+    }
+
+    fn is_fresh(&self, now: u64) -> bool {
+        now < self.fresh_until
+    }
+
+    /// Add `If-None-Match`/`If-Modified-Since`; `false` if the entry has no validator.
+    fn add_validators(&self, request: &mut Request) -> bool {
+        let mut added = false;
+        for (validator, condition) in [(header::ETAG, header::IF_NONE_MATCH),
+                                       (header::LAST_MODIFIED, header::IF_MODIFIED_SINCE)]
+        {
+            if let Some(value) = self.header(&validator)
+                                     .and_then(|value| HeaderValue::from_str(value).ok())
+            {
+                request.headers_mut()
+                       .insert(condition, value);
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Merge a `304`'s headers into the stored ones & recompute freshness.
+    fn refresh(&mut self, not_modified: &HeaderMap, now: u64) {
+        let updated = header_pairs(not_modified);
+        self.headers
+            .retain(|(name, _)| {
+                !updated.iter()
+                        .any(|(key, _)| key == name)
+            });
+        self.headers.extend(updated);
+        self.fresh_until = now + freshness_lifetime(&self.header_map()).as_secs();
+    }
+
+    fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((HeaderName::from_bytes(name.as_bytes()).ok()?,
+                      HeaderValue::from_str(value).ok()?))
+            })
+            .collect()
+    }
+
+    fn to_response(&self, url: Url) -> Response {
+        let mut builder = http::Response::builder().status(self.status)
+                                                   .url(url);
+        if let Some(headers) = builder.headers_mut() {
+            *headers = self.header_map();
+        }
+        builder.body(self.body.to_bytes())
+               .map(Response::from)
+               .expect("cached status & headers were taken from a valid response")
+    }
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// A response cache, for [`HttpClient::builder().cache(..)`](crate::HttpClient::builder).  Clones share entries & counters.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    inner: Arc<HttpCacheInner>,
+}
+#[derive(Debug)]
+struct HttpCacheInner {
+    dir:         Option<PathBuf>,
+    max_entries: usize,
+    entries:     Mutex<HashMap<String, CacheEntry>>,
+    counters:    Counters,
+    /// Recency ticks, for eviction.
+    clock:       AtomicU64,
+}
+#[bon]
+impl HttpCache {
+    /// - `dir`: also keep entries on disk, here (created on first write)
+    /// - `max_entries`: in memory; the least recently used entry is evicted beyond this (default: 1024)
+    #[builder]
+    pub fn new(#[builder(into)] dir: Option<PathBuf>,
+               #[builder(default = DEFAULT_MAX_ENTRIES)] max_entries: usize)
+               -> Self {
+        event!(L::DEBUG, dir = ?dir, max_entries, "http cache created");
+        Self { inner: Arc::new(HttpCacheInner { dir,
+                                                max_entries: max_entries.max(1),
+                                                entries: Mutex::new(HashMap::new()),
+                                                counters: Counters::default(),
+                                                clock: AtomicU64::new(0) }), }
+    }
+}
+impl HttpCache {
+    /// `$XDG_CACHE_HOME/<app_name>/http` (falling back to `~/.cache/<app_name>/http`), if either is set.
+    pub fn xdg_dir(app_name: &str) -> Option<PathBuf> {
+        let cache_home = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty())
+                                                      .map(PathBuf::from)
+                                                      .or_else(|| {
+                                                          env::var_os("HOME").map(|home| {
+                                                              PathBuf::from(home).join(".cache")
+                                                          })
+                                                      })?;
+        Some(cache_home.join(app_name)
+                       .join("http"))
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.inner.dir.as_deref()
+    }
+
+    pub fn stats(&self) -> HttpCacheStats {
+        self.inner.counters.snapshot()
+    }
+
+    /// Entries in memory.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every entry (memory & disk).  Counters are kept.
+    pub async fn clear(&self) {
+        self.lock().clear();
+        let Some(dir) = &self.inner.dir else {
+            return;
+        };
+        let Ok(mut files) = fs::read_dir(dir).await else {
+            return;
+        };
+        while let Ok(Some(file)) = files.next_entry().await {
+            let path = file.path();
+            if path.extension()
+                   .is_some_and(|ext| ext == "json")
+               && let Err(error) = fs::remove_file(&path).await
+            {
+                event!(L::WARN, path = %path.display(), %error, "could not remove http cache file");
+            }
+        }
+    }
+
+    /// Serve `request` from the cache, or via `send` (revalidating & storing as needed).
+    pub(crate) async fn execute<F, Fut, E>(&self,
+                                           mut request: Request,
+                                           send: F)
+                                           -> Result<Response, E>
+        where F: FnOnce(Request) -> Fut,
+              Fut: Future<Output = Result<Response, E>>,
+              E: From<reqwest::Error> {
+        let key = cache_key(request.method(), request.url());
+        let url = request.url().clone();
+        if request.method() != Method::GET {
+            let invalidates = !request.method().is_safe();
+            self.record(Outcome::Bypassed, &key);
+            let response = send(request).await?;
+            if invalidates && response.status().is_success() {
+                self.remove(&cache_key(&Method::GET, &url))
+                    .await;
+            }
+            return Ok(response);
+        }
+        let request_directives = directives(request.headers());
+        if request_directives.no_store
+           || request.headers()
+                     .contains_key(header::IF_NONE_MATCH)
+           || request.headers()
+                     .contains_key(header::IF_MODIFIED_SINCE)
+           || request.headers()
+                     .contains_key(header::RANGE)
+        {
+            self.record(Outcome::Bypassed, &key);
+            return send(request).await;
+        }
+
+        let now = unix_now();
+        let cached = self.lookup(&key)
+                         .await
+                         .filter(|entry| entry.matches_vary(&request));
+        let revalidating = match &cached {
+            Some(entry) if entry.is_fresh(now) && !request_directives.no_cache => {
+                self.record(Outcome::Hit, &key);
+                return Ok(entry.to_response(url));
+            },
+            Some(entry) => entry.add_validators(&mut request),
+            None => false,
+        };
+        let vary_request = VaryRequest::of(&request);
+        let response = send(request).await?;
+        let now = unix_now();
+
+        if let Some(mut entry) =
+            cached.filter(|_| revalidating && response.status() == StatusCode::NOT_MODIFIED)
+        {
+            entry.refresh(response.headers(), now);
+            self.record(Outcome::Revalidated, &key);
+            let response = entry.to_response(url);
+            self.store(entry).await;
+            return Ok(response);
+        }
+        self.record(Outcome::Miss, &key);
+        if !is_cacheable(&response) {
+            return Ok(response);
+        }
+        let status = response.status();
+        let headers = response.headers().clone();
+        let response_url = response.url().clone();
+        let body = response.bytes().await?;
+        let entry = CacheEntry { vary: vary_request.values(&headers),
+                                 status: status.as_u16(),
+                                 headers: header_pairs(&headers),
+                                 body: RecordedBody::from_bytes(body.to_vec()),
+                                 fresh_until: now + freshness_lifetime(&headers).as_secs(),
+                                 last_used: 0,
+                                 key };
+        let response = entry.to_response(response_url);
+        self.store(entry).await;
+        self.inner
+            .counters
+            .stores
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(response)
+    }
+
+    /// Memory first, then disk.
+    async fn lookup(&self, key: &str) -> Option<CacheEntry> {
+        let tick = self.tick();
+        if let Some(entry) = self.lock().get_mut(key) {
+            entry.last_used = tick;
+            return Some(entry.clone());
+        }
+        let path = self.file_path(key)?;
+        let contents = fs::read_to_string(&path).await
+                                                .ok()?;
+        match serde_json::from_str::<CacheEntry>(&contents) {
+            // a hash collision is just a miss
+            Ok(entry) if entry.key == key => {
+                self.insert(entry.clone());
+                Some(entry)
+            },
+            Ok(_) => None,
+            Err(error) => {
+                event!(L::WARN, path = %path.display(), %error, "ignoring unreadable http cache file");
+                None
+            },
+        }
+    }
+
+    async fn store(&self, entry: CacheEntry) {
+        if let Some(path) = self.file_path(&entry.key) {
+            let json =
+                serde_json::to_string(&entry).expect("cache entries should serialize to JSON");
+            let written = async {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).await?;
+                }
+                fs::write(&path, json).await
+            };
+            if let Err(error) = written.await {
+                event!(L::WARN, path = %path.display(), %error, "could not write http cache file");
+            }
+        }
+        self.insert(entry);
+    }
+
+    fn insert(&self, mut entry: CacheEntry) {
+        entry.last_used = self.tick();
+        let mut entries = self.lock();
+        entries.insert(entry.key.clone(), entry);
+        if entries.len() > self.inner.max_entries
+           && let Some(oldest) = entries.values()
+                                        .min_by_key(|entry| entry.last_used)
+                                        .map(|entry| entry.key.clone())
+        {
+            entries.remove(&oldest);
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        self.lock().remove(key);
+        if let Some(path) = self.file_path(key)
+           && let Err(error) = fs::remove_file(&path).await
+           && error.kind() != io::ErrorKind::NotFound
+        {
+            event!(L::WARN, path = %path.display(), %error, "could not remove http cache file");
+        }
+    }
+
+    fn record(&self, outcome: Outcome, key: &str) {
+        let counters = &self.inner.counters;
+        match outcome {
+            Outcome::Hit => &counters.hits,
+            Outcome::Miss => &counters.misses,
+            Outcome::Revalidated => &counters.revalidated,
+            Outcome::Bypassed => &counters.bypassed,
+        }.fetch_add(1, Ordering::Relaxed);
+        let HttpCacheStats { hits,
+                             misses,
+                             revalidated,
+                             stores,
+                             bypassed, } = counters.snapshot();
+        event!(L::DEBUG,
+               ?outcome,
+               key,
+               hits,
+               misses,
+               revalidated,
+               stores,
+               bypassed,
+               "http cache lookup");
+    }
+
+    fn file_path(&self, key: &str) -> Option<PathBuf> {
+        self.inner
+            .dir
+            .as_ref()
+            .map(|dir| {
+                dir.join(format!("{}.json",
+                                 hex::encode(digest::digest(&digest::SHA256, key.as_bytes()))))
+            })
+    }
+
+    fn tick(&self) -> u64 {
+        self.inner
+            .clock
+            .fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry>> {
+        self.inner
+            .entries
+            .lock()
+            .expect("http cache lock should not be poisoned")
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+fn cache_key(method: &Method, url: &Url) -> String {
+    format!("{method} {url}")
+}
+
+/// The request's headers, kept until the response says which ones it varies on.
+struct VaryRequest(HeaderMap);
+impl VaryRequest {
+    fn of(request: &Request) -> Self {
+        Self(request.headers().clone())
+    }
+
+    fn values(&self, response_headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+        vary_names(response_headers).map(|name| {
+                                        let value = self.0
+                                                        .get(&name)
+                                                        .and_then(|value| value.to_str().ok())
+                                                        .map(str::to_string);
+                                        (name, value)
+                                    })
+                                    .collect()
+    }
+}
+
+fn vary_names(headers: &HeaderMap) -> impl Iterator<Item = String> {
+    headers.get_all(header::VARY)
+           .iter()
+           .filter_map(|value| value.to_str().ok())
+           .flat_map(|value| value.split(','))
+           .map(|name| {
+               name.trim()
+                   .to_ascii_lowercase()
+           })
+           .filter(|name| !name.is_empty())
+           .collect::<Vec<_>>()
+           .into_iter()
+}
+
+fn request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.headers()
+           .get(name)
+           .and_then(|value| value.to_str().ok())
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+           .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+           .filter_map(|(name, value)| {
+               Some((name.to_string(),
+                     value.to_str()
+                          .ok()?
+                          .to_string()))
+           })
+           .collect()
+}
+
+/// The `Cache-Control` directives used here.
+#[derive(Debug, Default)]
+struct Directives {
+    no_store: bool,
+    /// `no-cache`, or `max-age=0`
+    no_cache: bool,
+    max_age:  Option<u64>,
+}
+fn directives(headers: &HeaderMap) -> Directives {
+    let mut directives = Directives::default();
+    for directive in headers.get_all(header::CACHE_CONTROL)
+                            .iter()
+                            .filter_map(|value| value.to_str().ok())
+                            .flat_map(|value| value.split(','))
+    {
+        let (name, value) =
+            directive.split_once('=')
+                     .map_or((directive, None), |(name, value)| (name, Some(value)));
+        match name.trim()
+                  .to_ascii_lowercase()
+                  .as_str()
+        {
+            "no-store" => directives.no_store = true,
+            "no-cache" => directives.no_cache = true,
+            "max-age" =>
+                directives.max_age = value.and_then(|value| {
+                                              value.trim()
+                                                   .trim_matches('"')
+                                                   .parse()
+                                                   .ok()
+                                          }),
+            _ => {},
+        }
+    }
+    if directives.max_age == Some(0) {
+        directives.no_cache = true;
+    }
+    directives
+}
+
+/// `200` to a `GET`, not `no-store`, not `Vary: *`, and either fresh for a while or revalidatable.
+fn is_cacheable(response: &Response) -> bool {
+    let headers = response.headers();
+    response.status() == StatusCode::OK
+    && !directives(headers).no_store
+    && !vary_names(headers).any(|name| name == "*")
+    && (!freshness_lifetime(headers).is_zero()
+        || headers.contains_key(header::ETAG)
+        || headers.contains_key(header::LAST_MODIFIED))
+}
+
+/// `max-age` (less `Age`), else `Expires` - `Date`; zero with `no-cache`, or when neither is given.
+fn freshness_lifetime(headers: &HeaderMap) -> Duration {
+    let directives = directives(headers);
+    if directives.no_cache {
+        return Duration::ZERO;
+    }
+    let date = |name| {
+        headers.get(name)
+               .and_then(|value| value.to_str().ok())
+               .and_then(|value| httpdate::parse_http_date(value).ok())
+    };
+    let lifetime = match directives.max_age {
+        Some(max_age) => Duration::from_secs(max_age),
+        None => match (date(header::EXPIRES), date(header::DATE)) {
+            (Some(expires), Some(date)) => expires.duration_since(date)
+                                                  .unwrap_or_default(),
+            (Some(expires), None) => expires.duration_since(SystemTime::now())
+                                            .unwrap_or_default(),
+            _ => Duration::ZERO,
+        },
+    };
+    let age = headers.get(header::AGE)
+                     .and_then(|value| value.to_str().ok())
+                     .and_then(|value| value.parse().ok())
+                     .map_or(Duration::ZERO, Duration::from_secs);
+    lifetime.saturating_sub(age)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+                     .unwrap_or_default()
+                     .as_secs()
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{HttpClient,
+                http_stub::{StubResponse, spawn_http_stub}};
+
+    fn client(base: &str, cache: &HttpCache) -> HttpClient {
+        HttpClient::builder().base_url(base.parse().unwrap())
+                             .allow_http(true)
+                             .cache(cache.clone())
+                             .build()
+                             .unwrap()
+    }
+    async fn get_text(api: &HttpClient, path: &str) -> (StatusCode, String) {
+        let response = api.send(api.get(path).unwrap())
+                          .await
+                          .unwrap();
+        (response.status(), response.text().await.unwrap())
+    }
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("utilities-http-cache-{}-{name}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_fresh_entry_served_without_request() {
+        let (base, requests) = spawn_http_stub(|_| {
+            StubResponse::ok_json("[1]").with_header("cache-control", "max-age=60")
+        });
+        let cache = HttpCache::builder().build();
+        let api = client(&base, &cache);
+        assert_eq!(get_text(&api, "todos").await,
+                   (StatusCode::OK, "[1]".to_string()));
+        assert_eq!(get_text(&api, "todos").await,
+                   (StatusCode::OK, "[1]".to_string()));
+        assert_eq!(requests.try_iter().count(), 1);
+        assert_eq!(cache.stats(),
+                   HttpCacheStats { hits: 1,
+                                    misses: 1,
+                                    stores: 1,
+                                    ..HttpCacheStats::default() });
+        // `no-cache` on the request forces a round trip (no validator: a plain re-fetch)
+        let response = api.send(api.get("todos")
+                                   .unwrap()
+                                   .header(header::CACHE_CONTROL, "no-cache"))
+                          .await
+                          .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.try_iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_etag_revalidation() {
+        let (base, requests) = spawn_http_stub(|request| match request.header("if-none-match") {
+            Some(r#""v1""#) => StubResponse::new(304, "").with_header("etag", r#""v1""#),
+            _ => StubResponse::ok_json("[1,2]").with_header("etag", r#""v1""#)
+                                               .with_header("cache-control", "no-cache"),
+        });
+        let cache = HttpCache::builder().build();
+        let api = client(&base, &cache);
+        assert_eq!(get_text(&api, "todos").await,
+                   (StatusCode::OK, "[1,2]".to_string()));
+        // stale at once (`no-cache`): revalidated, & the cached body is served for the `304`
+        assert_eq!(get_text(&api, "todos").await,
+                   (StatusCode::OK, "[1,2]".to_string()));
+        let sent: Vec<_> = requests.try_iter().collect();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].header("if-none-match"), None);
+        assert_eq!(sent[1].header("if-none-match"), Some(r#""v1""#));
+        assert_eq!(cache.stats(),
+                   HttpCacheStats { misses: 1,
+                                    revalidated: 1,
+                                    stores: 1,
+                                    ..HttpCacheStats::default() });
+    }
+
+    #[tokio::test]
+    async fn test_last_modified_revalidation_replaced_by_new_body() {
+        const MODIFIED: &str = "Tue, 15 Nov 1994 08:12:31 GMT";
+        let (base, requests) =
+            spawn_http_stub(|request| match request.header("if-modified-since") {
+                Some(_) =>
+                    StubResponse::ok_json("new").with_header("last-modified",
+                                                             "Wed, 16 Nov 1994 08:12:31 GMT"),
+                None => StubResponse::ok_json("old").with_header("last-modified", MODIFIED),
+            });
+        let cache = HttpCache::builder().build();
+        let api = client(&base, &cache);
+        assert_eq!(get_text(&api, "todos").await
+                                          .1,
+                   "old");
+        // changed upstream: a `200` replaces the entry
+        assert_eq!(get_text(&api, "todos").await
+                                          .1,
+                   "new");
+        let sent: Vec<_> = requests.try_iter().collect();
+        assert_eq!(sent[1].header("if-modified-since"), Some(MODIFIED));
+        assert_eq!(cache.stats().stores, 2);
+        assert_eq!(cache.stats().revalidated, 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_store_vary_and_writes() {
+        let (base, requests) = spawn_http_stub(|request| match request.path.as_str() {
+            "/secret" =>
+                StubResponse::ok_json("s").with_header("cache-control", "no-store, max-age=60"),
+            _ => StubResponse::ok_json(request.header("x-lang")
+                                              .unwrap_or("none")
+                                              .to_string()).with_header("cache-control",
+                                                                        "max-age=60")
+                                                           .with_header("vary", "X-Lang"),
+        });
+        let cache = HttpCache::builder().build();
+        let api = client(&base, &cache);
+        get_text(&api, "secret").await;
+        get_text(&api, "secret").await;
+        assert_eq!(requests.try_iter().count(), 2);
+        assert!(cache.is_empty());
+
+        let lang = |value: &'static str| {
+            let api = api.clone();
+            async move {
+                api.send(api.get("todos")
+                            .unwrap()
+                            .header("x-lang", value))
+                   .await
+                   .unwrap()
+                   .text()
+                   .await
+                   .unwrap()
+            }
+        };
+        assert_eq!(lang("en").await, "en");
+        assert_eq!(lang("en").await, "en");
+        // a different `x-lang` is a different variant: not served from the `en` entry
+        assert_eq!(lang("de").await, "de");
+        assert_eq!(requests.try_iter().count(), 2);
+
+        // a write to the URL drops its entry
+        api.send(api.request(Method::DELETE, "todos")
+                    .unwrap())
+           .await
+           .unwrap();
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().bypassed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_disk_entries_survive_a_new_cache() {
+        let dir = temp_dir("disk");
+        let _ = std::fs::remove_dir_all(&dir);
+        let (base, requests) = spawn_http_stub(|_| {
+            StubResponse::ok_json("[3]").with_header("cache-control", "max-age=60")
+        });
+        let first = HttpCache::builder().dir(&dir)
+                                        .build();
+        assert_eq!(get_text(&client(&base, &first), "todos").await
+                                                            .1,
+                   "[3]");
+
+        let second = HttpCache::builder().dir(&dir)
+                                         .build();
+        assert_eq!(get_text(&client(&base, &second), "todos").await
+                                                             .1,
+                   "[3]");
+        assert_eq!(requests.try_iter().count(), 1);
+        assert_eq!(second.stats().hits, 1);
+        // named by the key's SHA-256: stable across Rust releases
+        let key = format!("GET {base}/todos");
+        assert!(dir.join(format!("{}.json",
+                                 hex::encode(digest::digest(&digest::SHA256, key.as_bytes()))))
+                   .exists());
+
+        second.clear().await;
+        assert_eq!(std::fs::read_dir(&dir).unwrap()
+                                          .count(),
+                   0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            pairs.iter()
+                 .map(|(name, value)| {
+                     (HeaderName::from_static(name), HeaderValue::from_static(value))
+                 })
+                 .collect::<HeaderMap>()
+        };
+        assert_eq!(freshness_lifetime(&headers(&[("cache-control", "public, max-age=30"),
+                                                 ("age", "10")])),
+                   Duration::from_secs(20));
+        assert_eq!(freshness_lifetime(&headers(&[("date", "Tue, 15 Nov 1994 08:12:31 GMT"),
+                                                 ("expires", "Tue, 15 Nov 1994 08:13:31 GMT")])),
+                   Duration::from_secs(60));
+        assert_eq!(freshness_lifetime(&headers(&[("cache-control", "max-age=30, no-cache")])),
+                   Duration::ZERO);
+        assert_eq!(freshness_lifetime(&headers(&[])), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let cache = HttpCache::builder().max_entries(2)
+                                        .build();
+        let entry = |key: &str| CacheEntry { key:         key.to_string(),
+                                             vary:        Vec::new(),
+                                             status:      200,
+                                             headers:     Vec::new(),
+                                             body:        RecordedBody::from_bytes(Vec::new()),
+                                             fresh_until: 0,
+                                             last_used:   0, };
+        cache.store(entry("a")).await;
+        cache.store(entry("b")).await;
+        // touch `a`, so `b` is the least recently used
+        assert!(cache.lookup("a")
+                     .await
+                     .is_some());
+        cache.store(entry("c")).await;
+        assert!(cache.lookup("b")
+                     .await
+                     .is_none());
+        assert!(cache.lookup("a")
+                     .await
+                     .is_some());
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -0,0 +1,543 @@
+//! Shared HTTP client factory: a configured `reqwest::Client` bound to a base URL.
+//!
+//! Defaults match what the samples used to build by hand:
+//! https-only, rustls, JSON `accept`/`content-type`, `rust-reqwest-client` user agent, 30s total timeout.
+//!
+//! ## Sending
+//! [`HttpClient::get`] & [`HttpClient::request`] return a plain `reqwest::RequestBuilder`: pass it to
+//! [`HttpClient::send`] (likewise anything built via [`HttpClient::client`]).  Its own `.send()` goes straight to the
+//! network, bypassing the cache, circuit breakers, rate limits & fixtures below.
+//!
+//! ## Rate limits & circuit breakers
+//! With [`RateLimits`] configured, [`HttpClient::send`] & [`HttpClient::execute`] wait for the request's bucket first.
+//! With [`CircuitBreakers`] configured, they fail fast with [`SendError::CircuitOpen`] while the host's circuit is open.
+//! Clones and [`HttpClient::with_base_url`] share both.
+//!
+//! ## Response cache
+//! With an [`HttpCache`] configured, they serve `GET`s from it (or revalidate them) before any of the above (see [`crate::HttpCache`]).
+//!
+//! ## Fixtures
+//! With [`Fixtures`] configured, the same methods record exchanges to, or replay them from, a fixture file (see [`crate::fixtures`]).
+//!
+//! ## URL joins
+//! `Url::join` resolves like a browser link: `https://host/api` + `todos` is `https://host/todos`,
+//! and `https://host/api/` + `/todos` is *also* `https://host/todos`.
+//! [`HttpClient::url`] & [`HttpClient::url_segments`] always *append* to the base URL's path instead.
+//!
+//! ## Use:
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use utilities::{HiddenValue, HttpAuth, HttpClient};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let token = HiddenValue::from_env_builder().key("API_TOKEN")
+//!                                            .load_env_file(true)
+//!                                            .build()?;
+//! let api = HttpClient::builder().base_url("https://example.com/api/v1".parse()?)
+//!                                .auth(HttpAuth::Bearer(token))
+//!                                .connect_timeout(Duration::from_secs(5))
+//!                                .build()?;
+//! // GET https://example.com/api/v1/todos/3
+//! let _todo = api.send(api.get("todos/3")?)
+//!                .await?;
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::time::Duration;
+
+use bon::bon;
+use reqwest::{Method, Proxy, Request, RequestBuilder, Response, Url,
+              header::{self, HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue}};
+use thiserror::Error;
+use tracing::{Level as L, event};
+
+use crate::{CircuitBreakers, CircuitOpen, FixtureError, Fixtures, HedgePolicy, HiddenValue,
+            HttpCache, RateLimits, RetryPolicy, RetryableError};
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_USER_AGENT: &str = "rust-reqwest-client";
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum HttpClientError {
+    #[error("Error building HTTP client: {0}")]
+    Build(#[from] reqwest::Error),
+    #[error("Base URL `{url}` cannot be used as a base (e.g. `data:` or `mailto:` URLs)")]
+    CannotBeABase { url: String },
+    #[error("Base URL `{url}` is not https (set `allow_http` for local testing)")]
+    InsecureBaseUrl { url: String },
+    #[error("Invalid URL path `{path}`: {source}")]
+    InvalidPath {
+        path:   String,
+        source: url::ParseError,
+    },
+    #[error("Invalid auth header value (header values must be visible ASCII)")]
+    InvalidAuthValue(#[source] InvalidHeaderValue),
+}
+
+/// Failure to send a request via [`HttpClient::send`] or [`HttpClient::execute`].
+#[derive(Debug, Error)]
+pub enum SendError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    CircuitOpen(#[from] CircuitOpen),
+    #[error(transparent)]
+    Fixture(#[from] FixtureError),
+}
+/// Connect errors are retried; an open circuit is not (it will not close within a backoff).
+impl RetryableError for SendError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(error) => error.is_retryable(),
+            Self::CircuitOpen(_) | Self::Fixture(_) => false,
+        }
+    }
+}
+// ///////////////////////////////// [ config ] ///////////////////////////////// //
+/// Credentials sent with every request.  The header value is marked *sensitive* (redacted from `Debug`).
+#[derive(Debug, Clone)]
+pub enum HttpAuth {
+    /// `authorization: Bearer <token>`
+    Bearer(HiddenValue<String>),
+    /// An API-key style header, e.g. `x-api-key: <key>`
+    Header {
+        name:  HeaderName,
+        value: HiddenValue<String>,
+    },
+}
+impl HttpAuth {
+    fn header(&self) -> Result<(HeaderName, HeaderValue), HttpClientError> {
+        let (name, value) = match self {
+            Self::Bearer(token) =>
+                (header::AUTHORIZATION, format!("Bearer {}", token.expose_value())),
+            Self::Header { name, value } => (name.clone(), value.expose_value().clone()),
+        };
+        let mut value = HeaderValue::from_str(&value).map_err(HttpClientError::InvalidAuthValue)?;
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// A `reqwest::Client` plus the base URL requests are made against.
+///
+/// Cheap to clone (the inner client is an `Arc`'d connection pool).
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client:           reqwest::Client,
+    base_url:         Url,
+    rate_limits:      Option<RateLimits>,
+    circuit_breakers: Option<CircuitBreakers>,
+    fixtures:         Option<Fixtures>,
+    cache:            Option<HttpCache>,
+    /// Also checked by [`with_base_url`](Self::with_base_url).
+    allow_http:       bool,
+}
+#[bon]
+impl HttpClient {
+    /// Build a client for `base_url`.
+    ///
+    /// ## Timeouts
+    /// - `timeout`: whole request, incl. reading the body (default: 30s)
+    /// - `connect_timeout`: connection establishment only
+    /// - `read_timeout`: max time between body reads (resets on each read)
+    #[builder]
+    pub fn new(/// Requests are made relative to this.  A trailing `/` is added to its path if missing.
+               base_url: Url,
+               /// Sent with every request.
+               auth: Option<HttpAuth>,
+               /// Added to (and overriding) the JSON `accept`/`content-type` defaults.
+               #[builder(default)]
+               default_headers: HeaderMap,
+               #[builder(into, default = DEFAULT_USER_AGENT.to_string())] user_agent: String,
+               #[builder(default = DEFAULT_TIMEOUT)] timeout: Duration,
+               connect_timeout: Option<Duration>,
+               read_timeout: Option<Duration>,
+               /// Route all requests (http & https) through this proxy.
+               proxy: Option<Url>,
+               /// Keyed quotas applied by [`send`](Self::send) & [`execute`](Self::execute).
+               rate_limits: Option<RateLimits>,
+               /// Per-host circuits checked (& fed) by [`send`](Self::send) & [`execute`](Self::execute).
+               circuit_breakers: Option<CircuitBreakers>,
+               /// Record exchanges to (or replay them from) a fixture file, in [`send`](Self::send) & [`execute`](Self::execute).
+               fixtures: Option<Fixtures>,
+               /// Serve & revalidate `GET`s from this cache, in [`send`](Self::send) & [`execute`](Self::execute).
+               cache: Option<HttpCache>,
+               /// Permit plain `http` (base URL & requests), e.g. for a local stand-in server.
+               #[builder(default)]
+               allow_http: bool)
+               -> Result<Self, HttpClientError> {
+        let base_url = normalize_base(base_url, allow_http)?;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(header::CONTENT_TYPE,
+                       HeaderValue::from_static("application/json"));
+        headers.extend(default_headers);
+        if let Some(auth) = &auth {
+            let (name, value) = auth.header()?;
+            headers.insert(name, value);
+        }
+        let mut builder = reqwest::Client::builder().https_only(!allow_http)
+                                                    .tls_backend_rustls()
+                                                    .default_headers(headers)
+                                                    .user_agent(user_agent)
+                                                    .timeout(timeout);
+        if let Some(connect_timeout) = connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(read_timeout) = read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        let client = builder.build()?;
+        event!(L::DEBUG, %base_url, ?auth, ?rate_limits, ?circuit_breakers, ?fixtures, ?cache, allow_http, "http client built");
+        Ok(Self { client,
+                  base_url,
+                  rate_limits,
+                  circuit_breakers,
+                  fixtures,
+                  cache,
+                  allow_http })
+    }
+}
+impl HttpClient {
+    /// Same client (connection pool, rate limits, circuit breakers, fixtures & cache), different base URL.
+    /// Checked as by the builder: https-only unless built with `allow_http`.
+    pub fn with_base_url(&self, base_url: Url) -> Result<Self, HttpClientError> {
+        Ok(Self { base_url: normalize_base(base_url, self.allow_http)?,
+                  ..self.clone() })
+    }
+
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    pub fn rate_limits(&self) -> Option<&RateLimits> {
+        self.rate_limits.as_ref()
+    }
+
+    pub fn circuit_breakers(&self) -> Option<&CircuitBreakers> {
+        self.circuit_breakers.as_ref()
+    }
+
+    pub fn fixtures(&self) -> Option<&Fixtures> {
+        self.fixtures.as_ref()
+    }
+
+    pub fn cache(&self) -> Option<&HttpCache> {
+        self.cache.as_ref()
+    }
+
+    /// The underlying `reqwest::Client`, for anything not covered here.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// `path` (may include a query) appended to the base URL's path.  Leading `/`s are ignored.
+    ///
+    /// e.g. base `https://host/api/`: `todos?userId=1` & `/todos?userId=1` -> `https://host/api/todos?userId=1`
+    pub fn url(&self, path: &str) -> Result<Url, HttpClientError> {
+        self.base_url
+            .join(path.trim_start_matches('/'))
+            .map_err(|source| HttpClientError::InvalidPath { path: path.to_string(),
+                                                             source })
+    }
+
+    /// Base URL plus one path segment per item; each is percent-encoded (`/` included).
+    ///
+    /// e.g. `["todos", "a/b"]` -> `<base>/todos/a%2Fb`
+    pub fn url_segments<I>(&self, segments: I) -> Url
+        where I: IntoIterator,
+              I::Item: AsRef<str> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+           .expect("base url was checked as cannot-be-a-base at construction")
+           .pop_if_empty()
+           .extend(segments);
+        url
+    }
+
+    /// A request for `path` (see [`url`](Self::url)), to be sent with [`send`](Self::send).
+    /// (Its own `.send()` bypasses the cache, circuit breakers, rate limits & fixtures.)
+    pub fn request(&self, method: Method, path: &str) -> Result<RequestBuilder, HttpClientError> {
+        Ok(self.client
+               .request(method, self.url(path)?))
+    }
+
+    /// [`request`](Self::request) with `GET`.
+    pub fn get(&self, path: &str) -> Result<RequestBuilder, HttpClientError> {
+        self.request(Method::GET, path)
+    }
+
+    /// Execute `request` with this client: answered from the cache if it can be (revalidating if needed),
+    /// else fails fast if its host's circuit is open, then waits on its rate-limit bucket (if any).
+    /// With fixtures, the exchange is recorded or replayed.
+    pub async fn execute(&self, request: Request) -> Result<Response, SendError> {
+        match &self.cache {
+            Some(cache) =>
+                cache.execute(request, |request| self.execute_uncached(request))
+                     .await,
+            None =>
+                self.execute_uncached(request)
+                    .await,
+        }
+    }
+
+    /// [`execute`](Self::execute), minus the cache.  (e.g. streaming downloads, which must not be buffered)
+    pub(crate) async fn execute_uncached(&self, request: Request) -> Result<Response, SendError> {
+        let permit = self.circuit_breakers
+                         .as_ref()
+                         .map(|breakers| breakers.try_acquire(request.url()))
+                         .transpose()?;
+        if let Some(rate_limits) = &self.rate_limits {
+            rate_limits.until_ready(&request)
+                       .await;
+        }
+        let result = match &self.fixtures {
+            Some(fixtures) =>
+                fixtures.execute(&self.client, request)
+                        .await?,
+            None =>
+                self.client
+                    .execute(request)
+                    .await,
+        };
+        if let Some(permit) = permit {
+            permit.record_result(&result);
+        }
+        Ok(result?)
+    }
+
+    /// Build & [`execute`](Self::execute) `request`.  (Sent with *this* client, whichever client made the builder.)
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, SendError> {
+        self.execute(request.build()?)
+            .await
+    }
+
+    /// [`send`](Self::send), retrying per `policy`.  Every attempt goes through the circuit breaker & rate limits.
+    pub async fn send_with_retry(&self,
+                                 request: RequestBuilder,
+                                 policy: &RetryPolicy)
+                                 -> Result<Response, SendError> {
+        policy.execute_with(request.build()?, |request| self.execute(request))
+              .await
+    }
+
+    /// [`send`](Self::send), with a duplicate sent if it is slow per `policy` (first success wins).
+    /// Both attempts go through the cache, circuit breaker & rate limits.
+    pub async fn send_hedged(&self,
+                             request: RequestBuilder,
+                             policy: &HedgePolicy)
+                             -> Result<Response, SendError> {
+        policy.execute_with(request.build()?, |request| self.execute(request))
+              .await
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+/// Ensure the base path ends in `/`, so joins append rather than replace the last segment.
+/// Checked (a base, https unless `allow_http`), with a trailing `/` & no query or fragment.
+fn normalize_base(mut base_url: Url, allow_http: bool) -> Result<Url, HttpClientError> {
+    if base_url.cannot_be_a_base() {
+        return Err(HttpClientError::CannotBeABase { url: base_url.into(), });
+    }
+    if !allow_http && base_url.scheme() != "https" {
+        return Err(HttpClientError::InsecureBaseUrl { url: base_url.into(), });
+    }
+    if !base_url.path().ends_with('/') {
+        let path = format!("{}/", base_url.path());
+        base_url.set_path(&path);
+    }
+    base_url.set_query(None);
+    base_url.set_fragment(None);
+    Ok(base_url)
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::http_stub::{StubResponse, spawn_http_stub};
+    fn client(base: &str) -> HttpClient {
+        HttpClient::builder().base_url(base.parse().unwrap())
+                             .build()
+                             .unwrap()
+    }
+    #[test]
+    fn test_url_appends_to_base_path() {
+        for base in ["https://host.test/api", "https://host.test/api/"] {
+            let api = client(base);
+            assert_eq!(api.base_url().as_str(), "https://host.test/api/");
+            assert_eq!(api.url("todos")
+                          .unwrap()
+                          .as_str(),
+                       "https://host.test/api/todos");
+            assert_eq!(api.url("/todos/3?userId=1")
+                          .unwrap()
+                          .as_str(),
+                       "https://host.test/api/todos/3?userId=1");
+            // however many: never resolved against the host root (`//` would even replace the host)
+            assert_eq!(api.url("//todos")
+                          .unwrap()
+                          .as_str(),
+                       "https://host.test/api/todos");
+            assert_eq!(api.url("delay/")
+                          .unwrap()
+                          .as_str(),
+                       "https://host.test/api/delay/");
+        }
+        let root = client("https://host.test");
+        assert_eq!(root.url("/json")
+                       .unwrap()
+                       .as_str(),
+                   "https://host.test/json");
+    }
+    #[test]
+    fn test_url_segments_encode() {
+        let api = client("https://host.test/api");
+        assert_eq!(api.url_segments(["delay", "3"])
+                      .as_str(),
+                   "https://host.test/api/delay/3");
+        assert_eq!(api.url_segments(["todos", "a/b c"])
+                      .as_str(),
+                   "https://host.test/api/todos/a%2Fb%20c");
+    }
+    #[test]
+    fn test_http_requires_allow_http() {
+        let insecure = HttpClient::builder().base_url("http://127.0.0.1:8080".parse()
+                                                                             .unwrap())
+                                            .build();
+        assert!(matches!(insecure, Err(HttpClientError::InsecureBaseUrl { .. })));
+        // nor via another base
+        let insecure = client("https://host.test").with_base_url("http://127.0.0.1:8080".parse()
+                                                                                        .unwrap());
+        assert!(matches!(insecure, Err(HttpClientError::InsecureBaseUrl { .. })));
+        let allowed = HttpClient::builder().base_url("http://127.0.0.1:8080".parse()
+                                                                            .unwrap())
+                                           .allow_http(true)
+                                           .build()
+                                           .unwrap();
+        assert!(allowed.with_base_url("http://127.0.0.1:8081".parse()
+                                                             .unwrap())
+                       .is_ok());
+        let not_a_base = HttpClient::builder().base_url("mailto:someone@host.test".parse()
+                                                                                  .unwrap())
+                                              .allow_http(true)
+                                              .build();
+        assert!(matches!(not_a_base, Err(HttpClientError::CannotBeABase { .. })));
+    }
+    #[tokio::test]
+    async fn test_headers_and_auth_sent() {
+        let (base, received) = spawn_http_stub(|_| StubResponse::ok_json("{}"));
+        let token = HiddenValue::builder().value("s3cr3t-token".to_string())
+                                          .build()
+                                          .unwrap();
+        let mut extra = HeaderMap::new();
+        extra.insert("x-extra", HeaderValue::from_static("yes"));
+        let api = HttpClient::builder().base_url(format!("{base}/base").parse()
+                                                                       .unwrap())
+                                       .allow_http(true)
+                                       .auth(HttpAuth::Bearer(token))
+                                       .default_headers(extra)
+                                       .user_agent("stub-test")
+                                       .build()
+                                       .unwrap();
+        assert!(!format!("{api:?}").contains("s3cr3t-token"));
+
+        let response = api.get("/headers?probe=1")
+                          .unwrap()
+                          .send()
+                          .await
+                          .unwrap();
+        assert_eq!(response.status(), 200);
+        let request = received.recv().unwrap();
+        assert_eq!(request.path, "/base/headers?probe=1");
+        assert_eq!(request.header("authorization"), Some("Bearer s3cr3t-token"));
+        assert_eq!(request.header("accept"), Some("application/json"));
+        assert_eq!(request.header("x-extra"), Some("yes"));
+        assert_eq!(request.header("user-agent"), Some("stub-test"));
+    }
+    #[tokio::test]
+    async fn test_with_base_url_shares_client() {
+        let (base, received) = spawn_http_stub(|_| StubResponse::ok_json("{}"));
+        let first = HttpClient::builder().base_url(format!("{base}/one").parse()
+                                                                        .unwrap())
+                                         .allow_http(true)
+                                         .build()
+                                         .unwrap();
+        let second = first.with_base_url(format!("{base}/two").parse()
+                                                              .unwrap())
+                          .unwrap();
+        second.get("x")
+              .unwrap()
+              .send()
+              .await
+              .unwrap();
+        assert_eq!(received.recv().unwrap().path, "/two/x");
+    }
+    #[tokio::test]
+    async fn test_rate_limits_shared_across_bases() {
+        let (base, received) = spawn_http_stub(|_| StubResponse::ok_json("{}"));
+        let table = crate::QuotaTable::from_toml_str("[rate_limits]\ndefault = { per_second = 20, burst = 1 }").unwrap();
+        let first = HttpClient::builder().base_url(format!("{base}/one").parse()
+                                                                        .unwrap())
+                                         .allow_http(true)
+                                         .rate_limits(RateLimits::new(&table).unwrap())
+                                         .build()
+                                         .unwrap();
+        let second = first.with_base_url(format!("{base}/two").parse()
+                                                              .unwrap())
+                          .unwrap();
+        first.send(first.get("x").unwrap())
+             .await
+             .unwrap();
+        second.send(second.get("y").unwrap())
+              .await
+              .unwrap();
+        assert_eq!(received.recv().unwrap().path, "/one/x");
+        assert_eq!(received.recv().unwrap().path, "/two/y");
+        // same host: one bucket, and the second request had to wait for it
+        let stats = second.rate_limits()
+                          .unwrap()
+                          .stats();
+        assert_eq!(stats.keys()
+                        .collect::<Vec<_>>(),
+                   vec!["default@127.0.0.1"]);
+        assert_eq!(stats["default@127.0.0.1"].requests, 2);
+        assert_eq!(stats["default@127.0.0.1"].delayed, 1);
+    }
+    #[tokio::test]
+    async fn test_circuit_breaker_short_circuits() {
+        let (base, received) = spawn_http_stub(|_| StubResponse::new(503, ""));
+        let api = HttpClient::builder().base_url(base.parse().unwrap())
+                                       .allow_http(true)
+                                       .circuit_breakers(CircuitBreakers::builder().minimum_calls(2)
+                                                                                   .build())
+                                       .build()
+                                       .unwrap();
+        let policy = RetryPolicy::builder().base_delay(Duration::from_millis(1))
+                                           .build();
+        // 1st attempt & retry fail (503); the next retry is short-circuited & not retried further
+        let result = api.send_with_retry(api.get("x").unwrap(), &policy)
+                        .await;
+        assert!(matches!(result, Err(SendError::CircuitOpen(_))),
+                "{result:?}");
+        assert_eq!(received.try_iter().count(), 2);
+        assert_eq!(api.circuit_breakers()
+                      .unwrap()
+                      .state(api.base_url()),
+                   crate::CircuitState::Open);
+        // shared with derived clients
+        let other = api.with_base_url(format!("{base}/other").parse()
+                                                             .unwrap())
+                       .unwrap();
+        assert!(matches!(other.send(other.get("y").unwrap())
+                              .await,
+                         Err(SendError::CircuitOpen(_))));
+        assert_eq!(received.try_iter().count(), 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! (test-only) Minimal blocking HTTP/1.1 stub server: records each request & replies via a responder closure.
+//!
+//! One request per connection (`connection: close`), so each client request is seen separately.
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{io::{BufRead, BufReader, Read, Write},
+          net::TcpListener,
+          sync::mpsc,
+          thread};
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RecordedRequest {
+    pub method:  String,
+    /// Path and query, as sent.
+    pub path:    String,
+    /// Lower-cased names.
+    pub headers: Vec<(String, String)>,
+    pub body:    String,
+}
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StubResponse {
+    pub status:  u16,
+    pub headers: Vec<(String, String)>,
+    pub body:    String,
+}
+impl StubResponse {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self { status,
+               headers: Vec::new(),
+               body: body.into() }
+    }
+
+    pub fn ok_json(body: impl Into<String>) -> Self {
+        Self::new(200, body).with_header("content-type", "application/json")
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers
+            .push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Serve on an OS-assigned local port (on a detached thread).  Returns the base url (`http://127.0.0.1:PORT`).
+pub(crate) fn spawn_http_stub(respond: impl Fn(&RecordedRequest) -> StubResponse + Send + 'static)
+                              -> (String, mpsc::Receiver<RecordedRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("stub should bind a local port");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let Some(request) = read_request(&mut stream) else {
+                continue;
+            };
+            let response = respond(&request);
+            let mut head = format!("HTTP/1.1 {} STUB\r\ncontent-length: {}\r\nconnection: close\r\n",
+                                   response.status,
+                                   response.body.len());
+            for (name, value) in &response.headers {
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+            head.push_str("\r\n");
+            let _ = stream.write_all(head.as_bytes())
+                          .and_then(|()| stream.write_all(response.body.as_bytes()));
+            let _ = tx.send(request);
+        }
+    });
+    (base_url, rx)
+}
+fn read_request(stream: &mut std::net::TcpStream) -> Option<RecordedRequest> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)
+          .ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)
+              .ok()?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim()
+                              .to_ascii_lowercase(),
+                          value.trim().to_string()));
+        }
+    }
+    let content_length = headers.iter()
+                                .find(|(name, _)| name == "content-length")
+                                .and_then(|(_, value)| value.parse().ok())
+                                .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)
+          .ok()?;
+    Some(RecordedRequest { method,
+                           path,
+                           headers,
+                           body: String::from_utf8_lossy(&body).into_owned() })
+}
@@ -0,0 +1,693 @@
+//! Typed client for the [jsonplaceholder](https://jsonplaceholder.typicode.com) resources: users, posts, comments & todos.
+//!
+//! Each resource supports list (with filters) / get / create / update (`PUT`) / patch / delete,
+//! and `_page`/`_limit` pagination, as single pages or as a [`Stream`].
+//! Requests go through [`HttpClient::send`] (or [`HttpClient::send_with_retry`], with a retry policy: idempotent
+//! methods only),
+//! so rate limits, circuit breakers & fixtures apply; each carries the current trace context (`otlp` feature).
+//!
+//! ## Note
+//! jsonplaceholder *fakes* writes: created/updated resources are echoed back, but nothing is stored.
+//!
+//! ## Use:
+//! ```no_run
+//! use futures::TryStreamExt as _;
+//! use utilities::{HttpClient,
+//!                 jsonplaceholder::{JsonPlaceholder, TodoFilter, TodoPatch}};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let api = JsonPlaceholder::builder().http(HttpClient::builder().base_url(JsonPlaceholder::BASE_URL.parse()?)
+//!                                                                .build()?)
+//!                                     .build();
+//! let open = api.todos()
+//!               .list(&TodoFilter { user_id:   Some(1),
+//!                                   completed: Some(false), })
+//!               .await?;
+//! let done = api.todos()
+//!               .patch(open[0].id, &TodoPatch { completed: Some(true),
+//!                                               ..Default::default() })
+//!               .await?;
+//! // every todo, 20 per request
+//! let all: Vec<_> = api.todos()
+//!                      .stream(TodoFilter::default(), 20.try_into()?)
+//!                      .try_collect()
+//!                      .await?;
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{fmt, marker::PhantomData, num::NonZeroU32};
+
+use bon::bon;
+use futures::{Stream, TryStreamExt as _, stream};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
+use tracing::{Level as L, event, instrument};
+
+use crate::{HttpClient, HttpClientError, RetryPolicy, SendError, TraceContextExt as _};
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum JsonPlaceholderError {
+    #[error(transparent)]
+    Url(#[from] HttpClientError),
+    #[error(transparent)]
+    Send(#[from] SendError),
+    #[error("No {collection} with id {id}")]
+    NotFound {
+        collection: &'static str,
+        id:         u32,
+    },
+    #[error("`{method} {url}` returned {status}")]
+    Status {
+        method: Method,
+        url:    String,
+        status: StatusCode,
+    },
+    #[error("Error decoding `{method} {url}` response: {source}")]
+    Decode {
+        method: Method,
+        url:    String,
+        source: reqwest::Error,
+    },
+}
+// ///////////////////////////////// [ resources ] ///////////////////////////////// //
+/// A jsonplaceholder collection (`/{COLLECTION}` & `/{COLLECTION}/{id}`).
+pub trait Resource: DeserializeOwned + Serialize + fmt::Debug + Send + 'static {
+    const COLLECTION: &'static str;
+    /// Query-string filters for [`Resources::list`].  (`None` fields are omitted.)
+    type Filter: Serialize + Default + fmt::Debug + Send + Sync + 'static;
+    /// Body for [`Resources::create`] (no `id`).
+    type New: Serialize + fmt::Debug + Sync;
+    /// Body for [`Resources::patch`].  (`None` fields are omitted.)
+    type Patch: Serialize + Default + fmt::Debug + Sync;
+    fn id(&self) -> u32;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id:       u32,
+    pub name:     String,
+    pub username: String,
+    pub email:    String,
+    #[serde(default)]
+    pub phone:    String,
+    #[serde(default)]
+    pub website:  String,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewUser {
+    pub name:     String,
+    pub username: String,
+    pub email:    String,
+    pub phone:    String,
+    pub website:  String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserFilter {
+    pub username: Option<String>,
+    pub email:    Option<String>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name:     Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email:    Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone:    Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub website:  Option<String>,
+}
+impl Resource for User {
+    type Filter = UserFilter;
+    type New = NewUser;
+    type Patch = UserPatch;
+
+    const COLLECTION: &'static str = "users";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Post {
+    pub user_id: u32,
+    pub id:      u32,
+    pub title:   String,
+    pub body:    String,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPost {
+    pub user_id: u32,
+    pub title:   String,
+    pub body:    String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostFilter {
+    pub user_id: Option<u32>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title:   Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body:    Option<String>,
+}
+impl Resource for Post {
+    type Filter = PostFilter;
+    type New = NewPost;
+    type Patch = PostPatch;
+
+    const COLLECTION: &'static str = "posts";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub post_id: u32,
+    pub id:      u32,
+    pub name:    String,
+    pub email:   String,
+    pub body:    String,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewComment {
+    pub post_id: u32,
+    pub name:    String,
+    pub email:   String,
+    pub body:    String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentFilter {
+    pub post_id: Option<u32>,
+    pub email:   Option<String>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name:  Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body:  Option<String>,
+}
+impl Resource for Comment {
+    type Filter = CommentFilter;
+    type New = NewComment;
+    type Patch = CommentPatch;
+
+    const COLLECTION: &'static str = "comments";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Todo {
+    pub user_id:   u32,
+    pub id:        u32,
+    pub title:     String,
+    pub completed: bool,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTodo {
+    pub user_id:   u32,
+    pub title:     String,
+    pub completed: bool,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoFilter {
+    pub user_id:   Option<u32>,
+    pub completed: Option<bool>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TodoPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id:   Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title:     Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<bool>,
+}
+impl Resource for Todo {
+    type Filter = TodoFilter;
+    type New = NewTodo;
+    type Patch = TodoPatch;
+
+    const COLLECTION: &'static str = "todos";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+// ///////////////////////////////// [ pagination ] ///////////////////////////////// //
+/// One `_page` of a (filtered) collection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<R> {
+    pub items: Vec<R>,
+    /// 1-based
+    pub page:  u32,
+    pub limit: u32,
+    /// Matching items across all pages (`x-total-count`), if reported.
+    pub total: Option<u64>,
+}
+impl<R> Page<R> {
+    pub fn is_last(&self) -> bool {
+        let seen = u64::from(self.page) * u64::from(self.limit);
+        self.items.len() < self.limit as usize
+        || self.total
+               .is_some_and(|total| seen >= total)
+    }
+}
+// ///////////////////////////////// [ client ] ///////////////////////////////// //
+/// Entry point: one [`Resources`] handle per collection.  Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct JsonPlaceholder {
+    http:  HttpClient,
+    retry: Option<RetryPolicy>,
+}
+#[bon]
+impl JsonPlaceholder {
+    pub const BASE_URL: &str = "https://jsonplaceholder.typicode.com";
+
+    /// - `http`: its base URL is jsonplaceholder's (or a stand-in's)
+    /// - `retry`: send idempotent requests (`GET`, `PUT`, `DELETE`) with this policy.  `create` (`POST`) & `patch`
+    ///   (`PATCH`) are sent once: a retry after the server committed would repeat the write.
+    #[builder]
+    pub fn new(http: HttpClient, retry: Option<RetryPolicy>) -> Self {
+        Self { http, retry }
+    }
+}
+impl JsonPlaceholder {
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    pub fn users(&self) -> Resources<User> {
+        Resources::new(self.clone())
+    }
+
+    pub fn posts(&self) -> Resources<Post> {
+        Resources::new(self.clone())
+    }
+
+    pub fn comments(&self) -> Resources<Comment> {
+        Resources::new(self.clone())
+    }
+
+    pub fn todos(&self) -> Resources<Todo> {
+        Resources::new(self.clone())
+    }
+
+    async fn send(&self, method: &Method, request: RequestBuilder) -> Result<Response, SendError> {
+        let request = request.with_trace_context();
+        match &self.retry {
+            Some(policy) if method.is_idempotent() =>
+                self.http
+                    .send_with_retry(request, policy)
+                    .await,
+            _ => self.http.send(request).await,
+        }
+    }
+}
+
+/// Operations on one collection.
+#[derive(Debug)]
+pub struct Resources<R> {
+    api:      JsonPlaceholder,
+    resource: PhantomData<fn() -> R>,
+}
+// (derive would require `R: Clone`)
+impl<R> Clone for Resources<R> {
+    fn clone(&self) -> Self {
+        Self { api:      self.api.clone(),
+               resource: PhantomData, }
+    }
+}
+impl<R: Resource> Resources<R> {
+    fn new(api: JsonPlaceholder) -> Self {
+        Self { api,
+               resource: PhantomData }
+    }
+
+    /// All items matching `filter`.
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn list(&self, filter: &R::Filter) -> Result<Vec<R>, JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .get(R::COLLECTION)?
+                          .query(filter);
+        let response = self.send(Method::GET, request, None)
+                           .await?;
+        decode(response).await
+    }
+
+    /// One page (`page` is 1-based) of the items matching `filter`.
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn page(&self,
+                      filter: &R::Filter,
+                      page: u32,
+                      limit: NonZeroU32)
+                      -> Result<Page<R>, JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .get(R::COLLECTION)?
+                          .query(filter)
+                          .query(&[("_page", page), ("_limit", limit.get())]);
+        let response = self.send(Method::GET, request, None)
+                           .await?;
+        let total = response.1
+                            .headers()
+                            .get("x-total-count")
+                            .and_then(|total| total.to_str().ok())
+                            .and_then(|total| total.parse().ok());
+        let items: Vec<R> = decode(response).await?;
+        event!(L::DEBUG, page, items = items.len(), ?total, "page fetched");
+        Ok(Page { items,
+                  page,
+                  limit: limit.get(),
+                  total })
+    }
+
+    /// Pages of the items matching `filter`, fetched one at a time (as the stream is polled) until a short or final page.
+    pub fn pages(&self,
+                 filter: R::Filter,
+                 limit: NonZeroU32)
+                 -> impl Stream<Item = Result<Page<R>, JsonPlaceholderError>> + Send + use<R> {
+        stream::try_unfold((self.clone(), filter, Some(1)),
+                           move |(this, filter, next)| async move {
+                               let Some(page) = next else {
+                                   return Ok(None);
+                               };
+                               let page = this.page(&filter, page, limit)
+                                              .await?;
+                               let next = (!page.is_last()).then_some(page.page + 1);
+                               Ok(Some((page, (this, filter, next))))
+                           })
+    }
+
+    /// As [`pages`](Self::pages), flattened to items.
+    pub fn stream(&self,
+                  filter: R::Filter,
+                  limit: NonZeroU32)
+                  -> impl Stream<Item = Result<R, JsonPlaceholderError>> + Send + use<R> {
+        self.pages(filter, limit)
+            .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn get(&self, id: u32) -> Result<R, JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .get(&self.item_path(id))?;
+        let response = self.send(Method::GET, request, Some(id))
+                           .await?;
+        decode(response).await
+    }
+
+    /// `POST`: the created item, with its assigned `id`.
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn create(&self, new: &R::New) -> Result<R, JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .request(Method::POST, R::COLLECTION)?
+                          .json(new);
+        let response = self.send(Method::POST, request, None)
+                           .await?;
+        decode(response).await
+    }
+
+    /// `PUT`: replace the item with `resource`'s id.
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn update(&self, resource: &R) -> Result<R, JsonPlaceholderError> {
+        let id = resource.id();
+        let request = self.api
+                          .http
+                          .request(Method::PUT, &self.item_path(id))?
+                          .json(resource);
+        let response = self.send(Method::PUT, request, Some(id))
+                           .await?;
+        decode(response).await
+    }
+
+    /// `PATCH`: change only the fields set in `patch`.
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn patch(&self, id: u32, patch: &R::Patch) -> Result<R, JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .request(Method::PATCH, &self.item_path(id))?
+                          .json(patch);
+        let response = self.send(Method::PATCH, request, Some(id))
+                           .await?;
+        decode(response).await
+    }
+
+    #[instrument(skip(self), fields(collection = R::COLLECTION))]
+    pub async fn delete(&self, id: u32) -> Result<(), JsonPlaceholderError> {
+        let request = self.api
+                          .http
+                          .request(Method::DELETE, &self.item_path(id))?;
+        self.send(Method::DELETE, request, Some(id))
+            .await?;
+        Ok(())
+    }
+
+    fn item_path(&self, id: u32) -> String {
+        format!("{}/{id}", R::COLLECTION)
+    }
+
+    /// Send, and map error statuses.  (`404` is [`NotFound`](JsonPlaceholderError::NotFound) for item requests.)
+    async fn send(&self,
+                  method: Method,
+                  request: RequestBuilder,
+                  id: Option<u32>)
+                  -> Result<(Method, Response), JsonPlaceholderError> {
+        let response = self.api
+                           .send(&method, request)
+                           .await?;
+        let status = response.status();
+        match id {
+            _ if status.is_success() => Ok((method, response)),
+            Some(id) if status == StatusCode::NOT_FOUND =>
+                Err(JsonPlaceholderError::NotFound { collection: R::COLLECTION,
+                                                     id }),
+            _ => Err(JsonPlaceholderError::Status { method,
+                                                    url: response.url().to_string(),
+                                                    status }),
+        }
+    }
+}
+
+async fn decode<T: DeserializeOwned>((method, response): (Method, Response))
+                                     -> Result<T, JsonPlaceholderError> {
+    let url = response.url().to_string();
+    response.json()
+            .await
+            .map_err(|source| JsonPlaceholderError::Decode { method,
+                                                             url,
+                                                             source })
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::TryStreamExt as _;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::http_stub::{StubResponse, spawn_http_stub};
+
+    fn api(base: &str) -> JsonPlaceholder {
+        JsonPlaceholder::builder().http(HttpClient::builder().base_url(base.parse().unwrap())
+                                                             .allow_http(true)
+                                                             .build()
+                                                             .unwrap())
+                                  .build()
+    }
+    fn todo_json(id: u32) -> String {
+        format!(r#"{{"userId":1,"id":{id},"title":"todo {id}","completed":false}}"#)
+    }
+
+    #[tokio::test]
+    async fn test_list_sends_filters_and_parses() {
+        let (base, requests) = spawn_http_stub(|_| {
+            StubResponse::ok_json(format!("[{},{}]", todo_json(1), todo_json(2)))
+        });
+        let todos = api(&base).todos()
+                              .list(&TodoFilter { user_id:   Some(1),
+                                                  completed: None, })
+                              .await
+                              .unwrap();
+        assert_eq!(todos.iter()
+                        .map(Todo::id)
+                        .collect::<Vec<_>>(),
+                   [1, 2]);
+        // `None` filters are omitted
+        assert_eq!(requests.recv().unwrap().path, "/todos?userId=1");
+    }
+
+    #[tokio::test]
+    async fn test_crud_methods_paths_and_bodies() {
+        let (base, requests) = spawn_http_stub(|request| match request.method.as_str() {
+            "DELETE" => StubResponse::ok_json("{}"),
+            "POST" => StubResponse::new(201, todo_json(201)).with_header("content-type",
+                                                                         "application/json"),
+            _ => StubResponse::ok_json(todo_json(7)),
+        });
+        let todos = api(&base).todos();
+        let created = todos.create(&NewTodo { user_id:   1,
+                                              title:     "new".into(),
+                                              completed: false, })
+                           .await
+                           .unwrap();
+        assert_eq!(created.id, 201);
+        let mut todo = todos.get(7).await.unwrap();
+        todo.completed = true;
+        todos.update(&todo)
+             .await
+             .unwrap();
+        todos.patch(7,
+                    &TodoPatch { completed: Some(true),
+                                 ..Default::default() })
+             .await
+             .unwrap();
+        todos.delete(7).await.unwrap();
+
+        let seen: Vec<_> = requests.try_iter()
+                                   .map(|request| (request.method, request.path, request.body))
+                                   .collect();
+        assert_eq!(seen,
+                   [("POST".into(),
+                     "/todos".into(),
+                     r#"{"userId":1,"title":"new","completed":false}"#.into()),
+                    ("GET".into(), "/todos/7".into(), String::new()),
+                    ("PUT".into(),
+                     "/todos/7".into(),
+                     r#"{"userId":1,"id":7,"title":"todo 7","completed":true}"#.into()),
+                    ("PATCH".into(), "/todos/7".into(), r#"{"completed":true}"#.into()),
+                    ("DELETE".into(), "/todos/7".into(), String::new())]);
+    }
+
+    #[tokio::test]
+    async fn test_not_found_and_error_status() {
+        let (base, _requests) = spawn_http_stub(|request| match request.path.as_str() {
+            "/posts/999" => StubResponse::new(404, "{}"),
+            _ => StubResponse::new(500, ""),
+        });
+        let api = api(&base);
+        let missing = api.posts()
+                         .get(999)
+                         .await
+                         .unwrap_err();
+        assert!(matches!(missing,
+                         JsonPlaceholderError::NotFound { collection: "posts",
+                                                          id:         999, }),
+                "{missing:?}");
+        let failed = api.users()
+                        .list(&UserFilter::default())
+                        .await
+                        .unwrap_err();
+        assert!(matches!(failed, JsonPlaceholderError::Status { status, .. } if status == 500),
+                "{failed:?}");
+    }
+
+    #[tokio::test]
+    async fn test_retry_only_idempotent_methods() {
+        let (base, requests) = spawn_http_stub(|_| StubResponse::new(503, ""));
+        let api = JsonPlaceholder::builder().http(api(&base).http)
+                                            .retry(RetryPolicy::builder().max_retries(2)
+                                                                         .base_delay(Duration::ZERO)
+                                                                         .build())
+                                            .build();
+        let todos = api.todos();
+        assert!(todos.get(1).await.is_err());
+        assert!(todos.create(&NewTodo { user_id:   1,
+                                        title:     "once".into(),
+                                        completed: false, })
+                     .await
+                     .is_err());
+        assert!(todos.patch(1, &TodoPatch::default())
+                     .await
+                     .is_err());
+        // the stub records each request just after answering it: wait (bounded) for the last
+        let methods: Vec<_> = (0..5).map_while(|_| {
+                                        requests.recv_timeout(Duration::from_secs(5))
+                                                .ok()
+                                    })
+                                    .map(|request| request.method)
+                                    .collect();
+        assert_eq!(methods, ["GET", "GET", "GET", "POST", "PATCH"]);
+        assert_eq!(requests.try_iter().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_pages_until_short_page() {
+        // 5 todos, 2 per page: pages 1, 2 & (short) 3
+        let (base, requests) = spawn_http_stub(|request| {
+            let page: u32 = request.path
+                                   .split("_page=")
+                                   .nth(1)
+                                   .and_then(|rest| rest.split('&').next())
+                                   .and_then(|page| page.parse().ok())
+                                   .unwrap();
+            let ids = (page * 2 - 1..=(page * 2).min(5)).map(todo_json)
+                                                        .collect::<Vec<_>>();
+            StubResponse::ok_json(format!("[{}]", ids.join(",")))
+        });
+        let todos: Vec<Todo> = api(&base).todos()
+                                         .stream(TodoFilter { user_id:   None,
+                                                              completed: Some(false), },
+                                                 NonZeroU32::new(2).unwrap())
+                                         .try_collect()
+                                         .await
+                                         .unwrap();
+        assert_eq!(todos.iter()
+                        .map(Todo::id)
+                        .collect::<Vec<_>>(),
+                   [1, 2, 3, 4, 5]);
+        let paths: Vec<_> = requests.try_iter()
+                                    .map(|request| request.path)
+                                    .collect();
+        assert_eq!(paths,
+                   ["/todos?completed=false&_page=1&_limit=2",
+                    "/todos?completed=false&_page=2&_limit=2",
+                    "/todos?completed=false&_page=3&_limit=2"]);
+    }
+
+    #[test]
+    fn test_page_is_last_uses_total_when_reported() {
+        let page = |items: usize, page: u32, total: Option<u64>| Page { items: vec![(); items],
+                                                                        page,
+                                                                        limit: 2,
+                                                                        total };
+        assert!(!page(2, 1, None).is_last());
+        assert!(page(1, 1, None).is_last());
+        assert!(page(2, 2, Some(4)).is_last());
+        assert!(!page(2, 1, Some(4)).is_last());
+    }
+}
@@ -1,7 +1,45 @@
 //! Utility code for other Workspace Crates
 
+pub mod actor;
+mod circuit_breaker;
+mod concurrency;
+mod diagnostic;
+pub mod download;
+pub mod error;
+pub mod fixtures;
+mod hedge;
 mod hidden_value;
+mod http_cache;
+mod http_client;
+#[cfg(test)]
+mod http_stub;
+pub mod jsonplaceholder;
+pub mod logging_config;
+pub mod otel;
+pub mod rate_limit;
+mod retry;
+mod runtime_metrics;
+mod shutdown;
 mod subscriber;
+pub mod supervisor;
+pub mod testing;
 
+pub use circuit_breaker::{CircuitBreakers, CircuitOpen, CircuitPermit, CircuitState};
+pub use concurrency::{AdaptiveLimiter, AdaptivePermit, AdaptiveStreamExt, BufferAdaptive,
+                      LimiterOutcome};
+pub use diagnostic::{Diagnose, Report, ReportFormat, SpanEntry};
+pub use download::{DownloadError, DownloadProgress, Downloaded, ProgressFn};
+pub use error::{Categorize, CommonError, ErrorCategory, Traced};
+pub use fixtures::{FixtureError, FixtureMode, Fixtures};
+pub use hedge::{HedgePolicy, HedgeStats};
 pub use hidden_value::{HiddenValue, HiddenValueError};
-pub use subscriber::activate_global_default_tracing_subscriber;
+pub use http_cache::{HttpCache, HttpCacheStats};
+pub use http_client::{HttpAuth, HttpClient, HttpClientError, SendError};
+pub use logging_config::LoggingConfigSource;
+pub use otel::{OtlpConfig, TraceContextExt};
+pub use rate_limit::{QuotaRule, QuotaSpec, QuotaTable, RateLimitError, RateLimitStats, RateLimits};
+pub use retry::{DEFAULT_RETRYABLE_STATUSES, RetryExt, RetryPolicy, RetryableError};
+pub use runtime_metrics::spawn_runtime_metrics_logger;
+pub use shutdown::{Drained, INTERRUPTED_EXIT_CODE, Shutdown};
+pub use subscriber::{SpanProfiling, SubscriberError, SubscriberGuard,
+                     activate_global_default_tracing_subscriber};
@@ -0,0 +1,383 @@
+//! Config-file driven logging setup.
+//!
+//! A `[logging]` TOML section, loaded from an explicit path or the XDG config dir, with environment overrides.
+//!
+//! ## File
+//! ```toml
+//! [logging]
+//! level =       "info"                         # default directive
+//! format =      "pretty"                       # pretty | compact | full | json
+//! file =        ".output/logging/app.log"      # omit to log to stderr
+//! rotation =    "daily"                        # never | minutely | hourly | daily
+//! error_level = "trace"                        # span-trace capture level (error layer)
+//!
+//! [logging.targets]                            # per-target directives
+//! hyper =        "warn"
+//! sample_async = "debug"
+//! ```
+//!
+//! ## Precedence (highest first)
+//! 1. environment
+//!    - `RUST_LOG`: replaces `level` *and* `targets` (standard `EnvFilter` syntax)
+//!    - `LOGGING_LEVEL`, `LOGGING_FORMAT`, `LOGGING_FILE`, `LOGGING_ROTATION`, `LOGGING_ERROR_LEVEL`
+//! 2. config file
+//! 3. builder arguments (e.g. `default_logging_level`)
+//! 4. compiled defaults
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap,
+          env,
+          path::{Path, PathBuf},
+          str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum LoggingConfigError {
+    #[error("Could not read logging config file {path:?}: {source}")]
+    Read {
+        path:   PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not parse logging config file {path:?}: {source}")]
+    Parse {
+        path:   PathBuf,
+        // boxed: keeps `Result<_, LoggingConfigError>` (and callers' error enums) small
+        source: Box<toml::de::Error>,
+    },
+    #[error("Invalid value for environment variable `{key}`: {value:?}")]
+    EnvValue { key: &'static str, value: String },
+    #[error("Unknown {setting} {value:?} (expected one of: {expected})")]
+    UnknownValue {
+        setting:  &'static str,
+        value:    String,
+        expected: &'static str,
+    },
+}
+// ///////////////////////////////// [ source ] ///////////////////////////////// //
+/// Where to look for a `[logging]` config section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggingConfigSource {
+    /// An explicit TOML file.  Errors if missing.
+    Path(PathBuf),
+    /// `$XDG_CONFIG_HOME/<app_name>/config.toml` (falling back to `~/.config/<app_name>/config.toml`).
+    /// Silently skipped if missing.
+    Xdg { app_name: String },
+}
+impl LoggingConfigSource {
+    /// Convenience constructor for [`LoggingConfigSource::Xdg`].
+    pub fn xdg(app_name: impl Into<String>) -> Self {
+        Self::Xdg { app_name: app_name.into(), }
+    }
+
+    /// Resolved file path (if one can be determined).
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            Self::Path(path) => Some(path.clone()),
+            Self::Xdg { app_name } => {
+                let config_home = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty())
+                                                                .map(PathBuf::from)
+                                                                .or_else(|| {
+                                                                    env::var_os("HOME").map(|home| {
+                                                          PathBuf::from(home).join(".config")
+                                                      })
+                                                                })?;
+                Some(config_home.join(app_name)
+                                .join("config.toml"))
+            },
+        }
+    }
+}
+// ///////////////////////////////// [ config ] ///////////////////////////////// //
+/// Output format of the fmt layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Full,
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = LoggingConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase()
+               .as_str()
+        {
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "full" => Ok(Self::Full),
+            "json" => Ok(Self::Json),
+            _ => Err(LoggingConfigError::UnknownValue { setting:  "log format",
+                                                        value:    s.to_string(),
+                                                        expected: "pretty, compact, full, json", }),
+        }
+    }
+}
+
+/// Log-file rotation.  (Ignored when logging to stderr.)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// Truncate & rewrite a single file each run.
+    #[default]
+    Never,
+    Minutely,
+    Hourly,
+    Daily,
+}
+impl FromStr for LogRotation {
+    type Err = LoggingConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase()
+               .as_str()
+        {
+            "never" => Ok(Self::Never),
+            "minutely" => Ok(Self::Minutely),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            _ => Err(LoggingConfigError::UnknownValue { setting:  "log rotation",
+                                                        value:    s.to_string(),
+                                                        expected: "never, minutely, hourly, daily", }),
+        }
+    }
+}
+
+impl From<LogRotation> for tracing_appender::rolling::Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Never => Self::NEVER,
+            LogRotation::Minutely => Self::MINUTELY,
+            LogRotation::Hourly => Self::HOURLY,
+            LogRotation::Daily => Self::DAILY,
+        }
+    }
+}
+
+/// The `[logging]` section.  Every field is optional; unset fields fall through to builder args & defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    #[serde(with = "level_serde")]
+    pub level:       Option<LevelFilter>,
+    /// target -> level, e.g. `hyper = "warn"`
+    pub targets:     BTreeMap<String, String>,
+    pub format:      Option<LogFormat>,
+    pub file:        Option<PathBuf>,
+    pub rotation:    Option<LogRotation>,
+    #[serde(with = "level_serde")]
+    pub error_level: Option<LevelFilter>,
+}
+/// Wrapper matching the file layout (`[logging]` table; other tables ignored).
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    logging: LoggingConfig,
+}
+impl LoggingConfig {
+    /// Parse the `[logging]` section of a TOML document. (Other sections are ignored.)
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str::<ConfigFile>(toml_str).map(|file| file.logging)
+    }
+
+    /// Load from `source`.  `Ok(None)` if an XDG file is absent.
+    pub fn load(source: &LoggingConfigSource)
+                -> Result<Option<(Self, PathBuf)>, LoggingConfigError> {
+        let Some(path) = source.path() else {
+            return Ok(None);
+        };
+        if matches!(source, LoggingConfigSource::Xdg { .. }) && !path.is_file() {
+            return Ok(None);
+        }
+        let config = Self::from_file(&path)?;
+        Ok(Some((config, path)))
+    }
+
+    /// Load from an explicit file path.
+    pub fn from_file(path: &Path) -> Result<Self, LoggingConfigError> {
+        let path = path.to_path_buf();
+        match std::fs::read_to_string(&path) {
+            Err(source) => Err(LoggingConfigError::Read { path, source }),
+            Ok(contents) => Self::from_toml_str(&contents).map_err(|source| {
+                                                              LoggingConfigError::Parse { path,
+                                                                              source:
+                                                                                  Box::new(source) }
+                                                          }),
+        }
+    }
+
+    /// Apply `LOGGING_*` overrides from the process environment.
+    ///
+    /// (`RUST_LOG` is not read here; it is applied when the filter is built.)
+    pub fn with_env_overrides(self) -> Result<Self, LoggingConfigError> {
+        self.with_overrides_from(|key| env::var(key).ok())
+    }
+
+    /// Apply `LOGGING_*` overrides from an arbitrary lookup. (Testable without touching the process env.)
+    pub fn with_overrides_from(mut self,
+                               lookup: impl Fn(&'static str) -> Option<String>)
+                               -> Result<Self, LoggingConfigError> {
+        fn parse<T: FromStr>(key: &'static str, value: String) -> Result<T, LoggingConfigError> {
+            value.parse()
+                 .map_err(|_| LoggingConfigError::EnvValue { key, value })
+        }
+        const LEVEL: &str = "LOGGING_LEVEL";
+        const FORMAT: &str = "LOGGING_FORMAT";
+        const FILE: &str = "LOGGING_FILE";
+        const ROTATION: &str = "LOGGING_ROTATION";
+        const ERROR_LEVEL: &str = "LOGGING_ERROR_LEVEL";
+        if let Some(value) = lookup(LEVEL) {
+            self.level = Some(parse(LEVEL, value)?);
+        }
+        if let Some(value) = lookup(FORMAT) {
+            self.format = Some(parse(FORMAT, value)?);
+        }
+        if let Some(value) = lookup(FILE) {
+            self.file = Some(PathBuf::from(value));
+        }
+        if let Some(value) = lookup(ROTATION) {
+            self.rotation = Some(parse(ROTATION, value)?);
+        }
+        if let Some(value) = lookup(ERROR_LEVEL) {
+            self.error_level = Some(parse(ERROR_LEVEL, value)?);
+        }
+        Ok(self)
+    }
+
+    /// `target=level` directives, in target order.
+    pub fn target_directives(&self) -> impl Iterator<Item = String> + '_ {
+        self.targets
+            .iter()
+            .map(|(target, level)| format!("{}={}", target, level))
+    }
+}
+// ///////////////////////////////// [ serde helpers ] ///////////////////////////////// //
+/// `LevelFilter` has `FromStr`/`Display` but no serde impls.
+mod level_serde {
+    use super::*;
+    pub fn serialize<S: Serializer>(level: &Option<LevelFilter>,
+                                    serializer: S)
+                                    -> Result<S::Ok, S::Error> {
+        level.map(|l| l.to_string().to_lowercase())
+             .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+                                                  -> Result<Option<LevelFilter>, D::Error> {
+        Option::<String>::deserialize(deserializer)?.map(|s| {
+                                                        s.parse()
+                                                         .map_err(serde::de::Error::custom)
+                                                    })
+                                                    .transpose()
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    const SAMPLE: &str = r#"
+        [other_section]
+        ignored = true
+
+        [logging]
+        level = "debug"
+        format = "compact"
+        file = ".output/logging/app.log"
+        rotation = "daily"
+        error_level = "warn"
+
+        [logging.targets]
+        hyper = "warn"
+        sample_async = "trace"
+    "#;
+    #[test]
+    fn test_parse_full_section() {
+        let config = LoggingConfig::from_toml_str(SAMPLE).unwrap();
+        assert_eq!(config.level, Some(LevelFilter::DEBUG));
+        assert_eq!(config.format, Some(LogFormat::Compact));
+        assert_eq!(config.file, Some(PathBuf::from(".output/logging/app.log")));
+        assert_eq!(config.rotation, Some(LogRotation::Daily));
+        assert_eq!(config.error_level, Some(LevelFilter::WARN));
+        assert_eq!(config.target_directives()
+                         .collect::<Vec<_>>(),
+                   vec!["hyper=warn", "sample_async=trace"]);
+    }
+    #[test]
+    fn test_missing_section_is_default() {
+        let config = LoggingConfig::from_toml_str("[other]\nx = 1").unwrap();
+        assert_eq!(config, LoggingConfig::default());
+    }
+    #[test]
+    fn test_unknown_keys_and_bad_levels_rejected() {
+        assert!(LoggingConfig::from_toml_str("[logging]\nlevl = \"info\"").is_err());
+        assert!(LoggingConfig::from_toml_str("[logging]\nlevel = \"loud\"").is_err());
+        assert!(LoggingConfig::from_toml_str("[logging]\nformat = \"xml\"").is_err());
+    }
+    #[test]
+    fn test_env_overrides_file_values() {
+        let env: HashMap<&str, &str> = HashMap::from([("LOGGING_LEVEL", "error"),
+                                                      ("LOGGING_FORMAT", "JSON"),
+                                                      ("LOGGING_FILE", "other.log")]);
+        let config = LoggingConfig::from_toml_str(SAMPLE).unwrap()
+                                                         .with_overrides_from(|key| {
+                                                             env.get(key)
+                                                                .map(|v| v.to_string())
+                                                         })
+                                                         .unwrap();
+        assert_eq!(config.level, Some(LevelFilter::ERROR));
+        assert_eq!(config.format, Some(LogFormat::Json));
+        assert_eq!(config.file, Some(PathBuf::from("other.log")));
+        // untouched by env
+        assert_eq!(config.rotation, Some(LogRotation::Daily));
+        assert_eq!(config.error_level, Some(LevelFilter::WARN));
+    }
+    #[test]
+    fn test_bad_env_value_is_error() {
+        let result =
+            LoggingConfig::default().with_overrides_from(|key| {
+                                        (key == "LOGGING_ROTATION").then(|| "weekly".to_string())
+                                    });
+        assert!(matches!(result,
+                         Err(LoggingConfigError::EnvValue { key: "LOGGING_ROTATION",
+                                                            .. })));
+    }
+    #[test]
+    fn test_unknown_value_is_named() {
+        assert_eq!("JSON".parse::<LogFormat>()
+                         .unwrap(),
+                   LogFormat::Json);
+        assert_eq!("xml".parse::<LogFormat>()
+                        .unwrap_err()
+                        .to_string(),
+                   r#"Unknown log format "xml" (expected one of: pretty, compact, full, json)"#);
+        assert!(matches!("weekly".parse::<LogRotation>(),
+                         Err(LoggingConfigError::UnknownValue { setting: "log rotation",
+                                                                ref value,
+                                                                .. }) if value == "weekly"));
+    }
+    #[test]
+    fn test_round_trip_serialize() {
+        let config = LoggingConfig::from_toml_str(SAMPLE).unwrap();
+        let serialized = toml::to_string(&config).unwrap();
+        let reparsed: LoggingConfig = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed, config);
+    }
+    #[test]
+    fn test_missing_xdg_file_is_skipped_but_missing_path_errors() {
+        let missing_app = LoggingConfigSource::xdg("utilities-test-app-that-does-not-exist");
+        assert!(LoggingConfig::load(&missing_app).unwrap()
+                                                 .is_none());
+        let missing_path = LoggingConfigSource::Path("does/not/exist.toml".into());
+        assert!(matches!(LoggingConfig::load(&missing_path),
+                         Err(LoggingConfigError::Read { .. })));
+    }
+}
@@ -0,0 +1,224 @@
+//! OpenTelemetry span export (OTLP, http/json) and W3C trace-context propagation.
+//!
+//! ## Feature: `otlp`
+//! Without the feature [`OtlpConfig`] is accepted but ignored (with a warning),
+//! and [`TraceContextExt::with_trace_context`] is a no-op -- so call sites need no `cfg`.
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::{OtlpConfig, TraceContextExt as _};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! // e.g. `OTEL_EXPORTER_OTLP_ENDPOINT=http://localhost:4318`
+//! let _guard = utilities::activate_global_default_tracing_subscriber()
+//!     .maybe_otlp(OtlpConfig::from_env("my-service"))
+//!     .call()?;
+//! let client = reqwest::Client::new();
+//! // adds `traceparent` (& `tracestate`) for the current span
+//! let _response = client.get("https://httpbin.org/headers")
+//!                       .with_trace_context()
+//!                       .send()
+//!                       .await?;
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+#[cfg(feature = "otlp")]
+use opentelemetry::{propagation::Injector, trace::TracerProvider as _};
+#[cfg(feature = "otlp")]
+use opentelemetry_otlp::{Protocol, WithExportConfig as _};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+#[cfg(feature = "otlp")]
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(feature = "otlp")]
+use tracing::{Level as L, event};
+#[cfg(feature = "otlp")]
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+// ///////////////////////////////// [ config ] ///////////////////////////////// //
+/// Standard OTLP env var: collector base URL (signal paths like `/v1/traces` are appended).
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Where to export spans, and as whom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtlpConfig {
+    /// Collector base URL, e.g. `http://localhost:4318`.
+    pub collector:    String,
+    /// `service.name` resource attribute.
+    pub service_name: String,
+}
+impl OtlpConfig {
+    pub fn new(collector: impl Into<String>, service_name: impl Into<String>) -> Self {
+        Self { collector:    collector.into(),
+               service_name: service_name.into(), }
+    }
+
+    /// `Some` iff `OTEL_EXPORTER_OTLP_ENDPOINT` is set (and non-empty).
+    pub fn from_env(service_name: impl Into<String>) -> Option<Self> {
+        std::env::var(OTLP_ENDPOINT_ENV).ok()
+                                        .filter(|collector| !collector.is_empty())
+                                        .map(|collector| Self::new(collector, service_name))
+    }
+
+    /// Full traces endpoint: `<collector>/v1/traces`.
+    pub fn traces_endpoint(&self) -> String {
+        format!("{}/v1/traces",
+                self.collector
+                    .trim_end_matches('/'))
+    }
+}
+// ///////////////////////////////// [ propagation ] ///////////////////////////////// //
+/// Inject W3C trace-context headers (`traceparent`, `tracestate`) for the current `tracing` span.
+///
+/// Only has an effect when the `otlp` feature is on *and* an OTLP layer is installed;
+/// otherwise the current span has no OpenTelemetry context and nothing is added.
+pub trait TraceContextExt {
+    #[must_use]
+    fn with_trace_context(self) -> Self;
+}
+impl TraceContextExt for reqwest::RequestBuilder {
+    #[cfg(feature = "otlp")]
+    fn with_trace_context(self) -> Self {
+        let context = tracing::Span::current().context();
+        let mut headers = HeaderMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+        });
+        self.headers(headers)
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    fn with_trace_context(self) -> Self {
+        self
+    }
+}
+#[cfg(feature = "otlp")]
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+#[cfg(feature = "otlp")]
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value))
+        {
+            self.0.insert(name, value);
+        }
+    }
+}
+// ///////////////////////////////// [ layer ] ///////////////////////////////// //
+/// Flushes and shuts down the tracer provider on drop.
+#[cfg(feature = "otlp")]
+pub(crate) struct OtlpGuard {
+    provider: SdkTracerProvider,
+}
+#[cfg(feature = "otlp")]
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        if let Err(error) = self.provider.shutdown() {
+            event!(L::WARN, %error, "OTLP tracer provider did not shut down cleanly; spans may be lost");
+        }
+    }
+}
+
+/// Build an OTLP exporting layer (batched, on a background thread) and set the W3C propagator globally.
+#[cfg(feature = "otlp")]
+pub(crate) fn otlp_layer<S>(
+    config: &OtlpConfig)
+    -> Result<(tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::SdkTracer>,
+               OtlpGuard),
+              opentelemetry_otlp::ExporterBuildError>
+    where S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span> {
+    let exporter =
+        opentelemetry_otlp::SpanExporter::builder().with_http()
+                                                   .with_protocol(Protocol::HttpJson)
+                                                   .with_endpoint(config.traces_endpoint())
+                                                   .build()?;
+    let provider = SdkTracerProvider::builder().with_batch_exporter(exporter)
+                                               .with_resource(Resource::builder().with_service_name(config.service_name
+                                                                                                          .clone())
+                                                                                 .build())
+                                               .build();
+    let tracer = provider.tracer(config.service_name.clone());
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok((tracing_opentelemetry::layer().with_tracer(tracer), OtlpGuard { provider }))
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    #[test]
+    fn test_traces_endpoint_joins_cleanly() {
+        assert_eq!(OtlpConfig::new("http://localhost:4318", "svc").traces_endpoint(),
+                   "http://localhost:4318/v1/traces");
+        assert_eq!(OtlpConfig::new("http://localhost:4318/", "svc").traces_endpoint(),
+                   "http://localhost:4318/v1/traces");
+    }
+    #[cfg(not(feature = "otlp"))]
+    #[test]
+    fn test_with_trace_context_is_noop_without_feature() {
+        let request = reqwest::Client::new().get("http://localhost/")
+                                            .with_trace_context()
+                                            .build()
+                                            .unwrap();
+        assert!(request.headers().is_empty());
+    }
+}
+#[cfg(all(test, feature = "otlp"))]
+mod otlp_tests {
+    // ///////////////////////////////// [ stand-in collector tests ] ///////////////////////////////// //
+    use std::time::Duration;
+
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::http_stub::{StubResponse, spawn_http_stub};
+    #[test]
+    fn test_spans_exported_to_collector() {
+        let (collector, received) = spawn_http_stub(|_| StubResponse::ok_json("{}"));
+        let config = OtlpConfig::new(collector, "otlp-test-service");
+        let (layer, guard) = otlp_layer(&config).unwrap();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer_span");
+            let _entered = outer.enter();
+            tracing::info_span!("inner_span", answer = 42).in_scope(|| {
+                                                              tracing::info!("inside");
+                                                          });
+        });
+        drop(guard); // flush
+
+        let export = received.recv_timeout(Duration::from_secs(10))
+                             .expect("collector should have received an export");
+        let body = export.body;
+        assert_eq!(export.path, "/v1/traces");
+        assert!(body.contains("otlp-test-service"), "{body}");
+        assert!(body.contains("outer_span"), "{body}");
+        assert!(body.contains("inner_span"), "{body}");
+    }
+    #[test]
+    fn test_traceparent_injected_for_current_span() {
+        let (collector, _received) = spawn_http_stub(|_| StubResponse::ok_json("{}"));
+        let (layer, _guard) = otlp_layer(&OtlpConfig::new(collector, "otlp-test-service")).unwrap();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        let request = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request_span").in_scope(|| {
+                                                   reqwest::Client::new().get("http://localhost/")
+                                                                         .with_trace_context()
+                                                                         .build()
+                                                                         .unwrap()
+                                               })
+        });
+        let traceparent = request.headers()
+                                 .get("traceparent")
+                                 .expect("traceparent header should be set")
+                                 .to_str()
+                                 .unwrap();
+        // version-traceid-spanid-flags
+        let parts: Vec<_> = traceparent.split('-')
+                                       .collect();
+        assert_eq!(parts.len(), 4, "{traceparent}");
+        assert_eq!((parts[0], parts[1].len(), parts[2].len()), ("00", 32, 16));
+    }
+}
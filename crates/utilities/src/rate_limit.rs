@@ -0,0 +1,591 @@
+//! Keyed rate limiting from a quota table: different limits for different upstreams, within one process.
+//!
+//! Each rule selects requests (by host, route template, and/or an API-key header) and gives them a quota.
+//! Buckets are per rule *and* per host; rules with a `key_header` also get one bucket per header value.
+//! The first matching rule applies; requests matching no rule use `default` (per host), or are not limited.
+//!
+//! ## File
+//! ```toml
+//! [rate_limits]
+//! default = { per_second = 20, burst = 10 }    # omit: unmatched requests are not limited
+//!
+//! [[rate_limits.rules]]
+//! name =  "typicode-todo"
+//! host =  "jsonplaceholder.typicode.com"       # exact (case-insensitive); omit for any host
+//! route = "/todos/{id}"                        # `{..}` matches any one segment; omit for any path
+//! quota = { per_minute = 60 }
+//!
+//! [[rate_limits.rules]]
+//! name =       "per-api-key"
+//! key_header = "x-api-key"                     # a bucket per key; requests without the header don't match
+//! quota =      { per_second = 5, burst = 5 }
+//! ```
+//! A quota has exactly one of `per_second`/`per_minute`; `burst` defaults to that rate.
+//!
+//! ## Metrics
+//! [`RateLimits::stats`] reports requests, delays, and wait times per bucket.
+//! Bucket labels are `<rule>@<host>` (plus `#<hash>` of the header value for `key_header` rules: keys are never logged).
+//!
+//! ## Memory
+//! Every 1024 limited requests, buckets back at full burst (indistinguishable from new ones) are dropped,
+//! and the stats of buckets idle for the last 1024 requests are folded into one `<rule>@*` entry per rule,
+//! so a stream of distinct hosts or keys grows neither the limiters nor the stats without bound.
+//! (Also on demand: [`RateLimits::retain_recent`].)
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::{HttpClient, QuotaTable, RateLimits};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let table = QuotaTable::from_toml_str("[rate_limits]\ndefault = { per_second = 2 }")?;
+//! let api = HttpClient::builder().base_url("https://httpbin.org".parse()?)
+//!                                .rate_limits(RateLimits::new(&table)?)
+//!                                .build()?;
+//! let _json = api.send(api.get("json")?)
+//!                .await?;
+//! println!("{:#?}",
+//!          api.rate_limits()
+//!             .map(RateLimits::stats));
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap,
+          fmt,
+          hash::{DefaultHasher, Hash, Hasher},
+          num::NonZeroU32,
+          path::{Path, PathBuf},
+          sync::{Arc, Mutex,
+                 atomic::{AtomicU64, Ordering}},
+          time::{Duration, Instant}};
+
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use reqwest::{Request, header::HeaderName};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{Level as L, event};
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("Quota for `{rule}` needs exactly one of `per_second` or `per_minute`")]
+    QuotaRate { rule: String },
+    #[error("Invalid `key_header` for `{rule}`: {header:?}")]
+    KeyHeader { rule: String, header: String },
+    #[error("Could not read quota table {path:?}: {source}")]
+    Read {
+        path:   PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not parse quota table {path:?}: {source}")]
+    Parse {
+        path:   PathBuf,
+        // boxed: keeps `Result<_, RateLimitError>` (and callers' error enums) small
+        source: Box<toml::de::Error>,
+    },
+}
+// ///////////////////////////////// [ config ] ///////////////////////////////// //
+/// A rate: exactly one of `per_second`/`per_minute`, with an optional burst (default: the rate).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaSpec {
+    pub per_second: Option<NonZeroU32>,
+    pub per_minute: Option<NonZeroU32>,
+    pub burst:      Option<NonZeroU32>,
+}
+impl QuotaSpec {
+    fn to_quota(self, rule: &str) -> Result<Quota, RateLimitError> {
+        let quota = match (self.per_second, self.per_minute) {
+            (Some(rate), None) => Quota::per_second(rate),
+            (None, Some(rate)) => Quota::per_minute(rate),
+            _ => return Err(RateLimitError::QuotaRate { rule: rule.to_string(), }),
+        };
+        Ok(match self.burst {
+            Some(burst) => quota.allow_burst(burst),
+            None => quota,
+        })
+    }
+}
+
+/// Selects requests & gives them a quota.  Unset selectors match anything.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaRule {
+    /// Used in bucket labels (metrics & logs).
+    pub name:       String,
+    pub host:       Option<String>,
+    /// Path template, e.g. `/todos/{id}`.
+    pub route:      Option<String>,
+    /// Header whose value (e.g. an API key) gets its own bucket.
+    pub key_header: Option<String>,
+    pub quota:      QuotaSpec,
+}
+
+/// The `[rate_limits]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaTable {
+    /// Per host, for requests matching no rule.  (`None`: not limited.)
+    pub default: Option<QuotaSpec>,
+    /// In priority order.
+    pub rules:   Vec<QuotaRule>,
+}
+/// Wrapper matching the file layout (`[rate_limits]` table; other tables ignored).
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    rate_limits: QuotaTable,
+}
+impl QuotaTable {
+    /// Parse the `[rate_limits]` section of a TOML document. (Other sections are ignored.)
+    pub fn from_toml_str(toml_str: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str::<ConfigFile>(toml_str).map(|file| file.rate_limits)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, RateLimitError> {
+        let path = path.to_path_buf();
+        match std::fs::read_to_string(&path) {
+            Err(source) => Err(RateLimitError::Read { path, source }),
+            Ok(contents) => Self::from_toml_str(&contents).map_err(|source| {
+                                                              RateLimitError::Parse { path,
+                                                                          source:
+                                                                              Box::new(source) }
+                                                          }),
+        }
+    }
+}
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// Requests between automatic [`RateLimits::retain_recent`] sweeps.
+const RETAIN_EVERY: u64 = 1024;
+
+/// Bucket label the stats of idle `rule` buckets are folded into.
+fn folded_label(rule: &str) -> String {
+    format!("{rule}@*")
+}
+
+/// Wait-time metrics for one bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitStats {
+    pub requests:   u64,
+    /// Requests that had to wait at all.
+    pub delayed:    u64,
+    pub total_wait: Duration,
+    pub max_wait:   Duration,
+}
+impl RateLimitStats {
+    fn merge(&mut self, other: &Self) {
+        self.requests += other.requests;
+        self.delayed += other.delayed;
+        self.total_wait += other.total_wait;
+        self.max_wait = self.max_wait
+                            .max(other.max_wait);
+    }
+
+    pub fn mean_wait(&self) -> Duration {
+        match u32::try_from(self.requests) {
+            Ok(0) => Duration::ZERO,
+            Ok(requests) => self.total_wait / requests,
+            Err(_) => self.total_wait
+                          .div_f64(self.requests as f64),
+        }
+    }
+}
+
+/// Compiled quota table, shared (cheap to clone) across tasks & clients.  See [module docs](self).
+#[derive(Clone)]
+pub struct RateLimits {
+    inner: Arc<RateLimitsInner>,
+}
+struct RateLimitsInner {
+    rules:   Vec<CompiledRule>,
+    default: Option<DefaultKeyedRateLimiter<String>>,
+    /// With the `checked` count at the bucket's last request.
+    stats:   Mutex<BTreeMap<String, (RateLimitStats, u64)>>,
+    /// Limited requests so far (drives the periodic sweep).
+    checked: AtomicU64,
+}
+struct CompiledRule {
+    name:       String,
+    host:       Option<String>,
+    route:      Option<Vec<RouteSegment>>,
+    key_header: Option<HeaderName>,
+    limiter:    DefaultKeyedRateLimiter<String>,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RouteSegment {
+    Literal(String),
+    Param,
+}
+impl fmt::Debug for RateLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimits")
+         .field("rules",
+                &self.inner
+                     .rules
+                     .iter()
+                     .map(|rule| rule.name.as_str())
+                     .collect::<Vec<_>>())
+         .field("default", &self.inner.default.is_some())
+         .finish_non_exhaustive()
+    }
+}
+impl RateLimits {
+    pub fn new(table: &QuotaTable) -> Result<Self, RateLimitError> {
+        let rules = table.rules
+                         .iter()
+                         .map(CompiledRule::new)
+                         .collect::<Result<_, _>>()?;
+        let default = table.default
+                           .map(|spec| {
+                               spec.to_quota("default")
+                                   .map(RateLimiter::keyed)
+                           })
+                           .transpose()?;
+        Ok(Self { inner: Arc::new(RateLimitsInner { rules,
+                                                    default,
+                                                    stats: Mutex::default(),
+                                                    checked: AtomicU64::new(0) }), })
+    }
+
+    /// Wait until `request` may be sent.  Returns the time waited.
+    pub async fn until_ready(&self, request: &Request) -> Duration {
+        let Some((limiter, label)) = self.bucket(request) else {
+            return Duration::ZERO;
+        };
+        // delayed: the bucket had no cell free right away (however short the wait turns out)
+        let (delayed, waited) = match limiter.check_key(&label) {
+            Ok(()) => (false, Duration::ZERO),
+            Err(_) => {
+                let start = Instant::now();
+                limiter.until_key_ready(&label)
+                       .await;
+                let waited = start.elapsed();
+                event!(L::DEBUG, bucket = %label, ?waited, "rate limited");
+                (true, waited)
+            },
+        };
+        let checked = self.inner
+                          .checked
+                          .fetch_add(1, Ordering::Relaxed)
+                      + 1;
+        let mut stats = self.lock_stats();
+        let (entry, last_request) = stats.entry(label)
+                                         .or_default();
+        entry.merge(&RateLimitStats { requests:   1,
+                                      delayed:    u64::from(delayed),
+                                      total_wait: waited,
+                                      max_wait:   waited, });
+        *last_request = checked;
+        drop(stats);
+        if checked.is_multiple_of(RETAIN_EVERY) {
+            self.retain_recent();
+        }
+        waited
+    }
+
+    /// Drop buckets that are back at full burst (a new request would find them the same),
+    /// and fold the stats of buckets without a request in the last 1024 into their rule's `<rule>@*` entry.
+    pub fn retain_recent(&self) {
+        for limiter in self.limiters() {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+        }
+        let checked = self.inner
+                          .checked
+                          .load(Ordering::Relaxed);
+        let mut stats = self.lock_stats();
+        let idle: Vec<_> = stats.iter()
+                                .filter(|(label, (_, last_request))| {
+                                    !label.ends_with("@*") && last_request + RETAIN_EVERY <= checked
+                                })
+                                .map(|(label, _)| label.clone())
+                                .collect();
+        for label in idle {
+            let (idle_stats, last_request) = stats.remove(&label)
+                                                  .expect("label was just listed");
+            let rule = label.rsplit_once('@')
+                            .map_or(label.as_str(), |(rule, _)| rule);
+            let (folded, folded_last) = stats.entry(folded_label(rule))
+                                             .or_default();
+            folded.merge(&idle_stats);
+            *folded_last = (*folded_last).max(last_request);
+        }
+        event!(L::TRACE,
+               buckets = self.tracked_buckets(),
+               stats = stats.len(),
+               "rate limit buckets swept");
+    }
+
+    /// Buckets currently held across all rules.
+    pub fn tracked_buckets(&self) -> usize {
+        self.limiters()
+            .map(DefaultKeyedRateLimiter::len)
+            .sum()
+    }
+
+    fn limiters(&self) -> impl Iterator<Item = &DefaultKeyedRateLimiter<String>> {
+        self.inner
+            .rules
+            .iter()
+            .map(|rule| &rule.limiter)
+            .chain(&self.inner.default)
+    }
+
+    /// Bucket label the request would be counted under, if it is limited.
+    pub fn bucket_label(&self, request: &Request) -> Option<String> {
+        self.bucket(request)
+            .map(|(_, label)| label)
+    }
+
+    /// Metrics per bucket label (idle buckets: per rule, as `<rule>@*`).
+    pub fn stats(&self) -> BTreeMap<String, RateLimitStats> {
+        self.lock_stats()
+            .iter()
+            .map(|(label, (stats, _))| (label.clone(), *stats))
+            .collect()
+    }
+
+    fn lock_stats(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, (RateLimitStats, u64)>> {
+        self.inner
+            .stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn bucket(&self, request: &Request) -> Option<(&DefaultKeyedRateLimiter<String>, String)> {
+        let host = request.url()
+                          .host_str()
+                          .unwrap_or_default();
+        for rule in &self.inner.rules {
+            if let Some(label) = rule.label_for(request, host) {
+                return Some((&rule.limiter, label));
+            }
+        }
+        self.inner
+            .default
+            .as_ref()
+            .map(|limiter| (limiter, format!("default@{host}")))
+    }
+}
+impl CompiledRule {
+    fn new(rule: &QuotaRule) -> Result<Self, RateLimitError> {
+        let key_header = rule.key_header
+                             .as_deref()
+                             .map(|header| {
+                                 HeaderName::try_from(header).map_err(|_| RateLimitError::KeyHeader { rule:   rule.name.clone(),
+                                                                                                      header: header.to_string(), })
+                             })
+                             .transpose()?;
+        let route =
+            rule.route
+                .as_deref()
+                .map(|route| {
+                    path_segments(route).map(|segment| {
+                                            if segment.starts_with('{') && segment.ends_with('}') {
+                                                RouteSegment::Param
+                                            } else {
+                                                RouteSegment::Literal(segment.to_string())
+                                            }
+                                        })
+                                        .collect()
+                });
+        Ok(Self { name: rule.name.clone(),
+                  host: rule.host.clone(),
+                  route,
+                  key_header,
+                  limiter: RateLimiter::keyed(rule.quota
+                                                  .to_quota(&rule.name)?) })
+    }
+
+    /// `Some(bucket label)` if the rule applies to `request`.
+    fn label_for(&self, request: &Request, host: &str) -> Option<String> {
+        if self.host
+               .as_ref()
+               .is_some_and(|rule_host| !rule_host.eq_ignore_ascii_case(host))
+        {
+            return None;
+        }
+        if let Some(route) = &self.route {
+            let mut path = path_segments(request.url().path());
+            let matched = route.iter()
+                               .all(|expected| match (expected, path.next()) {
+                                   (_, None) => false,
+                                   (RouteSegment::Param, Some(_)) => true,
+                                   (RouteSegment::Literal(literal), Some(segment)) =>
+                                       literal == segment,
+                               });
+            if !matched || path.next().is_some() {
+                return None;
+            }
+        }
+        let mut label = format!("{}@{}", self.name, host);
+        if let Some(key_header) = &self.key_header {
+            let key = request.headers()
+                             .get(key_header)?;
+            let mut hasher = DefaultHasher::new();
+            key.as_bytes()
+               .hash(&mut hasher);
+            label.push_str(&format!("#{:08x}", hasher.finish() as u32));
+        }
+        Some(label)
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use reqwest::Method;
+
+    use super::*;
+    const TABLE: &str = r#"
+        [logging]
+        level = "info"
+
+        [rate_limits]
+        default = { per_second = 100 }
+
+        [[rate_limits.rules]]
+        name = "todo"
+        host = "api.test"
+        route = "/todos/{id}"
+        quota = { per_second = 20, burst = 1 }
+
+        [[rate_limits.rules]]
+        name = "keyed"
+        key_header = "x-api-key"
+        quota = { per_minute = 600, burst = 2 }
+    "#;
+    fn request(url: &str, api_key: Option<&str>) -> Request {
+        let mut request = Request::new(Method::GET, url.parse().unwrap());
+        if let Some(api_key) = api_key {
+            request.headers_mut()
+                   .insert("x-api-key", api_key.parse().unwrap());
+        }
+        request
+    }
+    #[test]
+    fn test_parse_table() {
+        let table = QuotaTable::from_toml_str(TABLE).unwrap();
+        assert_eq!(table.rules.len(), 2);
+        assert_eq!(table.rules[0].route
+                                 .as_deref(),
+                   Some("/todos/{id}"));
+        assert_eq!(table.default
+                        .and_then(|spec| spec.per_second)
+                        .map(NonZeroU32::get),
+                   Some(100));
+        assert_eq!(QuotaTable::from_toml_str("[other]").unwrap(),
+                   QuotaTable::default());
+        assert!(QuotaTable::from_toml_str("[rate_limits]\ndefualt = {}").is_err());
+    }
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let both = QuotaTable::from_toml_str("[rate_limits]\ndefault = { per_second = 1, per_minute = 1 }").unwrap();
+        assert!(matches!(RateLimits::new(&both),
+                         Err(RateLimitError::QuotaRate { .. })));
+        let neither =
+            QuotaTable::from_toml_str("[[rate_limits.rules]]\nname = \"x\"\nquota = {}").unwrap();
+        assert!(matches!(RateLimits::new(&neither),
+                         Err(RateLimitError::QuotaRate { .. })));
+        let header = QuotaTable::from_toml_str(
+            "[[rate_limits.rules]]\nname = \"x\"\nkey_header = \"bad header\"\nquota = { per_second = 1 }",
+        ).unwrap();
+        assert!(matches!(RateLimits::new(&header),
+                         Err(RateLimitError::KeyHeader { .. })));
+    }
+    #[test]
+    fn test_bucket_selection() {
+        let limits = RateLimits::new(&QuotaTable::from_toml_str(TABLE).unwrap()).unwrap();
+        let label = |url, key| limits.bucket_label(&request(url, key));
+        assert_eq!(label("https://api.test/todos/3", None).as_deref(),
+                   Some("todo@api.test"));
+        assert_eq!(label("https://API.test/todos/7/", None).as_deref(),
+                   Some("todo@api.test"));
+        // route or host mismatch: falls through to later rules / default
+        assert_eq!(label("https://api.test/todos", None).as_deref(),
+                   Some("default@api.test"));
+        assert_eq!(label("https://api.test/todos/3/comments", None).as_deref(),
+                   Some("default@api.test"));
+        assert_eq!(label("https://other.test/todos/3", None).as_deref(),
+                   Some("default@other.test"));
+        // a bucket per key; the key itself is not in the label
+        let key_a = label("https://other.test/x", Some("secret-a")).unwrap();
+        let key_b = label("https://other.test/x", Some("secret-b")).unwrap();
+        assert!(key_a.starts_with("keyed@other.test#"));
+        assert_ne!(key_a, key_b);
+        assert!(!key_a.contains("secret"));
+
+        let unlimited = RateLimits::new(&QuotaTable::default()).unwrap();
+        assert_eq!(unlimited.bucket_label(&request("https://api.test/", None)),
+                   None);
+    }
+    #[tokio::test]
+    async fn test_buckets_wait_independently() {
+        let limits = RateLimits::new(&QuotaTable::from_toml_str(TABLE).unwrap()).unwrap();
+        let todo = request("https://api.test/todos/1", None);
+        let other_host = request("https://other.test/todos/1", None);
+        // burst of 1 at 20/s: the second request waits ~50ms; other buckets are unaffected
+        assert_eq!(limits.until_ready(&todo)
+                         .await,
+                   Duration::ZERO);
+        assert_eq!(limits.until_ready(&other_host)
+                         .await,
+                   Duration::ZERO);
+        let waited = limits.until_ready(&todo)
+                           .await;
+        assert!(waited >= Duration::from_millis(30), "{waited:?}");
+
+        let stats = limits.stats();
+        assert_eq!(stats["todo@api.test"],
+                   RateLimitStats { requests:   2,
+                                    delayed:    1,
+                                    total_wait: waited,
+                                    max_wait:   waited, });
+        assert_eq!(stats["default@other.test"],
+                   RateLimitStats { requests: 1,
+                                    ..Default::default() });
+    }
+    #[tokio::test]
+    async fn test_retain_recent_drops_idle_buckets() {
+        let table = QuotaTable::from_toml_str("[[rate_limits.rules]]\nname = \"keyed\"\nkey_header = \"x-api-key\"\nquota = { per_second = 1000, burst = 1 }").unwrap();
+        let limits = RateLimits::new(&table).unwrap();
+        for key in 0..10 {
+            limits.until_ready(&request("https://api.test/", Some(&key.to_string())))
+                  .await;
+        }
+        assert_eq!(limits.tracked_buckets(), 10);
+        // 1ms per cell: all back at full burst
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        limits.retain_recent();
+        assert_eq!(limits.tracked_buckets(), 0);
+    }
+    #[tokio::test]
+    async fn test_stats_of_idle_buckets_are_folded() {
+        let table =
+            QuotaTable::from_toml_str("[rate_limits]\ndefault = { per_second = 1000 }").unwrap();
+        let limits = RateLimits::new(&table).unwrap();
+        let total = 3 * RETAIN_EVERY;
+        for host in 0..total {
+            limits.until_ready(&request(&format!("https://host-{host}.test/"), None))
+                  .await;
+        }
+        let stats = limits.stats();
+        // at most the buckets of the last two sweep intervals, plus the folded entry
+        assert!(stats.len() as u64 <= 2 * RETAIN_EVERY + 1,
+                "{}",
+                stats.len());
+        assert!(stats[&folded_label("default")].requests >= RETAIN_EVERY);
+        assert_eq!(stats.values()
+                        .map(|stats| stats.requests)
+                        .sum::<u64>(),
+                   total);
+        // recent buckets keep their own entries
+        assert_eq!(stats[&format!("default@host-{}.test", total - 1)].requests,
+                   1);
+    }
+}
@@ -0,0 +1,383 @@
+//! Retry policy for `reqwest` requests: exponential backoff with full jitter, bounded by an elapsed-time budget.
+//!
+//! ## Classification
+//! - retried errors: connection failures (`reqwest::Error::is_connect`); see [`RetryableError`]
+//! - retried statuses: `429 Too Many Requests`, `502`, `503`, `504` (configurable)
+//! - everything else is returned as-is, on the first attempt
+//! - only idempotent methods (`GET`, `HEAD`, `PUT`, `DELETE`, ...) are retried: a `POST` or `PATCH` whose response
+//!   was lost may have been applied, so it is sent once (unless `retry_non_idempotent`, e.g. with an idempotency key)
+//!
+//! ## Delays
+//! Attempt `n` (0-based) waits a uniformly random duration in `[0, min(max_delay, base_delay * 2^n)]` ("full jitter").
+//! A `Retry-After` header (seconds or HTTP-date) on a retryable response is used instead, when present.
+//! A retry whose delay would exceed the `max_elapsed` budget is not attempted: the last outcome is returned.
+//!
+//! ## Outcome
+//! Same as a plain `send()`: the final `Response` (which may still have a retryable status) or the final error.
+//! Requests with streaming bodies cannot be cloned, and are sent once.
+//!
+//! ## Use:
+//! ```no_run
+//! use utilities::{RetryExt as _, RetryPolicy};
+//!
+//! # async fn run() -> Result<(), reqwest::Error> {
+//! let policy = RetryPolicy::default();
+//! let response = reqwest::Client::new().get("https://httpbin.org/status/503")
+//!                                      .send_with_retry(&policy)
+//!                                      .await?;
+//! # Ok(())
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::time::{Duration, SystemTime};
+
+use bon::bon;
+use reqwest::{Client, Request, RequestBuilder, Response, StatusCode, header};
+use tokio::time::{self, Instant};
+use tracing::{Level as L, event, instrument};
+// ///////////////////////////////// [ constants ] ///////////////////////////////// //
+/// `429`, `502`, `503`, `504`
+pub const DEFAULT_RETRYABLE_STATUSES: [StatusCode; 4] = [StatusCode::TOO_MANY_REQUESTS,
+                                                         StatusCode::BAD_GATEWAY,
+                                                         StatusCode::SERVICE_UNAVAILABLE,
+                                                         StatusCode::GATEWAY_TIMEOUT];
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// When and how long to wait before re-sending a request.  See [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries:          u32,
+    base_delay:           Duration,
+    max_delay:            Duration,
+    max_elapsed:          Duration,
+    retryable_statuses:   Vec<StatusCode>,
+    honor_retry_after:    bool,
+    retry_non_idempotent: bool,
+}
+#[bon]
+impl RetryPolicy {
+    #[builder]
+    pub fn new(/// Retries after the first attempt.  (`0`: never retry)
+               #[builder(default = 3)]
+               max_retries: u32,
+               /// Backoff cap before jitter, for the first retry; doubles each retry.
+               #[builder(default = Duration::from_millis(100))]
+               base_delay: Duration,
+               /// Upper bound on any single backoff (before jitter).
+               #[builder(default = Duration::from_secs(10))]
+               max_delay: Duration,
+               /// Total time budget, measured from the first attempt.  Retries that would overrun it are skipped.
+               #[builder(default = Duration::from_secs(30))]
+               max_elapsed: Duration,
+               #[builder(default = DEFAULT_RETRYABLE_STATUSES.to_vec())]
+               retryable_statuses: Vec<StatusCode>,
+               /// Wait as instructed by a `Retry-After` header (instead of the computed backoff).
+               #[builder(default = true)]
+               honor_retry_after: bool,
+               /// Retry `POST`, `PATCH` & other non-idempotent methods too.  Only safe if the server deduplicates them.
+               #[builder(default)]
+               retry_non_idempotent: bool)
+               -> Self {
+        Self { max_retries,
+               base_delay,
+               max_delay,
+               max_elapsed,
+               retryable_statuses,
+               honor_retry_after,
+               retry_non_idempotent }
+    }
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self::builder().max_retries(0)
+                       .build()
+    }
+
+    /// Send `request` with `client`, retrying per this policy.
+    pub async fn execute(&self,
+                         client: &Client,
+                         request: Request)
+                         -> Result<Response, reqwest::Error> {
+        self.execute_with(request, |request| client.execute(request))
+            .await
+    }
+
+    /// As [`execute`](Self::execute), with each attempt sent by `send`.
+    /// (e.g. [`HttpClient::execute`](crate::HttpClient::execute), so that every attempt waits on its rate limit)
+    #[instrument(skip_all, fields(method = %request.method(), url = %request.url()))]
+    pub async fn execute_with<F, Fut, E>(&self,
+                                         request: Request,
+                                         mut send: F)
+                                         -> Result<Response, E>
+        where F: FnMut(Request) -> Fut,
+              Fut: Future<Output = Result<Response, E>>,
+              E: RetryableError {
+        let start = Instant::now();
+        let mut attempt = 0;
+        let mut request = request;
+        let retryable_method = self.retry_non_idempotent
+                               || request.method()
+                                         .is_idempotent();
+        loop {
+            // keep a copy for the next attempt (unless it's the last, the method isn't safe to repeat, or the body
+            // can't be cloned)
+            let next_request = if retryable_method && attempt < self.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+            let outcome = send(request).await;
+            let Some(next) = next_request else {
+                return outcome;
+            };
+            let delay = match &outcome {
+                Ok(response) if self.is_retryable_status(response.status()) => {
+                    let instructed = if self.honor_retry_after {
+                        retry_after(response)
+                    } else {
+                        None
+                    };
+                    instructed.unwrap_or_else(|| self.backoff(attempt))
+                },
+                Err(error) if error.is_retryable() => self.backoff(attempt),
+                _ => return outcome,
+            };
+            if start.elapsed() + delay > self.max_elapsed {
+                event!(L::WARN,
+                       attempt,
+                       ?delay,
+                       elapsed = ?start.elapsed(),
+                       budget = ?self.max_elapsed,
+                       "retry budget exhausted; returning last outcome");
+                return outcome;
+            }
+            match &outcome {
+                Ok(response) =>
+                    event!(L::INFO, attempt, status = %response.status(), ?delay, "retryable status; retrying"),
+                Err(error) => event!(L::INFO, attempt, %error, ?delay, "retryable error; retrying"),
+            }
+            drop(outcome);
+            time::sleep(delay).await;
+            attempt += 1;
+            request = next;
+        }
+    }
+
+    fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.retryable_statuses
+            .contains(&status)
+    }
+
+    /// Full jitter: uniform in `[0, min(max_delay, base_delay * 2^attempt)]`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self.base_delay
+                      .saturating_mul(2u32.saturating_pow(attempt))
+                      .min(self.max_delay);
+        cap.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// Errors a [`RetryPolicy`] can classify.
+pub trait RetryableError: std::fmt::Display {
+    /// Worth another attempt, after a backoff.
+    fn is_retryable(&self) -> bool;
+}
+impl RetryableError for reqwest::Error {
+    fn is_retryable(&self) -> bool {
+        self.is_connect()
+    }
+}
+
+/// Send a request through a [`RetryPolicy`].
+pub trait RetryExt {
+    fn send_with_retry(self,
+                       policy: &RetryPolicy)
+                       -> impl Future<Output = Result<Response, reqwest::Error>> + Send;
+}
+impl RetryExt for RequestBuilder {
+    async fn send_with_retry(self, policy: &RetryPolicy) -> Result<Response, reqwest::Error> {
+        let (client, request) = self.build_split();
+        policy.execute(&client, request?)
+              .await
+    }
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+/// `Retry-After` as delta-seconds or an HTTP-date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers()
+                        .get(header::RETRY_AFTER)?
+                        .to_str()
+                        .ok()?
+                        .trim();
+    parse_retry_after(value, SystemTime::now())
+}
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value).ok()
+                                                  .map(|date| {
+                                                      date.duration_since(now)
+                                                          .unwrap_or_default()
+                                                  }),
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc,
+                    atomic::{AtomicUsize, Ordering}};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{http_stub::{StubResponse, spawn_http_stub},
+                testing::EventCapture};
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy::builder().base_delay(Duration::from_millis(1))
+                              .max_delay(Duration::from_millis(5))
+                              .build()
+    }
+    /// Responds with `statuses` in order, then `200`; counts requests.
+    fn scripted_stub(statuses: &'static [u16]) -> (String, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let (base, _received) = spawn_http_stub(move |_| {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            match statuses.get(n) {
+                Some(&status) => StubResponse::new(status, "").with_header("retry-after", "0"),
+                None => StubResponse::ok_json("{}"),
+            }
+        });
+        (base, count)
+    }
+    #[test]
+    fn test_backoff_is_bounded() {
+        let policy = RetryPolicy::builder().base_delay(Duration::from_millis(100))
+                                           .max_delay(Duration::from_millis(350))
+                                           .build();
+        for attempt in 0..10 {
+            let cap =
+                Duration::from_millis(100 * 2u64.pow(attempt)).min(Duration::from_millis(350));
+            for _ in 0..20 {
+                assert!(policy.backoff(attempt) <= cap);
+            }
+        }
+    }
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(parse_retry_after("120", now),
+                   Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+                   Some(Duration::from_secs(30)));
+        // dates in the past: retry now
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+                   Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+    #[tokio::test]
+    async fn test_retries_retryable_statuses_until_success() {
+        let (base, count) = scripted_stub(&[503, 429, 502]);
+        let capture = EventCapture::start();
+        let response = Client::new().get(format!("{base}/flaky"))
+                                    .send_with_retry(&fast_policy())
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+        let retries: Vec<_> =
+            capture.events()
+                   .into_iter()
+                   .filter(|e| e.message.as_deref() == Some("retryable status; retrying"))
+                   .collect();
+        assert_eq!(retries.len(), 3);
+        assert_eq!(retries[0].field("status"), Some("503 Service Unavailable"));
+        assert_eq!(retries[2].field("attempt"), Some("2"));
+    }
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let (base, count) = scripted_stub(&[503, 503, 503, 503, 503]);
+        let policy = RetryPolicy::builder().max_retries(2)
+                                           .base_delay(Duration::from_millis(1))
+                                           .build();
+        let response = Client::new().get(format!("{base}/down"))
+                                    .send_with_retry(&policy)
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+    #[tokio::test]
+    async fn test_non_retryable_status_returned_immediately() {
+        let (base, count) = scripted_stub(&[404, 503]);
+        let response = Client::new().get(format!("{base}/missing"))
+                                    .send_with_retry(&fast_policy())
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+    #[tokio::test]
+    async fn test_non_idempotent_sent_once_unless_opted_in() {
+        let (base, count) = scripted_stub(&[503]);
+        let response = Client::new().post(format!("{base}/todos"))
+                                    .send_with_retry(&fast_policy())
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (base, count) = scripted_stub(&[503]);
+        let policy = RetryPolicy::builder().base_delay(Duration::from_millis(1))
+                                           .retry_non_idempotent(true)
+                                           .build();
+        let response = Client::new().patch(format!("{base}/todos/1"))
+                                    .send_with_retry(&policy)
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+    #[tokio::test]
+    async fn test_retry_after_beyond_budget_stops() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let (base, _received) = spawn_http_stub(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            StubResponse::new(429, "").with_header("retry-after", "60")
+        });
+        let policy = RetryPolicy::builder().max_elapsed(Duration::from_secs(5))
+                                           .build();
+        let response = Client::new().get(format!("{base}/limited"))
+                                    .send_with_retry(&policy)
+                                    .await
+                                    .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+    #[tokio::test]
+    async fn test_connect_errors_retried() {
+        // bind & drop: nothing listening on the port
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap()
+                                                             .local_addr()
+                                                             .unwrap()
+                                                             .port();
+        let capture = EventCapture::start();
+        let error = Client::new().get(format!("http://127.0.0.1:{port}/"))
+                                 .send_with_retry(&fast_policy())
+                                 .await
+                                 .unwrap_err();
+        assert!(error.is_connect());
+        assert_eq!(capture.events()
+                          .iter()
+                          .filter(|e| e.message.as_deref() == Some("retryable error; retrying"))
+                          .count(),
+                   3);
+    }
+}
@@ -0,0 +1,176 @@
+//! Periodic logging of `tokio::runtime::RuntimeMetrics`.
+//!
+//! Shows concurrency experiments (JoinSets, semaphores, buffered streams) from the runtime's perspective:
+//! how many tasks are alive, how deep the queues are, and how busy each worker was over the last period.
+//!
+//! ## Stable vs `tokio_unstable`
+//! Always logged: worker count, alive tasks, global queue depth, per-worker busy ratio & park count.
+//! With `RUSTFLAGS='--cfg tokio_unstable'` also: per-worker local queue depth, poll & steal counts,
+//! blocking-pool threads & queue depth, and tasks spawned.
+//!
+//! ## Use:
+//! ```no_run
+//! # async fn run() {
+//! let _metrics_task = utilities::spawn_runtime_metrics_logger(std::time::Duration::from_secs(5));
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::time::Duration;
+
+use tokio::{runtime::{Handle, RuntimeMetrics},
+            task::JoinHandle,
+            time::{self, Instant, MissedTickBehavior}};
+use tracing::{Level as L, event, instrument};
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// Spawn a task (on the current runtime) that logs runtime metrics every `period`.
+///
+/// Aggregate metrics are logged at `INFO`, per-worker metrics at `DEBUG`.
+/// Abort the returned handle (or drop the runtime) to stop logging.
+///
+/// ## Panics
+/// If called outside of a tokio runtime.
+#[instrument]
+pub fn spawn_runtime_metrics_logger(period: Duration) -> JoinHandle<()> {
+    let metrics = Handle::current().metrics();
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut previous = WorkerTotals::capture(&metrics);
+        let mut previous_at = Instant::now();
+        loop {
+            interval.tick().await;
+            let current = WorkerTotals::capture(&metrics);
+            let elapsed = previous_at.elapsed();
+            log_metrics(&metrics, &previous, &current, elapsed);
+            previous = current;
+            previous_at = Instant::now();
+        }
+    })
+}
+// ///////////////////////////////// [ internals ] ///////////////////////////////// //
+/// Monotonic per-worker counters; differenced between ticks.
+#[derive(Debug, Clone)]
+struct WorkerTotals {
+    busy:   Vec<Duration>,
+    parks:  Vec<u64>,
+    #[cfg(tokio_unstable)]
+    polls:  Vec<u64>,
+    #[cfg(tokio_unstable)]
+    steals: Vec<u64>,
+}
+impl WorkerTotals {
+    fn capture(metrics: &RuntimeMetrics) -> Self {
+        let workers = 0..metrics.num_workers();
+        Self { busy: workers.clone()
+                            .map(|w| metrics.worker_total_busy_duration(w))
+                            .collect(),
+               parks: workers.clone()
+                             .map(|w| metrics.worker_park_count(w))
+                             .collect(),
+               #[cfg(tokio_unstable)]
+               polls: workers.clone()
+                             .map(|w| metrics.worker_poll_count(w))
+                             .collect(),
+               #[cfg(tokio_unstable)]
+               steals: workers.map(|w| metrics.worker_steal_count(w))
+                              .collect(), }
+    }
+}
+
+/// Fraction of `elapsed` that a worker spent busy.
+fn busy_ratio(busy_delta: Duration, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    (busy_delta.as_secs_f64() / elapsed.as_secs_f64()).min(1.0)
+}
+
+fn log_metrics(metrics: &RuntimeMetrics,
+               previous: &WorkerTotals,
+               current: &WorkerTotals,
+               elapsed: Duration) {
+    let num_workers = metrics.num_workers();
+    let busy_ratios: Vec<f64> =
+        current.busy
+               .iter()
+               .zip(&previous.busy)
+               .map(|(now, before)| busy_ratio(now.saturating_sub(*before), elapsed))
+               .collect();
+    let mean_busy_ratio = busy_ratios.iter()
+                                     .sum::<f64>()
+                          / num_workers.max(1) as f64;
+    event!(L::INFO,
+           num_workers,
+           alive_tasks = metrics.num_alive_tasks(),
+           global_queue_depth = metrics.global_queue_depth(),
+           mean_busy_ratio = format_args!("{:.3}", mean_busy_ratio),
+           period_ms = elapsed.as_millis() as u64,
+           "runtime metrics");
+    #[cfg(tokio_unstable)]
+    event!(L::INFO,
+           blocking_threads = metrics.num_blocking_threads(),
+           idle_blocking_threads = metrics.num_idle_blocking_threads(),
+           blocking_queue_depth = metrics.blocking_queue_depth(),
+           spawned_tasks = metrics.spawned_tasks_count(),
+           "runtime metrics (unstable)");
+    for (worker, busy_ratio) in busy_ratios.into_iter()
+                                           .enumerate()
+    {
+        let parks = current.parks[worker].saturating_sub(previous.parks[worker]);
+        #[cfg(not(tokio_unstable))]
+        event!(L::DEBUG,
+               worker,
+               busy_ratio = format_args!("{:.3}", busy_ratio),
+               parks,
+               "worker metrics");
+        #[cfg(tokio_unstable)]
+        event!(L::DEBUG,
+               worker,
+               busy_ratio = format_args!("{:.3}", busy_ratio),
+               parks,
+               polls = current.polls[worker].saturating_sub(previous.polls[worker]),
+               steals = current.steals[worker].saturating_sub(previous.steals[worker]),
+               local_queue_depth = metrics.worker_local_queue_depth(worker),
+               "worker metrics");
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::testing::EventCapture;
+    #[test]
+    fn test_busy_ratio_bounds() {
+        assert_eq!(busy_ratio(Duration::from_millis(50), Duration::from_millis(100)),
+                   0.5);
+        assert_eq!(busy_ratio(Duration::from_millis(150), Duration::from_millis(100)),
+                   1.0);
+        assert_eq!(busy_ratio(Duration::from_millis(10), Duration::ZERO), 0.0);
+    }
+    #[test]
+    fn test_logger_emits_periodic_metrics() {
+        // current-thread runtime: the logger runs on this thread, so `EventCapture` sees its events
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time()
+                                                                   .build()
+                                                                   .unwrap();
+        let capture = EventCapture::start();
+        runtime.block_on(async {
+                   let handle = spawn_runtime_metrics_logger(Duration::from_millis(10));
+                   time::sleep(Duration::from_millis(55)).await;
+                   handle.abort();
+               });
+        let reports: Vec<_> = capture.events()
+                                     .into_iter()
+                                     .filter(|e| e.message.as_deref() == Some("runtime metrics"))
+                                     .collect();
+        assert!(reports.len() >= 3,
+                "expected several reports, got {}",
+                reports.len());
+        assert_eq!(reports[0].field("num_workers"), Some("1"));
+        assert!(reports[0].field("alive_tasks")
+                          .is_some());
+    }
+}
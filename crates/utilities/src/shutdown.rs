@@ -0,0 +1,239 @@
+//! Cooperative shutdown for async binaries: one trigger (Ctrl-C, a REPL command, ..) seen by every task, then a
+//! bounded wait for them to wind down before the stragglers are aborted.
+//!
+//! - trigger: [`Shutdown::trigger`], or Ctrl-C after [`Shutdown::with_ctrl_c`] (a second Ctrl-C exits at once)
+//! - observe: [`Shutdown::triggered`], [`Shutdown::run_until_triggered`], or a [`CancellationToken`] from
+//!   [`Shutdown::token`] for code that shouldn't know about this type
+//! - actors spawned with `.shutdown(..)` (see [`actor::spawn`](crate::actor::spawn) &
+//!   [`Supervisor`](crate::supervisor::Supervisor)) stop taking messages, handle those already queued, then run
+//!   `stopping` (their final report)
+//! - [`Shutdown::drain`]: trigger, wait up to a grace period for the tasks started with [`Shutdown::spawn`], then
+//!   abort whatever is left
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use utilities::Shutdown;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let shutdown = Shutdown::new(); // `.with_ctrl_c()` in a binary
+//! let token = shutdown.token();
+//! shutdown.spawn(async move {
+//!             token.cancelled().await;
+//!             // .. flush, report
+//!         });
+//! let drained = shutdown.drain(Duration::from_secs(2))
+//!                       .await;
+//! assert!(drained.is_clean());
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{fmt,
+          future::Future,
+          sync::{Arc, Mutex},
+          time::Duration};
+
+use tokio::{task::{AbortHandle, JoinHandle},
+            time::Instant};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{Level as L, event};
+// ///////////////////////////////// [ shutdown ] ///////////////////////////////// //
+/// Exit status after a second Ctrl-C: the conventional `128 + SIGINT`.
+pub const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+/// Shared shutdown trigger plus the tasks that should finish before the program exits.  Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    token:   CancellationToken,
+    tracker: TaskTracker,
+    aborts:  Arc<Mutex<Vec<AbortHandle>>>,
+}
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger on Ctrl-C; a second Ctrl-C exits the process immediately (with [`INTERRUPTED_EXIT_CODE`]).
+    /// Must be called within a tokio runtime.
+    pub fn with_ctrl_c(self) -> Self {
+        let token = self.token.clone();
+        // not tracked: `drain` shouldn't wait for a second Ctrl-C
+        tokio::spawn(async move {
+            if let Err(error) = tokio::signal::ctrl_c().await {
+                event!(L::WARN, %error, "can't listen for ctrl-c");
+                return;
+            }
+            event!(L::INFO, "ctrl-c: shutting down (again to exit now)");
+            token.cancel();
+            if tokio::signal::ctrl_c().await
+                                      .is_ok()
+            {
+                event!(L::WARN, "second ctrl-c: exiting");
+                std::process::exit(INTERRUPTED_EXIT_CODE.into());
+            }
+        });
+        self
+    }
+
+    /// Cancelled on shutdown.  (A child token: cancelling it doesn't trigger the shutdown.)
+    pub fn token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub async fn triggered(&self) {
+        self.token.cancelled().await;
+    }
+
+    /// `future`'s output, or `None` if shutdown is triggered first (`future` is then dropped).
+    pub async fn run_until_triggered<F: Future>(&self, future: F) -> Option<F::Output> {
+        self.token
+            .run_until_cancelled(future)
+            .await
+    }
+
+    /// Spawn a task that [`drain`](Self::drain) waits for.  It should watch for the shutdown itself.
+    ///
+    /// Once draining has started the task is aborted right away (its handle reports it cancelled).
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+        where F: Future + Send + 'static,
+              F::Output: Send + 'static {
+        let handle = self.tracker.spawn(task);
+        if self.tracker.is_closed() {
+            event!(L::DEBUG, "shutdown: draining; task aborted");
+            handle.abort();
+            return handle;
+        }
+        let mut aborts = self.aborts.lock().unwrap();
+        aborts.retain(|abort| !abort.is_finished());
+        aborts.push(handle.abort_handle());
+        handle
+    }
+
+    /// Trigger, then wait up to `grace` for the spawned tasks; abort any still running after that.
+    ///
+    /// Tasks spawned after this are aborted right away.
+    pub async fn drain(&self, grace: Duration) -> Drained {
+        let start = Instant::now();
+        self.trigger();
+        self.tracker.close();
+        if tokio::time::timeout(grace, self.tracker.wait()).await
+                                                           .is_ok()
+        {
+            event!(L::DEBUG, "shutdown: all tasks finished");
+            return Drained { aborted: 0,
+                             elapsed: start.elapsed(), };
+        }
+        let aborted = self.aborts
+                          .lock()
+                          .unwrap()
+                          .iter()
+                          .filter(|abort| !abort.is_finished())
+                          .inspect(|abort| abort.abort())
+                          .count();
+        event!(L::WARN,
+               aborted,
+               ?grace,
+               "shutdown: aborted tasks still running after the grace period");
+        Drained { aborted,
+                  elapsed: start.elapsed() }
+    }
+}
+
+/// Outcome of [`Shutdown::drain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drained {
+    /// Tasks still running after the grace period.
+    pub aborted: usize,
+    pub elapsed: Duration,
+}
+impl Drained {
+    pub fn is_clean(&self) -> bool {
+        self.aborted == 0
+    }
+}
+impl fmt::Display for Drained {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.aborted {
+            0 => write!(f, "all tasks finished in {:?}", self.elapsed),
+            n => write!(f, "{n} task(s) aborted after {:?}", self.elapsed),
+        }
+    }
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_waits_for_cooperative_tasks() {
+        let shutdown = Shutdown::new();
+        let flushed = Arc::new(AtomicBool::new(false));
+        let (token, task_flushed) = (shutdown.token(), flushed.clone());
+        shutdown.spawn(async move {
+                    token.cancelled().await;
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    task_flushed.store(true, Ordering::SeqCst);
+                });
+        let drained = shutdown.drain(Duration::from_secs(1))
+                              .await;
+        assert!(flushed.load(Ordering::SeqCst));
+        assert_eq!(drained,
+                   Drained { aborted: 0,
+                             elapsed: Duration::from_millis(100), });
+        assert!(shutdown.is_triggered());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_aborts_after_grace() {
+        let shutdown = Shutdown::new();
+        let stuck = shutdown.spawn(std::future::pending::<()>());
+        let done = shutdown.spawn(async {});
+        let drained = shutdown.drain(Duration::from_secs(1))
+                              .await;
+        assert_eq!(drained.aborted, 1);
+        assert_eq!(drained.to_string(), "1 task(s) aborted after 1s");
+        assert!(stuck.await
+                     .unwrap_err()
+                     .is_cancelled());
+        done.await.unwrap();
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let late_ran = ran.clone();
+        let late = shutdown.spawn(async move { late_ran.store(true, Ordering::SeqCst) });
+        assert!(late.await
+                    .unwrap_err()
+                    .is_cancelled());
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_run_until_triggered_and_child_tokens() {
+        let shutdown = Shutdown::new();
+        assert_eq!(shutdown.run_until_triggered(async { 7 })
+                           .await,
+                   Some(7));
+        // a child token doesn't trigger the shutdown
+        shutdown.token().cancel();
+        assert!(!shutdown.is_triggered());
+
+        let other = shutdown.clone();
+        tokio::spawn(async move { other.trigger() });
+        assert_eq!(shutdown.run_until_triggered(std::future::pending::<()>())
+                           .await,
+                   None);
+        shutdown.triggered().await;
+    }
+}
@@ -14,13 +14,28 @@
 //! - Tracing is poorly documented and methods poorly named.  One can easily use, e.g., `::fmt()` instead of `::fmt` and be greeted with cryptic or even misdirecting errors.
 //!   - I have no solution for this.  *Just be careful!*  It is very easy to lose a lot of time chain one's tail, on seemingly trivial configuration.
 // ///////////////////////////////// [ use ] ///////////////////////////////// //
-use std::path::PathBuf;
+use std::{fs::File,
+          io::{self, BufWriter},
+          path::{Path, PathBuf}};
 
 use bon::builder;
-use tracing::{level_filters::LevelFilter, subscriber::SetGlobalDefaultError};
-use tracing_appender::non_blocking::WorkerGuard;
+use thiserror::Error;
+use tracing::{Level as L, Subscriber, event, level_filters::LevelFilter,
+              subscriber::SetGlobalDefaultError};
+use tracing_appender::{non_blocking::WorkerGuard,
+                       rolling::{InitError, RollingFileAppender}};
+use tracing_chrome::{ChromeLayer, ChromeLayerBuilder};
 use tracing_error::ErrorLayer;
-use tracing_subscriber::prelude::*;
+use tracing_flame::FlameLayer;
+use tracing_subscriber::{EnvFilter,
+                         filter::{Directive, ParseError},
+                         fmt::format::FmtSpan,
+                         prelude::*,
+                         registry::LookupSpan};
+
+use crate::{logging_config::{LogFormat, LogRotation, LoggingConfig, LoggingConfigError,
+                             LoggingConfigSource},
+            otel::OtlpConfig};
 // ///////////////////////////////// [ compile context settings ] ///////////////////////////////// //
 #[cfg(debug_assertions)]
 const DEFAULT_LOGGING_LEVEL: LevelFilter = LevelFilter::INFO;
@@ -31,22 +46,107 @@ const DEFAULT_ERROR_LOGGING_LEVEL: LevelFilter = LevelFilter::TRACE;
 const DEFAULT_LOGGING_LEVEL: LevelFilter = LevelFilter::WARN;
 #[cfg(not(debug_assertions))]
 const DEFAULT_ERROR_LOGGING_LEVEL: LevelFilter = LevelFilter::WARN;
+// ///////////////////////////////// [ error ] ///////////////////////////////// //
+#[derive(Debug, Error)]
+pub enum SubscriberError {
+    #[error(transparent)]
+    SetGlobalDefault(#[from] SetGlobalDefaultError),
+    #[error(transparent)]
+    LoggingConfig(#[from] LoggingConfigError),
+    #[error("Invalid logging target directive: {0}")]
+    Directive(#[from] ParseError),
+    #[error("Could not create {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Could not open rolling log file {path:?}: {source}")]
+    RollingFile { path: PathBuf, source: InitError },
+    #[cfg(feature = "otlp")]
+    #[error("Error building OTLP span exporter: {0}")]
+    OtlpExporter(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+// ///////////////////////////////// [ guard & options ] ///////////////////////////////// //
+/// Opt-in span profiling output.  Written (flushed) when the [`SubscriberGuard`] is dropped.
+///
+/// Respects the same `RUST_LOG`/default-level filtering as the fmt layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanProfiling {
+    /// Folded-stack lines, for `inferno-flamegraph` (or `flamegraph.pl`).
+    /// e.g. `cat out.folded | inferno-flamegraph > flame.svg`
+    /// (`inferno-flamegraph --flamechart` preserves time ordering instead of merging stacks.)
+    FoldedStacks(PathBuf),
+    /// Chrome trace-event JSON, for `chrome://tracing` or [Perfetto](https://ui.perfetto.dev).
+    ChromeTrace(PathBuf),
+}
+
+/// Keeps log writing and (optional) span profiling alive.
+///
+/// Hold this until the end of `main`; dropping it flushes the non-blocking log writer and writes any profiling output.
+#[must_use = "logs and profiling output are flushed when the guard is dropped"]
+pub struct SubscriberGuard {
+    // drop order: OTLP export & profiling output first, then the log writer
+    #[cfg(feature = "otlp")]
+    _otlp_guard:      Option<crate::otel::OtlpGuard>,
+    _profiling_guard: Option<ProfilingGuard>,
+    _writer_guard:    WorkerGuard,
+}
+impl std::fmt::Debug for SubscriberGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("SubscriberGuard");
+        f.field("profiling",
+                &self._profiling_guard
+                     .is_some());
+        #[cfg(feature = "otlp")]
+        f.field("otlp", &self._otlp_guard.is_some());
+        f.finish_non_exhaustive()
+    }
+}
+/// Held only for its `Drop` (which writes the profiling output).
+enum ProfilingGuard {
+    FoldedStacks {
+        _guard: tracing_flame::FlushGuard<BufWriter<File>>,
+    },
+    ChromeTrace {
+        _guard: tracing_chrome::FlushGuard,
+    },
+}
 // ///////////////////////////////// [ core export ] ///////////////////////////////// //
 /// (Convenience function.) Generates a tracing_subcsriber and sets it as global default, while returning a writer guard.
 ///
 /// ## Caveat
 ///   - Side effect. (sets global default tracing subscriber)
 ///
+/// ## Span timing
+///   - `span_events`: have the fmt layer log span lifecycle events.
+///     e.g. `FmtSpan::CLOSE` logs `time.busy` & `time.idle` for each span; `FmtSpan::FULL` adds enter/exit.
+///   - `span_profiling`: write folded stacks or a Chrome trace file (see [`SpanProfiling`]) on guard drop.
+///
+/// ## Config file
+///   - `logging_config`: load a `[logging]` TOML section (level, per-target directives, format, file, rotation, error level)
+///     from a path or the XDG config dir. `LOGGING_*` & `RUST_LOG` env vars override file values; file values override builder args.
+///     See [`crate::logging_config`].  The effective configuration is logged (`INFO`) once the subscriber is set.
+///
+/// ## Feature: `otlp`
+///   - `otlp`: export spans to an OpenTelemetry collector (OTLP http/json), e.g. `OtlpConfig::from_env(..)`
+///     (reads `OTEL_EXPORTER_OTLP_ENDPOINT`).  Also installs the W3C trace-context propagator; see [`crate::otel`].
+///     Spans are flushed when the guard is dropped.  Ignored (with a warning) without the feature.
+///
+/// ## Feature: `tokio-console`
+/// Adds the [`console_subscriber`](https://docs.rs/console-subscriber) layer (serves `tokio-console` on `127.0.0.1:6669`).
+/// Requires building with `RUSTFLAGS='--cfg tokio_unstable'` (see: `just console`).
+///
 /// ## Use:
 /// ```no_run
 /// use std::error::Error;
 ///
-/// use tracing_subscriber::filter::LevelFilter;
+/// use tracing_subscriber::{filter::LevelFilter, fmt::format::FmtSpan};
+/// use utilities::{LoggingConfigSource, SpanProfiling};
 ///
 /// fn main() -> Result<(), Box<dyn Error>> {
 ///     let _writer_guard = utilities::activate_global_default_tracing_subscriber()
 ///         .default_logging_level(LevelFilter::WARN)
 ///         .maybe_error_logging_level(None)
+///         .span_events(FmtSpan::CLOSE)
+///         .span_profiling(SpanProfiling::FoldedStacks(".output/profiling/tracing.folded".into()))
+///         .logging_config(LoggingConfigSource::xdg("my-app"))
 ///         .call()?;
 ///     // ...
 ///     Ok(())
@@ -55,37 +155,62 @@ const DEFAULT_ERROR_LOGGING_LEVEL: LevelFilter = LevelFilter::WARN;
 #[builder]
 pub fn activate_global_default_tracing_subscriber(default_logging_level: Option<LevelFilter>,
                                                   error_logging_level: Option<LevelFilter>,
-                                                  file_to_write_to: Option<PathBuf>)
-                                                  -> Result<WorkerGuard, SetGlobalDefaultError> {
+                                                  file_to_write_to: Option<PathBuf>,
+                                                  span_events: Option<FmtSpan>,
+                                                  span_profiling: Option<SpanProfiling>,
+                                                  logging_config: Option<LoggingConfigSource>,
+                                                  otlp: Option<OtlpConfig>)
+                                                  -> Result<SubscriberGuard, SubscriberError> {
+    // config: file (optional) < env; both over builder args
+    let (file_config, config_path) = match logging_config {
+        None => (LoggingConfig::default(), None),
+        Some(source) => match LoggingConfig::load(&source)? {
+            None => (LoggingConfig::default(), None),
+            Some((config, path)) => (config, Some(path)),
+        },
+    };
+    let config = file_config.with_env_overrides()?;
     // filter with defaults
-    let env_default_level = default_logging_level.unwrap_or(DEFAULT_LOGGING_LEVEL);
-    let error_default_level = error_logging_level.unwrap_or(DEFAULT_ERROR_LOGGING_LEVEL);
+    let env_default_level = config.level
+                                  .or(default_logging_level)
+                                  .unwrap_or(DEFAULT_LOGGING_LEVEL);
+    let error_default_level = config.error_level
+                                    .or(error_logging_level)
+                                    .unwrap_or(DEFAULT_ERROR_LOGGING_LEVEL);
+    let format = config.format
+                       .unwrap_or_default();
+    let rotation = config.rotation
+                         .unwrap_or_default();
+    let target_directives: Vec<String> = config.target_directives()
+                                               .collect();
+    let file_to_write_to = config.file
+                                 .or(file_to_write_to);
+    for directive in &target_directives {
+        directive.parse::<Directive>()?;
+    }
+    let effective_config = LoggingConfig { level:       Some(env_default_level),
+                                           targets:     config.targets,
+                                           format:      Some(format),
+                                           file:        file_to_write_to.clone(),
+                                           rotation:    Some(rotation),
+                                           error_level: Some(error_default_level), };
     // filter-layer: filters events
-    let envfilter_layer =
-        tracing_subscriber::EnvFilter::builder().with_default_directive(env_default_level.into())
-                                                .from_env_lossy();
+    let envfilter_layer = env_filter(env_default_level, &target_directives);
     // subscriber-layer: captures spantraces
     let error_layer = ErrorLayer::default().with_filter(error_default_level);
     // log to file or stderr
     let ((non_blocking_writer, trace_writer_guard), use_ansi) = match file_to_write_to {
         None => (tracing_appender::non_blocking(std::io::stderr()), true),
         Some(file_path) => {
-            if let Some(parent) = file_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .expect("parent path should have been created if not already present");
-            }
-            (
-                tracing_appender::non_blocking(
-                    std::fs::File::create(file_path).expect("log file should have been created"),
-                ),
-                false,
-            )
+            let writer = match rotation {
+                LogRotation::Never => tracing_appender::non_blocking(create_file(&file_path)?),
+                rotation => tracing_appender::non_blocking(rolling_appender(&file_path, rotation)?),
+            };
+            (writer, false)
         },
     };
     // note: `tracing_subscriber::FmtSubscriber::builder()...` but `tracing_subscriber::fmt::Layer::default()...`
     let fmt_layer = tracing_subscriber::fmt::Layer::default()
-                                                             // .compact()
-                                                             .pretty()
                                                              // .with_timer(<timer>)
                                                              .with_target(true)
                                                              .with_thread_ids(true)
@@ -93,14 +218,215 @@ pub fn activate_global_default_tracing_subscriber(default_logging_level: Option<
                                                              .with_file(true)
                                                              .with_line_number(true)
                                                              .with_ansi(use_ansi)
-                                                             // .with_span_events(FmtSpan::FULL)
+                                                             .with_span_events(span_events.unwrap_or(FmtSpan::NONE))
                                                              .with_writer(non_blocking_writer);
+    // format choice changes the layer's type: box it
+    let fmt_layer = match format {
+        LogFormat::Pretty => fmt_layer.pretty()
+                                      .with_filter(envfilter_layer)
+                                      .boxed(),
+        LogFormat::Compact => fmt_layer.compact()
+                                       .with_filter(envfilter_layer)
+                                       .boxed(),
+        LogFormat::Full => fmt_layer.with_filter(envfilter_layer)
+                                    .boxed(),
+        LogFormat::Json => fmt_layer.json()
+                                    .with_filter(envfilter_layer)
+                                    .boxed(),
+    };
+    // profiling-layers: span timings to folded stacks xor chrome trace
+    let (flame_layer, chrome_layer, profiling_guard) = match span_profiling {
+        None => (None, None, None),
+        Some(span_profiling) => profiling_layers(&span_profiling)?,
+    };
+    let flame_layer = flame_layer.map(|layer| {
+                                     layer.with_filter(env_filter(env_default_level,
+                                                                  &target_directives))
+                                 });
+    let chrome_layer = chrome_layer.map(|layer| {
+                                       layer.with_filter(env_filter(env_default_level,
+                                                                    &target_directives))
+                                   });
     // combien various subscriber & filter layers
-    let subscriber =
-        tracing_subscriber::Registry::default().with(error_layer)
-                                               .with(fmt_layer.with_filter(envfilter_layer));
+    let subscriber = tracing_subscriber::Registry::default().with(error_layer)
+                                                            .with(fmt_layer)
+                                                            .with(flame_layer)
+                                                            .with(chrome_layer);
+    // otlp-layer: span export to a collector
+    #[cfg(feature = "otlp")]
+    let (subscriber, otlp_guard) = {
+        let (otlp_layer, otlp_guard) = match &otlp {
+            None => (None, None),
+            Some(otlp_config) => {
+                let (layer, guard) = crate::otel::otlp_layer(otlp_config)?;
+                (Some(layer.with_filter(env_filter(env_default_level, &target_directives))),
+                 Some(guard))
+            },
+        };
+        (subscriber.with(otlp_layer), otlp_guard)
+    };
+    // console-layer: unfiltered; reads tokio's own task & resource spans
+    #[cfg(feature = "tokio-console")]
+    let subscriber = subscriber.with(console_subscriber::spawn());
 
     // *side-effect* : subscribe
     tracing::subscriber::set_global_default(subscriber)?;
-    Ok(trace_writer_guard)
+    event!(L::INFO,
+           config_file = ?config_path,
+           rust_log = ?rust_log_env(),
+           ?effective_config,
+           "logging configured");
+    #[cfg(feature = "otlp")]
+    if let Some(otlp_config) = &otlp {
+        event!(L::INFO,
+               endpoint = otlp_config.traces_endpoint(),
+               service_name = otlp_config.service_name,
+               "exporting spans via OTLP");
+    }
+    #[cfg(not(feature = "otlp"))]
+    if let Some(otlp_config) = &otlp {
+        event!(L::WARN,
+               ?otlp_config,
+               "OTLP config given, but `utilities` was built without the `otlp` feature: spans will not be exported.");
+    }
+    #[cfg(all(feature = "tokio-console", not(tokio_unstable)))]
+    event!(L::WARN,
+           "`tokio-console` feature enabled without `--cfg tokio_unstable`: the console will not see any tasks.");
+    Ok(SubscriberGuard { #[cfg(feature = "otlp")]
+                         _otlp_guard: otlp_guard,
+                         _profiling_guard: profiling_guard,
+                         _writer_guard: trace_writer_guard, })
+}
+// ///////////////////////////////// [ helpers ] ///////////////////////////////// //
+/// `RUST_LOG` directives if set, else config `target_directives`; falling back to `default_level`.
+/// (`EnvFilter` is not `Clone`, so each per-layer filter is built fresh.)
+fn env_filter(default_level: LevelFilter, target_directives: &[String]) -> EnvFilter {
+    let builder = EnvFilter::builder().with_default_directive(default_level.into());
+    match rust_log_env() {
+        Some(_) => builder.from_env_lossy(),
+        // the default directive is dropped once any directive is present: include the level explicitly
+        // (directives were validated up front)
+        None => {
+            let mut directives = vec![default_level.to_string()];
+            directives.extend_from_slice(target_directives);
+            builder.parse_lossy(directives.join(","))
+        },
+    }
+}
+fn rust_log_env() -> Option<String> {
+    std::env::var(EnvFilter::DEFAULT_ENV).ok()
+                                         .filter(|directives| !directives.is_empty())
+}
+/// Folded-stack xor Chrome trace layer, with the guard that writes it out.
+/// (Each layer is generic over the subscriber below it, hence `F` & `C`.)
+#[expect(clippy::type_complexity)]
+fn profiling_layers<F, C>(
+    span_profiling: &SpanProfiling)
+    -> Result<(Option<FlameLayer<F, BufWriter<File>>>,
+               Option<ChromeLayer<C>>,
+               Option<ProfilingGuard>),
+              SubscriberError>
+    where F: Subscriber + for<'span> LookupSpan<'span>,
+          C: Subscriber + for<'span> LookupSpan<'span> + Send + Sync {
+    Ok(match span_profiling {
+        SpanProfiling::FoldedStacks(file_path) => {
+            let layer = FlameLayer::new(BufWriter::new(create_file(file_path)?));
+            let guard = layer.flush_on_drop();
+            (Some(layer), None, Some(ProfilingGuard::FoldedStacks { _guard: guard }))
+        },
+        SpanProfiling::ChromeTrace(file_path) => {
+            let (layer, guard) = ChromeLayerBuilder::new().writer(create_file(file_path)?)
+                                                          .include_args(true)
+                                                          .build();
+            (None, Some(layer), Some(ProfilingGuard::ChromeTrace { _guard: guard }))
+        },
+    })
+}
+/// Creates (truncates) `file_path`, and any missing parent directories.
+fn create_file(file_path: &Path) -> Result<File, SubscriberError> {
+    let io_error = |source| SubscriberError::Io { path: file_path.into(),
+                                                  source };
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    File::create(file_path).map_err(io_error)
+}
+/// Rolling log files named `<file name>.<date>`, next to `file_path`.
+fn rolling_appender(file_path: &Path,
+                    rotation: LogRotation)
+                    -> Result<RollingFileAppender, SubscriberError> {
+    let Some(file_name_prefix) = file_path.file_name() else {
+        return Err(SubscriberError::Io { path:   file_path.into(),
+                                         source: io::Error::new(io::ErrorKind::InvalidInput,
+                                                                "log file path should name a file"), });
+    };
+    let directory = file_path.parent()
+                             .unwrap_or(Path::new("."));
+    RollingFileAppender::builder().rotation(rotation.into())
+                                  .filename_prefix(file_name_prefix.to_string_lossy())
+                                  .build(directory)
+                                  .map_err(|source| {
+                                      SubscriberError::RollingFile { path: file_path.into(),
+                                                                     source }
+                                  })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tracing::info_span;
+    use tracing_subscriber::Registry;
+
+    use super::*;
+
+    fn profiling_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("utilities-subscriber-{}-{test}", std::process::id()))
+    }
+    /// Records `outer > inner` through the profiling layer, then drops a [`SubscriberGuard`] holding its guard.
+    fn profile_spans(span_profiling: &SpanProfiling) -> String {
+        let (flame_layer, chrome_layer, profiling_guard) =
+            profiling_layers(span_profiling).unwrap();
+        let subscriber = Registry::default().with(flame_layer)
+                                            .with(chrome_layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = info_span!("outer").entered();
+            let _inner = info_span!("inner").entered();
+        });
+        let (_, writer_guard) = tracing_appender::non_blocking(io::sink());
+        let guard = SubscriberGuard { #[cfg(feature = "otlp")]
+                                      _otlp_guard: None,
+                                      _profiling_guard: profiling_guard,
+                                      _writer_guard: writer_guard, };
+        drop(guard);
+        let (SpanProfiling::FoldedStacks(file_path) | SpanProfiling::ChromeTrace(file_path)) =
+            span_profiling;
+        fs::read_to_string(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_profiling_written_on_guard_drop() {
+        let dir = profiling_dir("written");
+        let folded = profile_spans(&SpanProfiling::FoldedStacks(dir.join("nested/spans.folded")));
+        assert!(folded.lines()
+                      .any(|line| line.contains("outer") && line.contains("inner")),
+                "{folded}");
+
+        let chrome = profile_spans(&SpanProfiling::ChromeTrace(dir.join("spans.json")));
+        assert!(chrome.starts_with('['), "{chrome}");
+        assert!(chrome.contains("\"name\":\"inner\""), "{chrome}");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_uncreatable_profiling_file_is_an_error() {
+        let dir = profiling_dir("uncreatable");
+        fs::create_dir_all(&dir).unwrap();
+        // a file where a parent directory should be
+        fs::write(dir.join("blocker"), "").unwrap();
+        let span_profiling = SpanProfiling::FoldedStacks(dir.join("blocker/spans.folded"));
+        let result = profiling_layers::<Registry, Registry>(&span_profiling);
+        assert!(matches!(result, Err(SubscriberError::Io { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
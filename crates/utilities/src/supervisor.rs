@@ -0,0 +1,594 @@
+//! Supervisors: restart [`Actor`]s that fail (handler error or panic), behind the same [`ActorHandle`]s.
+//!
+//! - each child's mailbox outlives its instances: handles stay valid & queued messages go to the next instance
+//!   - the message being handled when it failed is lost (an `ask` for it gets [`ActorError::NoReply`])
+//! - [`Strategy::OneForOne`]: restart just the failed child; [`Strategy::OneForAll`]: restart every child
+//! - restart intensity: more than `max_restarts` failures `within` a window and the supervisor gives up, stopping
+//!   every child (their handles then fail with [`ActorError::Stopped`])
+//! - state recovery: a new instance comes from the child's factory, then [`Actor::recover`] gets the failed one
+//! - a child that stops normally (all handles dropped, [`ActorHandle::stop`], [`Context::stop`]) is not restarted
+//! - [`SupervisorEvent`]s (restarts, giving up) are broadcast: see [`Supervisor::events`]
+//! - stopping: [`Supervisor::shutdown`] at once, or gracefully via a [`Shutdown`] given to the builder (children
+//!   drain their mailboxes, as with [`actor::spawn`](crate::actor::spawn))
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use utilities::{actor::{Actor, Context},
+//!                 supervisor::{Strategy, Supervisor}};
+//!
+//! #[derive(Default)]
+//! struct Flaky;
+//! impl Actor for Flaky {
+//!     type Error = std::convert::Infallible;
+//!     type Message = ();
+//!
+//!     async fn handle(&mut self, _msg: (), _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         panic!("flaked")
+//!     }
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let supervisor = Supervisor::builder().strategy(Strategy::OneForOne)
+//!                                       .max_restarts(3)
+//!                                       .within(Duration::from_secs(10))
+//!                                       .build();
+//! let mut events = supervisor.events();
+//! let flaky = supervisor.child(Flaky::default).name("flaky").call();
+//! flaky.tell(()).await.unwrap();
+//! println!("{}", events.recv().await.unwrap()); // flaky restarted (panicked: flaked), 1 of 3 within 10s
+//! assert!(!flaky.is_stopped());
+//! # }
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{any::Any,
+          collections::VecDeque,
+          fmt,
+          panic::AssertUnwindSafe,
+          sync::{Arc, Mutex},
+          time::Duration};
+
+use bon::bon;
+use futures::FutureExt as _;
+use tokio::{select,
+            sync::{broadcast, watch},
+            task::JoinHandle,
+            time::Instant};
+use tracing::{Instrument as _, Level as L, event, info_span};
+
+#[cfg(doc)]
+use crate::actor::{ActorError, Context};
+use crate::{Shutdown,
+            actor::{Actor, ActorHandle, Backpressure, mailbox_for, run_until_stop}};
+// ///////////////////////////////// [ config ] ///////////////////////////////// //
+/// Which children restart when one fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only the failed child: for independent children.
+    #[default]
+    OneForOne,
+    /// Every child: for children that rely on each other's state.
+    OneForAll,
+}
+
+/// Why a child was restarted (or the supervisor gave up).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// A handler (or `started`/`tick`) returned this error.
+    Error(String),
+    /// A handler panicked with this message.
+    Panic(String),
+    /// Restarted along with this failed sibling ([`Strategy::OneForAll`]).
+    Sibling(Arc<str>),
+}
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error(error) => write!(f, "failed: {error}"),
+            Self::Panic(message) => write!(f, "panicked: {message}"),
+            Self::Sibling(sibling) => write!(f, "sibling `{sibling}` failed"),
+        }
+    }
+}
+
+/// Broadcast by a [`Supervisor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorEvent {
+    /// `child` is running again, as a new instance.  `restarts`: failures counted in the current window.
+    Restarted {
+        child:        Arc<str>,
+        failure:      Failure,
+        restarts:     u32,
+        max_restarts: u32,
+        within:       Duration,
+    },
+    /// Too many failures: every child is stopping.
+    GaveUp { child: Arc<str>, failure: Failure },
+}
+impl fmt::Display for SupervisorEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Restarted { child,
+                              failure,
+                              restarts,
+                              max_restarts,
+                              within, } => write!(f,
+                                                  "{child} restarted ({failure}), {restarts} of {max_restarts} within {within:?}"),
+            Self::GaveUp { child, failure } => {
+                write!(f,
+                       "{child} {failure}: too many restarts, stopping all children")
+            },
+        }
+    }
+}
+// ///////////////////////////////// [ supervisor ] ///////////////////////////////// //
+/// Runs [`Actor`]s as children, restarting them per its [`Strategy`].  Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    shared: Arc<Shared>,
+}
+#[derive(Debug)]
+struct Shared {
+    strategy:     Strategy,
+    max_restarts: u32,
+    within:       Duration,
+    /// Failure times within the window.
+    restarts:     Mutex<VecDeque<Instant>>,
+    signal:       watch::Sender<Signal>,
+    events:       broadcast::Sender<SupervisorEvent>,
+    tasks:        Mutex<Vec<JoinHandle<()>>>,
+    shutdown:     Option<Shutdown>,
+}
+/// Seen by every child: a new `generation` restarts it (one-for-all); `shutdown` stops it.
+#[derive(Debug, Clone, Default)]
+struct Signal {
+    generation: u64,
+    failed:     Option<Arc<str>>,
+    shutdown:   bool,
+}
+#[bon]
+impl Supervisor {
+    /// - `max_restarts` failures are tolerated `within` any such window; one more and the supervisor gives up
+    /// - `shutdown`: children drain & stop when it's triggered; their tasks are ones [`Shutdown::drain`] waits for
+    #[builder]
+    pub fn new(#[builder(default)] strategy: Strategy,
+               #[builder(default = 3)] max_restarts: u32,
+               #[builder(default = Duration::from_secs(5))] within: Duration,
+               shutdown: Option<Shutdown>)
+               -> Self {
+        Self { shared: Arc::new(Shared { strategy,
+                                         max_restarts,
+                                         within,
+                                         restarts: Mutex::default(),
+                                         signal: watch::Sender::new(Signal::default()),
+                                         events: broadcast::Sender::new(64),
+                                         tasks: Mutex::default(),
+                                         shutdown }), }
+    }
+
+    /// Start a child from `factory`, which also makes its replacements.  Parameters as [`actor::spawn`](crate::actor::spawn).
+    #[builder]
+    pub fn child<A, F>(&self,
+                       #[builder(start_fn)] factory: F,
+                       #[builder(into)] name: Option<String>,
+                       #[builder(default = 16)] mailbox: usize,
+                       #[builder(default)] backpressure: Backpressure)
+                       -> ActorHandle<A>
+        where A: Actor,
+              F: Fn() -> A + Send + 'static {
+        let (handle, receiver, ctx) =
+            mailbox_for(name, mailbox, backpressure, self.shared.shutdown.as_ref());
+        // subscribed now: signals sent before the task first runs aren't missed
+        let signal = self.shared.signal.subscribe();
+        let span = info_span!("actor", name = %handle.name());
+        let supervise =
+            supervise(self.shared.clone(), signal, factory, receiver, ctx).instrument(span);
+        let task = match &self.shared.shutdown {
+            Some(shutdown) => shutdown.spawn(supervise),
+            None => tokio::spawn(supervise),
+        };
+        self.shared
+            .tasks
+            .lock()
+            .unwrap()
+            .push(task);
+        handle
+    }
+}
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+impl Supervisor {
+    pub fn strategy(&self) -> Strategy {
+        self.shared.strategy
+    }
+
+    /// Restarts & giving up, from now on.  (A receiver that falls behind skips the oldest events.)
+    pub fn events(&self) -> broadcast::Receiver<SupervisorEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// Stop every child now: a handler in progress is cancelled at its next `.await`; queued messages are dropped.
+    /// `stopping` runs.  (For a graceful stop, build the supervisor with a [`Shutdown`].)
+    pub fn shutdown(&self) {
+        self.shared
+            .signal
+            .send_modify(|signal| signal.shutdown = true);
+    }
+
+    /// Wait for every child started so far to end (see [`shutdown`](Self::shutdown)).
+    pub async fn join(&self) {
+        let tasks = std::mem::take(&mut *self.shared
+                                             .tasks
+                                             .lock()
+                                             .unwrap());
+        for task in tasks {
+            // `supervise` catches panics itself; a JoinError means the runtime is shutting down
+            let _ = task.await;
+        }
+    }
+}
+
+async fn supervise<A, F>(shared: Arc<Shared>,
+                         mut signal: watch::Receiver<Signal>,
+                         factory: F,
+                         mut receiver: crate::actor::Mailbox<A>,
+                         mut ctx: crate::actor::Context<A>)
+    where A: Actor,
+          F: Fn() -> A {
+    let mut actor = factory();
+    event!(L::DEBUG, "child starting");
+    while !signal.borrow().shutdown {
+        let run =
+            AssertUnwindSafe(run_until_stop(&mut actor, &mut receiver, &mut ctx)).catch_unwind();
+        let failure = select! {
+            biased;
+            _ = signal.changed() => {
+                let signal = signal.borrow_and_update().clone();
+                match (signal.shutdown, signal.failed) {
+                    (false, Some(sibling)) => Some(Failure::Sibling(sibling)),
+                    _ => None,
+                }
+            },
+            outcome = run => match outcome {
+                Ok(Ok(())) => None,
+                Ok(Err(error)) => Some(Failure::Error(error.to_string())),
+                Err(panic) => Some(Failure::Panic(panic_message(&*panic))),
+            },
+        };
+        // (a panicking `stopping` would end this task; the mailbox then closes)
+        actor.stopping(&mut ctx).await;
+        let Some(failure) = failure else { break };
+        if !matches!(failure, Failure::Sibling(_)) {
+            match shared.count_failure() {
+                Some(restarts) => {
+                    if shared.strategy == Strategy::OneForAll {
+                        shared.signal
+                              .send_modify(|signal| {
+                                  signal.generation += 1;
+                                  signal.failed = Some(ctx.name().into());
+                              });
+                        // (not a sibling failure for this child)
+                        signal.borrow_and_update();
+                    }
+                    shared.emit(SupervisorEvent::Restarted { child: ctx.name().into(),
+                                                             failure,
+                                                             restarts,
+                                                             max_restarts: shared.max_restarts,
+                                                             within: shared.within });
+                },
+                None => {
+                    event!(L::ERROR, %failure, "too many restarts: supervisor giving up");
+                    shared.emit(SupervisorEvent::GaveUp { child: ctx.name().into(),
+                                                          failure });
+                    shared.signal
+                          .send_modify(|signal| signal.shutdown = true);
+                    break;
+                },
+            }
+        } else {
+            let restarts = shared.restarts
+                                 .lock()
+                                 .unwrap()
+                                 .len() as u32;
+            shared.emit(SupervisorEvent::Restarted { child: ctx.name().into(),
+                                                     failure,
+                                                     restarts,
+                                                     max_restarts: shared.max_restarts,
+                                                     within: shared.within });
+        }
+        ctx.metrics.restarts += 1;
+        let failed = std::mem::replace(&mut actor, factory());
+        actor.recover(failed);
+    }
+    receiver.close();
+    event!(L::DEBUG, "child stopped");
+}
+
+impl Shared {
+    /// Record a failure: `Some(failures in the window)` if within the intensity limit.
+    fn count_failure(&self) -> Option<u32> {
+        let now = Instant::now();
+        let mut restarts = self.restarts.lock().unwrap();
+        while restarts.front()
+                      .is_some_and(|&at| now.duration_since(at) > self.within)
+        {
+            restarts.pop_front();
+        }
+        restarts.push_back(now);
+        let count = restarts.len() as u32;
+        (count <= self.max_restarts).then_some(count)
+    }
+
+    fn emit(&self, event: SupervisorEvent) {
+        event!(L::WARN, %event, "supervisor");
+        // no subscribers is fine
+        let _ = self.events.send(event);
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic.downcast_ref::<&str>()
+         .map(|message| (*message).to_owned())
+         .or_else(|| {
+             panic.downcast_ref::<String>()
+                  .cloned()
+         })
+         .unwrap_or_else(|| "(non-string panic payload)".to_owned())
+}
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use thiserror::Error;
+
+    use super::*;
+    use crate::actor::{ActorError, Context, Reply};
+
+    #[derive(Debug, Error)]
+    #[error("asked to fail")]
+    struct Asked;
+
+    /// `count` is recovered; `incarnation` counts instances.
+    #[derive(Default)]
+    struct Worker {
+        count:       u32,
+        incarnation: u32,
+        keep_count:  bool,
+    }
+    enum WorkerMsg {
+        Add,
+        Fail,
+        Panic,
+        Get(Reply<(u32, u32)>),
+    }
+    impl Actor for Worker {
+        type Error = Asked;
+        type Message = WorkerMsg;
+
+        async fn handle(&mut self, msg: WorkerMsg, _ctx: &mut Context<Self>) -> Result<(), Asked> {
+            match msg {
+                WorkerMsg::Add => self.count += 1,
+                WorkerMsg::Fail => return Err(Asked),
+                WorkerMsg::Panic => panic!("asked to panic"),
+                WorkerMsg::Get(reply) => reply.send((self.count, self.incarnation)),
+            }
+            Ok(())
+        }
+
+        fn recover(&mut self, failed: Self) {
+            self.incarnation = failed.incarnation + 1;
+            if failed.keep_count {
+                self.count = failed.count;
+            }
+        }
+    }
+    fn recovering() -> Worker {
+        Worker { keep_count: true,
+                 ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_one_for_one_restarts_failed_child_and_recovers_state() {
+        let supervisor = Supervisor::default();
+        let mut events = supervisor.events();
+        let worker = supervisor.child(recovering)
+                               .name("worker")
+                               .call();
+        let other = supervisor.child(Worker::default)
+                              .name("other")
+                              .call();
+        other.tell(WorkerMsg::Add)
+             .await
+             .unwrap();
+
+        worker.tell(WorkerMsg::Add)
+              .await
+              .unwrap();
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+        // queued behind the failure: handled by the next instance
+        worker.tell(WorkerMsg::Add)
+              .await
+              .unwrap();
+        assert_eq!(worker.ask(WorkerMsg::Get)
+                         .await
+                         .unwrap(),
+                   (2, 1));
+        assert_eq!(other.ask(WorkerMsg::Get)
+                        .await
+                        .unwrap(),
+                   (1, 0),
+                   "sibling untouched");
+
+        assert_eq!(events.recv().await.unwrap(),
+                   SupervisorEvent::Restarted { child:        "worker".into(),
+                                                failure:
+                                                    Failure::Error("asked to fail".into()),
+                                                restarts:     1,
+                                                max_restarts: 3,
+                                                within:       Duration::from_secs(5), });
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_restarted_and_pending_ask_gets_no_reply() {
+        let supervisor = Supervisor::default();
+        let mut events = supervisor.events();
+        let worker = supervisor.child(recovering)
+                               .call();
+        worker.tell(WorkerMsg::Add)
+              .await
+              .unwrap();
+        worker.tell(WorkerMsg::Panic)
+              .await
+              .unwrap();
+        assert_eq!(worker.ask(WorkerMsg::Get)
+                         .await
+                         .unwrap(),
+                   (1, 1));
+        assert!(matches!(events.recv().await.unwrap(),
+                         SupervisorEvent::Restarted { failure: Failure::Panic(message), .. }
+                         if message == "asked to panic"));
+    }
+
+    #[tokio::test]
+    async fn test_one_for_all_restarts_siblings() {
+        let supervisor = Supervisor::builder().strategy(Strategy::OneForAll)
+                                              .build();
+        let mut events = supervisor.events();
+        let failing = supervisor.child(Worker::default)
+                                .name("failing")
+                                .call();
+        let sibling = supervisor.child(Worker::default)
+                                .name("sibling")
+                                .call();
+        sibling.tell(WorkerMsg::Add)
+               .await
+               .unwrap();
+        assert_eq!(sibling.ask(WorkerMsg::Get)
+                          .await
+                          .unwrap(),
+                   (1, 0));
+        failing.tell(WorkerMsg::Fail)
+               .await
+               .unwrap();
+
+        let mut restarted = [events.recv().await.unwrap(), events.recv().await.unwrap()];
+        restarted.sort_by_key(|event| event.to_string());
+        assert!(matches!(&restarted[0],
+                         SupervisorEvent::Restarted { child, failure: Failure::Error(_), restarts: 1, .. }
+                         if &**child == "failing"));
+        assert!(matches!(&restarted[1],
+                         SupervisorEvent::Restarted { child, failure: Failure::Sibling(failed), .. }
+                         if &**child == "sibling" && &**failed == "failing"));
+        // a fresh sibling (its count not recovered)
+        assert_eq!(sibling.ask(WorkerMsg::Get)
+                          .await
+                          .unwrap(),
+                   (0, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_intensity_limit_gives_up_and_stops_children() {
+        let supervisor = Supervisor::builder().max_restarts(2)
+                                              .within(Duration::from_secs(10))
+                                              .build();
+        let mut events = supervisor.events();
+        let worker = supervisor.child(Worker::default)
+                               .name("worker")
+                               .call();
+        let other = supervisor.child(Worker::default)
+                              .name("other")
+                              .call();
+
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+        assert_eq!(worker.ask(WorkerMsg::Get)
+                         .await
+                         .unwrap()
+                         .1,
+                   2);
+        // the window has passed: counting starts over
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+        assert_eq!(worker.ask(WorkerMsg::Get)
+                         .await
+                         .unwrap()
+                         .1,
+                   4);
+        worker.tell(WorkerMsg::Fail)
+              .await
+              .unwrap();
+
+        supervisor.join().await;
+        let events: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(&events[4], SupervisorEvent::GaveUp { child, .. } if &**child == "worker"));
+        assert!(matches!(other.tell(WorkerMsg::Add)
+                              .await,
+                         Err(ActorError::Stopped { .. })));
+        assert!(worker.is_stopped());
+    }
+
+    #[tokio::test]
+    async fn test_normal_stop_and_shutdown_are_not_restarted() {
+        let supervisor = Supervisor::default();
+        let mut events = supervisor.events();
+        let stopping = supervisor.child(Worker::default)
+                                 .call();
+        let running = supervisor.child(Worker::default)
+                                .call();
+        stopping.stop().await.unwrap();
+        running.tell(WorkerMsg::Add)
+               .await
+               .unwrap();
+        supervisor.shutdown();
+        supervisor.join().await;
+        assert!(stopping.is_stopped() && running.is_stopped());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_drains_children() {
+        let shutdown = Shutdown::new();
+        let supervisor = Supervisor::builder().shutdown(shutdown.clone())
+                                              .build();
+        let mut events = supervisor.events();
+        let worker = supervisor.child(Worker::default)
+                               .call();
+        worker.tell(WorkerMsg::Add)
+              .await
+              .unwrap();
+        let (got, drained) = tokio::join!(worker.ask(WorkerMsg::Get),
+                                          shutdown.drain(Duration::from_secs(1)));
+        assert_eq!(got.unwrap(), (1, 0));
+        assert!(drained.is_clean());
+        assert!(worker.is_stopped());
+        assert!(events.try_recv().is_err(),
+                "a drained child isn't restarted");
+    }
+
+    #[test]
+    fn test_event_display() {
+        let event = SupervisorEvent::Restarted { child:        "printer-1".into(),
+                                                 failure:      Failure::Panic("boom".into()),
+                                                 restarts:     1,
+                                                 max_restarts: 3,
+                                                 within:       Duration::from_secs(10), };
+        assert_eq!(event.to_string(),
+                   "printer-1 restarted (panicked: boom), 1 of 3 within 10s");
+    }
+}
@@ -0,0 +1,235 @@
+//! Test-scoped subscriber that captures events for assertions.
+//!
+//! `test-log` lets tests *print* logs, but not assert on them.
+//! `EventCapture` installs a capturing subscriber as the **thread-local** default (`tracing::subscriber::set_default`)
+//! and records each event's level, target, message, fields, and enclosing span names.
+//! The global default subscriber is never touched, so this composes with `test_log::test` and parallel tests.
+//!
+//! ## Caveat
+//! - Thread-local: events emitted on *other* threads (e.g. `tokio` multi-thread workers) are not captured.
+//!   Use `#[tokio::test]` (current-thread flavor) or keep the code under test on the test thread.
+//!
+//! ## Use:
+//! ```
+//! use tracing::{Level, event};
+//! use utilities::testing::EventCapture;
+//!
+//! let capture = EventCapture::start();
+//! event!(Level::WARN, attempt = 2, "retrying");
+//! let events = capture.events();
+//! assert_eq!(events.len(), 1);
+//! assert_eq!(events[0].level, Level::WARN);
+//! assert_eq!(events[0].message.as_deref(), Some("retrying"));
+//! assert_eq!(events[0].field("attempt"), Some("2"));
+//! ```
+// ///////////////////////////////// [ use ] ///////////////////////////////// //
+use std::{collections::BTreeMap,
+          fmt,
+          sync::{Arc, Mutex}};
+
+use tracing::{Event, Level, Subscriber,
+              field::{Field, Visit},
+              level_filters::LevelFilter,
+              subscriber::DefaultGuard};
+use tracing_subscriber::{Layer, layer::Context, prelude::*, registry::LookupSpan};
+// ///////////////////////////////// [ core export ] ///////////////////////////////// //
+/// A single recorded tracing event.
+///
+/// Field values are stored via their `Debug` representation, except `&str` fields which are stored raw.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    pub level:   Level,
+    pub target:  String,
+    /// The event's `message` field, if present.
+    pub message: Option<String>,
+    /// All non-`message` fields.
+    pub fields:  BTreeMap<String, String>,
+    /// Names of enclosing spans, root first.
+    pub spans:   Vec<String>,
+}
+impl CapturedEvent {
+    /// Value of a (non-message) field, if recorded.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(name)
+            .map(String::as_str)
+    }
+
+    /// Whether the message or any field value contains `needle`.
+    pub fn contains(&self, needle: &str) -> bool {
+        self.message
+            .as_deref()
+            .is_some_and(|m| m.contains(needle))
+        || self.fields
+               .values()
+               .any(|v| v.contains(needle))
+    }
+}
+
+/// Captures events on the current thread until dropped.
+///
+/// Dropping restores whatever default subscriber was previously in effect.
+#[must_use = "capture stops when `EventCapture` is dropped"]
+pub struct EventCapture {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+    _guard: DefaultGuard,
+}
+impl fmt::Debug for EventCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventCapture")
+         .field("events", &self.events)
+         .finish_non_exhaustive()
+    }
+}
+impl EventCapture {
+    /// Start capturing all events (`TRACE` and above) on the current thread.
+    pub fn start() -> Self {
+        Self::with_level(LevelFilter::TRACE)
+    }
+
+    /// Start capturing events at or above `level` on the current thread.
+    pub fn with_level(level: LevelFilter) -> Self {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let layer = CaptureLayer { events: events.clone(), };
+        let subscriber = tracing_subscriber::Registry::default().with(layer.with_filter(level));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        Self { events, _guard }
+    }
+
+    /// Snapshot of all events captured so far.
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.lock().clone()
+    }
+
+    /// Snapshot of captured events at exactly `level`.
+    pub fn events_at(&self, level: Level) -> Vec<CapturedEvent> {
+        self.lock()
+            .iter()
+            .filter(|e| e.level == level)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether any captured event's message or field values contain `needle`.
+    pub fn any_contains(&self, needle: &str) -> bool {
+        self.lock()
+            .iter()
+            .any(|e| e.contains(needle))
+    }
+
+    /// Discard events captured so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CapturedEvent>> {
+        // a panicking test thread can poison the lock; the data is still valid for inspection
+        self.events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+// ///////////////////////////////// [ layer ] ///////////////////////////////// //
+struct CaptureLayer {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+impl<S> Layer<S> for CaptureLayer where S: Subscriber + for<'a> LookupSpan<'a> {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let spans = ctx.event_scope(event)
+                       .map(|scope| {
+                           scope.from_root()
+                                .map(|span| span.name().to_string())
+                                .collect()
+                       })
+                       .unwrap_or_default();
+        let captured = CapturedEvent { level: *event.metadata().level(),
+                                       target: event.metadata()
+                                                    .target()
+                                                    .to_string(),
+                                       message: visitor.message,
+                                       fields: visitor.fields,
+                                       spans };
+        self.events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(captured);
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields:  BTreeMap<String, String>,
+}
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "message" => self.message = Some(value),
+            name => {
+                self.fields
+                    .insert(name.to_string(), value);
+            },
+        }
+    }
+}
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value));
+    }
+}
+
+// ///////////////////////////////// [ tests ] ///////////////////////////////// //
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tracing::{event, info_span};
+
+    use super::*;
+    #[test]
+    fn test_captures_message_fields_and_spans() {
+        let capture = EventCapture::start();
+        let span = info_span!("outer");
+        let _entered = span.enter();
+        event!(Level::INFO, count = 3, name = "alpha", debugged = ?Some(1), "hello");
+
+        let events = capture.events();
+        assert_eq!(events.len(), 1);
+        let ev = &events[0];
+        assert_eq!(ev.level, Level::INFO);
+        assert_eq!(ev.message.as_deref(), Some("hello"));
+        assert_eq!(ev.field("count"), Some("3"));
+        assert_eq!(ev.field("name"), Some("alpha"));
+        assert_eq!(ev.field("debugged"), Some("Some(1)"));
+        assert_eq!(ev.spans, vec!["outer".to_string()]);
+        assert!(capture.any_contains("alph"));
+    }
+    #[test]
+    fn test_level_filter_and_clear() {
+        let capture = EventCapture::with_level(LevelFilter::WARN);
+        event!(Level::DEBUG, "ignored");
+        event!(Level::WARN, "kept");
+        event!(Level::ERROR, "kept too");
+        assert_eq!(capture.events().len(), 2);
+        assert_eq!(capture.events_at(Level::WARN)
+                          .len(),
+                   1);
+        capture.clear();
+        assert!(capture.events().is_empty());
+    }
+    #[test]
+    fn test_capture_ends_on_drop() {
+        let capture = EventCapture::start();
+        let events = capture.events.clone();
+        drop(capture);
+        event!(Level::ERROR, "after drop");
+        assert!(events.lock()
+                      .unwrap()
+                      .is_empty());
+    }
+}